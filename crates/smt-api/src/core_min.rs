@@ -0,0 +1,119 @@
+#![forbid(unsafe_code)]
+//! UNSAT core minimization over labeled assertions.
+//!
+//! Strategies work on indices into the current core and only talk to the solver through
+//! an oracle: `Some(core)` if the given subset is unsatisfiable (with the subset's own,
+//! possibly smaller, core), `None` if it is satisfiable, unknown, or the budget is spent.
+//! Treating "no answer" as satisfiable only keeps extra elements, so every strategy
+//! returns a valid core whatever the budget.
+
+/// How `Session::get_minimal_unsat_core` shrinks a core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoreStrategy {
+    /// Re-solve under the core until it stops shrinking. Cheap, not minimal.
+    Trim,
+    /// Drop one element at a time and keep the drop if still unsat. Minimal.
+    #[default]
+    Deletion,
+    /// Divide and conquer (Junker's QuickXplain). Minimal; fewer checks on small cores.
+    QuickXplain,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CoreMinConfig {
+    pub strategy: CoreStrategy,
+    /// Maximum number of solver calls per minimization.
+    pub max_checks: usize,
+}
+
+impl Default for CoreMinConfig {
+    fn default() -> Self {
+        Self { strategy: CoreStrategy::default(), max_checks: 1000 }
+    }
+}
+
+/// Oracle with a call budget.
+pub(crate) struct Budgeted<F> {
+    oracle: F,
+    left: usize,
+}
+
+impl<F: FnMut(&[usize]) -> Option<Vec<usize>>> Budgeted<F> {
+    pub(crate) fn new(oracle: F, max_checks: usize) -> Self {
+        Self { oracle, left: max_checks }
+    }
+
+    fn unsat(&mut self, subset: &[usize]) -> Option<Vec<usize>> {
+        if self.left == 0 {
+            return None;
+        }
+        self.left -= 1;
+        (self.oracle)(subset)
+    }
+}
+
+/// Shrink `core` (known unsat) with `strategy`.
+pub(crate) fn minimize<F>(core: Vec<usize>, strategy: CoreStrategy, oracle: &mut Budgeted<F>) -> Vec<usize>
+where
+    F: FnMut(&[usize]) -> Option<Vec<usize>>,
+{
+    let mut out = match strategy {
+        CoreStrategy::Trim => trim(core, oracle),
+        CoreStrategy::Deletion => deletion(core, oracle),
+        CoreStrategy::QuickXplain => quick_xplain(&[], &core, false, oracle),
+    };
+    out.sort_unstable();
+    out
+}
+
+fn trim<F>(mut core: Vec<usize>, oracle: &mut Budgeted<F>) -> Vec<usize>
+where
+    F: FnMut(&[usize]) -> Option<Vec<usize>>,
+{
+    while let Some(next) = oracle.unsat(&core) {
+        if next.len() >= core.len() {
+            break;
+        }
+        core = next;
+    }
+    core
+}
+
+fn deletion<F>(mut core: Vec<usize>, oracle: &mut Budgeted<F>) -> Vec<usize>
+where
+    F: FnMut(&[usize]) -> Option<Vec<usize>>,
+{
+    // Elements already shown necessary.
+    let mut kept: Vec<usize> = Vec::new();
+    while let Some(x) = core.pop() {
+        let candidate: Vec<usize> = kept.iter().chain(&core).copied().collect();
+        match oracle.unsat(&candidate) {
+            // Not needed; the solver's own core may drop more.
+            Some(smaller) => {
+                kept.retain(|k| smaller.contains(k));
+                core.retain(|k| smaller.contains(k));
+            }
+            None => kept.push(x),
+        }
+    }
+    kept
+}
+
+/// Minimal `d` within `c` such that `background ∪ d` is unsat (given `background ∪ c` is).
+fn quick_xplain<F>(background: &[usize], c: &[usize], check: bool, oracle: &mut Budgeted<F>) -> Vec<usize>
+where
+    F: FnMut(&[usize]) -> Option<Vec<usize>>,
+{
+    if check && oracle.unsat(background).is_some() {
+        return Vec::new();
+    }
+    if c.len() <= 1 {
+        return c.to_vec();
+    }
+    let (c1, c2) = c.split_at(c.len() / 2);
+    let with_c1: Vec<usize> = background.iter().chain(c1).copied().collect();
+    let d2 = quick_xplain(&with_c1, c2, true, oracle);
+    let with_d2: Vec<usize> = background.iter().chain(&d2).copied().collect();
+    let d1 = quick_xplain(&with_d2, c1, !d2.is_empty(), oracle);
+    d1.into_iter().chain(d2).collect()
+}
@@ -1,74 +1,318 @@
 #![forbid(unsafe_code)]
 //! High-level session API (scaffold).
 
-use smt_core::{Context, SortId, TermId};
+pub mod core_min;
+
+use smt_core::{BvOp, Context, DatatypeDecl, Evaluator, Model, Rational, SortId, TermId, Value};
 use smt_engine::engine::{SmtEngine, CheckSat};
-use smt_sat::DummySat;
+use smt_sat::{Cdcl, Lit};
+
+use crate::core_min::{Budgeted, CoreMinConfig};
 
 /// A label wrapper (kept minimal).
 #[derive(Debug, Clone)]
 pub struct Label(pub String);
 
-/// Session wraps a context and an engine.
-/// In a real solver, `assert` would create atoms and push them into SAT/theories.
+/// Session wraps an engine; terms are built directly in the engine's context.
 pub struct Session {
-    ctx: Context,
-    eng: SmtEngine<DummySat>,
+    eng: SmtEngine<Cdcl>,
     asserted: Vec<(TermId, Option<Label>)>,
+    /// Activation literal of every labeled assertion, assumed by `check_sat`.
+    guards: Vec<(Lit, Label)>,
+    /// Indices into `guards` forming the core of the last `Unsat`.
+    core: Vec<usize>,
+    core_min: CoreMinConfig,
+    /// Assumptions of the last check (`check_sat_assuming`), with their literals.
+    assumed: Vec<(TermId, Lit)>,
+    /// Those of `assumed` that the last `Unsat` depends on.
+    failed_assumed: Vec<TermId>,
+    /// Lengths of `asserted` and `guards` at each open `push`.
+    scopes: Vec<(usize, usize)>,
+    /// Result of the last `check_sat`, cleared by new assertions.
+    last: Option<CheckSat>,
 }
 
 impl Session {
     pub fn new(theories: Vec<Box<dyn smt_engine::theory::Theory>>) -> Self {
-        let ctx = Context::new();
-        let eng = SmtEngine::new(ctx.clone_for_engine(), DummySat::default(), theories);
-        Self { ctx, eng, asserted: Vec::new() }
+        Self::with_context(Context::new(), theories)
     }
 
+    /// Start from a pre-built context (useful when theories are configured with its terms).
+    pub fn with_context(ctx: Context, theories: Vec<Box<dyn smt_engine::theory::Theory>>) -> Self {
+        let eng = SmtEngine::new(ctx, Cdcl::new(), theories);
+        Self { eng, asserted: Vec::new(), guards: Vec::new(), core: Vec::new(), core_min: CoreMinConfig::default(), assumed: Vec::new(), failed_assumed: Vec::new(), scopes: Vec::new(), last: None }
+    }
+
+    /// Strategy and budget of `get_minimal_unsat_core`.
+    pub fn core_min_mut(&mut self) -> &mut CoreMinConfig { &mut self.core_min }
+
+    /// Engine configuration (sharing matrix, debug switches).
+    pub fn config_mut(&mut self) -> &mut smt_engine::config::EngineConfig { &mut self.eng.config }
+
     /// Access the context.
-    pub fn ctx(&self) -> &Context { &self.ctx }
+    pub fn ctx(&self) -> &Context { &self.eng.ctx }
 
     /// Mutable context (to declare sorts/terms).
-    pub fn ctx_mut(&mut self) -> &mut Context { &mut self.ctx }
+    pub fn ctx_mut(&mut self) -> &mut Context { &mut self.eng.ctx }
 
     /// Declare an uninterpreted sort.
     pub fn declare_uninterpreted_sort(&mut self, name: &str) -> SortId {
-        self.ctx.declare_uninterpreted_sort(name)
+        self.eng.ctx.declare_uninterpreted_sort(name)
+    }
+
+    /// The sort of arrays from `index` to `elem`.
+    pub fn array_sort(&mut self, index: SortId, elem: SortId) -> SortId {
+        self.eng.ctx.array_sort(index, elem)
+    }
+
+    /// The sort of bit-vectors of `width` bits.
+    pub fn bv_sort(&mut self, width: u32) -> SortId {
+        self.eng.ctx.bv_sort(width)
+    }
+
+    /// Declare one datatype (`FieldSort::Group(0)` refers to itself).
+    pub fn declare_datatype(&mut self, decl: DatatypeDecl) -> smt_core::Result<SortId> {
+        self.eng.ctx.declare_datatype(decl)
+    }
+
+    /// Declare mutually recursive datatypes; returns their sorts in order.
+    pub fn declare_datatypes(&mut self, decls: &[DatatypeDecl]) -> smt_core::Result<Vec<SortId>> {
+        self.eng.ctx.declare_datatypes(decls)
     }
 
     /// Declare a constant.
     pub fn declare_const(&mut self, name: &str, sort: SortId) -> TermId {
-        self.ctx.const_term(name, sort)
+        self.eng.ctx.const_term(name, sort)
     }
 
     /// UF application.
     pub fn app_uf(&mut self, name: &str, args: &[TermId], out_sort: SortId) -> TermId {
-        self.ctx.uf_app(name, args, out_sort)
+        self.eng.ctx.uf_app(name, args, out_sort)
     }
 
     /// Equality term.
     pub fn eq(&mut self, a: TermId, b: TermId) -> TermId {
-        self.ctx.eq(a, b)
+        self.eng.ctx.eq(a, b)
     }
 
     /// <= term.
     pub fn le(&mut self, a: TermId, b: TermId) -> TermId {
-        self.ctx.le(a, b)
+        self.eng.ctx.le(a, b)
+    }
+
+    /// Integer constant.
+    pub fn int_const(&mut self, v: i64) -> TermId {
+        self.eng.ctx.int_const(v)
+    }
+
+    /// Real constant.
+    pub fn rat_const(&mut self, v: Rational) -> TermId {
+        self.eng.ctx.rat_const(v)
+    }
+
+    /// + term.
+    pub fn add(&mut self, ts: &[TermId]) -> TermId {
+        self.eng.ctx.add(ts)
+    }
+
+    /// - term.
+    pub fn sub(&mut self, a: TermId, b: TermId) -> TermId {
+        self.eng.ctx.sub(a, b)
+    }
+
+    /// * term.
+    pub fn mul(&mut self, a: TermId, b: TermId) -> TermId {
+        self.eng.ctx.mul(a, b)
+    }
+
+    /// select term.
+    pub fn select(&mut self, a: TermId, i: TermId) -> TermId {
+        self.eng.ctx.select(a, i)
+    }
+
+    /// store term.
+    pub fn store(&mut self, a: TermId, i: TermId, v: TermId) -> TermId {
+        self.eng.ctx.store(a, i, v)
+    }
+
+    /// Bit-vector constant of `width` bits.
+    pub fn bv_const(&mut self, v: u128, width: u32) -> TermId {
+        self.eng.ctx.bv_const(v, width)
+    }
+
+    /// Bit-vector operator or predicate term.
+    pub fn bv(&mut self, op: BvOp, args: &[TermId]) -> TermId {
+        self.eng.ctx.bv(op, args)
+    }
+
+    /// Constructor application (`ctor` indexes the datatype's constructors).
+    pub fn construct(&mut self, sort: SortId, ctor: u32, args: &[TermId]) -> TermId {
+        self.eng.ctx.construct(sort, ctor, args)
+    }
+
+    /// Selector term: field `field` of constructor `ctor`.
+    pub fn selector(&mut self, ctor: u32, field: u32, t: TermId) -> TermId {
+        self.eng.ctx.selector(ctor, field, t)
+    }
+
+    /// Tester term: whether `t` was built by constructor `ctor`.
+    pub fn tester(&mut self, ctor: u32, t: TermId) -> TermId {
+        self.eng.ctx.tester(ctor, t)
     }
 
     /// not term.
     pub fn not(&mut self, t: TermId) -> TermId {
-        self.ctx.not(t)
+        self.eng.ctx.not(t)
+    }
+
+    /// and term.
+    pub fn and(&mut self, ts: &[TermId]) -> TermId {
+        self.eng.ctx.and(ts)
+    }
+
+    /// or term.
+    pub fn or(&mut self, ts: &[TermId]) -> TermId {
+        self.eng.ctx.or(ts)
+    }
+
+    /// implies term.
+    pub fn implies(&mut self, a: TermId, b: TermId) -> TermId {
+        self.eng.ctx.implies(a, b)
+    }
+
+    /// A variable for `forall` / `exists`.
+    pub fn bound_var(&mut self, name: &str, sort: SortId) -> TermId {
+        self.eng.ctx.bound_var(name, sort)
     }
 
-    /// Assert a formula (scaffold: store only).
-    pub fn assert(&mut self, t: TermId, label: Option<&str>) {
-        self.asserted.push((t, label.map(|s| Label(s.to_string()))));
+    /// forall term; `triggers` are multi-patterns (inferred from the body if empty).
+    pub fn forall(&mut self, vars: &[TermId], body: TermId, triggers: &[Vec<TermId>]) -> TermId {
+        self.eng.ctx.forall(vars, body, triggers)
+    }
+
+    /// exists term; `triggers` are used where it is negated.
+    pub fn exists(&mut self, vars: &[TermId], body: TermId, triggers: &[Vec<TermId>]) -> TermId {
+        self.eng.ctx.exists(vars, body, triggers)
+    }
+
+    /// Assert a Boolean formula: its skeleton is propositionalized into the engine right away.
+    /// Labeled assertions are guarded by an activation literal so they can show up in cores.
+    pub fn assert(&mut self, t: TermId, label: Option<&str>) -> smt_core::Result<()> {
+        let label = label.map(|s| Label(s.to_string()));
+        match &label {
+            Some(l) => {
+                let guard = self.eng.assert_guarded(t)?;
+                self.eng.label_assertion(t, &l.0);
+                self.guards.push((guard, l.clone()));
+            }
+            None => self.eng.assert_formula(t)?,
+        }
+        self.asserted.push((t, label));
+        self.last = None;
+        Ok(())
+    }
+
+    /// Open a scope: assertions and declarations from now on are undone by `pop`.
+    pub fn push(&mut self) {
+        self.eng.push();
+        self.scopes.push((self.asserted.len(), self.guards.len()));
+    }
+
+    /// Close the `n` innermost scopes. Terms declared inside them become invalid.
+    pub fn pop(&mut self, n: usize) -> smt_core::Result<()> {
+        self.eng.pop(n)?;
+        if let Some(&(asserted, guards)) = self.scopes.len().checked_sub(n).and_then(|k| self.scopes.get(k)) {
+            self.asserted.truncate(asserted);
+            self.guards.truncate(guards);
+            self.scopes.truncate(self.scopes.len() - n);
+        }
+        self.core.clear();
+        self.assumed.clear();
+        self.failed_assumed.clear();
+        self.last = None;
+        Ok(())
     }
 
     pub fn check_sat(&mut self) -> CheckSat {
-        // In a real solver, we'd translate asserted terms to atoms and feed them.
-        // Here: just run the engine stub.
-        self.eng.check_sat()
+        self.assumed.clear();
+        self.solve()
+    }
+
+    /// Solve with the Boolean terms `ts` temporarily assumed true (not asserted). After
+    /// `Unsat`, `get_unsat_assumptions` names the ones the refutation needs.
+    pub fn check_sat_assuming(&mut self, ts: &[TermId]) -> smt_core::Result<CheckSat> {
+        let bool_sort = self.eng.ctx.bool_sort();
+        if let Some(t) = ts.iter().find(|&&t| self.eng.ctx.term_sort(t) != bool_sort) {
+            return Err(format!("assumption {t:?} is not Boolean").into());
+        }
+        // Encodings are cached, so repeated queries over the same terms stay cheap.
+        self.assumed = ts.iter().map(|&t| (t, self.eng.encode_term(t))).collect();
+        Ok(self.solve())
+    }
+
+    /// Assumptions of the last `check_sat_assuming` that suffice for its `Unsat` (empty
+    /// after any other result).
+    pub fn get_unsat_assumptions(&self) -> Vec<TermId> {
+        if self.last != Some(CheckSat::Unsat) {
+            return Vec::new();
+        }
+        self.failed_assumed.clone()
+    }
+
+    fn solve(&mut self) -> CheckSat {
+        // Assertions are already encoded; labeled ones hold under their guards.
+        let lits: Vec<Lit> = self.guards.iter().map(|(g, _)| *g).chain(self.assumed.iter().map(|(_, l)| *l)).collect();
+        let r = self.eng.check_sat_assuming_lits(&lits);
+        self.last = Some(r);
+        self.core.clear();
+        self.failed_assumed.clear();
+        if r == CheckSat::Unsat {
+            let failed = self.eng.unsat_assumptions();
+            self.core = (0..self.guards.len()).filter(|&i| failed.contains(&self.guards[i].0)).collect();
+            self.failed_assumed = self.assumed.iter().filter(|(_, l)| failed.contains(l)).map(|(t, _)| *t).collect();
+        }
+        if r == CheckSat::Sat && self.eng.config.check_models {
+            self.check_model();
+        }
+        r
+    }
+
+    /// Re-evaluate every assertion (and assumption) under the model; panic on the first
+    /// one not true.
+    fn check_model(&self) {
+        let model = self.eng.build_model();
+        let mut ev = Evaluator::new(&self.eng.ctx, &model);
+        let asserted = self.asserted.iter().map(|(t, l)| ("assertion", *t, l.as_ref().map(|l| l.0.as_str())));
+        let assumed = self.assumed.iter().map(|&(t, _)| ("assumption", t, None));
+        for (kind, t, label) in asserted.chain(assumed) {
+            let failure = match ev.eval(t) {
+                Ok(Value::Bool(true)) => continue,
+                Ok(v) => format!("{kind} {t:?} {label:?} evaluates to {v}"),
+                Err(e) => format!("{kind} {t:?} {label:?} cannot be evaluated: {e}"),
+            };
+            let bundle = smt_engine::unsat_bundle::write_model_check_bundle(&self.eng, &model, &failure);
+            panic!("model check failed: {failure}; debug bundle: {bundle:?}");
+        }
+    }
+
+    /// Model of the last `check_sat`, which must have returned `Sat`.
+    pub fn get_model(&self) -> smt_core::Result<Model> {
+        if self.last != Some(CheckSat::Sat) {
+            return Err("no model: the last check_sat did not return sat".into());
+        }
+        Ok(self.eng.build_model())
+    }
+
+    /// Values of `ts` (any terms, evaluated) in the model of the last `check_sat`.
+    pub fn get_value(&self, ts: &[TermId]) -> smt_core::Result<Vec<Value>> {
+        let model = self.get_model()?;
+        let mut ev = Evaluator::new(&self.eng.ctx, &model);
+        ts.iter().map(|&t| ev.eval(t)).collect()
+    }
+
+    /// Quantifier instances made so far (and not retracted by `pop`), in order.
+    pub fn instances(&self) -> &[smt_engine::quantifiers::Instance] {
+        self.eng.instances()
     }
 
     /// Drain eqsharing events.
@@ -76,21 +320,39 @@ impl Session {
         self.eng.take_eqshare_events()
     }
 
-    /// UNSAT core (scaffold: empty).
-    pub fn get_unsat_core(&self) -> Vec<Label> { Vec::new() }
-}
-
-// --- small helper to clone context into engine ---
-// For the scaffold we want both Session ctx and Engine ctx.
-// In a real implementation you'd likely share an Arc<Context>.
+    /// Drain interface-equality split events.
+    pub fn take_split_events(&mut self) -> Vec<smt_engine::eqshare_trace::SplitEvent> {
+        self.eng.take_split_events()
+    }
 
-trait CloneForEngine {
-    fn clone_for_engine(&self) -> Context;
-}
+    /// Labels of assertions that suffice for the last `Unsat`, in assertion order (empty
+    /// after any other result, or if the unlabeled assertions alone are unsatisfiable).
+    pub fn get_unsat_core(&self) -> Vec<Label> {
+        if self.last != Some(CheckSat::Unsat) {
+            return Vec::new();
+        }
+        self.core.iter().map(|&i| self.guards[i].1.clone()).collect()
+    }
 
-impl CloneForEngine for Context {
-    fn clone_for_engine(&self) -> Context {
-        // Minimal: create a new empty Context; production: share or clone intern tables.
-        Context::new()
+    /// Shrink the core of the last `Unsat` by re-solving under subsets of it, as set up
+    /// by `core_min_mut`. The result is always a core; it is minimal unless the strategy
+    /// is `Trim` or the check budget ran out. Later `get_unsat_core` calls return it too.
+    pub fn get_minimal_unsat_core(&mut self) -> Vec<Label> {
+        if self.last != Some(CheckSat::Unsat) {
+            return Vec::new();
+        }
+        let (eng, guards, assumed) = (&mut self.eng, &self.guards, &self.assumed);
+        let oracle = |subset: &[usize]| {
+            // Assumptions of the last check stay fixed; only labels are dropped.
+            let lits: Vec<Lit> = subset.iter().map(|&i| guards[i].0).chain(assumed.iter().map(|(_, l)| *l)).collect();
+            if eng.check_sat_assuming_lits(&lits) != CheckSat::Unsat {
+                return None;
+            }
+            let failed = eng.unsat_assumptions();
+            Some(subset.iter().copied().filter(|&i| failed.contains(&guards[i].0)).collect())
+        };
+        let mut oracle = Budgeted::new(oracle, self.core_min.max_checks);
+        self.core = core_min::minimize(core::mem::take(&mut self.core), self.core_min.strategy, &mut oracle);
+        self.get_unsat_core()
     }
 }
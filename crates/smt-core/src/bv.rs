@@ -0,0 +1,172 @@
+#![forbid(unsafe_code)]
+//! Fixed-width bit-vector operators (SMT-LIB `QF_BV`) and their semantics on words.
+//!
+//! A bit-vector of width `w` (1 to `MAX_WIDTH`) is a `u128` whose bits above `w` are
+//! zero; signed operators read it in two's complement. Division by zero follows SMT-LIB:
+//! `bvudiv x 0` is all ones and `bvurem x 0` is `x`.
+
+/// Widest supported bit-vector.
+pub const MAX_WIDTH: u32 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BvOp {
+    Not,
+    And,
+    Or,
+    Xor,
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Udiv,
+    Urem,
+    Sdiv,
+    Srem,
+    Smod,
+    Shl,
+    Lshr,
+    Ashr,
+    /// `concat(hi, lo)`: the first argument is the high part.
+    Concat,
+    /// Bits `hi` down to `lo`, both included.
+    Extract(u32, u32),
+    ZeroExtend(u32),
+    SignExtend(u32),
+    RotateLeft(u32),
+    RotateRight(u32),
+    Ult,
+    Ule,
+    Slt,
+    Sle,
+}
+
+/// The word with the low `width` bits set.
+pub fn mask(width: u32) -> u128 {
+    if width >= 128 { u128::MAX } else { (1u128 << width) - 1 }
+}
+
+/// `x` (of width `width`) as a signed number.
+pub fn to_signed(x: u128, width: u32) -> i128 {
+    if width < 128 && x >> (width - 1) & 1 == 1 { (x | !mask(width)) as i128 } else { x as i128 }
+}
+
+impl BvOp {
+    /// Number of arguments.
+    pub fn arity(self) -> usize {
+        match self {
+            BvOp::Not | BvOp::Neg | BvOp::Extract(..) | BvOp::ZeroExtend(_) | BvOp::SignExtend(_) => 1,
+            BvOp::RotateLeft(_) | BvOp::RotateRight(_) => 1,
+            _ => 2,
+        }
+    }
+
+    /// Whether the operator is a predicate (Bool result).
+    pub fn is_predicate(self) -> bool {
+        matches!(self, BvOp::Ult | BvOp::Ule | BvOp::Slt | BvOp::Sle)
+    }
+
+    /// Width of the result given the argument widths, or an error for ill-sorted uses.
+    /// Predicates return the width of their (equally wide) arguments.
+    pub fn result_width(self, widths: &[u32]) -> Result<u32, String> {
+        if widths.len() != self.arity() {
+            return Err(format!("{self:?} takes {} arguments, got {}", self.arity(), widths.len()));
+        }
+        let w = widths[0];
+        let out = match self {
+            BvOp::Concat => widths[0] + widths[1],
+            BvOp::Extract(hi, lo) => {
+                if lo > hi || hi >= w {
+                    return Err(format!("extract {hi} {lo} out of a width-{w} bit-vector"));
+                }
+                hi - lo + 1
+            }
+            BvOp::ZeroExtend(n) | BvOp::SignExtend(n) => w + n,
+            _ if self.arity() == 2 && widths[1] != w => {
+                return Err(format!("{self:?} over widths {w} and {}", widths[1]));
+            }
+            _ => w,
+        };
+        if out > MAX_WIDTH {
+            return Err(format!("{self:?} yields width {out}, above {MAX_WIDTH}"));
+        }
+        Ok(out)
+    }
+
+    /// Apply a non-predicate operator to `(width, word)` arguments.
+    pub fn apply(self, args: &[(u32, u128)]) -> u128 {
+        let width = args[0].0;
+        let m = mask(width);
+        let x = args[0].1;
+        let y = args.get(1).map_or(0, |a| a.1);
+        let shift = |y: u128| -> Option<u32> { (y < width as u128).then_some(y as u32) };
+        let out = match self {
+            BvOp::Not => !x,
+            BvOp::And => x & y,
+            BvOp::Or => x | y,
+            BvOp::Xor => x ^ y,
+            BvOp::Neg => x.wrapping_neg(),
+            BvOp::Add => x.wrapping_add(y),
+            BvOp::Sub => x.wrapping_sub(y),
+            BvOp::Mul => x.wrapping_mul(y),
+            BvOp::Udiv => x.checked_div(y).unwrap_or(m),
+            BvOp::Urem => x.checked_rem(y).unwrap_or(x),
+            BvOp::Sdiv | BvOp::Srem | BvOp::Smod => return signed_div(self, width, x, y),
+            BvOp::Shl => shift(y).map_or(0, |s| x << s),
+            BvOp::Lshr => shift(y).map_or(0, |s| x >> s),
+            BvOp::Ashr => {
+                let s = shift(y).unwrap_or(width - 1).min(width - 1);
+                (to_signed(x, width) >> s) as u128
+            }
+            BvOp::Concat => (x << args[1].0) | y,
+            BvOp::Extract(_, lo) => x >> lo,
+            BvOp::ZeroExtend(_) => x,
+            BvOp::SignExtend(n) => return (to_signed(x, width) as u128) & mask(width + n),
+            BvOp::RotateLeft(k) | BvOp::RotateRight(k) => {
+                let k = k % width;
+                let k = if matches!(self, BvOp::RotateLeft(_)) { k } else { (width - k) % width };
+                if k == 0 { x } else { (x << k) | (x >> (width - k)) }
+            }
+            BvOp::Ult | BvOp::Ule | BvOp::Slt | BvOp::Sle => panic!("{self:?} is a predicate"),
+        };
+        let w = self.result_width(&args.iter().map(|a| a.0).collect::<Vec<_>>()).expect("well-sorted");
+        out & mask(w)
+    }
+
+    /// Apply a predicate to two words of width `width`.
+    pub fn test(self, width: u32, x: u128, y: u128) -> bool {
+        match self {
+            BvOp::Ult => x < y,
+            BvOp::Ule => x <= y,
+            BvOp::Slt => to_signed(x, width) < to_signed(y, width),
+            BvOp::Sle => to_signed(x, width) <= to_signed(y, width),
+            _ => panic!("{self:?} is not a predicate"),
+        }
+    }
+}
+
+/// `bvsdiv`, `bvsrem` and `bvsmod`, through the unsigned operators on absolute values.
+fn signed_div(op: BvOp, width: u32, x: u128, y: u128) -> u128 {
+    let m = mask(width);
+    let neg = |v: u128| v.wrapping_neg() & m;
+    let (sx, sy) = (to_signed(x, width) < 0, to_signed(y, width) < 0);
+    let (ax, ay) = (if sx { neg(x) } else { x }, if sy { neg(y) } else { y });
+    match op {
+        BvOp::Sdiv => {
+            let q = BvOp::Udiv.apply(&[(width, ax), (width, ay)]);
+            if sx != sy { neg(q) } else { q }
+        }
+        BvOp::Srem => {
+            let r = BvOp::Urem.apply(&[(width, ax), (width, ay)]);
+            if sx { neg(r) } else { r }
+        }
+        _ => {
+            let u = BvOp::Urem.apply(&[(width, ax), (width, ay)]);
+            match (u == 0, sx, sy) {
+                (true, ..) | (false, false, false) => u,
+                (false, true, false) => neg(u).wrapping_add(y) & m,
+                (false, false, true) => u.wrapping_add(y) & m,
+                (false, true, true) => neg(u),
+            }
+        }
+    }
+}
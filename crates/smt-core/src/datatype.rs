@@ -0,0 +1,123 @@
+#![forbid(unsafe_code)]
+//! Algebraic datatypes: declarations and their resolved form.
+//!
+//! Datatypes are declared in groups, so they can be mutually recursive: a field's sort
+//! is an existing sort or, by position, a datatype of its own group. A group is accepted
+//! if all its names are new (constructors and selectors share one namespace across the
+//! context) and every datatype has a finite value, i.e. a constructor whose fields can all
+//! be built without going through the datatype again.
+
+use crate::{Result, SortId};
+
+/// Sort of a field in a declaration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldSort {
+    Sort(SortId),
+    /// The `k`-th datatype of the group being declared.
+    Group(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstructorDecl {
+    pub name: String,
+    /// Selector names and sorts.
+    pub fields: Vec<(String, FieldSort)>,
+}
+
+impl ConstructorDecl {
+    pub fn new(name: impl Into<String>, fields: Vec<(&str, FieldSort)>) -> Self {
+        Self { name: name.into(), fields: fields.into_iter().map(|(f, s)| (f.to_owned(), s)).collect() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatatypeDecl {
+    pub name: String,
+    pub constructors: Vec<ConstructorDecl>,
+}
+
+impl DatatypeDecl {
+    pub fn new(name: impl Into<String>, constructors: Vec<ConstructorDecl>) -> Self {
+        Self { name: name.into(), constructors }
+    }
+}
+
+/// A declared constructor; its fields' sorts are resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constructor {
+    pub name: String,
+    pub fields: Vec<(String, SortId)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Datatype {
+    pub name: String,
+    pub constructors: Vec<Constructor>,
+}
+
+/// Check a group. `taken` tells whether a name is already used by a sort, constructor or
+/// selector of the context; `bool_sort` may not be a field sort.
+pub(crate) fn check_group(decls: &[DatatypeDecl], bool_sort: SortId, taken: impl Fn(&str) -> bool) -> Result<()> {
+    if decls.is_empty() {
+        return Err("empty datatype declaration".into());
+    }
+    let mut seen: Vec<&str> = Vec::new();
+    for d in decls {
+        claim(&mut seen, &taken, "datatype", &d.name)?;
+    }
+    for d in decls {
+        if d.constructors.is_empty() {
+            return Err(format!("datatype {} has no constructors", d.name).into());
+        }
+        for c in &d.constructors {
+            claim(&mut seen, &taken, "constructor", &c.name)?;
+            for (f, s) in &c.fields {
+                claim(&mut seen, &taken, "selector", f)?;
+                match *s {
+                    FieldSort::Group(k) if k >= decls.len() => {
+                        return Err(format!("selector {f} refers to datatype {k} of a group of {}", decls.len()).into());
+                    }
+                    FieldSort::Sort(s) if s == bool_sort => {
+                        return Err(format!("selector {f} has sort Bool, which datatypes do not support").into());
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    // Well-foundedness: grow the set of datatypes known to have a finite value.
+    let mut inhabited = vec![false; decls.len()];
+    loop {
+        let mut changed = false;
+        for (k, d) in decls.iter().enumerate() {
+            if inhabited[k] {
+                continue;
+            }
+            let ok = |c: &ConstructorDecl| {
+                c.fields.iter().all(|(_, s)| match *s {
+                    FieldSort::Sort(_) => true,
+                    FieldSort::Group(j) => inhabited[j],
+                })
+            };
+            if d.constructors.iter().any(ok) {
+                inhabited[k] = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    match inhabited.iter().position(|&ok| !ok) {
+        Some(k) => Err(format!("datatype {} has no finite value", decls[k].name).into()),
+        None => Ok(()),
+    }
+}
+
+fn claim<'a>(seen: &mut Vec<&'a str>, taken: &impl Fn(&str) -> bool, what: &str, name: &'a str) -> Result<()> {
+    if taken(name) || seen.contains(&name) {
+        return Err(format!("{what} {name} is already declared").into());
+    }
+    seen.push(name);
+    Ok(())
+}
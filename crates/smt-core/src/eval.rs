@@ -0,0 +1,185 @@
+#![forbid(unsafe_code)]
+//! Term evaluation under a `Model`.
+//!
+//! Deliberately independent of the solver: connectives and predicates are recomputed
+//! from the values of their arguments, and UF applications go through the function
+//! interpretations, so a model that merely parrots the SAT assignment is still checked.
+//! Quantified formulas are the exception: they range over whole sorts, so they take the
+//! value the model records for them.
+
+use rustc_hash::FxHashMap;
+
+use crate::{ArrayValue, Context, Model, OpKind, Rational, Result, TermId, TermKind, Value};
+
+pub struct Evaluator<'a> {
+    ctx: &'a Context,
+    model: &'a Model,
+    cache: FxHashMap<TermId, Value>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(ctx: &'a Context, model: &'a Model) -> Self {
+        Self { ctx, model, cache: FxHashMap::default() }
+    }
+
+    pub fn eval(&mut self, t: TermId) -> Result<Value> {
+        if let Some(v) = self.cache.get(&t) {
+            return Ok(v.clone());
+        }
+        let v = self.eval_node(t)?;
+        self.cache.insert(t, v.clone());
+        Ok(v)
+    }
+
+    fn eval_bool(&mut self, t: TermId) -> Result<bool> {
+        match self.eval(t)? {
+            Value::Bool(b) => Ok(b),
+            v => Err(format!("term {t:?} evaluates to non-Boolean {v}").into()),
+        }
+    }
+
+    fn eval_int(&mut self, t: TermId) -> Result<i64> {
+        match self.eval(t)? {
+            Value::Int(k) => Ok(k),
+            v => Err(format!("term {t:?} evaluates to non-integer {v}").into()),
+        }
+    }
+
+    /// Int or Real value, as a rational.
+    fn eval_num(&mut self, t: TermId) -> Result<Rational> {
+        match self.eval(t)? {
+            Value::Int(k) => Ok(Rational::from(k)),
+            Value::Real(r) => Ok(r),
+            v => Err(format!("term {t:?} evaluates to non-numeric {v}").into()),
+        }
+    }
+
+    fn eval_array(&mut self, t: TermId) -> Result<ArrayValue> {
+        match self.eval(t)? {
+            Value::Array(a) => Ok(*a),
+            v => Err(format!("expected an array for {t:?}, got {v}").into()),
+        }
+    }
+
+    fn eval_bv(&mut self, t: TermId) -> Result<(u32, u128)> {
+        match self.eval(t)? {
+            Value::BitVec(w, v) => Ok((w, v)),
+            v => Err(format!("expected a bit-vector for {t:?}, got {v}").into()),
+        }
+    }
+
+    fn eval_node(&mut self, t: TermId) -> Result<Value> {
+        let ctx = self.ctx;
+        Ok(match ctx.term_node(t).0 {
+            TermKind::IntConst(k) => Value::Int(*k),
+            TermKind::RatConst(r) => Value::Real(*r),
+            TermKind::BoolConst(b) => Value::Bool(*b),
+            TermKind::Const(name) => match self.model.value(t) {
+                Some(v) => v.clone(),
+                None => return Err(format!("no value for constant {name}").into()),
+            },
+            TermKind::App { op, args } => {
+                let OpKind::Uf(name) = &op.kind;
+                let vals = args.iter().map(|&a| self.eval(a)).collect::<Result<Vec<_>>>()?;
+                match (self.model.func(name), self.model.value(t)) {
+                    (Some(f), _) => f.apply(&vals).clone(),
+                    (None, Some(v)) => v.clone(),
+                    (None, None) => return Err(format!("no interpretation for {name}").into()),
+                }
+            }
+            TermKind::Eq(a, b) => Value::Bool(self.eval(*a)? == self.eval(*b)?),
+            TermKind::Le(a, b) => Value::Bool(self.eval_num(*a)? <= self.eval_num(*b)?),
+            TermKind::Add(xs) if ctx.term_sort(t) == ctx.real_sort() => {
+                let mut acc = Rational::ZERO;
+                for &x in xs {
+                    acc = acc.checked_add(self.eval_num(x)?).ok_or_else(|| overflow(t))?;
+                }
+                Value::Real(acc)
+            }
+            TermKind::Sub(a, b) if ctx.term_sort(t) == ctx.real_sort() => {
+                Value::Real(self.eval_num(*a)?.checked_sub(self.eval_num(*b)?).ok_or_else(|| overflow(t))?)
+            }
+            TermKind::Mul(a, b) if ctx.term_sort(t) == ctx.real_sort() => {
+                Value::Real(self.eval_num(*a)?.checked_mul(self.eval_num(*b)?).ok_or_else(|| overflow(t))?)
+            }
+            TermKind::Add(xs) => {
+                let mut acc: i64 = 0;
+                for &x in xs {
+                    acc = acc.checked_add(self.eval_int(x)?).ok_or_else(|| overflow(t))?;
+                }
+                Value::Int(acc)
+            }
+            TermKind::Sub(a, b) => {
+                Value::Int(self.eval_int(*a)?.checked_sub(self.eval_int(*b)?).ok_or_else(|| overflow(t))?)
+            }
+            TermKind::Mul(a, b) => {
+                Value::Int(self.eval_int(*a)?.checked_mul(self.eval_int(*b)?).ok_or_else(|| overflow(t))?)
+            }
+            TermKind::Select(a, i) => {
+                let i = self.eval(*i)?;
+                self.eval_array(*a)?.select(&i).clone()
+            }
+            TermKind::Store(a, i, v) => {
+                let (i, v) = (self.eval(*i)?, self.eval(*v)?);
+                Value::Array(Box::new(self.eval_array(*a)?.store(i, v)))
+            }
+            TermKind::BvConst(v) => Value::BitVec(ctx.bv_width(ctx.term_sort(t)).expect("bit-vector sort"), *v),
+            TermKind::Bv(op, args) => {
+                let vals = args.iter().map(|&a| self.eval_bv(a)).collect::<Result<Vec<_>>>()?;
+                if op.is_predicate() {
+                    Value::Bool(op.test(vals[0].0, vals[0].1, vals[1].1))
+                } else {
+                    Value::BitVec(ctx.bv_width(ctx.term_sort(t)).expect("bit-vector sort"), op.apply(&vals))
+                }
+            }
+            TermKind::Construct(k, args) => {
+                let name = ctx.constructor(ctx.term_sort(t), *k).name.clone();
+                Value::Ctor(name, args.iter().map(|&a| self.eval(a)).collect::<Result<Vec<_>>>()?)
+            }
+            TermKind::Selector(k, i, a) => {
+                let c = ctx.constructor(ctx.term_sort(*a), *k);
+                match (self.eval(*a)?, self.model.value(t)) {
+                    (Value::Ctor(name, mut fields), _) if name == c.name => fields.swap_remove(*i as usize),
+                    // Unspecified: the model picks the value.
+                    (Value::Ctor(..), Some(v)) => v.clone(),
+                    (Value::Ctor(name, _), None) => return Err(format!("no value for selector {} of {name}", c.fields[*i as usize].0).into()),
+                    (v, _) => return Err(format!("expected a datatype value for {a:?}, got {v}").into()),
+                }
+            }
+            TermKind::Tester(k, a) => match self.eval(*a)? {
+                Value::Ctor(name, _) => Value::Bool(name == ctx.constructor(ctx.term_sort(*a), *k).name),
+                v => return Err(format!("expected a datatype value for {a:?}, got {v}").into()),
+            },
+            TermKind::Not(a) => Value::Bool(!self.eval_bool(*a)?),
+            TermKind::And(xs) => {
+                let mut acc = true;
+                for &x in xs {
+                    acc &= self.eval_bool(x)?;
+                }
+                Value::Bool(acc)
+            }
+            TermKind::Or(xs) => {
+                let mut acc = false;
+                for &x in xs {
+                    acc |= self.eval_bool(x)?;
+                }
+                Value::Bool(acc)
+            }
+            TermKind::Implies(a, b) => Value::Bool(!self.eval_bool(*a)? || self.eval_bool(*b)?),
+            TermKind::Var(name) => return Err(format!("variable {name} is not bound to a value").into()),
+            TermKind::Forall(_) | TermKind::Exists(_) => match self.model.value(t) {
+                Some(v) => v.clone(),
+                None => return Err(format!("no value for quantified formula {t:?}").into()),
+            },
+        })
+    }
+}
+
+fn overflow(t: TermId) -> crate::Error {
+    format!("arithmetic overflow evaluating {t:?}").into()
+}
+
+/// Evaluate a single term (no cache sharing across calls).
+pub fn eval(ctx: &Context, model: &Model, t: TermId) -> Result<Value> {
+    Evaluator::new(ctx, model).eval(t)
+}
@@ -5,6 +5,20 @@
 
 use rustc_hash::FxHashMap;
 
+pub mod bv;
+pub mod datatype;
+pub mod eval;
+pub mod model;
+pub mod rational;
+pub mod value;
+
+pub use bv::BvOp;
+pub use datatype::{Constructor, ConstructorDecl, Datatype, DatatypeDecl, FieldSort};
+pub use eval::{eval, Evaluator};
+pub use model::{FuncInterp, Model};
+pub use rational::Rational;
+pub use value::{ArrayValue, Value};
+
 /// Error type for fallible APIs in this crate family.
 pub type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
 
@@ -20,7 +34,15 @@ pub struct SortId(pub u32);
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SortKind {
     Int,
+    Bool,
+    Real,
     Uninterpreted(String),
+    /// `Array(index, element)`.
+    Array(SortId, SortId),
+    /// Bit-vectors of the given width.
+    BitVec(u32),
+    /// An algebraic datatype, by name; its constructors are kept by the `Context`.
+    Datatype(String),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -34,20 +56,134 @@ pub struct Op {
     pub kind: OpKind,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TermKind {
     /// An n-ary application (including UF).
     App { op: Op, args: Vec<TermId> },
     /// Integer constant.
     IntConst(i64),
+    /// Real constant.
+    RatConst(Rational),
+    /// Boolean constant (`true` / `false`).
+    BoolConst(bool),
     /// A named constant/variable.
     Const(String),
     /// Equality (as a term).
     Eq(TermId, TermId),
-    /// Integer <= (as a term).
+    /// Arithmetic <= (as a term).
     Le(TermId, TermId),
+    /// n-ary sum.
+    Add(Vec<TermId>),
+    /// Difference.
+    Sub(TermId, TermId),
+    /// Product. Linear arithmetic needs one side to be constant; other products are
+    /// opaque to it.
+    Mul(TermId, TermId),
+    /// Array read `a[i]`.
+    Select(TermId, TermId),
+    /// Array write: `a` with `v` at `i`.
+    Store(TermId, TermId, TermId),
+    /// Bit-vector constant; the width is the sort's.
+    BvConst(u128),
+    /// Bit-vector operator or predicate.
+    Bv(BvOp, Vec<TermId>),
+    /// Constructor application, by the constructor's index in the term's datatype.
+    Construct(u32, Vec<TermId>),
+    /// Selector: field `.1` of constructor `.0`, applied to `.2`. Unspecified on values
+    /// built by another constructor.
+    Selector(u32, u32, TermId),
+    /// Tester: whether the argument was built by the constructor.
+    Tester(u32, TermId),
     /// Negation (as a term).
     Not(TermId),
+    /// n-ary conjunction.
+    And(Vec<TermId>),
+    /// n-ary disjunction.
+    Or(Vec<TermId>),
+    /// Implication `lhs => rhs`.
+    Implies(TermId, TermId),
+    /// A variable bound by the quantifiers listing it.
+    Var(String),
+    /// Universal quantification.
+    Forall(Quantified),
+    /// Existential quantification.
+    Exists(Quantified),
+}
+
+/// Body of a quantifier.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Quantified {
+    /// `Var` terms.
+    pub vars: Vec<TermId>,
+    pub body: TermId,
+    /// Multi-patterns: each is a list of patterns that together mention every variable.
+    /// A pattern is a UF application whose subterms are variables, ground terms or
+    /// patterns.
+    pub triggers: Vec<Vec<TermId>>,
+}
+
+impl TermKind {
+    /// Direct subterms, in order (for a quantifier: variables, body, then patterns).
+    pub fn children(&self) -> Vec<TermId> {
+        match self {
+            TermKind::IntConst(_)
+            | TermKind::RatConst(_)
+            | TermKind::BoolConst(_)
+            | TermKind::Const(_)
+            | TermKind::BvConst(_)
+            | TermKind::Var(_) => Vec::new(),
+            TermKind::App { args: xs, .. }
+            | TermKind::Add(xs)
+            | TermKind::Bv(_, xs)
+            | TermKind::Construct(_, xs)
+            | TermKind::And(xs)
+            | TermKind::Or(xs) => xs.clone(),
+            TermKind::Eq(a, b) | TermKind::Le(a, b) | TermKind::Sub(a, b) | TermKind::Mul(a, b) | TermKind::Select(a, b) | TermKind::Implies(a, b) => {
+                vec![*a, *b]
+            }
+            TermKind::Store(a, i, v) => vec![*a, *i, *v],
+            TermKind::Selector(_, _, a) | TermKind::Tester(_, a) | TermKind::Not(a) => vec![*a],
+            TermKind::Forall(q) | TermKind::Exists(q) => {
+                q.vars.iter().copied().chain([q.body]).chain(q.triggers.iter().flatten().copied()).collect()
+            }
+        }
+    }
+
+    /// The same node over `f` of each subterm.
+    pub fn map_children(&self, f: &mut impl FnMut(TermId) -> TermId) -> TermKind {
+        let mut all = |xs: &[TermId]| xs.iter().map(|&x| f(x)).collect::<Vec<_>>();
+        match self {
+            TermKind::App { op, args } => TermKind::App { op: op.clone(), args: all(args) },
+            TermKind::Add(xs) => TermKind::Add(all(xs)),
+            TermKind::Bv(op, xs) => TermKind::Bv(*op, all(xs)),
+            TermKind::Construct(k, xs) => TermKind::Construct(*k, all(xs)),
+            TermKind::And(xs) => TermKind::And(all(xs)),
+            TermKind::Or(xs) => TermKind::Or(all(xs)),
+            TermKind::Eq(a, b) => TermKind::Eq(f(*a), f(*b)),
+            TermKind::Le(a, b) => TermKind::Le(f(*a), f(*b)),
+            TermKind::Sub(a, b) => TermKind::Sub(f(*a), f(*b)),
+            TermKind::Mul(a, b) => TermKind::Mul(f(*a), f(*b)),
+            TermKind::Select(a, b) => TermKind::Select(f(*a), f(*b)),
+            TermKind::Implies(a, b) => TermKind::Implies(f(*a), f(*b)),
+            TermKind::Store(a, i, v) => TermKind::Store(f(*a), f(*i), f(*v)),
+            TermKind::Selector(k, i, a) => TermKind::Selector(*k, *i, f(*a)),
+            TermKind::Tester(k, a) => TermKind::Tester(*k, f(*a)),
+            TermKind::Not(a) => TermKind::Not(f(*a)),
+            TermKind::Forall(q) => TermKind::Forall(q.map(f)),
+            TermKind::Exists(q) => TermKind::Exists(q.map(f)),
+            leaf => leaf.clone(),
+        }
+    }
+}
+
+impl Quantified {
+    fn map(&self, f: &mut impl FnMut(TermId) -> TermId) -> Quantified {
+        Quantified {
+            vars: self.vars.iter().map(|&v| f(v)).collect(),
+            body: f(self.body),
+            triggers: self.triggers.iter().map(|ps| ps.iter().map(|&p| f(p)).collect()).collect(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -56,20 +192,32 @@ pub struct TermNode {
     pub sort: SortId,
 }
 
+/// Size of a `Context` at some point; `Context::rewind` drops everything created since.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextMark {
+    sorts: usize,
+    terms: usize,
+}
+
 #[derive(Debug, Default)]
 pub struct Context {
     sorts: Vec<SortKind>,
     terms: Vec<TermNode>,
     sort_cache: FxHashMap<SortKind, SortId>,
+    datatypes: FxHashMap<SortId, Datatype>,
+    /// Per term: no free variable occurs in it.
+    ground: Vec<bool>,
 }
 
 impl Context {
     /// Create an empty context.
     pub fn new() -> Self {
         let mut ctx = Self::default();
-        // Intern Int sort at SortId(0) for convenience.
-        ctx.sorts.push(SortKind::Int);
-        ctx.sort_cache.insert(SortKind::Int, SortId(0));
+        // Intern Int at SortId(0), Bool at SortId(1) and Real at SortId(2) for convenience.
+        for (i, k) in [SortKind::Int, SortKind::Bool, SortKind::Real].into_iter().enumerate() {
+            ctx.sorts.push(k.clone());
+            ctx.sort_cache.insert(k, SortId(i as u32));
+        }
         ctx
     }
 
@@ -78,6 +226,26 @@ impl Context {
         SortId(0)
     }
 
+    /// Built-in Bool sort.
+    pub fn bool_sort(&self) -> SortId {
+        SortId(1)
+    }
+
+    /// Built-in Real sort.
+    pub fn real_sort(&self) -> SortId {
+        SortId(2)
+    }
+
+    /// Read a sort.
+    pub fn sort_kind(&self, s: SortId) -> &SortKind {
+        &self.sorts[s.0 as usize]
+    }
+
+    /// Sort of a term.
+    pub fn term_sort(&self, t: TermId) -> SortId {
+        self.terms[t.0 as usize].sort
+    }
+
     /// Declare an uninterpreted sort.
     pub fn declare_uninterpreted_sort(&mut self, name: impl Into<String>) -> SortId {
         let k = SortKind::Uninterpreted(name.into());
@@ -90,13 +258,255 @@ impl Context {
         sid
     }
 
+    /// The array sort from `index` to `elem`. Bool is not supported as either: array
+    /// reasoning splits on equalities over them.
+    pub fn array_sort(&mut self, index: SortId, elem: SortId) -> SortId {
+        assert!(index != self.bool_sort() && elem != self.bool_sort(), "Bool array index or element sort");
+        let k = SortKind::Array(index, elem);
+        if let Some(&sid) = self.sort_cache.get(&k) {
+            return sid;
+        }
+        let sid = SortId(self.sorts.len() as u32);
+        self.sorts.push(k.clone());
+        self.sort_cache.insert(k, sid);
+        sid
+    }
+
+    /// Index and element sorts of an array sort.
+    pub fn array_parts(&self, s: SortId) -> Option<(SortId, SortId)> {
+        match self.sort_kind(s) {
+            SortKind::Array(i, e) => Some((*i, *e)),
+            _ => None,
+        }
+    }
+
+    /// The sort of bit-vectors of `width` bits (1 to `bv::MAX_WIDTH`).
+    pub fn bv_sort(&mut self, width: u32) -> SortId {
+        assert!((1..=bv::MAX_WIDTH).contains(&width), "bit-vector width {width}");
+        let k = SortKind::BitVec(width);
+        if let Some(&sid) = self.sort_cache.get(&k) {
+            return sid;
+        }
+        let sid = SortId(self.sorts.len() as u32);
+        self.sorts.push(k.clone());
+        self.sort_cache.insert(k, sid);
+        sid
+    }
+
+    /// Width of a bit-vector sort.
+    pub fn bv_width(&self, s: SortId) -> Option<u32> {
+        match self.sort_kind(s) {
+            SortKind::BitVec(w) => Some(*w),
+            _ => None,
+        }
+    }
+
+    /// Declare a group of (possibly mutually recursive) datatypes; returns their sorts,
+    /// in order.
+    pub fn declare_datatypes(&mut self, decls: &[DatatypeDecl]) -> Result<Vec<SortId>> {
+        datatype::check_group(decls, self.bool_sort(), |name| self.name_taken(name))?;
+        let sorts: Vec<SortId> = decls
+            .iter()
+            .map(|d| {
+                let (k, sid) = (SortKind::Datatype(d.name.clone()), SortId(self.sorts.len() as u32));
+                self.sorts.push(k.clone());
+                self.sort_cache.insert(k, sid);
+                sid
+            })
+            .collect();
+        for (d, &sid) in decls.iter().zip(&sorts) {
+            let constructors = d
+                .constructors
+                .iter()
+                .map(|c| Constructor {
+                    name: c.name.clone(),
+                    fields: c
+                        .fields
+                        .iter()
+                        .map(|(f, s)| match *s {
+                            FieldSort::Sort(s) => (f.clone(), s),
+                            FieldSort::Group(k) => (f.clone(), sorts[k]),
+                        })
+                        .collect(),
+                })
+                .collect();
+            self.datatypes.insert(sid, Datatype { name: d.name.clone(), constructors });
+        }
+        Ok(sorts)
+    }
+
+    /// Declare one datatype (`FieldSort::Group(0)` is the datatype itself).
+    pub fn declare_datatype(&mut self, decl: DatatypeDecl) -> Result<SortId> {
+        Ok(self.declare_datatypes(core::slice::from_ref(&decl))?[0])
+    }
+
+    /// Whether a sort, constructor or selector is called `name`.
+    fn name_taken(&self, name: &str) -> bool {
+        let sort = self.sorts.iter().any(|k| matches!(k, SortKind::Uninterpreted(n) | SortKind::Datatype(n) if n == name));
+        sort || self.datatypes.values().flat_map(|d| &d.constructors).any(|c| {
+            c.name == name || c.fields.iter().any(|(f, _)| f == name)
+        })
+    }
+
+    /// Constructors of a datatype sort.
+    pub fn datatype(&self, s: SortId) -> Option<&Datatype> {
+        self.datatypes.get(&s)
+    }
+
+    /// Constructor `ctor` of the datatype sort `s`; panics if there is none.
+    pub fn constructor(&self, s: SortId, ctor: u32) -> &Constructor {
+        let dt = self.datatype(s).expect("constructor of a non-datatype sort");
+        dt.constructors.get(ctor as usize).unwrap_or_else(|| panic!("datatype {} has no constructor {ctor}", dt.name))
+    }
+
+    /// Index of the constructor called `name` in the datatype sort `s`.
+    pub fn constructor_index(&self, s: SortId, name: &str) -> Option<u32> {
+        self.datatype(s)?.constructors.iter().position(|c| c.name == name).map(|k| k as u32)
+    }
+
+    /// Whether `s` has finitely many values.
+    pub fn is_finite_sort(&self, s: SortId) -> bool {
+        self.finite(s, &mut Vec::new())
+    }
+
+    /// `is_finite_sort`, with the datatypes being looked through (recursion means
+    /// infinitely many values).
+    fn finite(&self, s: SortId, open: &mut Vec<SortId>) -> bool {
+        match self.sort_kind(s) {
+            SortKind::Bool | SortKind::BitVec(_) => true,
+            SortKind::Int | SortKind::Real | SortKind::Uninterpreted(_) => false,
+            SortKind::Array(i, e) => self.finite(*i, open) && self.finite(*e, open),
+            SortKind::Datatype(_) => {
+                if open.contains(&s) {
+                    return false;
+                }
+                open.push(s);
+                let fields: Vec<SortId> = self.datatypes[&s].constructors.iter().flat_map(|c| c.fields.iter().map(|f| f.1)).collect();
+                let finite = fields.into_iter().all(|f| self.finite(f, open));
+                open.pop();
+                finite
+            }
+        }
+    }
+
+    /// Number of terms created so far (ids are `0..num_terms()`).
+    pub fn num_terms(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// Current size, for a later `rewind`.
+    pub fn mark(&self) -> ContextMark {
+        ContextMark { sorts: self.sorts.len(), terms: self.terms.len() }
+    }
+
+    /// Drop every sort and term created after `mark`. Their ids become invalid (and are
+    /// handed out again by later constructors).
+    pub fn rewind(&mut self, mark: ContextMark) {
+        for k in self.sorts.drain(mark.sorts..) {
+            self.sort_cache.remove(&k);
+        }
+        self.datatypes.retain(|s, _| (s.0 as usize) < mark.sorts);
+        self.terms.truncate(mark.terms);
+        self.ground.truncate(mark.terms);
+    }
+
     /// Add a term and return its id.
     pub fn intern(&mut self, kind: TermKind, sort: SortId) -> TermId {
+        let ground = match &kind {
+            TermKind::Var(_) => false,
+            TermKind::Forall(q) | TermKind::Exists(q) => {
+                let mut free = Vec::new();
+                for t in [q.body].into_iter().chain(q.triggers.iter().flatten().copied()) {
+                    self.collect_free(t, &mut free);
+                }
+                free.iter().all(|v| q.vars.contains(v))
+            }
+            k => k.children().iter().all(|c| self.ground[c.0 as usize]),
+        };
         let id = TermId(self.terms.len() as u32);
         self.terms.push(TermNode { kind, sort });
+        self.ground.push(ground);
         id
     }
 
+    /// Whether no free variable occurs in `t`.
+    pub fn is_ground(&self, t: TermId) -> bool {
+        self.ground[t.0 as usize]
+    }
+
+    /// Variables occurring free in `t`, in order of first occurrence.
+    pub fn free_vars(&self, t: TermId) -> Vec<TermId> {
+        let mut out = Vec::new();
+        self.collect_free(t, &mut out);
+        out
+    }
+
+    fn collect_free(&self, t: TermId, out: &mut Vec<TermId>) {
+        if self.is_ground(t) {
+            return;
+        }
+        match self.term_node(t).0 {
+            TermKind::Var(_) => {
+                if !out.contains(&t) {
+                    out.push(t);
+                }
+            }
+            TermKind::Forall(q) | TermKind::Exists(q) => {
+                let mut inner = Vec::new();
+                for p in [q.body].into_iter().chain(q.triggers.iter().flatten().copied()) {
+                    self.collect_free(p, &mut inner);
+                }
+                for v in inner {
+                    if !q.vars.contains(&v) && !out.contains(&v) {
+                        out.push(v);
+                    }
+                }
+            }
+            k => {
+                for c in k.children() {
+                    self.collect_free(c, out);
+                }
+            }
+        }
+    }
+
+    /// `t` with the free occurrences of each variable of `binding` replaced by its term.
+    /// Subterms without such occurrences are kept as they are.
+    pub fn substitute(&mut self, t: TermId, binding: &[(TermId, TermId)]) -> TermId {
+        self.subst(t, binding, &mut FxHashMap::default())
+    }
+
+    fn subst(&mut self, t: TermId, binding: &[(TermId, TermId)], memo: &mut FxHashMap<TermId, TermId>) -> TermId {
+        if self.is_ground(t) {
+            return t;
+        }
+        if let Some(&u) = memo.get(&t) {
+            return u;
+        }
+        let (kind, sort) = (self.term_node(t).0.clone(), self.term_sort(t));
+        let u = match &kind {
+            TermKind::Var(_) => binding.iter().find(|&&(v, _)| v == t).map_or(t, |&(_, x)| x),
+            TermKind::Forall(q) | TermKind::Exists(q) => {
+                // Variables the quantifier binds again are not free below it.
+                let inner: Vec<(TermId, TermId)> = binding.iter().copied().filter(|(v, _)| !q.vars.contains(v)).collect();
+                let mut memo = FxHashMap::default();
+                let q = Quantified {
+                    vars: q.vars.clone(),
+                    body: self.subst(q.body, &inner, &mut memo),
+                    triggers: q.triggers.iter().map(|ps| ps.iter().map(|&p| self.subst(p, &inner, &mut memo)).collect()).collect(),
+                };
+                let new = if matches!(kind, TermKind::Forall(_)) { TermKind::Forall(q) } else { TermKind::Exists(q) };
+                if new == kind { t } else { self.intern(new, sort) }
+            }
+            _ => {
+                let new = kind.map_children(&mut |c| self.subst(c, binding, memo));
+                if new == kind { t } else { self.intern(new, sort) }
+            }
+        };
+        memo.insert(t, u);
+        u
+    }
+
     /// Read a term node.
     pub fn term_node(&self, t: TermId) -> (&TermKind, SortId) {
         let n = &self.terms[t.0 as usize];
@@ -108,6 +518,11 @@ impl Context {
         self.intern(TermKind::IntConst(v), self.int_sort())
     }
 
+    /// Construct a real constant term.
+    pub fn rat_const(&mut self, v: Rational) -> TermId {
+        self.intern(TermKind::RatConst(v), self.real_sort())
+    }
+
     /// Construct a named constant/variable term.
     pub fn const_term(&mut self, name: impl Into<String>, sort: SortId) -> TermId {
         self.intern(TermKind::Const(name.into()), sort)
@@ -124,19 +539,162 @@ impl Context {
         )
     }
 
-    /// Construct equality term.
+    /// Construct a Boolean constant term.
+    pub fn bool_const(&mut self, v: bool) -> TermId {
+        self.intern(TermKind::BoolConst(v), self.bool_sort())
+    }
+
+    /// Construct equality term (over Bool arguments this is `iff`).
     pub fn eq(&mut self, a: TermId, b: TermId) -> TermId {
-        // Sort is Int-like boolean; we don't model Bool sort in this scaffold.
-        self.intern(TermKind::Eq(a, b), self.int_sort())
+        self.intern(TermKind::Eq(a, b), self.bool_sort())
     }
 
     /// Construct <= term.
     pub fn le(&mut self, a: TermId, b: TermId) -> TermId {
-        self.intern(TermKind::Le(a, b), self.int_sort())
+        self.intern(TermKind::Le(a, b), self.bool_sort())
+    }
+
+    /// Construct n-ary + term.
+    pub fn add(&mut self, ts: &[TermId]) -> TermId {
+        let sort = self.arith_sort(ts);
+        self.intern(TermKind::Add(ts.to_vec()), sort)
+    }
+
+    /// Construct - term.
+    pub fn sub(&mut self, a: TermId, b: TermId) -> TermId {
+        let sort = self.arith_sort(&[a, b]);
+        self.intern(TermKind::Sub(a, b), sort)
+    }
+
+    /// Construct * term.
+    pub fn mul(&mut self, a: TermId, b: TermId) -> TermId {
+        let sort = self.arith_sort(&[a, b]);
+        self.intern(TermKind::Mul(a, b), sort)
+    }
+
+    /// Real if any operand is, Int otherwise.
+    fn arith_sort(&self, ts: &[TermId]) -> SortId {
+        if ts.iter().any(|&t| self.term_sort(t) == self.real_sort()) { self.real_sort() } else { self.int_sort() }
+    }
+
+    /// Construct select term; panics if `a` is not an array.
+    pub fn select(&mut self, a: TermId, i: TermId) -> TermId {
+        let (_, elem) = self.array_parts(self.term_sort(a)).expect("select from a non-array term");
+        self.intern(TermKind::Select(a, i), elem)
+    }
+
+    /// Construct store term; panics if `a` is not an array.
+    pub fn store(&mut self, a: TermId, i: TermId, v: TermId) -> TermId {
+        let sort = self.term_sort(a);
+        assert!(self.array_parts(sort).is_some(), "store into a non-array term");
+        self.intern(TermKind::Store(a, i, v), sort)
+    }
+
+    /// Construct a bit-vector constant of `width` bits from the low bits of `v`.
+    pub fn bv_const(&mut self, v: u128, width: u32) -> TermId {
+        let sort = self.bv_sort(width);
+        self.intern(TermKind::BvConst(v & bv::mask(width)), sort)
+    }
+
+    /// Construct a bit-vector operator term; panics on arguments of the wrong widths.
+    pub fn bv(&mut self, op: BvOp, args: &[TermId]) -> TermId {
+        let widths: Vec<u32> = args
+            .iter()
+            .map(|&a| self.bv_width(self.term_sort(a)).expect("bit-vector operator over a non-bit-vector term"))
+            .collect();
+        let width = op.result_width(&widths).unwrap_or_else(|e| panic!("{e}"));
+        let sort = if op.is_predicate() { self.bool_sort() } else { self.bv_sort(width) };
+        self.intern(TermKind::Bv(op, args.to_vec()), sort)
+    }
+
+    /// Construct a constructor application; panics on ill-sorted arguments.
+    pub fn construct(&mut self, sort: SortId, ctor: u32, args: &[TermId]) -> TermId {
+        let c = self.constructor(sort, ctor);
+        let ok = c.fields.len() == args.len() && c.fields.iter().zip(args).all(|((_, s), &a)| *s == self.term_sort(a));
+        assert!(ok, "ill-sorted arguments to constructor {}", c.name);
+        self.intern(TermKind::Construct(ctor, args.to_vec()), sort)
+    }
+
+    /// Construct a selector term (field `field` of constructor `ctor`); panics if `t` has
+    /// no such field.
+    pub fn selector(&mut self, ctor: u32, field: u32, t: TermId) -> TermId {
+        let c = self.constructor(self.term_sort(t), ctor);
+        let (_, sort) = *c.fields.get(field as usize).unwrap_or_else(|| panic!("constructor {} has no field {field}", c.name));
+        self.intern(TermKind::Selector(ctor, field, t), sort)
+    }
+
+    /// Construct a tester term; panics if `t`'s datatype has no constructor `ctor`.
+    pub fn tester(&mut self, ctor: u32, t: TermId) -> TermId {
+        self.constructor(self.term_sort(t), ctor);
+        self.intern(TermKind::Tester(ctor, t), self.bool_sort())
     }
 
     /// Construct not term.
     pub fn not(&mut self, t: TermId) -> TermId {
-        self.intern(TermKind::Not(t), self.int_sort())
+        self.intern(TermKind::Not(t), self.bool_sort())
+    }
+
+    /// Construct n-ary and term.
+    pub fn and(&mut self, ts: &[TermId]) -> TermId {
+        self.intern(TermKind::And(ts.to_vec()), self.bool_sort())
+    }
+
+    /// Construct n-ary or term.
+    pub fn or(&mut self, ts: &[TermId]) -> TermId {
+        self.intern(TermKind::Or(ts.to_vec()), self.bool_sort())
+    }
+
+    /// Construct implication term.
+    pub fn implies(&mut self, a: TermId, b: TermId) -> TermId {
+        self.intern(TermKind::Implies(a, b), self.bool_sort())
+    }
+
+    /// A new variable, to be bound by `forall` / `exists`. Variables are told apart by
+    /// their ids, not their names.
+    pub fn bound_var(&mut self, name: impl Into<String>, sort: SortId) -> TermId {
+        self.intern(TermKind::Var(name.into()), sort)
+    }
+
+    /// Construct `forall vars. body`, instantiated through `triggers` (inferred from the
+    /// body if empty); panics on a malformed quantifier.
+    pub fn forall(&mut self, vars: &[TermId], body: TermId, triggers: &[Vec<TermId>]) -> TermId {
+        let q = self.quantified(vars, body, triggers);
+        self.intern(TermKind::Forall(q), self.bool_sort())
+    }
+
+    /// Construct `exists vars. body`; the triggers are used where it is negated.
+    pub fn exists(&mut self, vars: &[TermId], body: TermId, triggers: &[Vec<TermId>]) -> TermId {
+        let q = self.quantified(vars, body, triggers);
+        self.intern(TermKind::Exists(q), self.bool_sort())
+    }
+
+    fn quantified(&self, vars: &[TermId], body: TermId, triggers: &[Vec<TermId>]) -> Quantified {
+        assert!(!vars.is_empty(), "quantifier without variables");
+        for (i, &v) in vars.iter().enumerate() {
+            assert!(matches!(self.term_node(v).0, TermKind::Var(_)), "quantified term {v:?} is not a variable");
+            assert!(!vars[..i].contains(&v), "variable {v:?} quantified twice");
+        }
+        assert!(self.term_sort(body) == self.bool_sort(), "quantifier body is not Boolean");
+        for ps in triggers {
+            for &p in ps {
+                assert!(self.is_pattern(p), "trigger {p:?} is not a UF application over variables and ground terms");
+            }
+            let free: Vec<TermId> = ps.iter().flat_map(|&p| self.free_vars(p)).collect();
+            assert!(vars.iter().all(|v| free.contains(v)), "trigger {ps:?} does not mention every variable");
+        }
+        Quantified { vars: vars.to_vec(), body, triggers: triggers.to_vec() }
+    }
+
+    /// Whether `t` can be a trigger pattern: a UF application with arguments whose
+    /// subterms with variables are variables or such applications.
+    pub fn is_pattern(&self, t: TermId) -> bool {
+        match self.term_node(t).0 {
+            TermKind::App { args, .. } => !args.is_empty() && args.iter().all(|&a| self.is_pattern_arg(a)),
+            _ => false,
+        }
+    }
+
+    fn is_pattern_arg(&self, t: TermId) -> bool {
+        self.is_ground(t) || matches!(self.term_node(t).0, TermKind::Var(_)) || self.is_pattern(t)
     }
 }
@@ -0,0 +1,55 @@
+#![forbid(unsafe_code)]
+//! Models: values for terms and finite interpretations for UF symbols.
+
+use rustc_hash::FxHashMap;
+
+use crate::{TermId, Value};
+
+/// Finite function interpretation: explicit entries plus a default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncInterp {
+    /// `(argument values, result)`, one entry per distinct argument tuple.
+    pub entries: Vec<(Vec<Value>, Value)>,
+    /// Result for argument tuples not listed.
+    pub default: Value,
+}
+
+impl FuncInterp {
+    pub fn apply(&self, args: &[Value]) -> &Value {
+        self.entries.iter().find(|(a, _)| a == args).map_or(&self.default, |(_, v)| v)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Model {
+    values: FxHashMap<TermId, Value>,
+    funcs: FxHashMap<String, FuncInterp>,
+}
+
+impl Model {
+    pub fn set_value(&mut self, t: TermId, v: Value) {
+        self.values.insert(t, v);
+    }
+
+    /// Value assigned to `t`, if any.
+    pub fn value(&self, t: TermId) -> Option<&Value> {
+        self.values.get(&t)
+    }
+
+    pub fn set_func(&mut self, name: impl Into<String>, interp: FuncInterp) {
+        self.funcs.insert(name.into(), interp);
+    }
+
+    /// Interpretation of the UF symbol `name`, if any.
+    pub fn func(&self, name: &str) -> Option<&FuncInterp> {
+        self.funcs.get(name)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = (TermId, &Value)> + '_ {
+        self.values.iter().map(|(&t, v)| (t, v))
+    }
+
+    pub fn funcs(&self) -> impl Iterator<Item = (&str, &FuncInterp)> + '_ {
+        self.funcs.iter().map(|(n, f)| (n.as_str(), f))
+    }
+}
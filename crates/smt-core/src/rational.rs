@@ -0,0 +1,205 @@
+#![forbid(unsafe_code)]
+//! Exact rationals for the arithmetic theories.
+//!
+//! Kept in lowest terms with a positive denominator, so equal values compare and hash
+//! equal. The operators panic when a result overflows `i128`; the `checked_*` methods
+//! return `None` instead, for callers that can give up (the simplex never silently
+//! rounds). Comparisons never overflow.
+
+use core::cmp::Ordering;
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+fn checked(v: Option<Rational>) -> Rational {
+    v.expect("rational arithmetic overflow")
+}
+
+/// `a / b` against `c / d` (`b, d > 0`) by continued fractions, without products.
+fn cmp_fractions(mut a: i128, mut b: i128, mut c: i128, mut d: i128) -> Ordering {
+    loop {
+        let (q, r) = (a.div_euclid(b), a.rem_euclid(b));
+        let (p, t) = (c.div_euclid(d), c.rem_euclid(d));
+        match (q.cmp(&p), r, t) {
+            (Ordering::Equal, 0, 0) => return Ordering::Equal,
+            (Ordering::Equal, 0, _) => return Ordering::Less,
+            (Ordering::Equal, _, 0) => return Ordering::Greater,
+            // r / b against t / d is d / t against b / r.
+            (Ordering::Equal, _, _) => (a, b, c, d) = (d, t, b, r),
+            (o, _, _) => return o,
+        }
+    }
+}
+
+impl Rational {
+    pub const ZERO: Rational = Rational { num: 0, den: 1 };
+    pub const ONE: Rational = Rational { num: 1, den: 1 };
+
+    /// `num / den`; panics if `den` is zero.
+    pub fn new(num: i128, den: i128) -> Self {
+        checked(Self::checked_new(num, den))
+    }
+
+    fn checked_new(num: i128, den: i128) -> Option<Self> {
+        assert!(den != 0, "rational with zero denominator");
+        let g = gcd(num, den);
+        let (num, den) = (num / g, den / g);
+        if den < 0 {
+            Some(Self { num: num.checked_neg()?, den: den.checked_neg()? })
+        } else {
+            Some(Self { num, den })
+        }
+    }
+
+    pub fn int(v: i128) -> Self {
+        Self { num: v, den: 1 }
+    }
+
+    pub fn numer(&self) -> i128 { self.num }
+
+    pub fn denom(&self) -> i128 { self.den }
+
+    pub fn is_zero(&self) -> bool { self.num == 0 }
+
+    pub fn is_integer(&self) -> bool { self.den == 1 }
+
+    pub fn signum(&self) -> i128 { self.num.signum() }
+
+    pub fn abs(&self) -> Self {
+        if self.num < 0 { -*self } else { *self }
+    }
+
+    /// Largest integer not above `self`.
+    pub fn floor(&self) -> i128 {
+        self.num.div_euclid(self.den)
+    }
+
+    /// Smallest integer not below `self`.
+    pub fn ceil(&self) -> i128 {
+        -(-self.num).div_euclid(self.den)
+    }
+
+    /// `self - floor(self)`, in `[0, 1)`.
+    pub fn fract(&self) -> Self {
+        *self - Rational::int(self.floor())
+    }
+
+    pub fn recip(&self) -> Self {
+        Rational::new(self.den, self.num)
+    }
+
+    pub fn checked_add(self, o: Rational) -> Option<Rational> {
+        if self.den == o.den {
+            return Rational::checked_new(self.num.checked_add(o.num)?, self.den);
+        }
+        let num = self.num.checked_mul(o.den)?.checked_add(o.num.checked_mul(self.den)?)?;
+        Rational::checked_new(num, self.den.checked_mul(o.den)?)
+    }
+
+    pub fn checked_sub(self, o: Rational) -> Option<Rational> {
+        self.checked_add(o.checked_neg()?)
+    }
+
+    pub fn checked_mul(self, o: Rational) -> Option<Rational> {
+        // Cross-reduce first to keep intermediates small.
+        let (g1, g2) = (gcd(self.num, o.den).max(1), gcd(o.num, self.den).max(1));
+        let num = (self.num / g1).checked_mul(o.num / g2)?;
+        let den = (self.den / g2).checked_mul(o.den / g1)?;
+        Rational::checked_new(num, den)
+    }
+
+    /// `None` on overflow; panics if `o` is zero.
+    pub fn checked_div(self, o: Rational) -> Option<Rational> {
+        self.checked_mul(Rational::checked_new(o.den, o.num)?)
+    }
+
+    pub fn checked_neg(self) -> Option<Rational> {
+        Some(Rational { num: self.num.checked_neg()?, den: self.den })
+    }
+}
+
+impl Default for Rational {
+    fn default() -> Self {
+        Rational::ZERO
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(v: i64) -> Self {
+        Rational::int(v as i128)
+    }
+}
+
+impl Add for Rational {
+    type Output = Rational;
+    fn add(self, o: Rational) -> Rational {
+        checked(self.checked_add(o))
+    }
+}
+
+impl Sub for Rational {
+    type Output = Rational;
+    fn sub(self, o: Rational) -> Rational {
+        checked(self.checked_sub(o))
+    }
+}
+
+impl Mul for Rational {
+    type Output = Rational;
+    fn mul(self, o: Rational) -> Rational {
+        checked(self.checked_mul(o))
+    }
+}
+
+impl Div for Rational {
+    type Output = Rational;
+    fn div(self, o: Rational) -> Rational {
+        checked(self.checked_div(o))
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational {
+        checked(self.checked_neg())
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, o: &Self) -> Ordering {
+        if self.den == o.den {
+            return self.num.cmp(&o.num);
+        }
+        match (self.num.checked_mul(o.den), o.num.checked_mul(self.den)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => cmp_fractions(self.num, self.den, o.num, o.den),
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, o: &Self) -> Option<Ordering> {
+        Some(self.cmp(o))
+    }
+}
+
+impl core::fmt::Display for Rational {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
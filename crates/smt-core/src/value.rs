@@ -0,0 +1,89 @@
+#![forbid(unsafe_code)]
+//! Concrete values assigned to terms by models.
+
+use crate::{Rational, SortId};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Real(Rational),
+    /// The `n`-th abstract element of a sort (uninterpreted sorts, or a class id a theory
+    /// uses without committing to an actual value).
+    Elem(SortId, u32),
+    Array(Box<ArrayValue>),
+    /// A bit-vector: width and bits.
+    BitVec(u32, u128),
+    /// A datatype value: constructor name (unique in its context) and fields.
+    Ctor(String, Vec<Value>),
+}
+
+/// A finite map over a constant default. Kept canonical (entries sorted by index,
+/// none equal to the default) so that equal arrays compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ArrayValue {
+    pub default: Value,
+    pub entries: Vec<(Value, Value)>,
+}
+
+impl ArrayValue {
+    /// The array mapping every index to `default`.
+    pub fn constant(default: Value) -> Self {
+        Self { default, entries: Vec::new() }
+    }
+
+    pub fn select(&self, i: &Value) -> &Value {
+        match self.entries.binary_search_by(|(k, _)| k.cmp(i)) {
+            Ok(at) => &self.entries[at].1,
+            Err(_) => &self.default,
+        }
+    }
+
+    pub fn store(&self, i: Value, v: Value) -> Self {
+        let mut out = self.clone();
+        match (out.entries.binary_search_by(|(k, _)| k.cmp(&i)), v == out.default) {
+            (Ok(at), true) => {
+                out.entries.remove(at);
+            }
+            (Ok(at), false) => out.entries[at].1 = v,
+            (Err(_), true) => {}
+            (Err(at), false) => out.entries.insert(at, (i, v)),
+        }
+        out
+    }
+}
+
+impl core::fmt::Display for Value {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Int(v) if *v < 0 => write!(f, "(- {})", v.unsigned_abs()),
+            Value::Int(v) => write!(f, "{v}"),
+            Value::Real(r) if r.signum() < 0 => write!(f, "(- {})", Value::Real(-*r)),
+            Value::Real(r) if r.is_integer() => write!(f, "{}.0", r.numer()),
+            Value::Real(r) => write!(f, "(/ {}.0 {}.0)", r.numer(), r.denom()),
+            Value::Elem(s, n) => write!(f, "@elem_{}_{}", s.0, n),
+            Value::BitVec(w, v) if w % 4 == 0 => write!(f, "#x{v:0width$x}", width = (*w / 4) as usize),
+            Value::BitVec(w, v) => write!(f, "#b{v:0width$b}", width = *w as usize),
+            Value::Ctor(c, fields) if fields.is_empty() => write!(f, "{c}"),
+            Value::Ctor(c, fields) => {
+                write!(f, "({c}")?;
+                for v in fields {
+                    write!(f, " {v}")?;
+                }
+                write!(f, ")")
+            }
+            Value::Array(a) => {
+                // SMT-LIB has no array literals; this is the usual `store` chain.
+                for _ in &a.entries {
+                    write!(f, "(store ")?;
+                }
+                write!(f, "((as const Array) {})", a.default)?;
+                for (i, v) in &a.entries {
+                    write!(f, " {i} {v})")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
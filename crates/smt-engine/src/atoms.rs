@@ -1,28 +1,121 @@
 #![forbid(unsafe_code)]
 
+use hashbrown::HashMap;
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
 use smt_core::TermId;
+use smt_sat::VarId;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TheoryId(pub usize);
 
+/// Growable bitset of theories; the number of theories is not capped.
+#[derive(Debug, Clone, Default)]
+pub struct TheorySet {
+    words: Vec<u64>,
+}
+
+impl TheorySet {
+    /// Insert `t`; returns `true` if it was not present.
+    pub fn insert(&mut self, t: TheoryId) -> bool {
+        let (w, bit) = (t.0 / 64, 1u64 << (t.0 % 64));
+        if self.words.len() <= w {
+            self.words.resize(w + 1, 0);
+        }
+        let fresh = self.words[w] & bit == 0;
+        self.words[w] |= bit;
+        fresh
+    }
+
+    /// Remove `t`; returns `true` if it was present.
+    pub fn remove(&mut self, t: TheoryId) -> bool {
+        let (w, bit) = (t.0 / 64, 1u64 << (t.0 % 64));
+        match self.words.get_mut(w) {
+            Some(word) if *word & bit != 0 => {
+                *word &= !bit;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn contains(&self, t: TheoryId) -> bool {
+        self.words.get(t.0 / 64).is_some_and(|w| w & (1u64 << (t.0 % 64)) != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    pub fn union_with(&mut self, other: &TheorySet) {
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            *w |= o;
+        }
+    }
+
+    /// Members in increasing id order.
+    pub fn iter(&self) -> impl Iterator<Item = TheoryId> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &w)| {
+            (0..64).filter(move |b| w & (1u64 << b) != 0).map(move |b| TheoryId(i * 64 + b))
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Atom {
     pub term: TermId,
     pub theory: TheoryId,
+    /// SAT variable standing for this atom in the propositional skeleton.
+    pub var: VarId,
 }
 
 #[derive(Debug, Default)]
 pub struct AtomTable {
     atoms: Vec<Atom>,
+    by_term: HashMap<TermId, usize, FxBuild>,
+    by_var: HashMap<VarId, usize, FxBuild>,
 }
 
 impl AtomTable {
     pub fn len(&self) -> usize { self.atoms.len() }
 
+    pub fn is_empty(&self) -> bool { self.atoms.is_empty() }
+
     pub fn push(&mut self, atom: Atom) {
+        let idx = self.atoms.len();
+        self.by_term.insert(atom.term, idx);
+        self.by_var.insert(atom.var, idx);
         self.atoms.push(atom);
     }
 
+    /// Drop the atoms at index `len` and beyond (e.g. on pop).
+    pub fn truncate(&mut self, len: usize) {
+        for atom in self.atoms.drain(len.min(self.atoms.len())..) {
+            self.by_term.remove(&atom.term);
+            self.by_var.remove(&atom.var);
+        }
+    }
+
+    /// Atom registered for a term, if any.
+    pub fn lookup(&self, term: TermId) -> Option<Atom> {
+        self.by_term.get(&term).map(|&i| self.atoms[i])
+    }
+
+    /// Atom whose SAT variable is `var`, if any.
+    pub fn atom_of_var(&self, var: VarId) -> Option<Atom> {
+        self.by_var.get(&var).map(|&i| self.atoms[i])
+    }
+
     pub fn iter_atoms(&self) -> impl Iterator<Item = Atom> + '_ {
         self.atoms.iter().copied()
     }
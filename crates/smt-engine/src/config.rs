@@ -1,14 +1,120 @@
 #![forbid(unsafe_code)]
 
-#[derive(Debug, Clone, Copy)]
+use crate::atoms::TheoryId;
+use crate::export_shape::ExportShape;
+
+/// A theory named in the sharing matrix, either by position or by `Theory::name()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TheoryRef {
+    Id(TheoryId),
+    Name(String),
+}
+
+impl TheoryRef {
+    fn matches(&self, id: TheoryId, name: &str) -> bool {
+        match self {
+            TheoryRef::Id(t) => *t == id,
+            TheoryRef::Name(n) => n == name,
+        }
+    }
+}
+
+impl From<TheoryId> for TheoryRef {
+    fn from(t: TheoryId) -> Self { TheoryRef::Id(t) }
+}
+
+impl From<&str> for TheoryRef {
+    fn from(n: &str) -> Self { TheoryRef::Name(n.to_string()) }
+}
+
+/// One directed entry of the sharing matrix.
+#[derive(Debug, Clone)]
+pub struct SharingEdge {
+    pub src: TheoryRef,
+    pub dst: TheoryRef,
+    pub allow: bool,
+}
+
+/// Directed equality-sharing matrix over any number of theories.
+///
+/// Edges not mentioned fall back to `default_allow`; when several entries match,
+/// the last one wins.
+#[derive(Debug, Clone)]
 pub struct SharingConfig {
-    pub uf_to_dl: bool,
-    pub dl_to_uf: bool,
+    pub default_allow: bool,
+    pub edges: Vec<SharingEdge>,
+    /// Introduce interface equality atoms over pairs of shared terms at final check and
+    /// let the SAT kernel split on them (needed for non-convex theories).
+    pub split_interface_eqs: bool,
+    /// How classes from `EqualitySharing::export_classes` become equalities.
+    pub export_shape: ExportShape,
 }
 
 impl Default for SharingConfig {
     fn default() -> Self {
-        Self { uf_to_dl: true, dl_to_uf: true }
+        Self {
+            default_allow: true,
+            edges: Vec::new(),
+            split_interface_eqs: false,
+            export_shape: ExportShape::default(),
+        }
+    }
+}
+
+impl SharingConfig {
+    /// Every directed edge disabled.
+    pub fn none() -> Self {
+        Self { default_allow: false, ..Self::default() }
+    }
+
+    /// The classic two-theory setup: `UF` and `DL` by name.
+    pub fn uf_dl(uf_to_dl: bool, dl_to_uf: bool) -> Self {
+        Self::default().set("UF", "DL", uf_to_dl).set("DL", "UF", dl_to_uf)
+    }
+
+    /// Set a directed edge.
+    pub fn set(mut self, src: impl Into<TheoryRef>, dst: impl Into<TheoryRef>, allow: bool) -> Self {
+        self.edges.push(SharingEdge { src: src.into(), dst: dst.into(), allow });
+        self
+    }
+
+    pub fn allow(self, src: impl Into<TheoryRef>, dst: impl Into<TheoryRef>) -> Self {
+        self.set(src, dst, true)
+    }
+
+    pub fn deny(self, src: impl Into<TheoryRef>, dst: impl Into<TheoryRef>) -> Self {
+        self.set(src, dst, false)
+    }
+
+    pub fn split_interface_eqs(mut self, on: bool) -> Self {
+        self.split_interface_eqs = on;
+        self
+    }
+
+    pub fn export_shape(mut self, shape: ExportShape) -> Self {
+        self.export_shape = shape;
+        self
+    }
+
+    /// Whether equalities exported by `src` may be imported by `dst`.
+    pub fn allows(&self, src: (TheoryId, &str), dst: (TheoryId, &str)) -> bool {
+        self.edges
+            .iter()
+            .rev()
+            .find(|e| e.src.matches(src.0, src.1) && e.dst.matches(dst.0, dst.1))
+            .map_or(self.default_allow, |e| e.allow)
+    }
+
+    /// Resolve the matrix for a concrete theory list: `out[src * n + dst]`.
+    pub fn resolve(&self, names: &[&str]) -> Vec<bool> {
+        let n = names.len();
+        let mut out = vec![false; n * n];
+        for (i, si) in names.iter().enumerate() {
+            for (j, dj) in names.iter().enumerate() {
+                out[i * n + j] = i != j && self.allows((TheoryId(i), si), (TheoryId(j), dj));
+            }
+        }
+        out
     }
 }
 
@@ -33,8 +139,45 @@ impl Default for DebugEqSharing {
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+/// How theories agree on shared terms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CombinationStrategy {
+    /// Theories export implied equalities every round (`equality_sharing_round`).
+    #[default]
+    Eager,
+    /// No exchange during search. At final check each theory proposes values for its
+    /// shared terms; pairs the theories disagree on become interface equalities to split on.
+    ModelBased,
+}
+
+/// Limits of quantifier instantiation (`crate::quantifiers`). A check that hits them
+/// ends with `CheckSat::Unknown` unless it finds a conflict first.
+#[derive(Debug, Clone, Copy)]
+pub struct QuantifierConfig {
+    /// Terms of the input have generation 0, those built by an instance one more than the
+    /// largest of its bindings. Bindings of this generation are not instantiated.
+    pub max_generation: u32,
+    /// Instances added per instantiation round.
+    pub max_instances_per_round: usize,
+    /// Instances added per `check_sat`.
+    pub max_instances: usize,
+}
+
+impl Default for QuantifierConfig {
+    fn default() -> Self {
+        Self { max_generation: 8, max_instances_per_round: 256, max_instances: 4096 }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct EngineConfig {
     pub sharing: SharingConfig,
+    pub combination: CombinationStrategy,
+    /// After every `Sat`, re-evaluate all assertions under the model and panic (writing a
+    /// debug bundle) if one is false.
+    pub check_models: bool,
+    /// Cache flattened lemma reasons per `ReasonId` (see `ReasonArena::set_caching`).
+    pub cache_reason_expansions: bool,
+    pub quantifiers: QuantifierConfig,
     pub debug_eq: DebugEqSharing,
 }
@@ -1,28 +1,40 @@
 #![forbid(unsafe_code)]
-//! Core engine loop (scaffold).
+//! Core engine loop: CDCL(T) over the SAT kernel, plus the equality sharing round and
+//! the debug dump helpers.
 //!
-//! This file contains the **equality sharing round** and the debug dump helpers.
-//! A real solver would also implement: SAT trail syncing, theory propagation, conflicts, etc.
+//! Theories never touch the kernel. Whatever they report (on assignment, import,
+//! propagation or final check) comes back as a `TheoryOutcome`, which `feed` turns into
+//! clauses over the explanation literals.
+//!
+//! Quantifiers are instantiated once the theories accept a full assignment
+//! (`crate::quantifiers`); the search restarts from level 0 to encode the instances.
 
-use hashbrown::HashSet;
+use hashbrown::HashMap;
 use rustc_hash::FxHasher;
 use core::hash::BuildHasherDefault;
 
-use smt_core::Context;
+use smt_core::{Context, SortId, TermId, TermKind, Value};
+use smt_sat::{Decide, LazyReasons, Lit};
 
-use crate::atoms::{AtomTable, TheoryId};
-use crate::config::EngineConfig;
+use crate::atoms::{AtomTable, TheoryId, TheorySet};
+use crate::config::{CombinationStrategy, EngineConfig};
 use crate::eqshare_dot::{eqshare_to_dot, EqDotLimits};
-use crate::eqshare_trace::{EqShareEvent, EqShareTrace};
+use crate::export_dedup::ExportDedup;
+use crate::export_shape::shape_class;
+use crate::eqshare_trace::{EqShareEvent, EqShareTrace, SplitEvent};
+use crate::interface_eqs::InterfaceEqs;
+use crate::quantifiers::{Instance, Quantifiers};
 use crate::reason::{ReasonArena, ReasonId};
 use crate::reason_dot::{reason_to_dot, DotLimits};
+use crate::scopes::UserScope;
 use crate::shared_terms::SharedTermOracle;
-use crate::theory::{SharedEq, Theory};
+use crate::theory::{SharedEq, Theory, TheoryLemma, TheoryOutcome};
 use crate::theory_ctx::TheoryCtx;
+use crate::tseitin::{EncodeCx, TseitinEncoder};
 
 type FxBuild = BuildHasherDefault<FxHasher>;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CheckSat {
     Sat,
     Unsat,
@@ -34,23 +46,36 @@ pub struct SmtEngine<K: smt_sat::SatKernel> {
     pub sat: K,
 
     pub theories: Vec<Box<dyn Theory>>,
-    pub uf_id: TheoryId,
-    pub dl_id: TheoryId,
 
     pub atoms: AtomTable,
+    pub tseitin: TseitinEncoder,
 
     pub reasons: ReasonArena,
 
     pub shared_terms: SharedTermOracle,
     pub export_epoch: u64,
+    /// Equalities already imported on the current branch (rolled back with the trail).
+    pub export_dedup: ExportDedup,
+    pub interface_eqs: InterfaceEqs,
+    pub quantifiers: Quantifiers,
 
     pub config: EngineConfig,
 
+    /// Most recent theory conflict, pinned (root of the debug bundles). Only recorded
+    /// when debug output or model checking is on.
+    pub last_conflict: Option<ReasonId>,
+    /// Failed assumptions of the last `Unsat`.
+    unsat_assumptions: Vec<Lit>,
+    /// Open user scopes, innermost last.
+    scopes: Vec<UserScope>,
+
+    /// Decision level the theories are currently at.
+    theory_level: usize,
+    /// Atoms already passed to `Theory::register_atom`.
+    registered_atoms: usize,
+
     #[cfg(feature = "test-debug")]
     pub eqshare_trace: EqShareTrace,
-
-    eq_log_seen: HashSet<(TheoryId, TheoryId, smt_core::TermId, smt_core::TermId, u64), FxBuild>,
-    last_atom_count: usize,
 }
 
 impl<K: smt_sat::SatKernel> SmtEngine<K> {
@@ -58,18 +83,23 @@ impl<K: smt_sat::SatKernel> SmtEngine<K> {
         let mut eng = Self {
             ctx,
             sat,
-            uf_id: TheoryId(0),
-            dl_id: TheoryId(1),
             theories,
             atoms: AtomTable::default(),
+            tseitin: TseitinEncoder::default(),
             reasons: ReasonArena::default(),
             shared_terms: SharedTermOracle::default(),
             export_epoch: 0,
+            export_dedup: ExportDedup::default(),
+            interface_eqs: InterfaceEqs::default(),
+            quantifiers: Quantifiers::new(),
             config: EngineConfig::default(),
+            last_conflict: None,
+            unsat_assumptions: Vec::new(),
+            scopes: Vec::new(),
+            theory_level: 0,
+            registered_atoms: 0,
             #[cfg(feature = "test-debug")]
             eqshare_trace: EqShareTrace::default(),
-            eq_log_seen: HashSet::default(),
-            last_atom_count: 0,
         };
 
         // Cross-crate test toggle.
@@ -81,18 +111,164 @@ impl<K: smt_sat::SatKernel> SmtEngine<K> {
         eng
     }
 
-    /// (Scaffold) recompute shared terms when atom table changed.
-    pub fn maybe_recompute_shared_terms(&mut self) {
-        let n = self.atoms.len();
-        if n != self.last_atom_count {
-            self.shared_terms.recompute(&self.atoms, &mut self.theories);
-            self.last_atom_count = n;
+    /// Propositionalize `t` into the SAT kernel and assert it.
+    ///
+    /// Boolean leaves are classified by asking each theory (in order) whether it owns them.
+    pub fn assert_formula(&mut self, t: TermId) -> smt_core::Result<()> {
+        self.leave_search();
+        let theories = &self.theories;
+        let classify = |ctx: &Context, a: TermId| {
+            theories.iter().position(|th| th.owns_atom(ctx, a)).map(TheoryId)
+        };
+        let mut cx = EncodeCx {
+            ctx: &self.ctx,
+            atoms: &mut self.atoms,
+            classify: &classify,
+            sat: &mut self.sat,
+        };
+        match self.scopes.last() {
+            Some(scope) => self.tseitin.assert_guarded(&mut cx, t, scope.lit)?,
+            None => self.tseitin.assert_root(&mut cx, t)?,
+        }
+        self.register_new_atoms();
+        Ok(())
+    }
+
+    /// Assert `t` behind a fresh activation literal, returned for use as an assumption
+    /// of `check_sat_assuming_lits`.
+    pub fn assert_guarded(&mut self, t: TermId) -> smt_core::Result<Lit> {
+        self.leave_search();
+        let guard = Lit::pos(self.sat.new_var());
+        let theories = &self.theories;
+        let classify = |ctx: &Context, a: TermId| {
+            theories.iter().position(|th| th.owns_atom(ctx, a)).map(TheoryId)
+        };
+        let mut cx = EncodeCx {
+            ctx: &self.ctx,
+            atoms: &mut self.atoms,
+            classify: &classify,
+            sat: &mut self.sat,
+        };
+        self.tseitin.assert_guarded(&mut cx, t, guard)?;
+        // Assuming the guard after the scope is gone fails right away.
+        if let Some(scope) = self.scopes.last() {
+            self.sat.add_clause(&[!guard, scope.lit]);
+        }
+        self.register_new_atoms();
+        Ok(guard)
+    }
+
+    /// Name the theory literal an assertion of `t` asserts (`t` an atom or a negated atom;
+    /// other formulas are ignored): reasons built on it become `Reason::Assumption`
+    /// leaves carrying `label`, which conflict DOT output and bundles show.
+    pub fn label_assertion(&mut self, t: TermId, label: &str) {
+        let (a, pos) = match *self.ctx.term_node(t).0 {
+            TermKind::Not(a) => (a, false),
+            _ => (t, true),
+        };
+        let Some(atom) = self.atoms.lookup(a) else { return };
+        // Labels go with the checkpoint they are given under: give them below every level.
+        self.leave_search();
+        self.sync_levels();
+        let lit = if pos { Lit::pos(atom.var) } else { Lit::neg(atom.var) };
+        self.reasons.label(lit, label);
+    }
+
+    /// Literal for the Boolean term `t`, encoded for use with both polarities.
+    pub fn encode_term(&mut self, t: TermId) -> Lit {
+        self.leave_search();
+        self.encode(t)
+    }
+
+    /// Drop the assignment left by the last `check_sat` before adding clauses from
+    /// outside the search: a clause conflicting with it would be analysed without
+    /// access to the theories' lazy explanations.
+    fn leave_search(&mut self) {
+        self.sat.backtrack(0);
+    }
+
+    fn encode(&mut self, t: TermId) -> Lit {
+        let theories = &self.theories;
+        let classify = |ctx: &Context, a: TermId| {
+            theories.iter().position(|th| th.owns_atom(ctx, a)).map(TheoryId)
+        };
+        let mut cx = EncodeCx {
+            ctx: &self.ctx,
+            atoms: &mut self.atoms,
+            classify: &classify,
+            sat: &mut self.sat,
+        };
+        let l = self.tseitin.encode_both(&mut cx, t);
+        self.register_new_atoms();
+        l
+    }
+
+    fn register_new_atoms(&mut self) {
+        for atom in self.atoms.iter_atoms().skip(self.registered_atoms) {
+            // An equality atom over shared terms is an interface equality: its value
+            // also goes to the other theories mentioning its sides.
+            if let TermKind::Eq(a, b) = *self.ctx.term_node(atom.term).0 {
+                if self.ctx.term_sort(a) != self.ctx.bool_sort() && self.interface_eqs.lit_of(a, b).is_none() {
+                    self.interface_eqs.insert(a, b, Lit::pos(atom.var));
+                }
+            }
+            self.theories[atom.theory.0].register_atom(&self.ctx, atom);
+            for (k, th) in self.theories.iter_mut().enumerate() {
+                if k != atom.theory.0 {
+                    th.register_foreign_atom(&self.ctx, atom);
+                }
+            }
         }
+        self.registered_atoms = self.atoms.len();
     }
 
-    /// Perform one equality-sharing round: export from each theory, import into others.
-    pub fn equality_sharing_round(&mut self) {
-        self.maybe_recompute_shared_terms();
+    /// Fold new atoms into the shared-term oracle and tell theories about terms that
+    /// just became shared with them, including interface equalities over those terms
+    /// that were assigned before.
+    pub fn sync_shared_terms(&mut self) {
+        self.sync_shared();
+    }
+
+    /// `sync_shared_terms`; returns `true` if a late delivery added a clause.
+    fn sync_shared(&mut self) -> bool {
+        let mut added = false;
+        self.shared_terms.sync(&self.atoms, &self.theories);
+        for (th, t) in self.shared_terms.take_newly_shared() {
+            self.theories[th.0].notify_shared(t);
+            let pairs: Vec<(TermId, TermId, Lit)> =
+                self.interface_eqs.iter().filter(|&(a, b, _)| a == t || b == t).collect();
+            for (a, b, lit) in pairs {
+                // Read now: an earlier delivery may have backjumped.
+                let Some(v) = self.sat.value(lit) else { continue };
+                let lit = if v { lit } else { !lit };
+                if self.atoms.atom_of_var(lit.var()).is_some_and(|atom| atom.theory == th) {
+                    continue;
+                }
+                if lit.is_pos() && !self.export_dedup.insert(a, b, th) {
+                    continue;
+                }
+                let Some(sh) = self.theories[th.0].equality_sharing_mut() else { break };
+                let mut tcx = TheoryCtx::new(&mut self.reasons);
+                let eq = SharedEq { a, b, explain: tcx.r_lit(lit) };
+                let outcome = if lit.is_pos() { sh.import_equality(eq, &mut tcx) } else { sh.import_disequality(eq, &mut tcx) };
+                if self.feed(th, outcome) {
+                    added = true;
+                    self.sync_levels();
+                }
+            }
+        }
+        added
+    }
+
+    /// Perform one equality-sharing round: export from each theory, then route every
+    /// exported equality to the other theories owning either endpoint, as allowed by
+    /// the sharing matrix.
+    ///
+    /// Equalities a theory already received on the current branch are dropped
+    /// (`export_dedup`), so theories may re-export freely. Import outcomes go straight to
+    /// the SAT kernel; a conflict ends the round. Returns `true` if anything was imported.
+    pub fn equality_sharing_round(&mut self) -> bool {
+        self.sync_shared_terms();
 
         let dbg = self.config.debug_eq;
         let enabled = dbg.enabled;
@@ -106,7 +282,10 @@ impl<K: smt_sat::SatKernel> SmtEngine<K> {
         }
 
         let theory_names: Vec<&'static str> = self.theories.iter().map(|th| th.name()).collect();
+        let n = theory_names.len();
+        let allow = self.config.sharing.resolve(&theory_names);
 
+        let shape = self.config.sharing.export_shape;
         let mut exported: Vec<(TheoryId, SharedEq)> = Vec::new();
         {
             let mut tcx = TheoryCtx::new(&mut self.reasons);
@@ -116,74 +295,452 @@ impl<K: smt_sat::SatKernel> SmtEngine<K> {
                     for eq in sh.export_equalities(&self.shared_terms, self.export_epoch, &mut tcx) {
                         exported.push((src, eq));
                     }
+                    for class in sh.export_classes(&self.shared_terms, self.export_epoch, &mut tcx) {
+                        for eq in shape_class(shape, &class, &mut tcx) {
+                            exported.push((src, eq));
+                        }
+                    }
                 }
             }
         }
 
-        for (src, eq) in exported {
-            for (j, th) in self.theories.iter_mut().enumerate() {
-                let dst = TheoryId(j);
-                if dst == src { continue; }
+        let mut progress = false;
+        'route: for (src, eq) in exported {
+            let mut dsts = TheorySet::default();
+            for t in [eq.a, eq.b] {
+                if let Some(owners) = self.shared_terms.owners(t) {
+                    dsts.union_with(owners);
+                }
+            }
 
-                let allow = match (src, dst) {
-                    (s, d) if s == self.uf_id && d == self.dl_id => self.config.sharing.uf_to_dl,
-                    (s, d) if s == self.dl_id && d == self.uf_id => self.config.sharing.dl_to_uf,
-                    _ => true,
-                };
-                if !allow { continue; }
+            for dst in dsts.iter() {
+                if !allow[src.0 * n + dst.0] { continue; }
+                if self.theories[dst.0].equality_sharing_mut().is_none() { continue; }
+                if !self.export_dedup.insert(eq.a, eq.b, dst) { continue; }
 
                 // record trace (test/debug only)
                 #[cfg(feature = "test-debug")]
                 {
+                    let explain = self.reasons.pin(eq.explain);
                     self.eqshare_trace.push(EqShareEvent {
                         epoch: self.export_epoch,
                         src,
                         dst,
                         a: eq.a,
                         b: eq.b,
-                        explain: eq.explain,
+                        explain,
                     });
                 }
 
-                // (Optional) log first time seen
+                // (Optional) log; dedup already limits this to the first import on a branch
                 if enabled && (dbg.log_exports || dbg.log_imports) {
-                    let (p, q) = if eq.a < eq.b { (eq.a, eq.b) } else { (eq.b, eq.a) };
-                    if self.eq_log_seen.insert((src, dst, p, q, self.export_epoch)) {
-                        let a_str = format!("{:?}", eq.a);
-                        let b_str = format!("{:?}", eq.b);
-                        let lits = self.reasons.expand_lits(eq.explain);
-                        let mut rs = String::new();
-                        for (idx, l) in lits.iter().take(dbg.max_reason_lits).enumerate() {
-                            if idx > 0 { rs.push_str(", "); }
-                            let lit_str = if l.is_pos() {
-                                format!("v{}", l.var().0)
-                            } else {
-                                format!("¬v{}", l.var().0)
-                            };
-                            rs.push_str(&lit_str);
-                        }
-                        if lits.len() > dbg.max_reason_lits { rs.push_str(", ..."); }
-
-                        eprintln!(
-                            "[eqshare][epoch={}]: {} -> {} {} = {}{}",
-                            self.export_epoch,
-                            theory_names[src.0],
-                            theory_names[dst.0],
-                            a_str,
-                            b_str,
-                            if rs.is_empty() { "".to_string() } else { format!("  because {rs}") },
-                        );
+                    let a_str = format!("{:?}", eq.a);
+                    let b_str = format!("{:?}", eq.b);
+                    let (lits, more) = self.reasons.expand_lits_bounded(eq.explain, dbg.max_reason_lits);
+                    let mut rs = String::new();
+                    for (idx, l) in lits.iter().enumerate() {
+                        if idx > 0 { rs.push_str(", "); }
+                        let lit_str = if l.is_pos() {
+                            format!("v{}", l.var().0)
+                        } else {
+                            format!("¬v{}", l.var().0)
+                        };
+                        rs.push_str(&lit_str);
                     }
+                    if more { rs.push_str(", ..."); }
+
+                    eprintln!(
+                        "[eqshare][epoch={}]: {} -> {} {} = {}{}",
+                        self.export_epoch,
+                        theory_names[src.0],
+                        theory_names[dst.0],
+                        a_str,
+                        b_str,
+                        if rs.is_empty() { "".to_string() } else { format!("  because {rs}") },
+                    );
                 }
 
-                if let Some(sh) = th.equality_sharing_mut() {
-                    let mut tcx = TheoryCtx::new(&mut self.reasons);
-                    sh.import_equality(eq.clone(), &mut tcx);
+                let th = &mut self.theories[dst.0];
+                let outcome = match th.equality_sharing_mut() {
+                    Some(sh) => {
+                        let mut tcx = TheoryCtx::new(&mut self.reasons);
+                        let explain = tcx.r_imported(src, eq.a, eq.b, eq.explain);
+                        sh.import_equality(SharedEq { explain, ..eq.clone() }, &mut tcx)
+                    }
+                    None => continue,
+                };
+                progress = true;
+                let conflict = matches!(outcome, TheoryOutcome::Conflict(_));
+                self.feed(dst, outcome);
+                if conflict {
+                    break 'route;
                 }
             }
         }
 
         self.export_epoch = self.export_epoch.wrapping_add(1);
+        progress
+    }
+
+    /// Hand an outcome of theory `src` to the SAT kernel. Returns `true` if it assigned a
+    /// literal or added a clause.
+    ///
+    /// Lazy propagations are assigned without a clause where the kernel allows it; the
+    /// kernel asks `src` for the explanation only if conflict analysis reaches them.
+    pub fn feed(&mut self, src: TheoryId, outcome: TheoryOutcome) -> bool {
+        match outcome {
+            TheoryOutcome::Ok => false,
+            TheoryOutcome::Propagate(props) => {
+                let mut added = false;
+                for p in props {
+                    let value = self.sat.value(p.lit);
+                    if value == Some(true) {
+                        continue;
+                    }
+                    added = true;
+                    if p.explain.is_none() && value.is_none() && self.sat.assign_lazy(p.lit, src.0 as u32) {
+                        continue;
+                    }
+                    let explain = match p.explain {
+                        Some(r) => r,
+                        None => self.theories[src.0].explain(&self.ctx, p.lit, &mut TheoryCtx::new(&mut self.reasons)),
+                    };
+                    let mut clause: Vec<Lit> = self.reasons.expand_lits_cached(explain).into_iter().map(|l| !l).collect();
+                    clause.push(p.lit);
+                    self.add_lemma(&clause);
+                }
+                added
+            }
+            TheoryOutcome::Conflict(r) => {
+                // The node dies with its level; keep a copy only if a bundle may need it.
+                if self.config.debug_eq.enabled || self.config.check_models {
+                    self.last_conflict = Some(self.reasons.pin(r));
+                }
+                let clause: Vec<Lit> = self.reasons.expand_lits_cached(r).into_iter().map(|l| !l).collect();
+                self.add_lemma(&clause);
+                true
+            }
+            TheoryOutcome::Lemma(lemma) => {
+                self.add_theory_lemma(lemma);
+                true
+            }
+            TheoryOutcome::Lemmas(lemmas) => {
+                let added = !lemmas.is_empty();
+                for lemma in lemmas {
+                    self.add_theory_lemma(lemma);
+                }
+                added
+            }
+        }
+    }
+
+    fn add_theory_lemma(&mut self, lemma: TheoryLemma) {
+        let mut clause: Vec<Lit> = self.reasons.expand_lits_cached(lemma.because).into_iter().map(|l| !l).collect();
+        // Atoms (and negated atoms) are leaves: encoding them adds no clauses.
+        for t in lemma.atoms {
+            clause.push(self.encode(t));
+        }
+        self.add_lemma(&clause);
+    }
+
+    /// Add a theory clause mid-search.
+    fn add_lemma(&mut self, clause: &[Lit]) {
+        let mut ex = Explainer { ctx: &self.ctx, theories: &mut self.theories, reasons: &mut self.reasons };
+        self.sat.add_clause_with(clause, &mut ex);
+    }
+
+    /// Number of open user scopes.
+    pub fn num_scopes(&self) -> usize { self.scopes.len() }
+
+    /// Open a user scope: assertions, declarations and atoms from now on are retracted by
+    /// the matching `pop`.
+    pub fn push(&mut self) {
+        self.register_new_atoms();
+        self.sat.backtrack(0);
+        self.sync_levels();
+        let lit = Lit::pos(self.sat.new_var());
+        self.scopes.push(UserScope {
+            lit,
+            ctx: self.ctx.mark(),
+            atoms: self.atoms.len(),
+            interface_eqs: self.interface_eqs.len(),
+            #[cfg(feature = "test-debug")]
+            trace: self.eqshare_trace.mark(),
+        });
+        self.tseitin.push_scope();
+        self.export_dedup.push_checkpoint();
+        self.reasons.push_checkpoint();
+        self.quantifiers.push_scope();
+        for th in self.theories.iter_mut() {
+            th.push_scope();
+        }
+    }
+
+    /// Close the `n` innermost user scopes. Terms created inside them must not be used
+    /// afterwards (their ids are handed out again).
+    pub fn pop(&mut self, n: usize) -> smt_core::Result<()> {
+        if n > self.scopes.len() {
+            return Err(format!("cannot pop {n} scopes, only {} open", self.scopes.len()).into());
+        }
+        if n == 0 {
+            return Ok(());
+        }
+        self.sat.backtrack(0);
+        self.sync_levels();
+        let popped = self.scopes.split_off(self.scopes.len() - n);
+        let mark = popped[0];
+
+        for th in self.theories.iter_mut() {
+            th.pop_scopes(n);
+        }
+        self.reasons.pop_checkpoints(n);
+        self.export_dedup.pop_checkpoints(n);
+        self.tseitin.pop_scopes(n);
+        self.interface_eqs.truncate(mark.interface_eqs);
+        self.shared_terms.truncate(mark.atoms);
+        self.atoms.truncate(mark.atoms);
+        self.registered_atoms = self.atoms.len();
+        self.ctx.rewind(mark.ctx);
+        self.quantifiers.pop_scopes(n, &self.ctx);
+        #[cfg(feature = "test-debug")]
+        self.eqshare_trace.rewind(mark.trace);
+        self.last_conflict = None;
+        self.unsat_assumptions.clear();
+
+        for scope in &popped {
+            self.sat.add_clause(&[!scope.lit]);
+        }
+        Ok(())
+    }
+
+    /// Pop theories down to the kernel's decision level (after a backjump).
+    fn sync_levels(&mut self) {
+        let lvl = self.sat.decision_level();
+        if lvl < self.theory_level {
+            let n = self.theory_level - lvl;
+            for th in self.theories.iter_mut() {
+                th.pop_levels(n);
+            }
+            self.export_dedup.pop_checkpoints(n);
+            self.reasons.pop_checkpoints(n);
+            self.theory_level = lvl;
+        }
+    }
+
+    /// Follow a new SAT decision level.
+    fn push_level(&mut self) {
+        for th in self.theories.iter_mut() {
+            th.push_level();
+        }
+        self.export_dedup.push_checkpoint();
+        self.reasons.push_checkpoint();
+        self.theory_level += 1;
+    }
+
+    /// Pass new trail literals to the theories owning them, then let every theory
+    /// propagate. Returns `true` as soon as some outcome added a clause.
+    fn theory_round(&mut self) -> bool {
+        if self.sync_shared() {
+            return true;
+        }
+        loop {
+            let head = self.sat.trail_head();
+            let Some(&lit) = self.sat.trail().get(head) else { break };
+            self.sat.set_trail_head(head + 1);
+            if let Some(atom) = self.atoms.atom_of_var(lit.var()) {
+                let mut tcx = TheoryCtx::new(&mut self.reasons);
+                let outcome = self.theories[atom.theory.0].assert_atom(&self.ctx, atom, lit.is_pos(), &mut tcx);
+                if self.feed(atom.theory, outcome) {
+                    return true;
+                }
+            }
+            if self.interface_eqs.pair_of(lit.var()).is_some() && self.deliver_interface_eq(lit) {
+                return true;
+            }
+        }
+        for i in 0..self.theories.len() {
+            let mut tcx = TheoryCtx::new(&mut self.reasons);
+            let outcome = self.theories[i].propagate(&self.ctx, &mut tcx);
+            if self.feed(TheoryId(i), outcome) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Pass an assigned interface equality (true: `a = b`, false: `a != b`) to every
+    /// theory owning either side, except the one that already got it as its own atom.
+    fn deliver_interface_eq(&mut self, lit: Lit) -> bool {
+        let Some((a, b)) = self.interface_eqs.pair_of(lit.var()) else { return false };
+        self.sync_shared_terms();
+        let skip = self.atoms.atom_of_var(lit.var()).map(|atom| atom.theory);
+        let mut dsts = TheorySet::default();
+        for t in [a, b] {
+            if let Some(owners) = self.shared_terms.owners(t) {
+                dsts.union_with(owners);
+            }
+        }
+        for dst in dsts.iter() {
+            if Some(dst) == skip { continue; }
+            if lit.is_pos() && !self.export_dedup.insert(a, b, dst) { continue; }
+            let outcome = match self.theories[dst.0].equality_sharing_mut() {
+                Some(sh) => {
+                    let mut tcx = TheoryCtx::new(&mut self.reasons);
+                    let eq = SharedEq { a, b, explain: tcx.r_lit(lit) };
+                    if lit.is_pos() { sh.import_equality(eq, &mut tcx) } else { sh.import_disequality(eq, &mut tcx) }
+                }
+                None => continue,
+            };
+            if self.feed(dst, outcome) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Introduce (or look up) the interface equality `a = b`.
+    pub fn introduce_interface_eq(&mut self, a: TermId, b: TermId) -> Lit {
+        if let Some(l) = self.interface_eqs.lit_of(a, b) {
+            return l;
+        }
+        let (a, b) = InterfaceEqs::key(a, b);
+        let t = self.ctx.eq(a, b);
+        // A fresh leaf: no clauses, so this is fine mid-search.
+        let lit = self.encode(t);
+        self.interface_eqs.insert(a, b, lit);
+        #[cfg(feature = "test-debug")]
+        {
+            self.eqshare_trace.push_split(SplitEvent { epoch: self.export_epoch, a, b, var: lit.var() });
+        }
+        if self.config.debug_eq.enabled {
+            eprintln!("[eqshare][epoch={}]: split on {:?} = {:?} (v{})", self.export_epoch, a, b, lit.var().0);
+        }
+        lit
+    }
+
+    /// Arrangement splitting: introduce an interface equality for every pair of
+    /// same-sorted shared terms that has none yet. Returns `true` if any was added.
+    fn split_interface_equalities(&mut self) -> bool {
+        if !self.config.sharing.split_interface_eqs {
+            return false;
+        }
+        self.sync_shared_terms();
+        let mut shared: Vec<TermId> = self.shared_terms.shared_set().iter().copied().collect();
+        shared.sort();
+        let bool_sort = self.ctx.bool_sort();
+
+        let mut added = false;
+        for (i, &a) in shared.iter().enumerate() {
+            let sort = self.ctx.term_sort(a);
+            if sort == bool_sort { continue; }
+            for &b in &shared[i + 1..] {
+                if self.ctx.term_sort(b) != sort || self.interface_eqs.lit_of(a, b).is_some() {
+                    continue;
+                }
+                let lit = self.introduce_interface_eq(a, b);
+                added = true;
+                // Already assigned (e.g. the user asserted `a = b`): the trail has moved past it.
+                if let Some(v) = self.sat.value(lit) {
+                    self.deliver_interface_eq(if v { lit } else { !lit });
+                }
+            }
+        }
+        added
+    }
+
+    /// Model-based combination: compare the theories' candidate models on same-sorted
+    /// shared terms and introduce an interface equality wherever they disagree (one owner
+    /// says equal, another says different). Terms one theory gives the same value are
+    /// only compared with the first of them: if every other owner agrees on those pairs,
+    /// it agrees on the whole group. The new atom is branched on "equal" first. Returns
+    /// `true` if any was added.
+    fn model_based_combination(&mut self) -> bool {
+        if self.config.combination != CombinationStrategy::ModelBased {
+            return false;
+        }
+        self.sync_shared_terms();
+        let mut shared: Vec<TermId> = self.shared_terms.shared_set().iter().copied().collect();
+        shared.sort();
+        let bool_sort = self.ctx.bool_sort();
+
+        let mut disagree = Vec::new();
+        for (k, th) in self.theories.iter().enumerate() {
+            let mut first: HashMap<(SortId, Value), TermId, FxBuild> = HashMap::default();
+            for &b in &shared {
+                let sort = self.ctx.term_sort(b);
+                if sort == bool_sort || !self.shared_terms.is_owned_by(b, TheoryId(k)) { continue; }
+                let Some(v) = th.model_value(&self.ctx, b) else { continue };
+                let &mut a = first.entry((sort, v)).or_insert(b);
+                if a == b || self.interface_eqs.lit_of(a, b).is_some() || disagree.contains(&(a, b)) {
+                    continue;
+                }
+                let said_ne = self.theories.iter().enumerate().any(|(j, other)| {
+                    j != k
+                        && self.shared_terms.is_owned_by(a, TheoryId(j))
+                        && self.shared_terms.is_owned_by(b, TheoryId(j))
+                        && matches!(
+                            (other.model_value(&self.ctx, a), other.model_value(&self.ctx, b)),
+                            (Some(va), Some(vb)) if va != vb
+                        )
+                });
+                if said_ne {
+                    disagree.push((a, b));
+                }
+            }
+        }
+
+        for &(a, b) in &disagree {
+            let lit = self.introduce_interface_eq(a, b);
+            self.sat.set_phase(lit);
+        }
+        !disagree.is_empty()
+    }
+
+    fn final_check_round(&mut self) -> bool {
+        for i in 0..self.theories.len() {
+            let mut tcx = TheoryCtx::new(&mut self.reasons);
+            let outcome = self.theories[i].final_check(&mut self.ctx, &mut tcx);
+            if self.feed(TheoryId(i), outcome) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Instantiate the quantifiers assigned in the current (complete) assignment. The new
+    /// instances are asserted behind the quantifiers' literals from level 0; then `None`
+    /// tells the search to go on. Otherwise the result is `Sat`, or `Unknown` if a
+    /// universal quantifier is active (its instances are only the ones E-matching found).
+    fn quantifier_round(&mut self) -> Option<CheckSat> {
+        let mut active: Vec<(TermId, bool)> = Vec::new();
+        for &q in self.tseitin.quantified() {
+            if let Some(v) = self.tseitin.lit_of(q).and_then(|l| self.sat.value(l)) {
+                active.push((q, v));
+            }
+        }
+        let egraph = self.theories.iter().find_map(|th| th.egraph());
+        let instances = self.quantifiers.instantiate(&mut self.ctx, &active, egraph, &self.config.quantifiers);
+        if instances.is_empty() {
+            let universal = active.iter().any(|&(q, v)| Quantifiers::is_universal(&self.ctx, q, v));
+            return Some(if universal { CheckSat::Unknown } else { CheckSat::Sat });
+        }
+        self.leave_search();
+        self.sync_levels();
+        for i in instances {
+            let lit = self.tseitin.lit_of(i.quantifier).expect("encoded quantifier");
+            let guard = if i.value { lit } else { !lit };
+            let theories = &self.theories;
+            let classify = |ctx: &Context, a: TermId| theories.iter().position(|th| th.owns_atom(ctx, a)).map(TheoryId);
+            let mut cx = EncodeCx { ctx: &self.ctx, atoms: &mut self.atoms, classify: &classify, sat: &mut self.sat };
+            self.tseitin.assert_guarded(&mut cx, i.formula, guard).expect("instances are ground Boolean terms");
+        }
+        self.register_new_atoms();
+        None
+    }
+
+    /// Quantifier instances made so far (and not retracted by `pop`), in order.
+    pub fn instances(&self) -> &[Instance] {
+        self.quantifiers.instances()
     }
 
     /// Dump equality sharing trace to DOT (only meaningful if trace is enabled).
@@ -209,12 +766,82 @@ impl<K: smt_sat::SatKernel> SmtEngine<K> {
         reason_to_dot(&self.reasons, root, DotLimits::default())
     }
 
-    /// (Scaffold) Solve.
+    /// Solve the asserted formulas: SAT propagation, theory propagation and equality
+    /// sharing to fixpoint before every decision, theory final checks on full assignments.
     pub fn check_sat(&mut self) -> CheckSat {
-        // In a real solver, this would run CDCL(T) + theory propagation + conflicts.
-        // Here we just run one sharing round so that trace/dot infrastructure can be exercised.
-        self.equality_sharing_round();
-        CheckSat::Unknown
+        self.check_sat_assuming_lits(&[])
+    }
+
+    /// `check_sat` with `assumptions` decided first. On `Unsat`, `unsat_assumptions`
+    /// holds the assumptions the refutation used (empty if none was needed).
+    pub fn check_sat_assuming_lits(&mut self, assumptions: &[Lit]) -> CheckSat {
+        self.register_new_atoms();
+        self.sat.backtrack(0);
+        self.sync_levels();
+        let scoped: Vec<Lit> = self.scopes.iter().map(|s| s.lit).chain(assumptions.iter().copied()).collect();
+        self.sat.set_assumptions(&scoped);
+        self.unsat_assumptions.clear();
+        self.last_conflict = None;
+        self.reasons.set_caching(self.config.cache_reason_expansions);
+        self.quantifiers.start_check();
+
+        loop {
+            let mut ex = Explainer { ctx: &self.ctx, theories: &mut self.theories, reasons: &mut self.reasons };
+            if self.sat.propagate_with(&mut ex).is_err() {
+                return self.finish_unsat();
+            }
+            self.sync_levels();
+            if self.theory_round() {
+                continue;
+            }
+            if self.config.combination == CombinationStrategy::Eager && self.equality_sharing_round() {
+                continue;
+            }
+            let mut ex = Explainer { ctx: &self.ctx, theories: &mut self.theories, reasons: &mut self.reasons };
+            let decision = self.sat.decide_with(&mut ex);
+            // Assumptions that already hold open empty levels; mirror every new level.
+            while self.theory_level < self.sat.decision_level() {
+                self.push_level();
+            }
+            match decision {
+                Decide::Decided => {}
+                Decide::Failed => {
+                    let scopes = &self.scopes;
+                    self.unsat_assumptions = self
+                        .sat
+                        .failed_assumptions()
+                        .iter()
+                        .copied()
+                        .filter(|l| !scopes.iter().any(|s| s.lit == *l))
+                        .collect();
+                    return self.finish_unsat();
+                }
+                Decide::Complete => {
+                    if !self.final_check_round()
+                        && !self.model_based_combination()
+                        && !self.split_interface_equalities()
+                    {
+                        if let Some(result) = self.quantifier_round() {
+                            let incomplete = self.theories.iter().any(|th| th.incomplete());
+                            return if result == CheckSat::Sat && incomplete { CheckSat::Unknown } else { result };
+                        }
+                    }
+                }
+                Decide::Unknown => return CheckSat::Unknown,
+            }
+        }
+    }
+
+    /// Assumptions behind the last `Unsat` of `check_sat_assuming_lits`.
+    pub fn unsat_assumptions(&self) -> &[Lit] { &self.unsat_assumptions }
+
+    fn finish_unsat(&mut self) -> CheckSat {
+        if self.config.debug_eq.enabled {
+            if let Some(r) = self.last_conflict {
+                crate::unsat_bundle::write_unsat_debug_bundle(self, r);
+            }
+        }
+        CheckSat::Unsat
     }
 
     /// (Test/debug) Drain trace events.
@@ -227,4 +854,32 @@ impl<K: smt_sat::SatKernel> SmtEngine<K> {
     pub fn take_eqshare_events(&mut self) -> Vec<EqShareEvent> {
         Vec::new()
     }
+
+    /// (Test/debug) Drain interface-equality split events.
+    #[cfg(feature = "test-debug")]
+    pub fn take_split_events(&mut self) -> Vec<SplitEvent> {
+        self.eqshare_trace.take_splits()
+    }
+
+    #[cfg(not(feature = "test-debug"))]
+    pub fn take_split_events(&mut self) -> Vec<SplitEvent> {
+        Vec::new()
+    }
+}
+
+/// Routes the kernel's explanation requests for lazy propagations to the theory that made
+/// them (the handle is its `TheoryId`).
+struct Explainer<'a> {
+    ctx: &'a Context,
+    theories: &'a mut [Box<dyn Theory>],
+    reasons: &'a mut ReasonArena,
+}
+
+impl LazyReasons for Explainer<'_> {
+    fn explain(&mut self, lit: Lit, handle: u32) -> Vec<Lit> {
+        let r = self.theories[handle as usize].explain(self.ctx, lit, &mut TheoryCtx::new(self.reasons));
+        let mut clause: Vec<Lit> = self.reasons.expand_lits_cached(r).into_iter().map(|l| !l).collect();
+        clause.push(lit);
+        clause
+    }
 }
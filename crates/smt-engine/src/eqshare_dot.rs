@@ -73,13 +73,13 @@ pub fn eqshare_to_dot(
         let src_name = theory_names(ev.src);
         let dst_name = theory_names(ev.dst);
 
-        let lits = reasons.expand_lits(ev.explain);
+        let (lits, more) = reasons.expand_lits_bounded(ev.explain, limits.max_reason_lits);
         let mut rs = String::new();
-        for (i, l) in lits.iter().take(limits.max_reason_lits).enumerate() {
-            if i > 0 { rs.push_str(","); }
+        for (i, l) in lits.iter().enumerate() {
+            if i > 0 { rs.push(','); }
             rs.push_str(&fmt_lit_short(*l));
         }
-        if lits.len() > limits.max_reason_lits { rs.push_str(",..."); }
+        if more { rs.push_str(",..."); }
 
         let label = if rs.is_empty() {
             format!("{src_name}→{dst_name} @{}", ev.epoch)
@@ -2,6 +2,7 @@
 //! Equality-sharing trace (test/debug aid).
 
 use smt_core::TermId;
+use smt_sat::VarId;
 use crate::atoms::TheoryId;
 use crate::reason::ReasonId;
 
@@ -15,9 +16,28 @@ pub struct EqShareEvent {
     pub explain: ReasonId,
 }
 
+/// An interface equality `a = b` was introduced as a SAT variable to split on.
+#[derive(Debug, Clone)]
+pub struct SplitEvent {
+    pub epoch: u64,
+    pub a: TermId,
+    pub b: TermId,
+    pub var: VarId,
+}
+
+/// Number of events (of each kind) ever recorded, drained ones included.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceMark {
+    events: usize,
+    splits: usize,
+}
+
 #[derive(Default)]
 pub struct EqShareTrace {
     events: Vec<EqShareEvent>,
+    splits: Vec<SplitEvent>,
+    /// Events already drained or cleared, so marks survive `take`.
+    gone: TraceMark,
 }
 
 impl EqShareTrace {
@@ -25,12 +45,39 @@ impl EqShareTrace {
         self.events.push(ev);
     }
 
-    pub fn clear(&mut self) { self.events.clear(); }
+    pub fn push_split(&mut self, ev: SplitEvent) {
+        self.splits.push(ev);
+    }
+
+    pub fn clear(&mut self) {
+        self.gone.events += self.events.len();
+        self.gone.splits += self.splits.len();
+        self.events.clear();
+        self.splits.clear();
+    }
+
+    pub fn mark(&self) -> TraceMark {
+        TraceMark { events: self.gone.events + self.events.len(), splits: self.gone.splits + self.splits.len() }
+    }
+
+    /// Drop events recorded after `mark` that were not drained yet (e.g. on pop).
+    pub fn rewind(&mut self, mark: TraceMark) {
+        self.events.truncate(mark.events.saturating_sub(self.gone.events));
+        self.splits.truncate(mark.splits.saturating_sub(self.gone.splits));
+    }
 
     pub fn events(&self) -> &[EqShareEvent] { &self.events }
 
     /// Drain all events (useful in tests after a single `check_sat()`).
     pub fn take(&mut self) -> Vec<EqShareEvent> {
+        self.gone.events += self.events.len();
         core::mem::take(&mut self.events)
     }
+
+    pub fn splits(&self) -> &[SplitEvent] { &self.splits }
+
+    pub fn take_splits(&mut self) -> Vec<SplitEvent> {
+        self.gone.splits += self.splits.len();
+        core::mem::take(&mut self.splits)
+    }
 }
@@ -0,0 +1,59 @@
+#![forbid(unsafe_code)]
+//! Engine-owned deduplication of shared equalities.
+//!
+//! Keys are normalized pairs `(min, max)` per importing theory. Every insertion is
+//! recorded on a trail; checkpoints are pushed with SAT decision levels (and user scopes),
+//! and rolling back removes exactly the keys inserted since, so an equality can be
+//! imported again on a different branch but never twice on the same one.
+
+use hashbrown::HashSet;
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::TermId;
+
+use crate::atoms::TheoryId;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+type Key = (TermId, TermId, TheoryId);
+
+#[derive(Debug, Default)]
+pub struct ExportDedup {
+    seen: HashSet<Key, FxBuild>,
+    trail: Vec<Key>,
+    checkpoints: Vec<usize>,
+}
+
+impl ExportDedup {
+    fn key(a: TermId, b: TermId, dst: TheoryId) -> Key {
+        if a <= b { (a, b, dst) } else { (b, a, dst) }
+    }
+
+    /// Record that `dst` received `a = b`; returns `false` if it already had it.
+    pub fn insert(&mut self, a: TermId, b: TermId, dst: TheoryId) -> bool {
+        let k = Self::key(a, b, dst);
+        if !self.seen.insert(k) {
+            return false;
+        }
+        self.trail.push(k);
+        true
+    }
+
+    pub fn push_checkpoint(&mut self) {
+        self.checkpoints.push(self.trail.len());
+    }
+
+    /// Undo the `n` most recent checkpoints.
+    pub fn pop_checkpoints(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let keep = self.checkpoints.len() - n;
+        let target = self.checkpoints[keep];
+        self.checkpoints.truncate(keep);
+        for k in self.trail.drain(target..) {
+            self.seen.remove(&k);
+        }
+    }
+}
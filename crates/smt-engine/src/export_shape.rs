@@ -0,0 +1,52 @@
+#![forbid(unsafe_code)]
+//! Export shaping: turn a theory's equivalence class over shared terms into the
+//! equalities actually exported.
+//!
+//! For a class `rep, m1, .., mk` where `ri` explains `mi = rep`:
+//! - `Star`: `rep = mi` because `ri` (k equalities, shortest explanations).
+//! - `Chain`: `m(i-1) = mi` because `r(i-1) ∧ ri` (k equalities).
+//! - `AllPairs`: every pair, `mi = mj` because `ri ∧ rj` (quadratic, but importers never
+//!   need transitivity to connect two members).
+
+use crate::theory::{EqClass, SharedEq};
+use crate::theory_ctx::TheoryCtx;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportShape {
+    #[default]
+    Star,
+    Chain,
+    AllPairs,
+}
+
+/// Equalities for one class under `shape`.
+pub fn shape_class(shape: ExportShape, class: &EqClass, tcx: &mut TheoryCtx) -> Vec<SharedEq> {
+    let mut nodes = vec![(class.rep, None)];
+    nodes.extend(class.members.iter().filter(|(t, _)| *t != class.rep).map(|&(t, r)| (t, Some(r))));
+
+    let mut out = Vec::new();
+    let mut eq = |tcx: &mut TheoryCtx, (a, ra): (_, Option<_>), (b, rb): (_, Option<_>)| {
+        let explain = tcx.r_and(ra.into_iter().chain(rb).collect());
+        out.push(SharedEq { a, b, explain });
+    };
+    match shape {
+        ExportShape::Star => {
+            for &n in &nodes[1..] {
+                eq(tcx, nodes[0], n);
+            }
+        }
+        ExportShape::Chain => {
+            for w in nodes.windows(2) {
+                eq(tcx, w[0], w[1]);
+            }
+        }
+        ExportShape::AllPairs => {
+            for (i, &a) in nodes.iter().enumerate() {
+                for &b in &nodes[i + 1..] {
+                    eq(tcx, a, b);
+                }
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,68 @@
+#![forbid(unsafe_code)]
+//! Interface equalities: engine-introduced atoms `a = b` over pairs of shared terms, used
+//! to let the SAT kernel case-split on the arrangement of shared terms (delayed theory
+//! combination). Non-convex theories only have to be consistent with the chosen
+//! arrangement instead of having to export disjunctions of equalities.
+
+use hashbrown::HashMap;
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::TermId;
+use smt_sat::{Lit, VarId};
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+#[derive(Debug, Default)]
+pub struct InterfaceEqs {
+    by_pair: HashMap<(TermId, TermId), Lit, FxBuild>,
+    by_var: HashMap<VarId, (TermId, TermId), FxBuild>,
+    /// Keys in insertion order (for `truncate`).
+    order: Vec<(TermId, TermId)>,
+}
+
+impl InterfaceEqs {
+    /// Unordered pair key.
+    pub fn key(a: TermId, b: TermId) -> (TermId, TermId) {
+        if a <= b { (a, b) } else { (b, a) }
+    }
+
+    pub fn len(&self) -> usize { self.order.len() }
+
+    pub fn is_empty(&self) -> bool { self.by_pair.is_empty() }
+
+    /// Literal standing for `a = b`, if it was introduced.
+    pub fn lit_of(&self, a: TermId, b: TermId) -> Option<Lit> {
+        self.by_pair.get(&Self::key(a, b)).copied()
+    }
+
+    /// Pair whose equality `var` stands for.
+    pub fn pair_of(&self, var: VarId) -> Option<(TermId, TermId)> {
+        self.by_var.get(&var).copied()
+    }
+
+    /// Every pair with its literal, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (TermId, TermId, Lit)> + '_ {
+        self.order.iter().map(|&(a, b)| (a, b, self.by_pair[&(a, b)]))
+    }
+
+    /// Record that the positive literal `lit` means `a = b`.
+    pub fn insert(&mut self, a: TermId, b: TermId, lit: Lit) {
+        let key = Self::key(a, b);
+        if let Some(old) = self.by_pair.insert(key, lit) {
+            self.by_var.remove(&old.var());
+        } else {
+            self.order.push(key);
+        }
+        self.by_var.insert(lit.var(), key);
+    }
+
+    /// Forget all but the first `len` introduced pairs (e.g. on pop).
+    pub fn truncate(&mut self, len: usize) {
+        for key in self.order.drain(len.min(self.order.len())..) {
+            if let Some(lit) = self.by_pair.remove(&key) {
+                self.by_var.remove(&lit.var());
+            }
+        }
+    }
+}
@@ -5,11 +5,19 @@ pub mod atoms;
 pub mod config;
 pub mod eqshare_trace;
 pub mod eqshare_dot;
+pub mod export_dedup;
+pub mod export_shape;
+pub mod interface_eqs;
+pub mod model_builder;
+pub mod quantifiers;
 pub mod reason;
 pub mod reason_dot;
+pub mod scopes;
 pub mod shared_terms;
+pub mod theories;
 pub mod theory;
 pub mod theory_ctx;
+pub mod tseitin;
 pub mod unsat_bundle;
 pub mod engine;
 
@@ -0,0 +1,393 @@
+#![forbid(unsafe_code)]
+//! Merge the theories' candidate models into one `Model` after `Sat`.
+//!
+//! Boolean leaves take their SAT value. Other terms are grouped into classes: terms a
+//! theory gives equal values are merged, and so are terms with the same interpreted
+//! (non-`Elem`) value. Each class then takes its interpreted value if it has one, and a
+//! fresh value of its sort otherwise (an unused integer for Int and Real, an unused word
+//! for bit-vectors, a new element for uninterpreted sorts). Combination guarantees
+//! owners agree on shared terms, so merging never joins terms a theory keeps apart.
+//!
+//! Datatype classes come next. A class with a constructor application takes the value
+//! it builds from its arguments' (the datatype theory keeps classes acyclic); any other
+//! class takes an unused value of its sort, chosen so that distinct classes stay distinct.
+//!
+//! Array classes come last, inner sorts first: a class maps the index of every read from
+//! one of its members to the read's value, and all other indices to a default.
+//!
+//! Terms with free variables (under quantifiers) get no value.
+
+use hashbrown::{HashMap, HashSet};
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{ArrayValue, Context, FuncInterp, Model, OpKind, Rational, SortId, SortKind, TermId, TermKind, Value};
+use smt_sat::SatKernel;
+
+use crate::engine::SmtEngine;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+struct UnionFind(Vec<usize>);
+
+impl UnionFind {
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.0[x] != x {
+            self.0[x] = self.0[self.0[x]];
+            x = self.0[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            // Keep the smaller id as root so classes are numbered deterministically.
+            let (lo, hi) = if ra < rb { (ra, rb) } else { (rb, ra) };
+            self.0[hi] = lo;
+        }
+    }
+}
+
+fn is_connective(kind: &TermKind) -> bool {
+    matches!(
+        kind,
+        TermKind::BoolConst(_) | TermKind::Not(_) | TermKind::And(_) | TermKind::Or(_) | TermKind::Implies(..)
+    )
+}
+
+impl<K: SatKernel> SmtEngine<K> {
+    /// Model of the current (complete) assignment. Only meaningful right after `Sat`.
+    pub fn build_model(&self) -> Model {
+        let ctx = &self.ctx;
+        let n = ctx.num_terms();
+        let bool_sort = ctx.bool_sort();
+        let mut model = Model::default();
+
+        for i in 0..n {
+            let t = TermId(i as u32);
+            let (kind, sort) = ctx.term_node(t);
+            if sort != bool_sort { continue; }
+            match kind {
+                TermKind::BoolConst(b) => model.set_value(t, Value::Bool(*b)),
+                TermKind::Eq(a, _) if ctx.term_sort(*a) == bool_sort => {}
+                k if is_connective(k) => {}
+                _ => {
+                    if let Some(v) = self.tseitin.lit_of(t).and_then(|l| self.sat.value(l)) {
+                        model.set_value(t, Value::Bool(v));
+                    }
+                }
+            }
+        }
+
+        // Classes of non-Boolean terms.
+        let mut uf = UnionFind((0..n).collect());
+        let mut interpreted: Vec<Option<Value>> = vec![None; n];
+        let mut by_interp: HashMap<Value, usize, FxBuild> = HashMap::default();
+        for (i, slot) in interpreted.iter_mut().enumerate() {
+            match ctx.term_node(TermId(i as u32)).0 {
+                TermKind::IntConst(v) => *slot = Some(Value::Int(*v)),
+                TermKind::RatConst(r) => *slot = Some(Value::Real(*r)),
+                TermKind::BvConst(v) => *slot = ctx.bv_width(ctx.term_sort(TermId(i as u32))).map(|w| Value::BitVec(w, *v)),
+                _ => {}
+            }
+        }
+        for th in &self.theories {
+            let mut seen: HashMap<Value, usize, FxBuild> = HashMap::default();
+            for (i, slot) in interpreted.iter_mut().enumerate() {
+                let t = TermId(i as u32);
+                if ctx.term_sort(t) == bool_sort { continue; }
+                let Some(v) = th.model_value(ctx, t) else { continue };
+                if !matches!(v, Value::Elem(..)) && slot.is_none() {
+                    *slot = Some(v.clone());
+                }
+                match seen.get(&v) {
+                    Some(&j) => uf.union(i, j),
+                    None => { seen.insert(v, i); }
+                }
+            }
+        }
+        for (i, v) in interpreted.iter().enumerate() {
+            let Some(v) = v else { continue };
+            match by_interp.get(v) {
+                Some(&j) => uf.union(i, j),
+                None => { by_interp.insert(v.clone(), i); }
+            }
+        }
+
+        let mut class_value: Vec<Option<Value>> = vec![None; n];
+        for (i, v) in interpreted.into_iter().enumerate() {
+            if let Some(v) = v {
+                let r = uf.find(i);
+                class_value[r].get_or_insert(v);
+            }
+        }
+        let mut next_int = by_interp
+            .keys()
+            .filter_map(|v| if let Value::Int(k) = v { Some(*k) } else { None })
+            .max()
+            .map_or(0, |m| m + 1);
+        let mut next_real = by_interp
+            .keys()
+            .filter_map(|v| if let Value::Real(r) = v { Some(r.floor()) } else { None })
+            .max()
+            .map_or(0, |m| m + 1);
+        let mut next_elem: HashMap<SortId, u32, FxBuild> = HashMap::default();
+        let mut next_word: HashMap<u32, u128, FxBuild> = HashMap::default();
+        for i in 0..n {
+            let t = TermId(i as u32);
+            let sort = ctx.term_sort(t);
+            if sort == bool_sort || ctx.array_parts(sort).is_some() || ctx.datatype(sort).is_some() || !ctx.is_ground(t) { continue; }
+            let r = uf.find(i);
+            let v = class_value[r]
+                .get_or_insert_with(|| {
+                    if sort == ctx.int_sort() {
+                        next_int += 1;
+                        Value::Int(next_int - 1)
+                    } else if sort == ctx.real_sort() {
+                        next_real += 1;
+                        Value::Real(Rational::int(next_real - 1))
+                    } else if let Some(w) = ctx.bv_width(sort) {
+                        let (mask, k) = (smt_core::bv::mask(w), next_word.entry(w).or_insert(0));
+                        // Unused if the width leaves one.
+                        for _ in 0..=by_interp.len() {
+                            if !by_interp.contains_key(&Value::BitVec(w, *k)) { break; }
+                            *k = k.wrapping_add(1) & mask;
+                        }
+                        let v = *k;
+                        *k = k.wrapping_add(1) & mask;
+                        Value::BitVec(w, v)
+                    } else {
+                        let k = next_elem.entry(sort).or_insert(0);
+                        *k += 1;
+                        Value::Elem(sort, *k - 1)
+                    }
+                })
+                .clone();
+            model.set_value(t, v);
+        }
+
+        let root_of: Vec<usize> = (0..n).map(|i| uf.find(i)).collect();
+        datatype_classes(ctx, &root_of, &mut class_value, &mut model);
+
+        let mut reads: HashMap<usize, Vec<TermId>, FxBuild> = HashMap::default();
+        for i in 0..n {
+            if let TermKind::Select(a, _) = ctx.term_node(TermId(i as u32)).0 {
+                reads.entry(uf.find(a.0 as usize)).or_default().push(TermId(i as u32));
+            }
+        }
+        let mut arrays: Vec<usize> = (0..n)
+            .filter(|&i| ctx.array_parts(ctx.term_sort(TermId(i as u32))).is_some() && ctx.is_ground(TermId(i as u32)))
+            .collect();
+        arrays.sort_by_key(|&i| (sort_depth(ctx, ctx.term_sort(TermId(i as u32))), i));
+        for i in arrays {
+            let t = TermId(i as u32);
+            let r = uf.find(i);
+            if class_value[r].is_none() {
+                let (_, elem) = ctx.array_parts(ctx.term_sort(t)).expect("array sort");
+                let mut value = ArrayValue::constant(default_value(ctx, elem));
+                for &s in reads.get(&r).into_iter().flatten() {
+                    let TermKind::Select(_, j) = ctx.term_node(s).0 else { continue };
+                    if let (Some(k), Some(v)) = (model.value(*j), model.value(s)) {
+                        value = value.store(k.clone(), v.clone());
+                    }
+                }
+                class_value[r] = Some(Value::Array(Box::new(value)));
+            }
+            model.set_value(t, class_value[r].clone().expect("array class value"));
+        }
+
+        // UF symbols: one entry per distinct argument tuple.
+        let mut funcs: Vec<(String, FuncInterp)> = Vec::new();
+        for i in 0..n {
+            let t = TermId(i as u32);
+            let TermKind::App { op, args } = ctx.term_node(t).0 else { continue };
+            let OpKind::Uf(name) = &op.kind;
+            let (Some(res), Some(vals)) = (
+                model.value(t).cloned(),
+                args.iter().map(|&a| model.value(a).cloned()).collect::<Option<Vec<_>>>(),
+            ) else {
+                continue;
+            };
+            match funcs.iter_mut().find(|(f, _)| f == name) {
+                Some((_, interp)) => {
+                    if !interp.entries.iter().any(|(a, _)| *a == vals) {
+                        interp.entries.push((vals, res));
+                    }
+                }
+                None => funcs.push((name.clone(), FuncInterp { entries: vec![(vals, res.clone())], default: res })),
+            }
+        }
+        for (name, interp) in funcs {
+            model.set_func(name, interp);
+        }
+
+        model
+    }
+}
+
+/// Rounds of picking unused datatype values before giving up on keeping classes apart.
+const DATATYPE_ROUNDS: usize = 8;
+
+/// Most values `sample_values` returns.
+const SAMPLE_CAP: usize = 256;
+
+/// Values of the datatype classes. Unused values are picked first; a built value can
+/// still collide with one of them, which is then avoided in the next round.
+fn datatype_classes(ctx: &Context, root_of: &[usize], class_value: &mut [Option<Value>], model: &mut Model) {
+    let terms: Vec<usize> = (0..root_of.len())
+        .filter(|&i| ctx.datatype(ctx.term_sort(TermId(i as u32))).is_some() && ctx.is_ground(TermId(i as u32)))
+        .collect();
+    let mut ctor_of: HashMap<usize, TermId, FxBuild> = HashMap::default();
+    let (mut roots, mut seen): (Vec<usize>, HashSet<usize, FxBuild>) = Default::default();
+    for &i in &terms {
+        let r = root_of[i];
+        if seen.insert(r) {
+            roots.push(r);
+        }
+        if matches!(ctx.term_node(TermId(i as u32)).0, TermKind::Construct(..)) {
+            ctor_of.entry(r).or_insert(TermId(i as u32));
+        }
+    }
+    roots.retain(|r| !ctor_of.contains_key(r));
+
+    let mut avoid: HashSet<Value, FxBuild> = HashSet::default();
+    let mut values: HashMap<usize, Value, FxBuild> = HashMap::default();
+    for _ in 0..DATATYPE_ROUNDS {
+        values.clear();
+        let mut used = avoid.clone();
+        for &r in &roots {
+            let v = unused_value(ctx, ctx.term_sort(TermId(r as u32)), &used);
+            used.insert(v.clone());
+            values.insert(r, v);
+        }
+        for &i in &terms {
+            build_value(ctx, model, root_of, &ctor_of, &mut values, &mut Vec::new(), root_of[i]);
+        }
+        let mut seen: HashMap<&Value, usize, FxBuild> = HashMap::default();
+        let mut collided = false;
+        for (&r, v) in &values {
+            if seen.insert(v, r).is_some_and(|other| other != r) {
+                avoid.insert(v.clone());
+                collided = true;
+            }
+        }
+        if !collided {
+            break;
+        }
+    }
+    for i in terms {
+        let v = values[&root_of[i]].clone();
+        class_value[root_of[i]] = Some(v.clone());
+        model.set_value(TermId(i as u32), v);
+    }
+}
+
+/// Value of class `r`, built from a constructor application in it.
+fn build_value(
+    ctx: &Context,
+    model: &Model,
+    root_of: &[usize],
+    ctor_of: &HashMap<usize, TermId, FxBuild>,
+    values: &mut HashMap<usize, Value, FxBuild>,
+    open: &mut Vec<usize>,
+    r: usize,
+) -> Value {
+    if let Some(v) = values.get(&r) {
+        return v.clone();
+    }
+    let c = ctor_of[&r];
+    let sort = ctx.term_sort(c);
+    if open.contains(&r) {
+        // A cyclic class (no datatype theory ruled it out): any value will do.
+        return default_value(ctx, sort);
+    }
+    let TermKind::Construct(k, args) = ctx.term_node(c).0 else { unreachable!("constructor application") };
+    open.push(r);
+    let fields = args
+        .iter()
+        .map(|&a| {
+            let s = ctx.term_sort(a);
+            if ctx.datatype(s).is_some() {
+                build_value(ctx, model, root_of, ctor_of, values, open, root_of[a.0 as usize])
+            } else {
+                model.value(a).cloned().unwrap_or_else(|| default_value(ctx, s))
+            }
+        })
+        .collect();
+    open.pop();
+    let v = Value::Ctor(ctx.constructor(sort, *k).name.clone(), fields);
+    values.insert(r, v.clone());
+    v
+}
+
+/// A value of `s` not in `used`, if `s` has one.
+fn unused_value(ctx: &Context, s: SortId, used: &HashSet<Value, FxBuild>) -> Value {
+    for depth in 0..=used.len() + SAMPLE_CAP {
+        if let Some(v) = sample_values(ctx, s, depth).into_iter().find(|v| !used.contains(v)) {
+            return v;
+        }
+        if ctx.is_finite_sort(s) && depth > used.len() {
+            break;
+        }
+    }
+    default_value(ctx, s)
+}
+
+/// Some values of `s`: datatype values up to `depth` nested constructors, over
+/// `depth + 1` values of each other sort. At most `SAMPLE_CAP` of them.
+fn sample_values(ctx: &Context, s: SortId, depth: usize) -> Vec<Value> {
+    let n = depth as u128 + 1;
+    match ctx.sort_kind(s) {
+        SortKind::Bool => vec![Value::Bool(false), Value::Bool(true)],
+        SortKind::Int => (0..n as i64).map(Value::Int).collect(),
+        SortKind::Real => (0..n as i128).map(|k| Value::Real(Rational::int(k))).collect(),
+        SortKind::Uninterpreted(_) => (0..n as u32).map(|k| Value::Elem(s, k)).collect(),
+        SortKind::BitVec(w) => (0..n).take_while(|&k| k <= smt_core::bv::mask(*w)).map(|k| Value::BitVec(*w, k)).collect(),
+        SortKind::Array(_, e) => {
+            sample_values(ctx, *e, depth).into_iter().map(|v| Value::Array(Box::new(ArrayValue::constant(v)))).collect()
+        }
+        SortKind::Datatype(_) => {
+            let mut out = Vec::new();
+            for c in &ctx.datatype(s).expect("datatype sort").constructors {
+                if depth == 0 && !c.fields.is_empty() {
+                    continue;
+                }
+                let mut tuples: Vec<Vec<Value>> = vec![Vec::new()];
+                for &(_, f) in &c.fields {
+                    let vals = sample_values(ctx, f, depth - 1);
+                    tuples = tuples
+                        .iter()
+                        .flat_map(|t| vals.iter().map(move |v| t.iter().cloned().chain([v.clone()]).collect()))
+                        .take(SAMPLE_CAP)
+                        .collect();
+                }
+                out.extend(tuples.into_iter().map(|fields| Value::Ctor(c.name.clone(), fields)));
+            }
+            out.truncate(SAMPLE_CAP);
+            out
+        }
+    }
+}
+
+/// Nesting depth of array sorts (0 for other sorts).
+fn sort_depth(ctx: &Context, s: SortId) -> usize {
+    match ctx.sort_kind(s) {
+        SortKind::Array(i, e) => 1 + sort_depth(ctx, *i).max(sort_depth(ctx, *e)),
+        _ => 0,
+    }
+}
+
+/// The value arrays take outside their reads.
+fn default_value(ctx: &Context, s: SortId) -> Value {
+    match ctx.sort_kind(s) {
+        SortKind::Bool => Value::Bool(false),
+        SortKind::Int => Value::Int(0),
+        SortKind::Real => Value::Real(Rational::ZERO),
+        SortKind::Uninterpreted(_) => Value::Elem(s, 0),
+        SortKind::BitVec(w) => Value::BitVec(*w, 0),
+        SortKind::Array(_, e) => Value::Array(Box::new(ArrayValue::constant(default_value(ctx, *e)))),
+        // Well-founded, so some depth has a value.
+        SortKind::Datatype(_) => (0..).find_map(|depth| sample_values(ctx, s, depth).into_iter().next()).expect("datatype value"),
+    }
+}
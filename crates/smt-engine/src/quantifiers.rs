@@ -0,0 +1,336 @@
+#![forbid(unsafe_code)]
+//! Quantifier instantiation by E-matching.
+//!
+//! Quantified formulas are leaves of the Boolean skeleton (`TseitinEncoder::quantified`).
+//! Once the theories accept a full assignment, the engine hands this module the
+//! quantifiers with their values. A quantifier read universally (`forall` true, `exists`
+//! false) is instantiated with every binding under which one of its triggers matches
+//! terms of the E-graph, the congruence closure of the UF theory (`Theory::egraph`): a
+//! pattern matches any term its structure is equal to there, not only syntactic copies.
+//! A quantifier read existentially is skolemized once, with fresh constants
+//! `q!<var><n>`. Each instance is valid whenever the quantifier has the value it was made
+//! for, and the engine asserts it behind the quantifier's literal.
+//!
+//! Without explicit triggers, each smallest UF application of the body that mentions
+//! every variable is one; failing that, one multi-pattern is collected greedily.
+//! Instances are made once per binding, and their number and the generation of the
+//! terms they bind are capped (`QuantifierConfig`). E-matching is incomplete, so a check
+//! that leaves a universal quantifier active ends `Unknown` rather than `Sat`.
+
+use core::cmp::Reverse;
+use hashbrown::{HashMap, HashSet};
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{Context, OpKind, Quantified, TermId, TermKind};
+
+use crate::config::QuantifierConfig;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+/// Terms bound so far, as `(variable, term)` pairs.
+type Binding = Vec<(TermId, TermId)>;
+
+/// Ground terms and their classes, as E-matching sees them.
+pub trait EGraph {
+    /// Applications of the function `f`.
+    fn apps(&self, f: &str) -> Vec<TermId>;
+
+    /// Representative of the class of `t`; `None` if the graph has no node for `t`.
+    fn find(&self, t: TermId) -> Option<TermId>;
+
+    /// Members of the class of `t` (just `t` if the graph has no node for it).
+    fn class(&self, t: TermId) -> Vec<TermId>;
+}
+
+/// A formula produced from a quantifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instance {
+    pub quantifier: TermId,
+    /// Value of the quantifier the instance follows from.
+    pub value: bool,
+    /// A term per variable, in order: the match, or the skolem constants.
+    pub binding: Vec<TermId>,
+    /// Trigger that matched; `None` for a skolemization.
+    pub trigger: Option<usize>,
+    /// Largest generation among the bound terms.
+    pub generation: u32,
+    /// The body under `binding`, negated if the quantifier is false.
+    pub formula: TermId,
+}
+
+#[derive(Default)]
+pub struct Quantifiers {
+    instances: Vec<Instance>,
+    /// Quantifier, value and binding of every instance (no binding for skolemizations).
+    made: HashSet<(TermId, bool, Vec<TermId>), FxBuild>,
+    /// Triggers of each quantifier seen, explicit or inferred.
+    triggers: HashMap<TermId, Vec<Vec<TermId>>, FxBuild>,
+    /// Generation of the terms built by instances; others have generation 0.
+    generation: HashMap<TermId, u32, FxBuild>,
+    /// Instances made in the current check.
+    this_check: usize,
+    skolems: u32,
+    /// `instances` length at each open user scope.
+    scopes: Vec<usize>,
+}
+
+impl Quantifiers {
+    pub fn new() -> Self { Self::default() }
+
+    /// Every instance made so far and not undone by `pop_scopes`, in order.
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    /// Whether `q` (a quantifier) having `value` is a universal statement.
+    pub fn is_universal(ctx: &Context, q: TermId, value: bool) -> bool {
+        matches!(ctx.term_node(q).0, TermKind::Forall(_)) == value
+    }
+
+    /// A `check_sat` starts: reset the per-check instance count.
+    pub fn start_check(&mut self) {
+        self.this_check = 0;
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(self.instances.len());
+    }
+
+    /// Forget the instances made in the `n` innermost scopes, whose terms `ctx` no longer
+    /// has (it is already rewound).
+    pub fn pop_scopes(&mut self, n: usize, ctx: &Context) {
+        if n == 0 {
+            return;
+        }
+        let mark = self.scopes[self.scopes.len() - n];
+        self.scopes.truncate(self.scopes.len() - n);
+        for i in self.instances.drain(mark..) {
+            let binding = if i.trigger.is_some() { i.binding } else { Vec::new() };
+            self.made.remove(&(i.quantifier, i.value, binding));
+        }
+        let live = ctx.num_terms();
+        self.triggers.retain(|q, _| (q.0 as usize) < live);
+        self.generation.retain(|t, _| (t.0 as usize) < live);
+    }
+
+    /// New instances of the quantifiers `active` (with their values), within the limits
+    /// of `config`. They are also appended to `instances`.
+    pub fn instantiate(
+        &mut self,
+        ctx: &mut Context,
+        active: &[(TermId, bool)],
+        egraph: Option<&dyn EGraph>,
+        config: &QuantifierConfig,
+    ) -> Vec<Instance> {
+        let start = self.instances.len();
+        let budget = config.max_instances_per_round.min(config.max_instances.saturating_sub(self.this_check));
+        let mut matched = 0;
+        for &(q, value) in active {
+            if !Self::is_universal(ctx, q, value) {
+                self.skolemize(ctx, q, value);
+            } else if let Some(g) = egraph {
+                matched += self.match_quantifier(ctx, q, value, g, config, budget - matched);
+            }
+        }
+        self.this_check += matched;
+        self.instances[start..].to_vec()
+    }
+
+    fn skolemize(&mut self, ctx: &mut Context, q: TermId, value: bool) {
+        if !self.made.insert((q, value, Vec::new())) {
+            return;
+        }
+        let Quantified { vars, body, .. } = quantified(ctx, q).clone();
+        let mut binding = Vec::new();
+        for &v in &vars {
+            let TermKind::Var(name) = ctx.term_node(v).0.clone() else { unreachable!("quantified non-variable") };
+            let sort = ctx.term_sort(v);
+            binding.push(ctx.const_term(format!("q!{name}{}", self.skolems), sort));
+            self.skolems += 1;
+        }
+        let formula = instance(ctx, &vars, body, &binding, value);
+        self.instances.push(Instance { quantifier: q, value, binding, trigger: None, generation: 0, formula });
+    }
+
+    /// Instantiate `q` with up to `room` new matches; returns how many were made.
+    fn match_quantifier(
+        &mut self,
+        ctx: &mut Context,
+        q: TermId,
+        value: bool,
+        g: &dyn EGraph,
+        config: &QuantifierConfig,
+        room: usize,
+    ) -> usize {
+        let Quantified { vars, body, .. } = quantified(ctx, q).clone();
+        let triggers = self.triggers.entry(q).or_insert_with(|| infer_triggers(ctx, quantified(ctx, q))).clone();
+        let mut found: Vec<(usize, Vec<TermId>)> = Vec::new();
+        for (k, trigger) in triggers.iter().enumerate() {
+            let mut bindings = Vec::new();
+            match_multi(ctx, g, trigger, Vec::new(), &mut bindings);
+            for b in bindings {
+                let terms: Option<Vec<TermId>> = vars.iter().map(|v| b.iter().find(|(x, _)| x == v).map(|&(_, t)| t)).collect();
+                found.extend(terms.map(|terms| (k, terms)));
+            }
+        }
+
+        let mut made = 0;
+        // Bindings equal in the graph give equivalent instances: one per round suffices.
+        let mut classes: HashSet<Vec<TermId>, FxBuild> = HashSet::default();
+        for (k, terms) in found {
+            if made == room {
+                break;
+            }
+            let key = (q, value, terms);
+            if self.made.contains(&key) || !classes.insert(key.2.iter().map(|&t| g.find(t).unwrap_or(t)).collect()) {
+                continue;
+            }
+            let generation = key.2.iter().map(|t| self.generation.get(t).copied().unwrap_or(0)).max().unwrap_or(0);
+            if generation >= config.max_generation {
+                continue;
+            }
+            let before = ctx.num_terms();
+            let formula = instance(ctx, &vars, body, &key.2, value);
+            for k in before..ctx.num_terms() {
+                self.generation.insert(TermId(k as u32), generation + 1);
+            }
+            let binding = key.2.clone();
+            self.made.insert(key);
+            self.instances.push(Instance { quantifier: q, value, binding, trigger: Some(k), generation, formula });
+            made += 1;
+        }
+        made
+    }
+}
+
+fn quantified(ctx: &Context, q: TermId) -> &Quantified {
+    match ctx.term_node(q).0 {
+        TermKind::Forall(body) | TermKind::Exists(body) => body,
+        _ => panic!("term {q:?} is not a quantifier"),
+    }
+}
+
+/// `body` with `vars` bound to `terms`, negated unless `value`.
+fn instance(ctx: &mut Context, vars: &[TermId], body: TermId, terms: &[TermId], value: bool) -> TermId {
+    let binding: Binding = vars.iter().copied().zip(terms.iter().copied()).collect();
+    let t = ctx.substitute(body, &binding);
+    if value { t } else { ctx.not(t) }
+}
+
+/// The explicit triggers of `q`, or triggers chosen from its body.
+fn infer_triggers(ctx: &Context, q: &Quantified) -> Vec<Vec<TermId>> {
+    if !q.triggers.is_empty() {
+        return q.triggers.clone();
+    }
+    let mut candidates = Vec::new();
+    collect_patterns(ctx, q.body, &mut candidates);
+    let full: Vec<TermId> =
+        candidates.iter().copied().filter(|&p| ctx.free_vars(p).len() == q.vars.len()).collect();
+    if !full.is_empty() {
+        return full
+            .iter()
+            .filter(|&&p| !full.iter().any(|&o| o != p && occurs_in(ctx, o, p)))
+            .map(|&p| vec![p])
+            .collect();
+    }
+    // Patterns mentioning the most variables first.
+    candidates.sort_by_key(|&p| Reverse(ctx.free_vars(p).len()));
+    let (mut multi, mut covered) = (Vec::new(), Vec::new());
+    for p in candidates {
+        let vars = ctx.free_vars(p);
+        if vars.iter().any(|v| !covered.contains(v)) {
+            covered.extend(vars);
+            multi.push(p);
+        }
+    }
+    if covered.len() < q.vars.len() {
+        return Vec::new();
+    }
+    vec![multi]
+}
+
+/// Patterns with variables in `t`, not looking into nested quantifiers.
+fn collect_patterns(ctx: &Context, t: TermId, out: &mut Vec<TermId>) {
+    let kind = ctx.term_node(t).0;
+    if ctx.is_ground(t) || matches!(kind, TermKind::Forall(_) | TermKind::Exists(_)) {
+        return;
+    }
+    if ctx.is_pattern(t) && !out.iter().any(|&p| ctx.term_node(p).0 == kind) {
+        out.push(t);
+    }
+    for c in kind.children() {
+        collect_patterns(ctx, c, out);
+    }
+}
+
+/// Whether `inner` is a proper subterm of `t`.
+fn occurs_in(ctx: &Context, inner: TermId, t: TermId) -> bool {
+    ctx.term_node(t).0.children().into_iter().any(|c| c == inner || occurs_in(ctx, inner, c))
+}
+
+/// Extensions of `binding` under which each of `patterns` matches some term of `g`.
+fn match_multi(ctx: &Context, g: &dyn EGraph, patterns: &[TermId], binding: Binding, out: &mut Vec<Binding>) {
+    let Some((&p, rest)) = patterns.split_first() else {
+        out.push(binding);
+        return;
+    };
+    let TermKind::App { op, args } = ctx.term_node(p).0 else { return };
+    let OpKind::Uf(name) = &op.kind;
+    for t in g.apps(name) {
+        let TermKind::App { args: targs, .. } = ctx.term_node(t).0 else { continue };
+        if targs.len() != args.len() {
+            continue;
+        }
+        let mut partial = Vec::new();
+        match_terms(ctx, g, args.iter().copied().zip(targs.iter().copied()).collect(), binding.clone(), &mut partial);
+        for b in partial {
+            match_multi(ctx, g, rest, b, out);
+        }
+    }
+}
+
+/// Extensions of `binding` under which each pattern of `todo` equals its term in `g`.
+fn match_terms(ctx: &Context, g: &dyn EGraph, mut todo: Vec<(TermId, TermId)>, mut binding: Binding, out: &mut Vec<Binding>) {
+    let Some((p, t)) = todo.pop() else {
+        out.push(binding);
+        return;
+    };
+    match ctx.term_node(p).0 {
+        TermKind::Var(_) => match binding.iter().find(|&&(v, _)| v == p) {
+            Some(&(_, bound)) if !equal(ctx, g, bound, t) => {}
+            Some(_) => match_terms(ctx, g, todo, binding, out),
+            None => {
+                binding.push((p, t));
+                match_terms(ctx, g, todo, binding, out);
+            }
+        },
+        _ if ctx.is_ground(p) && equal(ctx, g, p, t) => match_terms(ctx, g, todo, binding, out),
+        _ if ctx.is_ground(p) => {}
+        TermKind::App { op, args } => {
+            for m in g.class(t) {
+                let TermKind::App { op: mop, args: margs } = ctx.term_node(m).0 else { continue };
+                if mop != op || margs.len() != args.len() {
+                    continue;
+                }
+                let mut todo = todo.clone();
+                todo.extend(args.iter().copied().zip(margs.iter().copied()));
+                match_terms(ctx, g, todo, binding.clone(), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `a = b` in `g`, or the same leaf (e.g. two copies of a constant).
+fn equal(ctx: &Context, g: &dyn EGraph, a: TermId, b: TermId) -> bool {
+    if a == b {
+        return true;
+    }
+    if let (Some(x), Some(y)) = (g.find(a), g.find(b)) {
+        return x == y;
+    }
+    let (ka, sa) = ctx.term_node(a);
+    let (kb, sb) = ctx.term_node(b);
+    sa == sb && ka.children().is_empty() && ka == kb
+}
@@ -1,43 +1,301 @@
 #![forbid(unsafe_code)]
-//! Minimal reason arena with AND-composition and SAT literal leaves.
+//! Reason arena: a DAG of typed explanation nodes over SAT literal leaves.
+//!
+//! Every variant flattens to literals through `expand_lits`; the tags only add structure
+//! for proofs, DOT rendering and core attribution. Literals asserted by labeled input
+//! assertions are registered with `label`, and leaves on them become `Assumption`s.
+//!
+//! Allocation is scoped: the engine pushes a checkpoint with every decision level (and
+//! user scope) and popping it truncates the nodes created since. Anything that must
+//! outlive its level (the reported conflict, trace events) is `pin`ned, i.e. copied into
+//! a permanent region whose ids carry the `PINNED` bit.
 
+use hashbrown::{HashMap, HashSet};
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{Rational, TermId};
 use smt_sat::Lit;
 
+use crate::atoms::TheoryId;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ReasonId(pub u32);
 
+impl ReasonId {
+    /// Tag bit of ids in the permanent region.
+    pub const PINNED: u32 = 1 << 31;
+
+    pub fn is_pinned(self) -> bool { self.0 & Self::PINNED != 0 }
+}
+
 #[derive(Debug, Clone)]
 pub enum Reason {
     Atom(Lit),
     And(Vec<ReasonId>),
+    /// `a = b` by congruence; `args[i]` explains the equality of the i-th arguments.
+    Congruence { a: TermId, b: TermId, args: Vec<ReasonId> },
+    /// `a = b` by transitivity over `steps`, in chain order.
+    Trans { a: TermId, b: TermId, steps: Vec<ReasonId> },
+    /// Difference-logic path `path[0] -> .. -> path[k]`; `edges[i]` explains edge `i`.
+    DlPath { path: Vec<TermId>, edges: Vec<ReasonId> },
+    /// `a = b` exported by `src` and imported through equality sharing.
+    Imported { src: TheoryId, a: TermId, b: TermId, explain: ReasonId },
+    /// A literal asserted by the input assertion named `label`.
+    Assumption { label: String, lit: Lit },
+    /// Arithmetic bounds whose combination with the positive `coeffs` is infeasible (or
+    /// implies the propagated bound); `coeffs[i]` scales `premises[i]`.
+    Farkas { coeffs: Vec<Rational>, premises: Vec<ReasonId> },
+}
+
+impl Reason {
+    /// Sub-reasons (empty for leaves).
+    pub fn children(&self) -> &[ReasonId] {
+        match self {
+            Reason::Atom(_) | Reason::Assumption { .. } => &[],
+            Reason::And(kids) => kids,
+            Reason::Congruence { args, .. } => args,
+            Reason::Trans { steps, .. } => steps,
+            Reason::DlPath { edges, .. } => edges,
+            Reason::Imported { explain, .. } => core::slice::from_ref(explain),
+            Reason::Farkas { premises, .. } => premises,
+        }
+    }
+
+    fn children_mut(&mut self) -> &mut [ReasonId] {
+        match self {
+            Reason::Atom(_) | Reason::Assumption { .. } => &mut [],
+            Reason::And(kids) => kids,
+            Reason::Congruence { args, .. } => args,
+            Reason::Trans { steps, .. } => steps,
+            Reason::DlPath { edges, .. } => edges,
+            Reason::Imported { explain, .. } => core::slice::from_mut(explain),
+            Reason::Farkas { premises, .. } => premises,
+        }
+    }
+
+    /// The literal of a leaf.
+    pub fn leaf_lit(&self) -> Option<Lit> {
+        match self {
+            Reason::Atom(l) | Reason::Assumption { lit: l, .. } => Some(*l),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReasonStats {
+    /// Nodes in the scoped region.
+    pub live: usize,
+    /// Nodes in the permanent region.
+    pub pinned: usize,
+    /// High-water mark of `live`.
+    pub peak_live: usize,
+    /// Nodes freed by popping checkpoints, in total.
+    pub reclaimed: u64,
+    pub checkpoints: usize,
+    /// Roots with a cached expansion.
+    pub cached: usize,
+    pub cache_hits: u64,
 }
 
 #[derive(Debug, Default)]
 pub struct ReasonArena {
     reasons: Vec<Reason>,
+    pinned: Vec<Reason>,
+    /// Node and label counts at each checkpoint.
+    checkpoints: Vec<(usize, usize)>,
+    peak_live: usize,
+    reclaimed: u64,
+    /// Flattened literals per root, filled by `expand_lits_cached` while caching is on.
+    cache: HashMap<ReasonId, Vec<Lit>, FxBuild>,
+    caching: bool,
+    cache_hits: u64,
+    /// Labels of literals asserted by labeled input assertions.
+    labels: HashMap<Lit, String, FxBuild>,
+    /// Labeled literals in registration order.
+    labeled: Vec<Lit>,
 }
 
 impl ReasonArena {
     pub fn push(&mut self, r: Reason) -> ReasonId {
         let id = ReasonId(self.reasons.len() as u32);
         self.reasons.push(r);
+        self.peak_live = self.peak_live.max(self.reasons.len());
         id
     }
 
     pub fn get(&self, rid: ReasonId) -> &Reason {
-        &self.reasons[rid.0 as usize]
+        if rid.is_pinned() {
+            &self.pinned[(rid.0 & !ReasonId::PINNED) as usize]
+        } else {
+            &self.reasons[rid.0 as usize]
+        }
     }
 
-    /// Expand a reason into a flat list of literals (bounded by traversal).
-    pub fn expand_lits(&self, root: ReasonId) -> Vec<Lit> {
-        let mut out = Vec::new();
+    pub fn push_checkpoint(&mut self) {
+        self.checkpoints.push((self.reasons.len(), self.labeled.len()));
+    }
+
+    /// Free every scoped node created since the `n`-th most recent checkpoint.
+    pub fn pop_checkpoints(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let keep = self.checkpoints.len() - n;
+        let (target, labeled) = self.checkpoints[keep];
+        self.checkpoints.truncate(keep);
+        for l in self.labeled.drain(labeled..) {
+            self.labels.remove(&l);
+        }
+        self.reclaimed += (self.reasons.len() - target) as u64;
+        self.reasons.truncate(target);
+        // Freed ids get reused; drop their expansions.
+        self.cache.retain(|r, _| r.is_pinned() || (r.0 as usize) < target);
+    }
+
+    /// Copy the DAG under `root` into the permanent region (shared nodes once) and
+    /// return the permanent id. Already pinned ids are returned as is.
+    pub fn pin(&mut self, root: ReasonId) -> ReasonId {
+        if root.is_pinned() {
+            return root;
+        }
+        let mut map: HashMap<ReasonId, ReasonId, FxBuild> = HashMap::default();
+        let mut stack = vec![(root, false)];
+        while let Some((r, children_done)) = stack.pop() {
+            if r.is_pinned() || map.contains_key(&r) {
+                continue;
+            }
+            if !children_done {
+                stack.push((r, true));
+                stack.extend(self.get(r).children().iter().map(|&k| (k, false)));
+                continue;
+            }
+            let mut node = self.get(r).clone();
+            for k in node.children_mut() {
+                if !k.is_pinned() {
+                    *k = map[k];
+                }
+            }
+            let id = ReasonId(self.pinned.len() as u32 | ReasonId::PINNED);
+            self.pinned.push(node);
+            map.insert(r, id);
+        }
+        map[&root]
+    }
+
+    /// Attribute `lit` to the input assertion `label` (the first label given wins). Like
+    /// nodes, labels go with the checkpoint they were given under.
+    pub fn label(&mut self, lit: Lit, label: &str) {
+        if !self.labels.contains_key(&lit) {
+            self.labels.insert(lit, label.to_string());
+            self.labeled.push(lit);
+        }
+    }
+
+    pub fn label_of(&self, lit: Lit) -> Option<&str> {
+        self.labels.get(&lit).map(String::as_str)
+    }
+
+    /// Labels of the `Assumption` leaves under `root`, in first-visit order.
+    pub fn assumption_labels(&self, root: ReasonId) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        let mut visited: HashSet<ReasonId, FxBuild> = HashSet::default();
         let mut stack = vec![root];
         while let Some(r) = stack.pop() {
+            if !visited.insert(r) {
+                continue;
+            }
             match self.get(r) {
-                Reason::Atom(l) => out.push(*l),
-                Reason::And(kids) => stack.extend(kids.iter().copied()),
+                Reason::Assumption { label, .. } => {
+                    if !out.contains(label) {
+                        out.push(label.clone());
+                    }
+                }
+                node => stack.extend(node.children().iter().rev().copied()),
             }
         }
         out
     }
+
+    pub fn stats(&self) -> ReasonStats {
+        ReasonStats {
+            live: self.reasons.len(),
+            pinned: self.pinned.len(),
+            peak_live: self.peak_live,
+            reclaimed: self.reclaimed,
+            checkpoints: self.checkpoints.len(),
+            cached: self.cache.len(),
+            cache_hits: self.cache_hits,
+        }
+    }
+
+    /// Turn caching of flattened roots on or off (turning it off clears the cache).
+    pub fn set_caching(&mut self, on: bool) {
+        self.caching = on;
+        if !on {
+            self.cache.clear();
+        }
+    }
+
+    /// Distinct literals of a reason, in first-visit order. Shared sub-reasons are
+    /// walked once.
+    pub fn expand_lits(&self, root: ReasonId) -> Vec<Lit> {
+        self.walk(root, usize::MAX).0
+    }
+
+    /// `expand_lits`, reusing (and, with caching on, recording) flattened roots.
+    pub fn expand_lits_cached(&mut self, root: ReasonId) -> Vec<Lit> {
+        if let Some(lits) = self.cache.get(&root) {
+            self.cache_hits += 1;
+            return lits.clone();
+        }
+        let lits = self.expand_lits(root);
+        if self.caching {
+            self.cache.insert(root, lits.clone());
+        }
+        lits
+    }
+
+    /// At most `max_lits` distinct literals, plus whether some were left out. Meant for
+    /// debug printing; stops walking as soon as the bound is exceeded.
+    pub fn expand_lits_bounded(&self, root: ReasonId, max_lits: usize) -> (Vec<Lit>, bool) {
+        self.walk(root, max_lits)
+    }
+
+    fn walk(&self, root: ReasonId, max_lits: usize) -> (Vec<Lit>, bool) {
+        let mut out = Vec::new();
+        let mut lits: HashSet<Lit, FxBuild> = HashSet::default();
+        let mut visited: HashSet<ReasonId, FxBuild> = HashSet::default();
+        let mut stack = vec![root];
+        while let Some(r) = stack.pop() {
+            if !visited.insert(r) {
+                continue;
+            }
+            let leaf;
+            let found: &[Lit] = match self.cache.get(&r) {
+                Some(cached) => cached,
+                None => {
+                    let node = self.get(r);
+                    let Some(l) = node.leaf_lit() else {
+                        stack.extend(node.children().iter().rev().copied());
+                        continue;
+                    };
+                    leaf = [l];
+                    &leaf
+                }
+            };
+            for &l in found {
+                if lits.insert(l) {
+                    if out.len() == max_lits {
+                        return (out, true);
+                    }
+                    out.push(l);
+                }
+            }
+        }
+        (out, false)
+    }
 }
@@ -39,13 +39,8 @@ pub fn reason_to_dot(arena: &ReasonArena, root: ReasonId, limits: DotLimits) ->
     while let Some(r) = queue.pop_front() {
         order.push(r);
         if order.len() >= limits.max_reason_nodes { break; }
-        match arena.get(r) {
-            Reason::Atom(_) => {}
-            Reason::And(kids) => {
-                for &k in kids {
-                    if seen.insert(k) { queue.push_back(k); }
-                }
-            }
+        for &k in arena.get(r).children() {
+            if seen.insert(k) { queue.push_back(k); }
         }
     }
 
@@ -61,25 +56,36 @@ pub fn reason_to_dot(arena: &ReasonArena, root: ReasonId, limits: DotLimits) ->
 
     for rid in &order {
         let nid = &name[rid];
-        match arena.get(*rid) {
-            Reason::Atom(l) => {
-                let mut lab = fmt_lit(*l);
-                if lab.len() > limits.max_lit_label_len { lab.truncate(limits.max_lit_label_len); }
-                writeln!(&mut out, "  {nid} [shape=ellipse,label=\"{lab}\"];").ok();
+        let (shape, mut lab) = match arena.get(*rid) {
+            Reason::Atom(l) => ("ellipse", fmt_lit(*l)),
+            Reason::Assumption { label, lit } => ("ellipse", format!("ASSUME {label} ({})", fmt_lit(*lit))),
+            Reason::And(kids) => ("box", format!("AND ({})", kids.len())),
+            Reason::Congruence { a, b, .. } => ("box", format!("CONG t{} = t{}", a.0, b.0)),
+            Reason::Trans { a, b, steps } => ("box", format!("TRANS t{} = t{} ({})", a.0, b.0, steps.len())),
+            Reason::DlPath { path, .. } => {
+                let nodes: Vec<String> = path.iter().map(|t| format!("t{}", t.0)).collect();
+                ("box", format!("DL {}", nodes.join(" -> ")))
             }
-            Reason::And(kids) => {
-                writeln!(&mut out, "  {nid} [shape=box,label=\"AND ({})\"];", kids.len()).ok();
+            Reason::Imported { src, a, b, .. } => ("box", format!("IMPORT t{} = t{} from th{}", a.0, b.0, src.0)),
+            Reason::Farkas { coeffs, .. } => {
+                let cs: Vec<String> = coeffs.iter().map(|c| c.to_string()).collect();
+                ("box", format!("FARKAS [{}]", cs.join(", ")))
             }
+        };
+        if lab.len() > limits.max_lit_label_len {
+            let mut cut = limits.max_lit_label_len;
+            while !lab.is_char_boundary(cut) { cut -= 1; }
+            lab.truncate(cut);
         }
+        let lab = lab.replace('"', "\\\"");
+        writeln!(&mut out, "  {nid} [shape={shape},label=\"{lab}\"];").ok();
     }
 
     for rid in &order {
         let src = &name[rid];
-        if let Reason::And(kids) = arena.get(*rid) {
-            for &k in kids {
-                if let Some(dst) = name.get(&k) {
-                    writeln!(&mut out, "  {src} -> {dst};").ok();
-                }
+        for k in arena.get(*rid).children() {
+            if let Some(dst) = name.get(k) {
+                writeln!(&mut out, "  {src} -> {dst};").ok();
             }
         }
     }
@@ -0,0 +1,26 @@
+#![forbid(unsafe_code)]
+//! User scopes (SMT-LIB `push`/`pop`), independent of SAT decision levels.
+//!
+//! Assertions made inside a scope are guarded by the scope's literal, which `check_sat`
+//! assumes while the scope is open; popping fixes it false. Clauses learned meanwhile,
+//! theory lemmas included, are consequences of the clause set and stay. Everything else
+//! created inside the scope (sorts, terms, atoms, encodings, shared terms, interface
+//! equalities, reasons, dedup entries, trace events) is truncated back to its size at the
+//! matching push.
+
+use smt_core::ContextMark;
+use smt_sat::Lit;
+
+#[cfg(feature = "test-debug")]
+use crate::eqshare_trace::TraceMark;
+
+#[derive(Debug, Clone, Copy)]
+pub struct UserScope {
+    /// Assumed while the scope is open.
+    pub lit: Lit,
+    pub ctx: ContextMark,
+    pub atoms: usize,
+    pub interface_eqs: usize,
+    #[cfg(feature = "test-debug")]
+    pub trace: TraceMark,
+}
@@ -1,5 +1,10 @@
 #![forbid(unsafe_code)]
 //! Engine-owned shared-term oracle for Nelson–Oppen style combination.
+//!
+//! Maintained incrementally: `sync` folds in atoms appended to the `AtomTable`, and
+//! `truncate` retracts atoms removed on pop. Ownership is reference-counted per
+//! `(term, theory)`, so a term stops being shared exactly when its last mention by the
+//! second-to-last owner goes away.
 
 use hashbrown::{HashMap, HashSet};
 use rustc_hash::FxHasher;
@@ -7,18 +12,28 @@ use core::hash::BuildHasherDefault;
 
 use smt_core::TermId;
 
-use crate::atoms::AtomTable;
+use crate::atoms::{AtomTable, TheoryId, TheorySet};
 use crate::theory::Theory;
 
 type FxBuild = BuildHasherDefault<FxHasher>;
 
 #[derive(Default)]
 pub struct SharedTermOracle {
+    /// Number of atoms of a theory mentioning a term.
+    refs: HashMap<(TermId, TheoryId), u32, FxBuild>,
+    owners: HashMap<TermId, TheorySet, FxBuild>,
     shared: HashSet<TermId, FxBuild>,
+    /// Per theory: shared terms it owns ("terms shared with me").
+    by_theory: Vec<HashSet<TermId, FxBuild>>,
+    /// Terms each theory mentions in every synced atom, in `AtomTable` order.
+    synced: Vec<Vec<(TheoryId, Vec<TermId>)>>,
+    /// `(theory, term)` pairs where `term` became shared with `theory`, not yet delivered.
+    pending: Vec<(TheoryId, TermId)>,
     epoch: u64,
 }
 
 impl SharedTermOracle {
+    /// Bumped whenever the shared set changes.
     pub fn epoch(&self) -> u64 { self.epoch }
 
     pub fn is_shared(&self, t: TermId) -> bool {
@@ -29,26 +44,127 @@ impl SharedTermOracle {
         &self.shared
     }
 
-    /// Recompute shared terms based on current atoms and theory endpoint extraction.
-    pub fn recompute(&mut self, atoms: &AtomTable, theories: &mut [Box<dyn Theory>]) {
-        let mut owners: HashMap<TermId, u32, FxBuild> = HashMap::default();
+    /// Theories whose atoms mention `t` (empty if none does).
+    pub fn owners(&self, t: TermId) -> Option<&TheorySet> {
+        self.owners.get(&t)
+    }
+
+    /// Whether theory `th` mentions `t`.
+    pub fn is_owned_by(&self, t: TermId, th: TheoryId) -> bool {
+        self.owners.get(&t).is_some_and(|o| o.contains(th))
+    }
 
-        for atom in atoms.iter_atoms() {
-            let th_idx = atom.theory.0;
-            let bit = 1u32 << (th_idx.min(31) as u32);
+    /// Shared terms owned by `th`.
+    pub fn shared_with(&self, th: TheoryId) -> impl Iterator<Item = TermId> + '_ {
+        self.by_theory.get(th.0).into_iter().flat_map(|s| s.iter().copied())
+    }
 
-            for t in theories[th_idx].atom_endpoints(atom.term) {
-                owners.entry(t).and_modify(|m| *m |= bit).or_insert(bit);
+    /// Number of atoms folded in so far.
+    pub fn synced_atoms(&self) -> usize { self.synced.len() }
+
+    /// Fold in atoms appended to `atoms` since the last sync.
+    pub fn sync(&mut self, atoms: &AtomTable, theories: &[Box<dyn Theory>]) {
+        let mut changed = false;
+        for atom in atoms.iter_atoms().skip(self.synced.len()) {
+            let mut mentions = vec![(atom.theory, theories[atom.theory.0].atom_endpoints(atom.term))];
+            for (k, th) in theories.iter().enumerate() {
+                let ends = if k == atom.theory.0 { Vec::new() } else { th.foreign_endpoints(atom.term) };
+                if !ends.is_empty() {
+                    mentions.push((TheoryId(k), ends));
+                }
+            }
+            for (th, ends) in &mentions {
+                for &t in ends {
+                    changed |= self.add_ref(t, *th);
+                }
             }
+            self.synced.push(mentions);
+        }
+        if changed {
+            self.epoch = self.epoch.wrapping_add(1);
         }
+    }
+
+    /// Retract every synced atom at index `len` and beyond (e.g. on pop).
+    pub fn truncate(&mut self, len: usize) {
+        let mut changed = false;
+        while self.synced.len() > len {
+            for (th, ends) in self.synced.pop().expect("non-empty") {
+                for t in ends {
+                    changed |= self.remove_ref(t, th);
+                }
+            }
+        }
+        self.pending.retain(|(th, t)| self.by_theory.get(th.0).is_some_and(|s| s.contains(t)));
+        if changed {
+            self.epoch = self.epoch.wrapping_add(1);
+        }
+    }
 
-        self.shared.clear();
-        for (t, mask) in owners {
-            if mask.count_ones() >= 2 {
+    /// Drain "term became shared with theory" notifications.
+    pub fn take_newly_shared(&mut self) -> Vec<(TheoryId, TermId)> {
+        core::mem::take(&mut self.pending)
+    }
+
+    /// Returns `true` if the shared set changed.
+    fn add_ref(&mut self, t: TermId, th: TheoryId) -> bool {
+        let cnt = self.refs.entry((t, th)).or_insert(0);
+        *cnt += 1;
+        if *cnt > 1 {
+            return false;
+        }
+        let owners = self.owners.entry(t).or_default();
+        owners.insert(th);
+        match owners.len() {
+            2 => {
                 self.shared.insert(t);
+                let owners: Vec<TheoryId> = owners.iter().collect();
+                for o in owners {
+                    self.view_mut(o).insert(t);
+                    self.pending.push((o, t));
+                }
+                true
+            }
+            n if n > 2 => {
+                self.view_mut(th).insert(t);
+                self.pending.push((th, t));
+                false
             }
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the shared set changed.
+    fn remove_ref(&mut self, t: TermId, th: TheoryId) -> bool {
+        let Some(cnt) = self.refs.get_mut(&(t, th)) else { return false };
+        *cnt -= 1;
+        if *cnt > 0 {
+            return false;
         }
+        self.refs.remove(&(t, th));
+        self.view_mut(th).remove(&t);
 
-        self.epoch = self.epoch.wrapping_add(1);
+        let owners = self.owners.get_mut(&t).expect("owned term");
+        owners.remove(th);
+        match owners.len() {
+            0 => {
+                self.owners.remove(&t);
+                false
+            }
+            1 => {
+                let last = owners.iter().next().expect("one owner");
+                self.view_mut(last).remove(&t);
+                self.shared.remove(&t);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn view_mut(&mut self, th: TheoryId) -> &mut HashSet<TermId, FxBuild> {
+        if self.by_theory.len() <= th.0 {
+            self.by_theory.resize_with(th.0 + 1, HashSet::default);
+        }
+        &mut self.by_theory[th.0]
     }
 }
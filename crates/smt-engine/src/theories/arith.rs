@@ -0,0 +1,831 @@
+#![forbid(unsafe_code)]
+//! Linear arithmetic over `<=` and `=`, shared by LIA and LRA.
+//!
+//! An atom `Σ a_i x_i ⋈ k` is normalized to a positive leading coefficient (scaled by
+//! the [`Domain`]'s content of the combination). Its left-hand side gets a simplex
+//! variable, the term's own for a single `x` and a shared slack row otherwise, and
+//! assigning the atom bounds that variable. Bounds are justified by their literal;
+//! infeasible rows and propagated atoms are explained by Farkas combinations of bounds.
+//! Atoms are propagated lazily: the bounds they follow from are recorded, and only
+//! turned into a reason when the SAT solver asks for one.
+//!
+//! Final checks split a violated disequality `a != b` into `a < b ∨ b < a` as a
+//! `TheoryOutcome::Lemma`, then hand over to the domain (integrality for LIA).
+//!
+//! Equalities between shared terms are exported when the bounds entail them (both terms
+//! fixed to one value, or `x - y` bounded to 0 on both sides). Others only show in the
+//! model, so a final check that finds two shared terms equal without such bounds splits
+//! on `a = b`: the other theories then either hear of it or see the terms apart.
+//!
+//! Numbers that outgrow the rationals make the theory give up rather than panic: an atom
+//! whose row overflows is untracked, a check that overflows stays silent, and while
+//! either matters the theory reports itself `incomplete` (so `Sat` becomes `Unknown`).
+
+use hashbrown::{HashMap, HashSet};
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{Context, Rational, SortId, TermId, TermKind, Value};
+use smt_sat::{Lit, VarId};
+
+use crate::atoms::Atom;
+use crate::reason::ReasonId;
+use crate::shared_terms::SharedTermOracle;
+use crate::theories::linear::{difference, linearize, LinExpr};
+use crate::theories::simplex::{Bound, CheckFailure, Combination, Infeasible, Num, Overflow, Simplex, Var};
+use crate::theory::{EqClass, EqualitySharing, SharedEq, Theory, TheoryLemma, TheoryOutcome, TheoryPropagation};
+use crate::theory_ctx::TheoryCtx;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+/// What distinguishes the arithmetic theories: the sort they own, the values the simplex
+/// assigns, how bounds round, and what final checks add on top of the relaxation.
+pub trait Domain: Default + Sized {
+    type V: Num;
+
+    const NAME: &'static str;
+
+    fn sort(ctx: &Context) -> SortId;
+
+    /// Positive factor a normalized combination (sorted by variable) is divided by.
+    fn content(comb: &[(Var, Rational)]) -> Rational;
+
+    /// Bound for `Σ comb <= k` (`>=` if `lower`, `=` if `eq`); `None` if unsatisfiable.
+    fn round(k: Rational, eq: bool, lower: bool) -> Option<Rational>;
+
+    /// `k` as a value.
+    fn exact(k: Rational) -> Self::V;
+
+    /// Least value above `k`.
+    fn above(k: Rational) -> Self::V;
+
+    /// Greatest value below `k`.
+    fn below(k: Rational) -> Self::V;
+
+    /// An implied upper (or lower) bound, tightened to the values the domain admits.
+    fn tighten(v: Self::V, upper: bool) -> Self::V;
+
+    /// `v` as a plain rational, in the model the last final check settled on (`None` if
+    /// it does not fit).
+    fn concrete(&self, v: Self::V) -> Option<Rational>;
+
+    fn model_value(&self, v: Rational) -> Option<Value>;
+
+    /// Fix whatever the model still needs once the relaxation is feasible.
+    fn settle(_th: &mut ArithTheory<Self>) -> Result<(), Overflow> {
+        Ok(())
+    }
+
+    /// Last stage of the theory's final check.
+    fn final_check(_th: &mut ArithTheory<Self>, _ctx: &mut Context, _tcx: &mut TheoryCtx) -> TheoryOutcome {
+        TheoryOutcome::Ok
+    }
+
+    fn push_scope(&mut self) {}
+
+    fn pop_scopes(&mut self, _n: usize) {}
+}
+
+/// Normalized atom: a bound on a simplex variable, or a constant truth value.
+#[derive(Debug, Clone, Copy)]
+enum Constraint {
+    Le(Var, Rational),
+    Ge(Var, Rational),
+    Eq(Var, Rational),
+    Const(bool),
+    /// Its row does not fit the rationals: asserting it only makes the theory incomplete.
+    Untracked,
+}
+
+#[derive(Debug, Clone)]
+struct Diseq {
+    a: TermId,
+    b: TermId,
+    explain: ReasonId,
+    /// Atom it comes from (`None` if imported).
+    atom: Option<VarId>,
+    /// User scopes open when it was asserted.
+    scope: usize,
+}
+
+/// Bounds a propagated atom follows from, as they were when it was propagated.
+#[derive(Debug, Clone)]
+enum Antecedents {
+    /// Bounds on the atom's own variable.
+    Bounds(Vec<ReasonId>),
+    /// Bounds on the variables of a slack's definition, as a Farkas combination.
+    Farkas(Infeasible),
+}
+
+/// Per-level undo entries (bounds are undone by the simplex itself).
+#[derive(Debug, Clone, Copy)]
+enum Undo {
+    Assigned(VarId),
+    Diseq,
+    Antecedents(VarId),
+    Unrepresented,
+}
+
+/// Per-scope undo entries: everything keyed by terms that a pop may delete.
+#[derive(Debug, Clone)]
+enum ScopeUndo {
+    TermVar(TermId),
+    Slack(Combination),
+    Atom(VarId, TermId),
+    Split(TermId, TermId, Option<VarId>),
+    Shared,
+    Arranged(TermId, TermId),
+}
+
+pub struct ArithTheory<D: Domain> {
+    pub(super) domain: D,
+    pub(super) simplex: Simplex<D::V>,
+    term_var: HashMap<TermId, Var, FxBuild>,
+    /// Term of every original variable (`None` for slacks).
+    pub(super) var_term: Vec<Option<TermId>>,
+    slacks: HashMap<Combination, Var, FxBuild>,
+    /// Definition of every slack over original variables.
+    pub(super) slack_def: HashMap<Var, Combination, FxBuild>,
+    atoms: HashMap<VarId, Constraint, FxBuild>,
+    /// Atoms bounding each simplex variable.
+    atoms_on: HashMap<Var, Vec<VarId>, FxBuild>,
+    endpoints: HashMap<TermId, Vec<TermId>, FxBuild>,
+    /// Atoms asserted on the current branch.
+    assigned: HashSet<VarId, FxBuild>,
+    diseqs: Vec<Diseq>,
+    /// Facts asserted on the current branch that the tableau could not take (untracked
+    /// atoms, imports whose row overflows): the atom, or `None` and the open user scopes.
+    unrepresented: Vec<(Option<VarId>, usize)>,
+    /// The last final check gave up on numbers that do not fit the rationals.
+    pub(super) stuck: bool,
+    /// Antecedents of the atoms propagated on the current branch.
+    antecedents: HashMap<VarId, Antecedents, FxBuild>,
+    trail: Vec<Undo>,
+    levels: Vec<usize>,
+    /// Variables whose bounds tightened since the last `propagate`.
+    touched: Vec<Var>,
+    /// Literals of constant atoms, not yet propagated.
+    pending_const: Vec<Lit>,
+    /// Disequalities already split, with the atom each came from: one pair may have
+    /// several equality atoms.
+    split: HashSet<(TermId, TermId, Option<VarId>), FxBuild>,
+    /// Terms shared with other theories, in the order they became shared.
+    shared: Vec<TermId>,
+    /// Pairs of shared terms already split on `a = b`.
+    arranged: HashSet<(TermId, TermId), FxBuild>,
+    scope_undo: Vec<ScopeUndo>,
+    scope_marks: Vec<usize>,
+}
+
+impl<D: Domain> Default for ArithTheory<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: Domain> ArithTheory<D> {
+    pub fn new() -> Self {
+        Self {
+            domain: D::default(),
+            simplex: Simplex::default(),
+            term_var: HashMap::default(),
+            var_term: Vec::new(),
+            slacks: HashMap::default(),
+            slack_def: HashMap::default(),
+            atoms: HashMap::default(),
+            atoms_on: HashMap::default(),
+            endpoints: HashMap::default(),
+            assigned: HashSet::default(),
+            diseqs: Vec::new(),
+            unrepresented: Vec::new(),
+            stuck: false,
+            antecedents: HashMap::default(),
+            trail: Vec::new(),
+            levels: Vec::new(),
+            touched: Vec::new(),
+            pending_const: Vec::new(),
+            split: HashSet::default(),
+            shared: Vec::new(),
+            arranged: HashSet::default(),
+            scope_undo: Vec::new(),
+            scope_marks: Vec::new(),
+        }
+    }
+
+    fn var_of(&mut self, t: TermId) -> Var {
+        if let Some(&x) = self.term_var.get(&t) {
+            return x;
+        }
+        let x = self.simplex.new_var();
+        self.var_term.resize(x + 1, None);
+        self.var_term[x] = Some(t);
+        self.term_var.insert(t, x);
+        self.log(ScopeUndo::TermVar(t));
+        x
+    }
+
+    /// Variable standing for `comb` (over original variables, normalized).
+    fn slack_of(&mut self, comb: Combination) -> Result<Var, Overflow> {
+        if let [(x, c)] = comb[..] {
+            if c == Rational::ONE {
+                return Ok(x);
+            }
+        }
+        if let Some(&s) = self.slacks.get(&comb) {
+            return Ok(s);
+        }
+        let s = self.simplex.add_row(&comb)?;
+        self.var_term.resize(s + 1, None);
+        self.slacks.insert(comb.clone(), s);
+        self.slack_def.insert(s, comb.clone());
+        self.log(ScopeUndo::Slack(comb));
+        Ok(s)
+    }
+
+    fn log(&mut self, u: ScopeUndo) {
+        if !self.scope_marks.is_empty() {
+            self.scope_undo.push(u);
+        }
+    }
+
+    /// `e ⋈ 0` as a constraint, `⋈` being `<=` or (if `eq`) `=`.
+    fn normalize(&mut self, e: &LinExpr, eq: bool) -> Constraint {
+        if e.coeffs.is_empty() {
+            let c = e.constant;
+            return Constraint::Const(if eq { c.is_zero() } else { c.signum() <= 0 });
+        }
+        let mut comb: Combination = e.coeffs.iter().map(|&(t, c)| (self.var_of(t), c)).collect();
+        comb.sort_by_key(|&(x, _)| x);
+        // A positive leading coefficient lets `x - y <= k` and `y - x <= k` share a slack.
+        let g = D::content(&comb);
+        let g = if comb[0].1.signum() < 0 { -g } else { g };
+        for entry in &mut comb {
+            entry.1 = entry.1 / g;
+        }
+        let flip = g.signum() < 0;
+        let Some(k) = D::round(-e.constant / g, eq, flip) else {
+            return Constraint::Const(false);
+        };
+        let Ok(x) = self.slack_of(comb) else {
+            return Constraint::Untracked;
+        };
+        match (eq, flip) {
+            (true, _) => Constraint::Eq(x, k),
+            (false, false) => Constraint::Le(x, k),
+            (false, true) => Constraint::Ge(x, k),
+        }
+    }
+
+    /// Reasons kept by level-0 state must survive the level-0 reason region.
+    fn keep(&self, r: ReasonId, tcx: &mut TheoryCtx) -> ReasonId {
+        if self.levels.is_empty() { tcx.pin(r) } else { r }
+    }
+
+    fn bound(&mut self, x: Var, upper: bool, value: D::V, reason: ReasonId, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let b = Bound { value, reason };
+        let r = if upper { self.simplex.assert_upper(x, b) } else { self.simplex.assert_lower(x, b) };
+        match r {
+            Ok(true) => {
+                self.touched.push(x);
+                TheoryOutcome::Ok
+            }
+            Ok(false) => TheoryOutcome::Ok,
+            Err(inf) => TheoryOutcome::Conflict(tcx.r_farkas(inf)),
+        }
+    }
+
+    /// Bounds of `Σ def` implied by the bounds of its variables, if their sum fits.
+    fn implied(&self, def: &[(Var, Rational)], upper: bool) -> Option<(D::V, Infeasible)> {
+        let mut total = D::V::default();
+        let mut premises = Vec::new();
+        for &(x, a) in def {
+            let b = if (a.signum() > 0) == upper { self.simplex.upper(x) } else { self.simplex.lower(x) }?;
+            total = total.checked_add(b.value.checked_scale(a)?)?;
+            premises.push((a.abs(), b.reason));
+        }
+        Some((total, premises))
+    }
+
+    /// Truth value of atom `c` on `x` forced by `x ∈ [lo, hi]` (bounds the domain admits).
+    fn forced(c: Constraint, lo: Option<D::V>, hi: Option<D::V>) -> Option<bool> {
+        let lo_ge = |k: Rational| lo.is_some_and(|l| l >= D::exact(k));
+        let lo_gt = |k: Rational| lo.is_some_and(|l| l > D::exact(k));
+        let hi_le = |k: Rational| hi.is_some_and(|h| h <= D::exact(k));
+        let hi_lt = |k: Rational| hi.is_some_and(|h| h < D::exact(k));
+        match c {
+            Constraint::Le(_, k) if hi_le(k) => Some(true),
+            Constraint::Le(_, k) if lo_gt(k) => Some(false),
+            Constraint::Ge(_, k) if lo_ge(k) => Some(true),
+            Constraint::Ge(_, k) if hi_lt(k) => Some(false),
+            Constraint::Eq(_, k) if lo_ge(k) && hi_le(k) => Some(true),
+            Constraint::Eq(_, k) if lo_gt(k) || hi_lt(k) => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Atoms on `x` decided by its bounds, explained by the bounds themselves.
+    fn propagate_var(&self, x: Var, out: &mut Vec<(Lit, Antecedents)>, seen: &mut HashSet<VarId, FxBuild>) {
+        let (lo, hi) = (self.simplex.lower(x), self.simplex.upper(x));
+        for &v in self.atoms_on.get(&x).into_iter().flatten() {
+            if self.assigned.contains(&v) || seen.contains(&v) {
+                continue;
+            }
+            let c = self.atoms[&v];
+            let by_lo = lo.and_then(|l| Self::forced(c, Some(l.value), None).map(|val| (val, vec![l.reason])));
+            let by_hi = hi.and_then(|h| Self::forced(c, None, Some(h.value)).map(|val| (val, vec![h.reason])));
+            let both = || {
+                let (l, h) = (lo?, hi?);
+                Self::forced(c, Some(l.value), Some(h.value)).map(|val| (val, vec![l.reason, h.reason]))
+            };
+            let Some((val, used)) = by_lo.or(by_hi).or_else(both) else { continue };
+            seen.insert(v);
+            let lit = if val { Lit::pos(v) } else { Lit::neg(v) };
+            out.push((lit, Antecedents::Bounds(used)));
+        }
+    }
+
+    /// Atoms on slack `s` decided by the bounds of the variables of its definition.
+    fn propagate_slack(&self, s: Var, out: &mut Vec<(Lit, Antecedents)>, seen: &mut HashSet<VarId, FxBuild>) {
+        let def = &self.slack_def[&s];
+        let hi = self.implied(def, true);
+        let lo = self.implied(def, false);
+        for &v in self.atoms_on.get(&s).into_iter().flatten() {
+            if self.assigned.contains(&v) || seen.contains(&v) {
+                continue;
+            }
+            let lo_v = lo.as_ref().map(|(l, _)| D::tighten(*l, false));
+            let hi_v = hi.as_ref().map(|(h, _)| D::tighten(*h, true));
+            let c = self.atoms[&v];
+            let by_lo = Self::forced(c, lo_v, None).map(|val| (val, lo.clone().expect("lower").1));
+            let by_hi = Self::forced(c, None, hi_v).map(|val| (val, hi.clone().expect("upper").1));
+            let both = || {
+                let val = Self::forced(c, lo_v, hi_v)?;
+                Some((val, lo.clone()?.1.into_iter().chain(hi.clone()?.1).collect()))
+            };
+            let Some((val, premises)) = by_lo.or(by_hi).or_else(both) else { continue };
+            seen.insert(v);
+            let lit = if val { Lit::pos(v) } else { Lit::neg(v) };
+            out.push((lit, Antecedents::Farkas(premises)));
+        }
+    }
+
+    /// Value of the arithmetic term `t` in the current model, if all its variables are
+    /// known and it fits.
+    fn eval(&self, ctx: &Context, t: TermId) -> Option<Rational> {
+        let e = linearize(ctx, t);
+        e.coeffs.iter().try_fold(e.constant, |acc, &(x, c)| {
+            acc.checked_add(c.checked_mul(self.domain.concrete(self.simplex.value(*self.term_var.get(&x)?))?)?)
+        })
+    }
+
+    /// Note a fact the tableau could not take, for as long as it is asserted.
+    fn unrepresented(&mut self, atom: Option<VarId>) {
+        self.unrepresented.push((atom, self.scope_marks.len()));
+        if !self.levels.is_empty() {
+            self.trail.push(Undo::Unrepresented);
+        }
+    }
+
+    /// Record what `lit` was propagated from, for `explain`. The first record stands: it
+    /// is the one the SAT solver's assignment goes with.
+    fn record(&mut self, lit: Lit, ante: Antecedents) {
+        if self.antecedents.contains_key(&lit.var()) {
+            return;
+        }
+        self.antecedents.insert(lit.var(), ante);
+        // Level-0 records live as long as their atom; `pop_scopes` drops them.
+        if !self.levels.is_empty() {
+            self.trail.push(Undo::Antecedents(lit.var()));
+        }
+    }
+
+    fn push_diseq(&mut self, a: TermId, b: TermId, explain: ReasonId, atom: Option<VarId>) {
+        self.diseqs.push(Diseq { a, b, explain, atom, scope: self.scope_marks.len() });
+        // Level-0 disequalities outlive the search; `pop_scopes` filters them.
+        if !self.levels.is_empty() {
+            self.trail.push(Undo::Diseq);
+        }
+    }
+
+    /// Equalities among `terms` (sorted) that the bounds entail, each with the bounds'
+    /// reasons: terms fixed to the same value, and `x - y` fixed to 0.
+    fn entailed_eqs(&self, terms: &[TermId]) -> Vec<(TermId, TermId, Vec<ReasonId>)> {
+        let mut out = Vec::new();
+        let mut fixed = Vec::new();
+        for &t in terms {
+            let Some(&x) = self.term_var.get(&t) else { continue };
+            if let (Some(lo), Some(hi)) = (self.simplex.lower(x), self.simplex.upper(x)) {
+                if lo.value == hi.value {
+                    fixed.push((t, lo, hi));
+                }
+            }
+        }
+        fixed.sort_by(|p, q| p.1.value.cmp(&q.1.value).then(p.0.cmp(&q.0)));
+        for w in fixed.windows(2) {
+            let [(a, la, ha), (b, lb, hb)] = [w[0], w[1]];
+            if la.value == lb.value {
+                out.push((a, b, vec![la.reason, ha.reason, lb.reason, hb.reason]));
+            }
+        }
+        let zero = D::exact(Rational::ZERO);
+        let mut diffs: Vec<Var> = self.slack_def.keys().copied().collect();
+        diffs.sort_unstable();
+        for s in diffs {
+            let [(x, a), (y, b)] = self.slack_def[&s][..] else { continue };
+            if a != Rational::ONE || b != -Rational::ONE {
+                continue;
+            }
+            let (Some(tx), Some(ty)) = (self.var_term[x], self.var_term[y]) else { continue };
+            if terms.binary_search(&tx).is_err() || terms.binary_search(&ty).is_err() {
+                continue;
+            }
+            if let (Some(lo), Some(hi)) = (self.simplex.lower(s), self.simplex.upper(s)) {
+                if lo.value == zero && hi.value == zero {
+                    out.push((tx, ty, vec![lo.reason, hi.reason]));
+                }
+            }
+        }
+        out
+    }
+
+    /// Classes of `terms` (sorted) under `eqs`, each listing its members (the first one
+    /// the representative) with the reasons of the equalities linking them to it.
+    fn eq_classes(terms: &[TermId], eqs: &[(TermId, TermId, Vec<ReasonId>)]) -> Vec<Vec<(TermId, Vec<ReasonId>)>> {
+        let mut adj: HashMap<TermId, Vec<(TermId, usize)>, FxBuild> = HashMap::default();
+        for (i, (a, b, _)) in eqs.iter().enumerate() {
+            adj.entry(*a).or_default().push((*b, i));
+            adj.entry(*b).or_default().push((*a, i));
+        }
+        let mut seen: HashSet<TermId, FxBuild> = HashSet::default();
+        let mut classes = Vec::new();
+        for &t in terms {
+            if !seen.insert(t) {
+                continue;
+            }
+            let mut class = vec![(t, Vec::new())];
+            let mut head = 0;
+            while head < class.len() {
+                let (u, path) = class[head].clone();
+                head += 1;
+                for &(w, i) in adj.get(&u).into_iter().flatten() {
+                    if seen.insert(w) {
+                        class.push((w, path.iter().chain(&eqs[i].2).copied().collect()));
+                    }
+                }
+            }
+            classes.push(class);
+        }
+        classes
+    }
+
+    /// Split on `a = b` for the first two shared terms the model makes equal without the
+    /// bounds entailing it; `Ok` if there are none.
+    fn arrange_shared(&mut self, ctx: &mut Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let mut terms: Vec<TermId> = self.shared.iter().copied().filter(|t| self.term_var.contains_key(t)).collect();
+        terms.sort_unstable();
+        let eqs = self.entailed_eqs(&terms);
+        let reps: Option<Vec<(Rational, TermId)>> = Self::eq_classes(&terms, &eqs)
+            .into_iter()
+            .map(|class| {
+                let rep = class[0].0;
+                Some((self.domain.concrete(self.simplex.value(self.term_var[&rep]))?, rep))
+            })
+            .collect();
+        let Some(mut reps) = reps else {
+            self.stuck = true;
+            return TheoryOutcome::Ok;
+        };
+        reps.sort_unstable();
+        for w in reps.windows(2) {
+            let ((va, a), (vb, b)) = (w[0], w[1]);
+            if va != vb || !self.arranged.insert((a, b)) {
+                continue;
+            }
+            self.log(ScopeUndo::Arranged(a, b));
+            let eq = ctx.eq(a, b);
+            let ne = ctx.not(eq);
+            return TheoryOutcome::Lemma(TheoryLemma { because: tcx.r_and(Vec::new()), atoms: vec![eq, ne] });
+        }
+        TheoryOutcome::Ok
+    }
+
+    /// Whether every term has a value `model_value` can report.
+    fn reportable(&self) -> bool {
+        self.term_var.values().all(|&x| self.domain.concrete(self.simplex.value(x)).and_then(|v| self.domain.model_value(v)).is_some())
+    }
+
+    /// `a < b ∨ b < a`, given the disequality's reason.
+    fn split_diseq(&mut self, ctx: &mut Context, d: &Diseq) -> TheoryOutcome {
+        let (ge, le) = (ctx.le(d.b, d.a), ctx.le(d.a, d.b));
+        let (lt, gt) = (ctx.not(ge), ctx.not(le));
+        TheoryOutcome::Lemma(TheoryLemma { because: d.explain, atoms: vec![lt, gt] })
+    }
+}
+
+fn sides(ctx: &Context, t: TermId) -> Option<(TermId, TermId, bool)> {
+    match ctx.term_node(t).0 {
+        TermKind::Le(a, b) => Some((*a, *b, false)),
+        TermKind::Eq(a, b) => Some((*a, *b, true)),
+        _ => None,
+    }
+}
+
+impl<D: Domain> Theory for ArithTheory<D> {
+    fn name(&self) -> &'static str { D::NAME }
+
+    fn owns_atom(&self, ctx: &Context, atom_term: TermId) -> bool {
+        sides(ctx, atom_term).is_some_and(|(a, _, _)| ctx.term_sort(a) == D::sort(ctx))
+    }
+
+    fn atom_endpoints(&self, atom_term: TermId) -> Vec<TermId> {
+        self.endpoints.get(&atom_term).cloned().unwrap_or_default()
+    }
+
+    fn notify_shared(&mut self, t: TermId) {
+        if !self.shared.contains(&t) {
+            self.shared.push(t);
+            self.log(ScopeUndo::Shared);
+        }
+    }
+
+    fn register_atom(&mut self, ctx: &Context, atom: Atom) {
+        let (a, b, eq) = sides(ctx, atom.term).expect("arithmetic atom");
+        let e = difference(ctx, a, b);
+        let c = self.normalize(&e, eq);
+        let mut ends: Vec<TermId> = [a, b].iter().flat_map(|&side| linearize(ctx, side).coeffs).map(|(t, _)| t).collect();
+        ends.sort();
+        ends.dedup();
+        self.endpoints.insert(atom.term, ends);
+        match c {
+            Constraint::Const(v) => self.pending_const.push(if v { Lit::pos(atom.var) } else { Lit::neg(atom.var) }),
+            Constraint::Le(x, _) | Constraint::Ge(x, _) | Constraint::Eq(x, _) => {
+                self.atoms_on.entry(x).or_default().push(atom.var);
+            }
+            Constraint::Untracked => {}
+        }
+        self.atoms.insert(atom.var, c);
+        self.log(ScopeUndo::Atom(atom.var, atom.term));
+    }
+
+    fn assert_atom(&mut self, ctx: &Context, atom: Atom, value: bool, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let Some(&c) = self.atoms.get(&atom.var) else { return TheoryOutcome::Ok };
+        if !self.assigned.insert(atom.var) {
+            return TheoryOutcome::Ok;
+        }
+        self.trail.push(Undo::Assigned(atom.var));
+        let lit = if value { Lit::pos(atom.var) } else { Lit::neg(atom.var) };
+        let r = tcx.r_lit(lit);
+        let reason = self.keep(r, tcx);
+        match (c, value) {
+            (Constraint::Const(v), _) if v != value => TheoryOutcome::Conflict(reason),
+            (Constraint::Const(_), _) => TheoryOutcome::Ok,
+            (Constraint::Untracked, _) => {
+                self.unrepresented(Some(atom.var));
+                TheoryOutcome::Ok
+            }
+            (Constraint::Le(x, k), true) => self.bound(x, true, D::exact(k), reason, tcx),
+            (Constraint::Le(x, k), false) => self.bound(x, false, D::above(k), reason, tcx),
+            (Constraint::Ge(x, k), true) => self.bound(x, false, D::exact(k), reason, tcx),
+            (Constraint::Ge(x, k), false) => self.bound(x, true, D::below(k), reason, tcx),
+            (Constraint::Eq(x, k), true) => match self.bound(x, true, D::exact(k), reason, tcx) {
+                TheoryOutcome::Ok => self.bound(x, false, D::exact(k), reason, tcx),
+                conflict => conflict,
+            },
+            (Constraint::Eq(..), false) => {
+                let (a, b, _) = sides(ctx, atom.term).expect("arithmetic atom");
+                self.push_diseq(a, b, reason, Some(atom.var));
+                TheoryOutcome::Ok
+            }
+        }
+    }
+
+    fn propagate(&mut self, _ctx: &Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let mut props = Vec::new();
+        for lit in core::mem::take(&mut self.pending_const) {
+            if self.atoms.contains_key(&lit.var()) {
+                props.push(TheoryPropagation::new(lit, tcx.r_and(Vec::new())));
+            }
+        }
+        // An overflow is left to the final check.
+        if let Err(CheckFailure::Infeasible(inf)) = self.simplex.check() {
+            return TheoryOutcome::Conflict(tcx.r_farkas(inf));
+        }
+        if !self.touched.is_empty() {
+            let mut touched = core::mem::take(&mut self.touched);
+            touched.sort_unstable();
+            touched.dedup();
+            let mut seen = HashSet::default();
+            let mut found = Vec::new();
+            for &x in &touched {
+                self.propagate_var(x, &mut found, &mut seen);
+            }
+            let mut slacks: Vec<Var> = self.slack_def.keys().copied().filter(|s| self.atoms_on.contains_key(s)).collect();
+            slacks.sort_unstable();
+            for s in slacks {
+                if self.slack_def[&s].iter().any(|(x, _)| touched.binary_search(x).is_ok()) {
+                    self.propagate_slack(s, &mut found, &mut seen);
+                }
+            }
+            for (lit, ante) in found {
+                self.record(lit, ante);
+                props.push(TheoryPropagation::lazy(lit));
+            }
+        }
+        if props.is_empty() { TheoryOutcome::Ok } else { TheoryOutcome::Propagate(props) }
+    }
+
+    fn final_check(&mut self, ctx: &mut Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        self.stuck = false;
+        match self.simplex.check() {
+            Ok(()) => {}
+            Err(CheckFailure::Infeasible(inf)) => return TheoryOutcome::Conflict(tcx.r_farkas(inf)),
+            Err(CheckFailure::Overflow) => {
+                self.stuck = true;
+                return TheoryOutcome::Ok;
+            }
+        }
+        if D::settle(self).is_err() {
+            self.stuck = true;
+            return TheoryOutcome::Ok;
+        }
+        for d in self.diseqs.clone() {
+            let key = (d.a.min(d.b), d.a.max(d.b), d.atom);
+            if self.split.contains(&key) {
+                continue;
+            }
+            let (Some(va), Some(vb)) = (self.eval(ctx, d.a), self.eval(ctx, d.b)) else {
+                self.stuck = true;
+                continue;
+            };
+            if va != vb {
+                continue;
+            }
+            self.split.insert(key);
+            self.log(ScopeUndo::Split(key.0, key.1, key.2));
+            return self.split_diseq(ctx, &d);
+        }
+        let outcome = match D::final_check(self, ctx, tcx) {
+            TheoryOutcome::Ok => self.arrange_shared(ctx, tcx),
+            other => other,
+        };
+        // A value `model_value` cannot report (an Int beyond i64) leaves the answer open.
+        if outcome.is_ok() && !self.reportable() {
+            self.stuck = true;
+        }
+        outcome
+    }
+
+    fn model_value(&self, _ctx: &Context, t: TermId) -> Option<Value> {
+        let v = self.simplex.value(*self.term_var.get(&t)?);
+        self.domain.model_value(self.domain.concrete(v)?)
+    }
+
+    fn incomplete(&self) -> bool {
+        self.stuck || !self.unrepresented.is_empty()
+    }
+
+    fn explain(&mut self, _ctx: &Context, lit: Lit, tcx: &mut TheoryCtx) -> ReasonId {
+        match &self.antecedents[&lit.var()] {
+            Antecedents::Bounds(used) => tcx.r_and(used.clone()),
+            Antecedents::Farkas(premises) => tcx.r_farkas(premises.clone()),
+        }
+    }
+
+    fn push_level(&mut self) {
+        self.simplex.push_level();
+        self.levels.push(self.trail.len());
+    }
+
+    fn pop_levels(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.simplex.pop_levels(n);
+        let target = self.levels[self.levels.len() - n];
+        self.levels.truncate(self.levels.len() - n);
+        while self.trail.len() > target {
+            match self.trail.pop().expect("non-empty") {
+                Undo::Assigned(v) => {
+                    self.assigned.remove(&v);
+                }
+                Undo::Diseq => {
+                    self.diseqs.pop();
+                }
+                Undo::Antecedents(v) => {
+                    self.antecedents.remove(&v);
+                }
+                Undo::Unrepresented => {
+                    self.unrepresented.pop();
+                }
+            }
+        }
+        self.touched.clear();
+    }
+
+    fn push_scope(&mut self) {
+        self.scope_marks.push(self.scope_undo.len());
+        self.domain.push_scope();
+    }
+
+    fn pop_scopes(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.domain.pop_scopes(n);
+        let target = self.scope_marks[self.scope_marks.len() - n];
+        self.scope_marks.truncate(self.scope_marks.len() - n);
+        // Variables stay in the tableau, unreachable: their rows remain valid identities.
+        while self.scope_undo.len() > target {
+            match self.scope_undo.pop().expect("non-empty") {
+                ScopeUndo::TermVar(t) => {
+                    if let Some(x) = self.term_var.remove(&t) {
+                        self.var_term[x] = None;
+                    }
+                }
+                ScopeUndo::Slack(comb) => {
+                    if let Some(s) = self.slacks.remove(&comb) {
+                        self.slack_def.remove(&s);
+                    }
+                }
+                ScopeUndo::Atom(v, t) => {
+                    self.atoms.remove(&v);
+                    self.assigned.remove(&v);
+                    self.antecedents.remove(&v);
+                    self.endpoints.remove(&t);
+                    for list in self.atoms_on.values_mut() {
+                        list.retain(|&w| w != v);
+                    }
+                }
+                ScopeUndo::Split(a, b, atom) => {
+                    self.split.remove(&(a, b, atom));
+                }
+                ScopeUndo::Shared => {
+                    self.shared.pop();
+                }
+                ScopeUndo::Arranged(a, b) => {
+                    self.arranged.remove(&(a, b));
+                }
+            }
+        }
+        // A level-0 disequality stays as long as its atom (whose level-0 value the SAT
+        // solver keeps) does; an imported one goes with the scope it was asserted in.
+        let depth = self.scope_marks.len();
+        let atoms = &self.atoms;
+        self.diseqs.retain(|d| match d.atom {
+            Some(v) => atoms.contains_key(&v),
+            None => d.scope <= depth,
+        });
+        self.unrepresented.retain(|&(atom, scope)| match atom {
+            Some(v) => atoms.contains_key(&v),
+            None => scope <= depth,
+        });
+        self.atoms_on.retain(|_, list| !list.is_empty());
+        self.pending_const.retain(|l| self.atoms.contains_key(&l.var()));
+    }
+
+    fn equality_sharing_mut(&mut self) -> Option<&mut dyn EqualitySharing> { Some(self) }
+}
+
+impl<D: Domain> EqualitySharing for ArithTheory<D> {
+    /// Classes of shared terms under the equalities their bounds entail.
+    fn export_classes(&mut self, oracle: &SharedTermOracle, _export_epoch: u64, tcx: &mut TheoryCtx) -> Vec<EqClass> {
+        let mut terms: Vec<TermId> = oracle.shared_set().iter().copied().collect();
+        terms.sort_unstable();
+        let eqs = self.entailed_eqs(&terms);
+        Self::eq_classes(&terms, &eqs)
+            .into_iter()
+            .filter(|class| class.len() > 1)
+            .map(|class| {
+                let rep = class[0].0;
+                let members = class[1..].iter().map(|(t, path)| (*t, tcx.r_and(path.clone()))).collect();
+                EqClass { rep, members }
+            })
+            .collect()
+    }
+
+    fn import_equality(&mut self, eq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let (Some(&x), Some(&y)) = (self.term_var.get(&eq.a), self.term_var.get(&eq.b)) else {
+            return TheoryOutcome::Ok;
+        };
+        if x == y {
+            return TheoryOutcome::Ok;
+        }
+        let (lo, hi) = (x.min(y), x.max(y));
+        let Ok(s) = self.slack_of(vec![(lo, Rational::ONE), (hi, -Rational::ONE)]) else {
+            self.unrepresented(None);
+            return TheoryOutcome::Ok;
+        };
+        let reason = self.keep(eq.explain, tcx);
+        let zero = D::exact(Rational::ZERO);
+        match self.bound(s, true, zero, reason, tcx) {
+            TheoryOutcome::Ok => self.bound(s, false, zero, reason, tcx),
+            conflict => conflict,
+        }
+    }
+
+    fn import_disequality(&mut self, diseq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let explain = self.keep(diseq.explain, tcx);
+        self.push_diseq(diseq.a, diseq.b, explain, None);
+        TheoryOutcome::Ok
+    }
+}
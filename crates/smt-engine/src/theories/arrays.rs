@@ -0,0 +1,486 @@
+#![forbid(unsafe_code)]
+//! Arrays: `select`/`store` with extensionality, by lemmas on demand.
+//!
+//! The theory owns equalities between arrays and between elements of uninterpreted
+//! sorts, and keeps a congruence closure over every `select`/`store` term of the context
+//! and the terms its atoms mention. The array axioms are not asserted up front; a final
+//! check instantiates one that the current classes need, as a `TheoryOutcome::Lemma`:
+//!
+//! - read over write, hit: `select(store(a, i, v), i) = v`, once per store;
+//! - read over write, miss: `i = j ∨ select(store(a, i, v), j) = select(a, j)` for a
+//!   read `select(b, j)` with `b` in the class of the store or of `a`;
+//! - extensionality: `a = b ∨ select(a, k) != select(b, k)` for an asserted `a != b`,
+//!   with `k` a fresh constant;
+//! - index splits: `i = j ∨ i != j` for two reads of one class whose indices the closure
+//!   cannot tell apart, so that congruence can apply.
+//!
+//! Index and element terms owned by other theories (arithmetic) reach the closure
+//! through `foreign_endpoints` and equality sharing: equalities over them are interface
+//! equalities, and classes of shared terms are exported back.
+
+use hashbrown::{HashMap, HashSet};
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{Context, SortKind, TermId, TermKind, Value};
+use smt_sat::{Lit, VarId};
+
+use crate::atoms::Atom;
+use crate::reason::ReasonId;
+use crate::shared_terms::SharedTermOracle;
+use crate::theories::congruence::{CongruenceClosure, Node};
+use crate::theory::{EqClass, EqualitySharing, SharedEq, Theory, TheoryLemma, TheoryOutcome};
+use crate::theory_ctx::TheoryCtx;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+const SELECT: u32 = 0;
+const STORE: u32 = 1;
+
+/// A lemma already instantiated, by the terms it was made for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Instance {
+    Extensionality(TermId),
+    Hit(TermId),
+    Miss(TermId, TermId),
+    Split(TermId, TermId),
+}
+
+/// `a = b` or `a != b`, with its reason and the owned atom it came from.
+type Pending = (TermId, TermId, bool, ReasonId, Option<VarId>);
+
+/// An (in)equality asserted at level 0, replayed when the closure is rebuilt.
+struct Fact {
+    a: TermId,
+    b: TermId,
+    eq: bool,
+    reason: ReasonId,
+    /// The owned atom it came from; `None` for imported facts.
+    atom: Option<VarId>,
+    scope: usize,
+}
+
+#[derive(Default)]
+struct Scope {
+    atoms: Vec<(VarId, TermId)>,
+    foreign: Vec<TermId>,
+    instances: Vec<Instance>,
+}
+
+#[derive(Default)]
+pub struct ArraysTheory {
+    cc: CongruenceClosure,
+    /// Owned atoms: term, sides, and whether the sides are arrays.
+    atoms: HashMap<VarId, (TermId, TermId, TermId, bool), FxBuild>,
+    /// Terms mentioned by each atom, owned or foreign.
+    mentions: HashMap<TermId, Vec<TermId>, FxBuild>,
+    /// Context terms already scanned for `select`/`store`.
+    scanned: usize,
+    /// Asserted array disequalities, as `(a, b, atom term)`.
+    diseqs: Vec<(TermId, TermId, TermId)>,
+    /// Facts over terms the closure has no node for yet, applied on the next `sync`.
+    deferred: Vec<Pending>,
+    /// `diseqs` and `deferred` lengths at each open decision level.
+    levels: Vec<(usize, usize)>,
+    facts: Vec<Fact>,
+    instances: HashSet<Instance, FxBuild>,
+    scopes: Vec<Scope>,
+    /// A scope was popped: rebuild the closure before using it.
+    dirty: bool,
+    skolems: usize,
+}
+
+impl ArraysTheory {
+    pub fn new() -> Self { Self::default() }
+
+    fn keep(&self, r: ReasonId, tcx: &mut TheoryCtx) -> ReasonId {
+        if self.levels.is_empty() { tcx.pin(r) } else { r }
+    }
+
+    fn add_term(&mut self, ctx: &Context, t: TermId) -> Node {
+        if let Some(n) = self.cc.node(t) {
+            return n;
+        }
+        match *ctx.term_node(t).0 {
+            TermKind::Select(a, i) => {
+                let args = vec![self.add_term(ctx, a), self.add_term(ctx, i)];
+                self.cc.add_app(t, SELECT, args)
+            }
+            TermKind::Store(a, i, v) => {
+                let args = vec![self.add_term(ctx, a), self.add_term(ctx, i), self.add_term(ctx, v)];
+                self.cc.add_app(t, STORE, args)
+            }
+            TermKind::IntConst(k) => self.cc.add_leaf(t, Some(Value::Int(k))),
+            TermKind::RatConst(k) => self.cc.add_leaf(t, Some(Value::Real(k))),
+            TermKind::BvConst(v) => {
+                let width = ctx.bv_width(ctx.term_sort(t)).expect("bit-vector sort");
+                self.cc.add_leaf(t, Some(Value::BitVec(width, v)))
+            }
+            _ => self.cc.add_leaf(t, None),
+        }
+    }
+
+    /// Bring the closure up to date with the context, rebuilding it after a pop.
+    fn sync(&mut self, ctx: &Context, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        if self.dirty {
+            self.dirty = false;
+            self.cc = CongruenceClosure::new();
+            self.scanned = 0;
+            self.diseqs.clear();
+            let mut terms: Vec<TermId> = self.mentions.values().flatten().copied().collect();
+            terms.sort();
+            for t in terms {
+                self.add_term(ctx, t);
+            }
+            self.scan(ctx);
+            // Replayed facts are permanent; facts deferred since are reapplied at the
+            // decision level they were asserted at.
+            let marks: Vec<usize> = self.levels.iter().map(|&(_, deferred)| deferred).collect();
+            let mut deferred = core::mem::take(&mut self.deferred);
+            self.levels.clear();
+            let mut replayed: Vec<Pending> = self.facts.iter().map(|f| (f.a, f.b, f.eq, f.reason, f.atom)).collect();
+            let mut chunks = Vec::new();
+            for &mark in marks.iter().rev() {
+                chunks.push(deferred.split_off(mark));
+            }
+            replayed.append(&mut deferred);
+            let mut result = self.apply_all(ctx, replayed, tcx);
+            for chunk in chunks.into_iter().rev() {
+                self.push_level();
+                result = result.and_then(|()| self.apply_all(ctx, chunk, tcx));
+            }
+            result?;
+        }
+        self.scan(ctx);
+        let deferred = core::mem::take(&mut self.deferred);
+        self.apply_all(ctx, deferred, tcx)?;
+        self.cc.close(tcx)
+    }
+
+    fn apply_all(&mut self, ctx: &Context, facts: Vec<Pending>, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        for (a, b, eq, r, atom) in facts {
+            self.add_term(ctx, a);
+            self.add_term(ctx, b);
+            self.apply(a, b, eq, r, atom, tcx)?;
+        }
+        Ok(())
+    }
+
+    fn scan(&mut self, ctx: &Context) {
+        for k in self.scanned..ctx.num_terms() {
+            let t = TermId(k as u32);
+            if matches!(ctx.term_node(t).0, TermKind::Select(..) | TermKind::Store(..)) && ctx.is_ground(t) {
+                self.add_term(ctx, t);
+            }
+        }
+        self.scanned = ctx.num_terms();
+    }
+
+    /// Assert `a = b` or `a != b` (from `atom`, if owned).
+    fn apply(&mut self, a: TermId, b: TermId, eq: bool, r: ReasonId, atom: Option<VarId>, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        let (Some(na), Some(nb)) = (self.cc.node(a), self.cc.node(b)) else {
+            self.deferred.push((a, b, eq, r, atom));
+            return Ok(());
+        };
+        if eq {
+            return self.cc.assert_eq(na, nb, r, tcx);
+        }
+        self.cc.assert_diseq(na, nb, r, tcx)?;
+        if let Some(&(t, _, _, true)) = atom.and_then(|v| self.atoms.get(&v)) {
+            self.diseqs.push((a, b, t));
+        }
+        Ok(())
+    }
+
+    /// `eq` or `not eq`, recording the fact if at level 0.
+    fn assert_fact(&mut self, a: TermId, b: TermId, eq: bool, r: ReasonId, atom: Option<VarId>, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let reason = self.keep(r, tcx);
+        if self.levels.is_empty() {
+            self.facts.push(Fact { a, b, eq, reason, atom, scope: self.scopes.len() });
+        }
+        if self.dirty {
+            self.deferred.push((a, b, eq, reason, atom));
+            return TheoryOutcome::Ok;
+        }
+        match self.apply(a, b, eq, reason, atom, tcx) {
+            Ok(()) => TheoryOutcome::Ok,
+            Err(conflict) => TheoryOutcome::Conflict(conflict),
+        }
+    }
+
+    fn instantiate(&mut self, key: Instance, because: ReasonId, atoms: Vec<TermId>) -> Option<TheoryOutcome> {
+        if !self.instances.insert(key) {
+            return None;
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.instances.push(key);
+        }
+        Some(TheoryOutcome::Lemma(TheoryLemma { because, atoms }))
+    }
+
+    /// The first array axiom instance the current classes need.
+    fn next_lemma(&mut self, ctx: &mut Context, tcx: &mut TheoryCtx) -> Option<TheoryOutcome> {
+        for (a, b, atom) in self.diseqs.clone() {
+            if self.instances.contains(&Instance::Extensionality(atom)) {
+                continue;
+            }
+            let (index, _) = ctx.array_parts(ctx.term_sort(a)).expect("array sort");
+            let k = ctx.const_term(format!("arr!k{}", self.skolems), index);
+            self.skolems += 1;
+            let (sa, sb) = (ctx.select(a, k), ctx.select(b, k));
+            let eq = ctx.eq(sa, sb);
+            let ne = ctx.not(eq);
+            let because = tcx.r_and(Vec::new());
+            return self.instantiate(Instance::Extensionality(atom), because, vec![atom, ne]);
+        }
+
+        let mut stores = Vec::new();
+        let mut selects = Vec::new();
+        for n in 0..self.cc.num_nodes() {
+            match self.cc.app(n) {
+                Some((STORE, args)) => stores.push((n, args[0], args[1])),
+                Some((SELECT, args)) => selects.push((n, args[0], args[1])),
+                _ => {}
+            }
+        }
+
+        for &(s, _, i) in &stores {
+            let st = self.cc.term(s);
+            if self.instances.contains(&Instance::Hit(st)) {
+                continue;
+            }
+            let TermKind::Store(_, _, v) = *ctx.term_node(st).0 else { unreachable!("store node") };
+            let read = ctx.select(st, self.cc.term(i));
+            let eq = ctx.eq(read, v);
+            let because = tcx.r_and(Vec::new());
+            return self.instantiate(Instance::Hit(st), because, vec![eq]);
+        }
+
+        for &(s, a, i) in &stores {
+            for &(_, b, j) in &selects {
+                let (st, jt) = (self.cc.term(s), self.cc.term(j));
+                let related = self.cc.are_equal(b, s) || self.cc.are_equal(b, a);
+                if !related || self.cc.are_equal(i, j) || self.instances.contains(&Instance::Miss(st, jt)) {
+                    continue;
+                }
+                let same = ctx.eq(self.cc.term(i), jt);
+                let (outer, inner) = (ctx.select(st, jt), ctx.select(self.cc.term(a), jt));
+                let eq = ctx.eq(outer, inner);
+                let because = tcx.r_and(Vec::new());
+                return self.instantiate(Instance::Miss(st, jt), because, vec![same, eq]);
+            }
+        }
+
+        for (k, &(_, a, i)) in selects.iter().enumerate() {
+            for &(_, b, j) in &selects[k + 1..] {
+                if !self.cc.are_equal(a, b) || self.cc.are_equal(i, j) || self.cc.known_disequal(i, j) {
+                    continue;
+                }
+                let (it, jt) = (self.cc.term(i), self.cc.term(j));
+                let key = Instance::Split(it.min(jt), it.max(jt));
+                if self.instances.contains(&key) {
+                    continue;
+                }
+                let eq = ctx.eq(it, jt);
+                let ne = ctx.not(eq);
+                let because = tcx.r_and(Vec::new());
+                return self.instantiate(key, because, vec![eq, ne]);
+            }
+        }
+        None
+    }
+}
+
+/// `t` and, through `select`/`store`, its arguments.
+fn mentioned(ctx: &Context, t: TermId, out: &mut Vec<TermId>) {
+    out.push(t);
+    match ctx.term_node(t).0 {
+        TermKind::Select(a, i) => {
+            mentioned(ctx, *a, out);
+            mentioned(ctx, *i, out);
+        }
+        TermKind::Store(a, i, v) => {
+            mentioned(ctx, *a, out);
+            mentioned(ctx, *i, out);
+            mentioned(ctx, *v, out);
+        }
+        _ => {}
+    }
+}
+
+/// Array terms (and what they mention) under the structure of another theory's atom.
+fn foreign_mentions(ctx: &Context, t: TermId, out: &mut Vec<TermId>) {
+    match ctx.term_node(t).0 {
+        TermKind::Select(..) | TermKind::Store(..) => mentioned(ctx, t, out),
+        TermKind::Eq(a, b) | TermKind::Le(a, b) | TermKind::Sub(a, b) | TermKind::Mul(a, b) => {
+            foreign_mentions(ctx, *a, out);
+            foreign_mentions(ctx, *b, out);
+        }
+        TermKind::Add(xs) | TermKind::Bv(_, xs) | TermKind::App { args: xs, .. } | TermKind::Construct(_, xs) => {
+            for &x in xs {
+                foreign_mentions(ctx, x, out);
+            }
+        }
+        TermKind::Selector(_, _, a) => foreign_mentions(ctx, *a, out),
+        _ => {}
+    }
+}
+
+fn is_array_or_uninterpreted(ctx: &Context, t: TermId) -> bool {
+    matches!(ctx.sort_kind(ctx.term_sort(t)), SortKind::Array(..) | SortKind::Uninterpreted(_))
+}
+
+impl Theory for ArraysTheory {
+    fn name(&self) -> &'static str { "Arrays" }
+
+    fn owns_atom(&self, ctx: &Context, atom_term: TermId) -> bool {
+        matches!(*ctx.term_node(atom_term).0, TermKind::Eq(a, _) if is_array_or_uninterpreted(ctx, a))
+    }
+
+    fn atom_endpoints(&self, atom_term: TermId) -> Vec<TermId> {
+        self.mentions.get(&atom_term).cloned().unwrap_or_default()
+    }
+
+    fn foreign_endpoints(&self, atom_term: TermId) -> Vec<TermId> {
+        self.mentions.get(&atom_term).cloned().unwrap_or_default()
+    }
+
+    fn register_atom(&mut self, ctx: &Context, atom: Atom) {
+        let TermKind::Eq(a, b) = *ctx.term_node(atom.term).0 else { panic!("arrays atom is not an equality") };
+        let array = ctx.array_parts(ctx.term_sort(a)).is_some();
+        self.atoms.insert(atom.var, (atom.term, a, b, array));
+        let mut ends = Vec::new();
+        mentioned(ctx, a, &mut ends);
+        mentioned(ctx, b, &mut ends);
+        ends.sort();
+        ends.dedup();
+        if !self.dirty {
+            for &t in &ends {
+                self.add_term(ctx, t);
+            }
+        }
+        self.mentions.insert(atom.term, ends);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.atoms.push((atom.var, atom.term));
+        }
+    }
+
+    fn register_foreign_atom(&mut self, ctx: &Context, atom: Atom) {
+        let mut ends = Vec::new();
+        foreign_mentions(ctx, atom.term, &mut ends);
+        // An equality over terms the closure knows is one it must hear about.
+        if let TermKind::Eq(a, b) = *ctx.term_node(atom.term).0 {
+            if !ends.is_empty() || self.cc.node(a).is_some() || self.cc.node(b).is_some() {
+                ends.extend([a, b]);
+            }
+        }
+        if ends.is_empty() {
+            return;
+        }
+        ends.sort();
+        ends.dedup();
+        if !self.dirty {
+            for &t in &ends {
+                self.add_term(ctx, t);
+            }
+        }
+        self.mentions.insert(atom.term, ends);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.foreign.push(atom.term);
+        }
+    }
+
+    fn assert_atom(&mut self, ctx: &Context, atom: Atom, value: bool, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        if let Err(conflict) = self.sync(ctx, tcx) {
+            return TheoryOutcome::Conflict(conflict);
+        }
+        let Some(&(_, a, b, _)) = self.atoms.get(&atom.var) else { return TheoryOutcome::Ok };
+        let lit = if value { Lit::pos(atom.var) } else { Lit::neg(atom.var) };
+        let r = tcx.r_lit(lit);
+        self.assert_fact(a, b, value, r, Some(atom.var), tcx)
+    }
+
+    fn propagate(&mut self, ctx: &Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        match self.sync(ctx, tcx) {
+            Ok(()) => TheoryOutcome::Ok,
+            Err(conflict) => TheoryOutcome::Conflict(conflict),
+        }
+    }
+
+    fn final_check(&mut self, ctx: &mut Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        if let Err(conflict) = self.sync(ctx, tcx) {
+            return TheoryOutcome::Conflict(conflict);
+        }
+        self.next_lemma(ctx, tcx).unwrap_or(TheoryOutcome::Ok)
+    }
+
+    /// The class of `t`; the model builder turns array classes into values.
+    fn model_value(&self, ctx: &Context, t: TermId) -> Option<Value> {
+        let n = self.cc.node(t)?;
+        Some(Value::Elem(ctx.term_sort(t), self.cc.find(n) as u32))
+    }
+
+    fn push_level(&mut self) {
+        self.cc.push_level();
+        self.levels.push((self.diseqs.len(), self.deferred.len()));
+    }
+
+    fn pop_levels(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.cc.pop_levels(n);
+        let (diseqs, deferred) = self.levels[self.levels.len() - n];
+        self.levels.truncate(self.levels.len() - n);
+        self.diseqs.truncate(diseqs);
+        self.deferred.truncate(deferred);
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn pop_scopes(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        for scope in self.scopes.drain(self.scopes.len() - n..) {
+            for (v, t) in scope.atoms {
+                self.atoms.remove(&v);
+                self.mentions.remove(&t);
+            }
+            for t in scope.foreign {
+                self.mentions.remove(&t);
+            }
+            for key in scope.instances {
+                self.instances.remove(&key);
+            }
+        }
+        let (atoms, depth) = (&self.atoms, self.scopes.len());
+        self.facts.retain(|f| match f.atom {
+            Some(v) => atoms.contains_key(&v),
+            None => f.scope <= depth,
+        });
+        self.dirty = true;
+    }
+
+    fn equality_sharing_mut(&mut self) -> Option<&mut dyn EqualitySharing> { Some(self) }
+}
+
+impl EqualitySharing for ArraysTheory {
+    /// Classes of the closure among shared terms.
+    fn export_classes(&mut self, oracle: &SharedTermOracle, _export_epoch: u64, tcx: &mut TheoryCtx) -> Vec<EqClass> {
+        if self.dirty {
+            return Vec::new();
+        }
+        self.cc.classes(oracle.shared_set().iter().copied(), tcx)
+    }
+
+    fn import_equality(&mut self, eq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        self.assert_fact(eq.a, eq.b, true, eq.explain, None, tcx)
+    }
+
+    fn import_disequality(&mut self, diseq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        self.assert_fact(diseq.a, diseq.b, false, diseq.explain, None, tcx)
+    }
+}
@@ -0,0 +1,785 @@
+#![forbid(unsafe_code)]
+//! Bit-vectors by bit-blasting.
+//!
+//! The theory owns equalities between bit-vectors and the bit-vector predicates. A final
+//! check blasts the atoms registered since the last one: each term is first rewritten
+//! at the word level (constant folding, neutral and absorbing operands, `extract` over
+//! `concat`), then turned into one Boolean per bit, least significant first. Bits are
+//! constants where folding decides them and fresh Boolean constants (`bv!b…`, owned by
+//! the theory) otherwise, defined by Tseitin clauses returned as `TheoryOutcome::Lemmas`.
+//! From then on the SAT kernel does the reasoning; the theory only reads the bits back.
+//!
+//! Circuits: ripple-carry adders, shift-and-add multipliers, barrel shifters, borrow
+//! chains for comparisons. `bvudiv`/`bvurem` get fresh quotient and remainder bits tied
+//! by `q * b + r = a`, `r < b` (computed at double width) when `b != 0`; the signed
+//! divisions go through absolute values, as SMT-LIB defines them.
+//!
+//! Equalities with other theories: every bit-vector term an atom mentions is blasted,
+//! including indices and elements under `select`/`store` in other theories' atoms, so
+//! the theory decides the values of all of them (other theories may not know the sorts
+//! are finite). Shared terms whose bits are all assigned are exported by value;
+//! imported equalities propagate bits across their sides.
+
+use hashbrown::{HashMap, HashSet};
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+use core::ops::Not;
+
+use smt_core::bv::mask;
+use smt_core::{BvOp, Context, TermId, TermKind, Value};
+use smt_sat::{Lit, VarId};
+
+use crate::atoms::Atom;
+use crate::reason::ReasonId;
+use crate::shared_terms::SharedTermOracle;
+use crate::theory::{EqClass, EqualitySharing, SharedEq, Theory, TheoryLemma, TheoryOutcome, TheoryPropagation};
+use crate::theory_ctx::TheoryCtx;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+/// A bit of a blasted term: a constant, or a Boolean term (negated if `false`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Bit {
+    Const(bool),
+    Var(TermId, bool),
+}
+
+impl Not for Bit {
+    type Output = Bit;
+
+    fn not(self) -> Bit {
+        match self {
+            Bit::Const(b) => Bit::Const(!b),
+            Bit::Var(t, pos) => Bit::Var(t, !pos),
+        }
+    }
+}
+
+/// Something created within a user scope, forgotten when it is popped.
+#[derive(Debug, Clone, Copy)]
+enum Undo {
+    Bits(TermId),
+    Rewrite(TermId),
+    Neg(TermId),
+    Bit(TermId),
+    Div(TermId, TermId, bool),
+    Atom(TermId),
+    Blasted(TermId),
+}
+
+/// Quotient and remainder bits.
+type DivRem = (Vec<Bit>, Vec<Bit>);
+
+/// `a = b` from another theory, with its reason and the scope depth it came at.
+type Import = (TermId, TermId, ReasonId, usize);
+
+#[derive(Default)]
+pub struct BitVecTheory {
+    /// Bits of blasted terms.
+    bits: HashMap<TermId, Vec<Bit>, FxBuild>,
+    /// Word-level rewrites.
+    rewrites: HashMap<TermId, TermId, FxBuild>,
+    /// Negations of bits, built once for lemmas.
+    negs: HashMap<TermId, TermId, FxBuild>,
+    /// Quotient and remainder bits by (dividend, divisor, signed).
+    divs: HashMap<(TermId, TermId, bool), DivRem, FxBuild>,
+    /// Bit constants created by the theory.
+    bit_terms: HashSet<TermId, FxBuild>,
+    /// SAT variables of owned atoms and bits.
+    vars: HashMap<TermId, VarId, FxBuild>,
+    /// Values of assigned bits.
+    values: HashMap<TermId, bool, FxBuild>,
+    /// Bit-vector terms mentioned by each atom, and whether the atom is owned.
+    endpoints: HashMap<TermId, (Vec<TermId>, bool), FxBuild>,
+    /// Atoms (owned or foreign) registered but not blasted yet.
+    unblasted: Vec<TermId>,
+    /// Clauses of the blasting in progress.
+    clauses: Vec<Vec<Bit>>,
+    imports: Vec<Import>,
+    /// Bits assigned above level 0, for undo.
+    trail: Vec<TermId>,
+    /// `trail` and `imports` lengths at each open decision level.
+    levels: Vec<(usize, usize)>,
+    log: Vec<Undo>,
+    /// `log` length at each open user scope.
+    scopes: Vec<usize>,
+    fresh: usize,
+}
+
+impl BitVecTheory {
+    pub fn new() -> Self { Self::default() }
+
+    fn keep(&self, r: ReasonId, tcx: &mut TheoryCtx) -> ReasonId {
+        if self.levels.is_empty() { tcx.pin(r) } else { r }
+    }
+
+    fn record(&mut self, u: Undo) {
+        if !self.scopes.is_empty() {
+            self.log.push(u);
+        }
+    }
+
+    fn fresh(&mut self, ctx: &mut Context) -> Bit {
+        let b = ctx.bool_sort();
+        let t = ctx.const_term(format!("bv!b{}", self.fresh), b);
+        self.fresh += 1;
+        self.bit_terms.insert(t);
+        self.record(Undo::Bit(t));
+        Bit::Var(t, true)
+    }
+
+    /// Fresh bits for a term the theory does not interpret. The tautologies register
+    /// every bit with the engine, used in a circuit or not.
+    fn fresh_word(&mut self, ctx: &mut Context, width: u32) -> Vec<Bit> {
+        let bits: Vec<Bit> = (0..width).map(|_| self.fresh(ctx)).collect();
+        for &b in &bits {
+            self.clauses.push(vec![b, !b]);
+        }
+        bits
+    }
+
+    fn and(&mut self, ctx: &mut Context, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(false), _) | (_, Bit::Const(false)) => Bit::Const(false),
+            (Bit::Const(true), x) | (x, Bit::Const(true)) => x,
+            _ if a == b => a,
+            _ if a == !b => Bit::Const(false),
+            _ => {
+                let o = self.fresh(ctx);
+                self.clauses.extend([vec![!o, a], vec![!o, b], vec![o, !a, !b]]);
+                o
+            }
+        }
+    }
+
+    fn or(&mut self, ctx: &mut Context, a: Bit, b: Bit) -> Bit {
+        !self.and(ctx, !a, !b)
+    }
+
+    fn xor(&mut self, ctx: &mut Context, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(c), x) | (x, Bit::Const(c)) => if c { !x } else { x },
+            _ if a == b => Bit::Const(false),
+            _ if a == !b => Bit::Const(true),
+            _ => {
+                let o = self.fresh(ctx);
+                self.clauses.extend([vec![!o, a, b], vec![!o, !a, !b], vec![o, !a, b], vec![o, a, !b]]);
+                o
+            }
+        }
+    }
+
+    /// `c ? t : e`.
+    fn mux(&mut self, ctx: &mut Context, c: Bit, t: Bit, e: Bit) -> Bit {
+        match c {
+            Bit::Const(true) => return t,
+            Bit::Const(false) => return e,
+            _ => {}
+        }
+        if t == e {
+            return t;
+        }
+        if let (Bit::Const(t), Bit::Const(_)) = (t, e) {
+            return if t { c } else { !c };
+        }
+        let o = self.fresh(ctx);
+        self.clauses.extend([vec![!c, !t, o], vec![!c, t, !o], vec![c, !e, o], vec![c, e, !o]]);
+        o
+    }
+
+    fn all(&mut self, ctx: &mut Context, xs: &[Bit]) -> Bit {
+        xs.iter().fold(Bit::Const(true), |acc, &x| self.and(ctx, acc, x))
+    }
+
+    fn any(&mut self, ctx: &mut Context, xs: &[Bit]) -> Bit {
+        let negated: Vec<Bit> = xs.iter().map(|&x| !x).collect();
+        !self.all(ctx, &negated)
+    }
+
+    fn mux_word(&mut self, ctx: &mut Context, c: Bit, t: &[Bit], e: &[Bit]) -> Vec<Bit> {
+        t.iter().zip(e).map(|(&t, &e)| self.mux(ctx, c, t, e)).collect()
+    }
+
+    /// `a + b + carry`, truncated.
+    fn add(&mut self, ctx: &mut Context, a: &[Bit], b: &[Bit], mut carry: Bit) -> Vec<Bit> {
+        let mut out = Vec::with_capacity(a.len());
+        for (&x, &y) in a.iter().zip(b) {
+            let t = self.xor(ctx, x, y);
+            out.push(self.xor(ctx, t, carry));
+            let (g, p) = (self.and(ctx, x, y), self.and(ctx, t, carry));
+            carry = self.or(ctx, g, p);
+        }
+        out
+    }
+
+    fn neg(&mut self, ctx: &mut Context, a: &[Bit]) -> Vec<Bit> {
+        let inverted: Vec<Bit> = a.iter().map(|&x| !x).collect();
+        let zero = vec![Bit::Const(false); a.len()];
+        self.add(ctx, &inverted, &zero, Bit::Const(true))
+    }
+
+    fn mul(&mut self, ctx: &mut Context, a: &[Bit], b: &[Bit]) -> Vec<Bit> {
+        let w = a.len();
+        let mut acc = vec![Bit::Const(false); w];
+        for i in 0..w {
+            if b[i] == Bit::Const(false) {
+                continue;
+            }
+            // Bits below `i` are not affected by this row.
+            let row: Vec<Bit> = (i..w).map(|j| self.and(ctx, b[i], a[j - i])).collect();
+            let sum = self.add(ctx, &acc[i..], &row, Bit::Const(false));
+            acc.splice(i.., sum);
+        }
+        acc
+    }
+
+    fn ult(&mut self, ctx: &mut Context, a: &[Bit], b: &[Bit]) -> Bit {
+        let mut lt = Bit::Const(false);
+        for (&x, &y) in a.iter().zip(b) {
+            let differ = self.xor(ctx, x, y);
+            lt = self.mux(ctx, differ, y, lt);
+        }
+        lt
+    }
+
+    fn slt(&mut self, ctx: &mut Context, a: &[Bit], b: &[Bit]) -> Bit {
+        let flip = |x: &[Bit]| {
+            let mut x = x.to_vec();
+            let msb = x.len() - 1;
+            x[msb] = !x[msb];
+            x
+        };
+        self.ult(ctx, &flip(a), &flip(b))
+    }
+
+    fn equal_bits(&mut self, ctx: &mut Context, a: &[Bit], b: &[Bit]) -> Bit {
+        let same: Vec<Bit> = a.iter().zip(b).map(|(&x, &y)| !self.xor(ctx, x, y)).collect();
+        self.all(ctx, &same)
+    }
+
+    fn shift(&mut self, ctx: &mut Context, op: BvOp, a: &[Bit], b: &[Bit]) -> Vec<Bit> {
+        let w = a.len();
+        let fill = if op == BvOp::Ashr { a[w - 1] } else { Bit::Const(false) };
+        let mut x = a.to_vec();
+        let mut k = 0;
+        while (1usize << k) < w {
+            let s = 1usize << k;
+            let shifted: Vec<Bit> = (0..w)
+                .map(|i| match op {
+                    BvOp::Shl if i >= s => x[i - s],
+                    BvOp::Shl => Bit::Const(false),
+                    _ if i + s < w => x[i + s],
+                    _ => fill,
+                })
+                .collect();
+            x = self.mux_word(ctx, b[k], &shifted, &x);
+            k += 1;
+        }
+        let width = const_word(w as u128, w as u32);
+        let over = !self.ult(ctx, b, &width);
+        let filled = vec![fill; w];
+        self.mux_word(ctx, over, &filled, &x)
+    }
+
+    /// Quotient and remainder of `a / b`, with SMT-LIB's results for `b = 0`.
+    fn udivrem(&mut self, ctx: &mut Context, key: (TermId, TermId, bool), a: &[Bit], b: &[Bit]) -> DivRem {
+        if let Some(qr) = self.divs.get(&key) {
+            return qr.clone();
+        }
+        let w = a.len();
+        let q: Vec<Bit> = (0..w).map(|_| self.fresh(ctx)).collect();
+        let r: Vec<Bit> = (0..w).map(|_| self.fresh(ctx)).collect();
+        let wide = |x: &[Bit]| x.iter().copied().chain(core::iter::repeat_n(Bit::Const(false), w)).collect::<Vec<_>>();
+        let prod = self.mul(ctx, &wide(&q), &wide(b));
+        let sum = self.add(ctx, &prod, &wide(&r), Bit::Const(false));
+        let exact = self.equal_bits(ctx, &sum, &wide(a));
+        let below = self.ult(ctx, &r, b);
+        let nonzero = self.any(ctx, b);
+        self.clauses.extend([vec![!nonzero, exact], vec![!nonzero, below]]);
+        for i in 0..w {
+            self.clauses.extend([vec![nonzero, q[i]], vec![nonzero, !r[i], a[i]], vec![nonzero, r[i], !a[i]]]);
+        }
+        self.divs.insert(key, (q.clone(), r.clone()));
+        self.record(Undo::Div(key.0, key.1, key.2));
+        (q, r)
+    }
+
+    fn signed_div(&mut self, ctx: &mut Context, op: BvOp, key: (TermId, TermId), a: &[Bit], b: &[Bit]) -> Vec<Bit> {
+        let w = a.len();
+        let (sa, sb) = (a[w - 1], b[w - 1]);
+        let (na, nb) = (self.neg(ctx, a), self.neg(ctx, b));
+        let abs_a = self.mux_word(ctx, sa, &na, a);
+        let abs_b = self.mux_word(ctx, sb, &nb, b);
+        let (q, r) = self.udivrem(ctx, (key.0, key.1, true), &abs_a, &abs_b);
+        match op {
+            BvOp::Sdiv => {
+                let differ = self.xor(ctx, sa, sb);
+                let nq = self.neg(ctx, &q);
+                self.mux_word(ctx, differ, &nq, &q)
+            }
+            BvOp::Srem => {
+                let nr = self.neg(ctx, &r);
+                self.mux_word(ctx, sa, &nr, &r)
+            }
+            _ => {
+                let nr = self.neg(ctx, &r);
+                let nr_b = self.add(ctx, &nr, b, Bit::Const(false));
+                let r_b = self.add(ctx, &r, b, Bit::Const(false));
+                let negative_a = self.mux_word(ctx, sb, &nr, &nr_b);
+                let positive_a = self.mux_word(ctx, sb, &r_b, &r);
+                let signed = self.mux_word(ctx, sa, &negative_a, &positive_a);
+                let zero = !self.any(ctx, &r);
+                self.mux_word(ctx, zero, &r, &signed)
+            }
+        }
+    }
+
+    /// Word-level simplification of a bit-vector term.
+    fn rewrite(&mut self, ctx: &mut Context, t: TermId) -> TermId {
+        if let Some(&r) = self.rewrites.get(&t) {
+            return r;
+        }
+        let r = match ctx.term_node(t).0.clone() {
+            TermKind::Bv(op, args) => {
+                let simple: Vec<TermId> = args.iter().map(|&a| self.rewrite(ctx, a)).collect();
+                match simplify(ctx, op, &simple) {
+                    Some(s) if s != t => self.rewrite(ctx, s),
+                    _ if simple != args => ctx.bv(op, &simple),
+                    _ => t,
+                }
+            }
+            _ => t,
+        };
+        self.rewrites.insert(t, r);
+        self.record(Undo::Rewrite(t));
+        r
+    }
+
+    fn blast(&mut self, ctx: &mut Context, t: TermId) -> Vec<Bit> {
+        if let Some(bits) = self.bits.get(&t) {
+            return bits.clone();
+        }
+        let r = self.rewrite(ctx, t);
+        let bits = if r != t {
+            self.blast(ctx, r)
+        } else {
+            let width = ctx.bv_width(ctx.term_sort(t)).expect("bit-vector term");
+            match ctx.term_node(t).0.clone() {
+                TermKind::BvConst(v) => const_word(v, width),
+                TermKind::Bv(op, args) => self.blast_op(ctx, op, &args),
+                _ => self.fresh_word(ctx, width),
+            }
+        };
+        self.bits.insert(t, bits.clone());
+        self.record(Undo::Bits(t));
+        bits
+    }
+
+    fn blast_op(&mut self, ctx: &mut Context, op: BvOp, args: &[TermId]) -> Vec<Bit> {
+        let xs: Vec<Vec<Bit>> = args.iter().map(|&a| self.blast(ctx, a)).collect();
+        let a = &xs[0];
+        let b = xs.get(1).map_or(&[][..], |b| &b[..]);
+        let w = a.len();
+        match op {
+            BvOp::Not => a.iter().map(|&x| !x).collect(),
+            BvOp::And => a.iter().zip(b).map(|(&x, &y)| self.and(ctx, x, y)).collect(),
+            BvOp::Or => a.iter().zip(b).map(|(&x, &y)| self.or(ctx, x, y)).collect(),
+            BvOp::Xor => a.iter().zip(b).map(|(&x, &y)| self.xor(ctx, x, y)).collect(),
+            BvOp::Neg => self.neg(ctx, a),
+            BvOp::Add => self.add(ctx, a, b, Bit::Const(false)),
+            BvOp::Sub => {
+                let nb: Vec<Bit> = b.iter().map(|&y| !y).collect();
+                self.add(ctx, a, &nb, Bit::Const(true))
+            }
+            BvOp::Mul => self.mul(ctx, a, b),
+            BvOp::Udiv => self.udivrem(ctx, (args[0], args[1], false), a, b).0,
+            BvOp::Urem => self.udivrem(ctx, (args[0], args[1], false), a, b).1,
+            BvOp::Sdiv | BvOp::Srem | BvOp::Smod => self.signed_div(ctx, op, (args[0], args[1]), a, b),
+            BvOp::Shl | BvOp::Lshr | BvOp::Ashr => self.shift(ctx, op, a, b),
+            BvOp::Concat => b.iter().chain(a).copied().collect(),
+            BvOp::Extract(hi, lo) => a[lo as usize..=hi as usize].to_vec(),
+            BvOp::ZeroExtend(n) => a.iter().copied().chain(core::iter::repeat_n(Bit::Const(false), n as usize)).collect(),
+            BvOp::SignExtend(n) => a.iter().copied().chain(core::iter::repeat_n(a[w - 1], n as usize)).collect(),
+            BvOp::RotateLeft(k) => (0..w).map(|i| a[(i + w - k as usize % w) % w]).collect(),
+            BvOp::RotateRight(k) => (0..w).map(|i| a[(i + k as usize) % w]).collect(),
+            BvOp::Ult | BvOp::Ule | BvOp::Slt | BvOp::Sle => panic!("{op:?} is a predicate"),
+        }
+    }
+
+    /// Queue an atom and the bit-vector terms it mentions for blasting.
+    fn watch(&mut self, ctx: &Context, atom: TermId, owned: bool) {
+        let mut ends = Vec::new();
+        mentioned(ctx, atom, &mut ends);
+        if ends.is_empty() && !owned {
+            return;
+        }
+        ends.sort();
+        ends.dedup();
+        self.endpoints.insert(atom, (ends, owned));
+        self.unblasted.push(atom);
+        self.record(Undo::Atom(atom));
+    }
+
+    /// The bit an atom stands for.
+    fn blast_atom(&mut self, ctx: &mut Context, atom: TermId) -> Bit {
+        let (op, x, y) = match ctx.term_node(atom).0 {
+            TermKind::Eq(x, y) => (None, *x, *y),
+            TermKind::Bv(op, args) => (Some(*op), args[0], args[1]),
+            _ => panic!("bit-vector atom is not an equality or a predicate"),
+        };
+        let (x, y) = (self.rewrite(ctx, x), self.rewrite(ctx, y));
+        if x == y {
+            return Bit::Const(!matches!(op, Some(BvOp::Ult | BvOp::Slt)));
+        }
+        let (a, b) = (self.blast(ctx, x), self.blast(ctx, y));
+        match op {
+            None => self.equal_bits(ctx, &a, &b),
+            Some(BvOp::Ult) => self.ult(ctx, &a, &b),
+            Some(BvOp::Ule) => !self.ult(ctx, &b, &a),
+            Some(BvOp::Slt) => self.slt(ctx, &a, &b),
+            Some(BvOp::Sle) => !self.slt(ctx, &b, &a),
+            Some(op) => panic!("{op:?} is not a predicate"),
+        }
+    }
+
+    fn neg_term(&mut self, ctx: &mut Context, t: TermId) -> TermId {
+        if let Some(&n) = self.negs.get(&t) {
+            return n;
+        }
+        let n = ctx.not(t);
+        self.negs.insert(t, n);
+        self.record(Undo::Neg(t));
+        n
+    }
+
+    fn value(&self, b: Bit) -> Option<bool> {
+        match b {
+            Bit::Const(c) => Some(c),
+            Bit::Var(t, pos) => self.values.get(&t).map(|&v| v == pos),
+        }
+    }
+
+    /// The literal of `b`'s variable that makes `b` equal `value`.
+    fn lit(&self, b: Bit, value: bool) -> Option<Lit> {
+        let Bit::Var(t, pos) = b else { return None };
+        let var = *self.vars.get(&t)?;
+        Some(if value == pos { Lit::pos(var) } else { Lit::neg(var) })
+    }
+
+    /// Reason for the current value of `b` (none for constants).
+    fn why(&self, b: Bit, tcx: &mut TheoryCtx) -> Option<ReasonId> {
+        let v = self.value(b)?;
+        self.lit(b, v).map(|l| tcx.r_lit(l))
+    }
+
+    /// The word of a term whose bits are all assigned.
+    fn word(&self, bits: &[Bit]) -> Option<u128> {
+        let mut v = 0;
+        for (i, &b) in bits.iter().enumerate() {
+            if self.value(b)? {
+                v |= 1 << i;
+            }
+        }
+        Some(v)
+    }
+
+    /// Propagate bits across imported equalities.
+    fn propagate_imports(&mut self, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let mut props = Vec::new();
+        for &(a, b, explain, _) in &self.imports {
+            let (Some(xa), Some(xb)) = (self.bits.get(&a), self.bits.get(&b)) else { continue };
+            for (&x, &y) in xa.iter().zip(xb) {
+                let (from, to, v) = match (self.value(x), self.value(y)) {
+                    (Some(u), Some(v)) if u != v => {
+                        let mut kids = vec![explain];
+                        kids.extend(self.why(x, tcx));
+                        kids.extend(self.why(y, tcx));
+                        return TheoryOutcome::Conflict(tcx.r_and(kids));
+                    }
+                    (Some(u), None) => (x, y, u),
+                    (None, Some(v)) => (y, x, v),
+                    _ => continue,
+                };
+                let Some(lit) = self.lit(to, v) else { continue };
+                let mut kids = vec![explain];
+                kids.extend(self.why(from, tcx));
+                props.push(TheoryPropagation::new(lit, tcx.r_and(kids)));
+            }
+        }
+        if props.is_empty() { TheoryOutcome::Ok } else { TheoryOutcome::Propagate(props) }
+    }
+}
+
+fn const_word(v: u128, width: u32) -> Vec<Bit> {
+    (0..width).map(|i| Bit::Const(v >> i & 1 == 1)).collect()
+}
+
+/// A simpler term equal to `op(args)` (arguments already simplified), if one is known.
+fn simplify(ctx: &mut Context, op: BvOp, args: &[TermId]) -> Option<TermId> {
+    let width = |ctx: &Context, t: TermId| ctx.bv_width(ctx.term_sort(t)).expect("bit-vector term");
+    let word = |ctx: &Context, t: TermId| match ctx.term_node(t).0 {
+        TermKind::BvConst(v) => Some(*v),
+        _ => None,
+    };
+    let w = width(ctx, args[0]);
+    let words: Option<Vec<(u32, u128)>> = args.iter().map(|&a| word(ctx, a).map(|v| (width(ctx, a), v))).collect();
+    if let Some(words) = words {
+        let out = op.result_width(&words.iter().map(|a| a.0).collect::<Vec<_>>()).expect("well-sorted");
+        return Some(ctx.bv_const(op.apply(&words), out));
+    }
+    let x = args[0];
+    let (wx, wy) = (word(ctx, x), args.get(1).and_then(|&y| word(ctx, y)));
+    let (zero, ones) = (Some(0), Some(mask(w)));
+    let inner = |ctx: &Context, t: TermId| match ctx.term_node(t).0 {
+        TermKind::Bv(op, args) => Some((*op, args.clone())),
+        _ => None,
+    };
+    match op {
+        BvOp::Not | BvOp::Neg => match inner(ctx, x) {
+            Some((o, a)) if o == op => Some(a[0]),
+            _ => None,
+        },
+        BvOp::ZeroExtend(0) | BvOp::SignExtend(0) => Some(x),
+        BvOp::RotateLeft(k) | BvOp::RotateRight(k) if k % w == 0 => Some(x),
+        BvOp::Extract(hi, lo) if lo == 0 && hi == w - 1 => Some(x),
+        BvOp::Extract(hi, lo) => match inner(ctx, x)? {
+            (BvOp::Concat, parts) => {
+                let low = width(ctx, parts[1]);
+                if hi < low {
+                    Some(ctx.bv(BvOp::Extract(hi, lo), &[parts[1]]))
+                } else if lo >= low {
+                    Some(ctx.bv(BvOp::Extract(hi - low, lo - low), &[parts[0]]))
+                } else {
+                    None
+                }
+            }
+            (BvOp::Extract(_, base), a) => Some(ctx.bv(BvOp::Extract(hi + base, lo + base), &a)),
+            _ => None,
+        },
+        _ if op.arity() == 1 => None,
+        _ => {
+            let y = args[1];
+            match op {
+                BvOp::Add | BvOp::Or | BvOp::Xor if wx == zero => Some(y),
+                BvOp::Add | BvOp::Or | BvOp::Xor | BvOp::Sub if wy == zero => Some(x),
+                BvOp::And | BvOp::Mul if wx == zero => Some(x),
+                BvOp::And | BvOp::Mul if wy == zero => Some(y),
+                BvOp::And if wx == ones => Some(y),
+                BvOp::And if wy == ones => Some(x),
+                BvOp::Or if wx == ones => Some(x),
+                BvOp::Or if wy == ones => Some(y),
+                BvOp::Mul if wx == Some(1) => Some(y),
+                BvOp::Mul if wy == Some(1) => Some(x),
+                BvOp::And | BvOp::Or if x == y => Some(x),
+                BvOp::Xor | BvOp::Sub if x == y => Some(ctx.bv_const(0, w)),
+                BvOp::Shl | BvOp::Lshr | BvOp::Ashr if wy == zero => Some(x),
+                BvOp::Shl | BvOp::Lshr if wy.is_some_and(|s| s >= w as u128) => Some(ctx.bv_const(0, w)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// The bit-vector terms in `t`, at any depth.
+fn mentioned(ctx: &Context, t: TermId, out: &mut Vec<TermId>) {
+    if ctx.bv_width(ctx.term_sort(t)).is_some() {
+        out.push(t);
+    }
+    match ctx.term_node(t).0 {
+        TermKind::Bv(_, xs) | TermKind::Add(xs) | TermKind::App { args: xs, .. } | TermKind::Construct(_, xs) => {
+            for &x in xs {
+                mentioned(ctx, x, out);
+            }
+        }
+        TermKind::Selector(_, _, a) | TermKind::Tester(_, a) => mentioned(ctx, *a, out),
+        TermKind::Eq(a, b) | TermKind::Le(a, b) | TermKind::Sub(a, b) | TermKind::Mul(a, b) | TermKind::Select(a, b) => {
+            mentioned(ctx, *a, out);
+            mentioned(ctx, *b, out);
+        }
+        TermKind::Store(a, i, v) => {
+            mentioned(ctx, *a, out);
+            mentioned(ctx, *i, out);
+            mentioned(ctx, *v, out);
+        }
+        _ => {}
+    }
+}
+
+impl Theory for BitVecTheory {
+    fn name(&self) -> &'static str { "BV" }
+
+    fn owns_atom(&self, ctx: &Context, atom_term: TermId) -> bool {
+        match ctx.term_node(atom_term).0 {
+            TermKind::Eq(a, _) => ctx.bv_width(ctx.term_sort(*a)).is_some(),
+            TermKind::Bv(op, _) => op.is_predicate(),
+            _ => self.bit_terms.contains(&atom_term),
+        }
+    }
+
+    fn atom_endpoints(&self, atom_term: TermId) -> Vec<TermId> {
+        self.endpoints.get(&atom_term).map(|e| e.0.clone()).unwrap_or_default()
+    }
+
+    fn foreign_endpoints(&self, atom_term: TermId) -> Vec<TermId> {
+        self.endpoints.get(&atom_term).map(|e| e.0.clone()).unwrap_or_default()
+    }
+
+    fn register_atom(&mut self, ctx: &Context, atom: Atom) {
+        self.vars.insert(atom.term, atom.var);
+        if !self.bit_terms.contains(&atom.term) {
+            self.watch(ctx, atom.term, true);
+        }
+    }
+
+    fn register_foreign_atom(&mut self, ctx: &Context, atom: Atom) {
+        self.watch(ctx, atom.term, false);
+    }
+
+    fn assert_atom(&mut self, _ctx: &Context, atom: Atom, value: bool, _tcx: &mut TheoryCtx) -> TheoryOutcome {
+        if self.bit_terms.contains(&atom.term) {
+            self.values.insert(atom.term, value);
+            if !self.levels.is_empty() {
+                self.trail.push(atom.term);
+            }
+        }
+        TheoryOutcome::Ok
+    }
+
+    fn propagate(&mut self, _ctx: &Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        self.propagate_imports(tcx)
+    }
+
+    /// Blast the atoms (and foreign terms) registered since the last check.
+    fn final_check(&mut self, ctx: &mut Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        for atom in core::mem::take(&mut self.unblasted) {
+            let (ends, owned) = self.endpoints[&atom].clone();
+            if owned {
+                let bit = self.blast_atom(ctx, atom);
+                let v = Bit::Var(atom, true);
+                self.clauses.extend([vec![!v, bit], vec![v, !bit]]);
+            }
+            for t in ends {
+                self.blast(ctx, t);
+            }
+            self.record(Undo::Blasted(atom));
+        }
+        if self.clauses.is_empty() {
+            return TheoryOutcome::Ok;
+        }
+        let because = tcx.r_and(Vec::new());
+        let mut lemmas = Vec::new();
+        'clauses: for clause in core::mem::take(&mut self.clauses) {
+            let mut atoms = Vec::with_capacity(clause.len());
+            for b in clause {
+                match b {
+                    Bit::Const(true) => continue 'clauses,
+                    Bit::Const(false) => {}
+                    Bit::Var(t, true) => atoms.push(t),
+                    Bit::Var(t, false) => atoms.push(self.neg_term(ctx, t)),
+                }
+            }
+            lemmas.push(TheoryLemma { because, atoms });
+        }
+        TheoryOutcome::Lemmas(lemmas)
+    }
+
+    /// The word the bits of `t` currently spell (unassigned bits read as 0).
+    fn model_value(&self, _ctx: &Context, t: TermId) -> Option<Value> {
+        let bits = self.bits.get(&t)?;
+        let v = bits.iter().enumerate().fold(0, |v, (i, &b)| if self.value(b) == Some(true) { v | 1 << i } else { v });
+        Some(Value::BitVec(bits.len() as u32, v))
+    }
+
+    fn push_level(&mut self) {
+        self.levels.push((self.trail.len(), self.imports.len()));
+    }
+
+    fn pop_levels(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let (trail, imports) = self.levels[self.levels.len() - n];
+        self.levels.truncate(self.levels.len() - n);
+        for t in self.trail.drain(trail..) {
+            self.values.remove(&t);
+        }
+        self.imports.truncate(imports);
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(self.log.len());
+    }
+
+    fn pop_scopes(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let mark = self.scopes[self.scopes.len() - n];
+        self.scopes.truncate(self.scopes.len() - n);
+        for u in self.log.drain(mark..).rev() {
+            match u {
+                Undo::Bits(t) => {
+                    self.bits.remove(&t);
+                }
+                Undo::Rewrite(t) => {
+                    self.rewrites.remove(&t);
+                }
+                Undo::Neg(t) => {
+                    self.negs.remove(&t);
+                }
+                Undo::Bit(t) => {
+                    self.bit_terms.remove(&t);
+                    self.vars.remove(&t);
+                    self.values.remove(&t);
+                }
+                Undo::Div(a, b, signed) => {
+                    self.divs.remove(&(a, b, signed));
+                }
+                Undo::Atom(t) => {
+                    self.vars.remove(&t);
+                    self.endpoints.remove(&t);
+                    self.unblasted.retain(|&u| u != t);
+                }
+                // Blasted with terms of the scope: blast again on the next check.
+                Undo::Blasted(t) => self.unblasted.push(t),
+            }
+        }
+        let depth = self.scopes.len();
+        self.imports.retain(|i| i.3 <= depth);
+    }
+
+    fn equality_sharing_mut(&mut self) -> Option<&mut dyn EqualitySharing> { Some(self) }
+}
+
+impl EqualitySharing for BitVecTheory {
+    /// Shared terms whose bits spell the same word.
+    fn export_classes(&mut self, oracle: &SharedTermOracle, _export_epoch: u64, tcx: &mut TheoryCtx) -> Vec<EqClass> {
+        let mut fixed = Vec::new();
+        for &t in oracle.shared_set() {
+            let Some(bits) = self.bits.get(&t) else { continue };
+            if let Some(v) = self.word(bits) {
+                fixed.push((bits.len(), v, t));
+            }
+        }
+        fixed.sort();
+        let mut out = Vec::new();
+        for group in fixed.chunk_by(|p, q| (p.0, p.1) == (q.0, q.1)).filter(|g| g.len() > 1) {
+            let rep = group[0].2;
+            let members = group[1..]
+                .iter()
+                .map(|&(_, _, t)| {
+                    let kids = self.bits[&rep].iter().chain(&self.bits[&t]).filter_map(|&x| self.why(x, tcx)).collect();
+                    (t, tcx.r_and(kids))
+                })
+                .collect();
+            out.push(EqClass { rep, members });
+        }
+        out
+    }
+
+    fn import_equality(&mut self, eq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let explain = self.keep(eq.explain, tcx);
+        self.imports.push((eq.a, eq.b, explain, self.scopes.len()));
+        self.propagate_imports(tcx)
+    }
+}
@@ -0,0 +1,422 @@
+#![forbid(unsafe_code)]
+//! Backtrackable congruence closure with explanations.
+//!
+//! Nodes are terms, either leaves or applications of a symbol to argument nodes. Classes
+//! are a union-find without path compression, so undoing a union only resets one parent.
+//! Every merge also adds an edge to a proof forest, labelled with why the two nodes are
+//! equal (a reason, an axiom, or congruence of two applications); an explanation is the
+//! path between two nodes, with congruence edges explained recursively by their
+//! arguments. Leaves may carry a constant value: two constants with the same value are
+//! equal, and a class with two different values is a conflict.
+//!
+//! Undoing a level forgets the merges made at it, including those found when a node was
+//! (re)checked for an equal partner at that level; such nodes are rechecked on the next
+//! `close`.
+
+use hashbrown::HashMap;
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{TermId, Value};
+
+use crate::reason::ReasonId;
+use crate::theory::EqClass;
+use crate::theory_ctx::TheoryCtx;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+pub type Node = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Why {
+    Given(ReasonId),
+    /// Equal constants.
+    Axiom,
+    /// Two applications with equal arguments.
+    Congruence(Node, Node),
+}
+
+enum Undo {
+    Union { child: Node, root: Node, members: usize, constant: Option<(Value, Node)> },
+    Edge(Node, Node),
+    Diseq,
+    Checked(Node),
+    Explained(Node, Node),
+}
+
+#[derive(Default)]
+pub struct CongruenceClosure {
+    terms: Vec<TermId>,
+    node_of: HashMap<TermId, Node, FxBuild>,
+    /// Symbol and arguments of application nodes.
+    apps: Vec<Option<(u32, Vec<Node>)>>,
+    /// Applications having the node as an argument.
+    uses: Vec<Vec<Node>>,
+    parent: Vec<Node>,
+    /// Per root: the nodes of its class.
+    members: Vec<Vec<Node>>,
+    /// Per root: the class's constant value and a node carrying it.
+    constant: Vec<Option<(Value, Node)>>,
+    /// First node of each constant value.
+    constants: HashMap<Value, Node, FxBuild>,
+    edges: Vec<Vec<(Node, Why)>>,
+    diseqs: Vec<(Node, Node, ReasonId)>,
+    trail: Vec<Undo>,
+    levels: Vec<usize>,
+    pending: Vec<(Node, Node, Why)>,
+    recheck: Vec<Node>,
+    /// Explanations handed out by `classes`, until the level that built them is undone.
+    explained: HashMap<(Node, Node), ReasonId, FxBuild>,
+}
+
+impl CongruenceClosure {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn node(&self, t: TermId) -> Option<Node> {
+        self.node_of.get(&t).copied()
+    }
+
+    pub fn term(&self, n: Node) -> TermId {
+        self.terms[n]
+    }
+
+    pub fn num_nodes(&self) -> usize { self.terms.len() }
+
+    /// Symbol and arguments of `n`, if it is an application.
+    pub fn app(&self, n: Node) -> Option<(u32, &[Node])> {
+        self.apps[n].as_ref().map(|(f, args)| (*f, args.as_slice()))
+    }
+
+    /// Add a leaf, with its value if it is a constant.
+    pub fn add_leaf(&mut self, t: TermId, value: Option<Value>) -> Node {
+        if let Some(n) = self.node(t) {
+            return n;
+        }
+        let n = self.push_node(t, None);
+        if let Some(v) = value {
+            self.constant[n] = Some((v.clone(), n));
+            self.constants.entry(v).or_insert(n);
+        }
+        self.recheck.push(n);
+        n
+    }
+
+    /// Add the application of `f` to `args` (nodes already added).
+    pub fn add_app(&mut self, t: TermId, f: u32, args: Vec<Node>) -> Node {
+        if let Some(n) = self.node(t) {
+            return n;
+        }
+        let n = self.push_node(t, Some((f, args.clone())));
+        for a in args {
+            if !self.uses[a].contains(&n) {
+                self.uses[a].push(n);
+            }
+        }
+        self.recheck.push(n);
+        n
+    }
+
+    fn push_node(&mut self, t: TermId, app: Option<(u32, Vec<Node>)>) -> Node {
+        let n = self.terms.len();
+        self.terms.push(t);
+        self.node_of.insert(t, n);
+        self.apps.push(app);
+        self.uses.push(Vec::new());
+        self.parent.push(n);
+        self.members.push(vec![n]);
+        self.constant.push(None);
+        self.edges.push(Vec::new());
+        n
+    }
+
+    /// Record how to undo a change; level-0 changes are permanent.
+    fn log(&mut self, u: Undo) {
+        if !self.levels.is_empty() {
+            self.trail.push(u);
+        }
+    }
+
+    pub fn find(&self, mut n: Node) -> Node {
+        while self.parent[n] != n {
+            n = self.parent[n];
+        }
+        n
+    }
+
+    pub fn are_equal(&self, a: Node, b: Node) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    /// Nodes of the class of `n`.
+    pub fn class(&self, n: Node) -> &[Node] {
+        &self.members[self.find(n)]
+    }
+
+    /// Whether `a` and `b` are known to be different.
+    pub fn known_disequal(&self, a: Node, b: Node) -> bool {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if let (Some((va, _)), Some((vb, _))) = (&self.constant[ra], &self.constant[rb]) {
+            if va != vb {
+                return true;
+            }
+        }
+        self.diseqs.iter().any(|&(x, y, _)| {
+            let (rx, ry) = (self.find(x), self.find(y));
+            (rx, ry) == (ra, rb) || (rx, ry) == (rb, ra)
+        })
+    }
+
+    /// Why `a` and `b` are known to be different, if they are.
+    pub fn disequal(&self, a: Node, b: Node, tcx: &mut TheoryCtx) -> Option<ReasonId> {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra == rb {
+            return None;
+        }
+        if let (Some((va, na)), Some((vb, nb))) = (&self.constant[ra], &self.constant[rb]) {
+            if va != vb {
+                let (na, nb) = (*na, *nb);
+                let steps = vec![self.explain(a, na, tcx), self.explain(b, nb, tcx)];
+                return Some(tcx.r_and(steps));
+            }
+        }
+        let &(x, y, r) = self.diseqs.iter().find(|&&(x, y, _)| {
+            let (rx, ry) = (self.find(x), self.find(y));
+            (rx, ry) == (ra, rb) || (rx, ry) == (rb, ra)
+        })?;
+        let (x, y) = if self.find(x) == ra { (x, y) } else { (y, x) };
+        let steps = vec![self.explain(a, x, tcx), self.explain(b, y, tcx), r];
+        Some(tcx.r_and(steps))
+    }
+
+    /// Assert `a = b` and close. On conflict, returns its reason.
+    pub fn assert_eq(&mut self, a: Node, b: Node, r: ReasonId, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        self.pending.push((a, b, Why::Given(r)));
+        self.close(tcx)
+    }
+
+    /// Assert `a != b`. On conflict, returns its reason.
+    pub fn assert_diseq(&mut self, a: Node, b: Node, r: ReasonId, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        if self.are_equal(a, b) {
+            let eq = self.explain(a, b, tcx);
+            return Err(tcx.r_and(vec![eq, r]));
+        }
+        self.diseqs.push((a, b, r));
+        self.log(Undo::Diseq);
+        Ok(())
+    }
+
+    /// Process pending merges and rechecks until congruence-closed.
+    pub fn close(&mut self, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        for n in core::mem::take(&mut self.recheck) {
+            self.log(Undo::Checked(n));
+            self.find_partner(n);
+        }
+        while let Some((a, b, why)) = self.pending.pop() {
+            if let Err(r) = self.union(a, b, why, tcx) {
+                // Merges found but not made are looked for again on the next close.
+                for (a, b, why) in self.pending.drain(..) {
+                    if !matches!(why, Why::Given(_)) {
+                        self.recheck.extend([a, b]);
+                    }
+                }
+                return Err(r);
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue merges of a new (or rechecked) node with the existing nodes equal to it.
+    fn find_partner(&mut self, n: Node) {
+        // Only a constant leaf carries its own value.
+        if let Some((v, m)) = &self.constant[n] {
+            let c = if *m == n { self.constants[v] } else { n };
+            if !self.are_equal(n, c) {
+                self.pending.push((n, c, Why::Axiom));
+            }
+        }
+        let Some((_, args)) = &self.apps[n] else { return };
+        let Some(&first) = args.first() else { return };
+        // Every partner, not just one: nodes rechecked together may be congruent to each
+        // other as well as to an older node.
+        let partners: Vec<Node> = self.members[self.find(first)]
+            .iter()
+            .flat_map(|&m| self.uses[m].iter().copied())
+            .filter(|&u| u != n && !self.are_equal(n, u) && self.congruent(n, u))
+            .collect();
+        for u in partners {
+            self.pending.push((n, u, Why::Congruence(n, u)));
+        }
+    }
+
+    fn congruent(&self, u: Node, w: Node) -> bool {
+        match (&self.apps[u], &self.apps[w]) {
+            (Some((f, xs)), Some((g, ys))) => {
+                f == g && xs.len() == ys.len() && xs.iter().zip(ys).all(|(&x, &y)| self.are_equal(x, y))
+            }
+            _ => false,
+        }
+    }
+
+    fn union(&mut self, a: Node, b: Node, why: Why, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra == rb {
+            return Ok(());
+        }
+        let (child, root) = if self.members[ra].len() < self.members[rb].len() { (ra, rb) } else { (rb, ra) };
+        let class_uses = |cc: &Self, r: Node| -> Vec<Node> {
+            cc.members[r].iter().flat_map(|&m| cc.uses[m].iter().copied()).collect()
+        };
+        let (child_uses, root_uses) = (class_uses(self, child), class_uses(self, root));
+
+        self.edges[a].push((b, why));
+        self.edges[b].push((a, why));
+        self.log(Undo::Edge(a, b));
+        let old = self.constant[root].clone();
+        self.log(Undo::Union { child, root, members: self.members[root].len(), constant: old.clone() });
+        self.parent[child] = root;
+        let moved = self.members[child].clone();
+        self.members[root].extend(moved);
+
+        match (&self.constant[child], &old) {
+            (Some((vc, nc)), Some((vr, nr))) if vc != vr => {
+                let (nc, nr) = (*nc, *nr);
+                return Err(self.explain(nc, nr, tcx));
+            }
+            (Some(c), None) => self.constant[root] = Some(c.clone()),
+            _ => {}
+        }
+        if let Some(&(x, y, r)) = self.diseqs.iter().find(|&&(x, y, _)| self.are_equal(x, y)) {
+            let eq = self.explain(x, y, tcx);
+            return Err(tcx.r_and(vec![eq, r]));
+        }
+        for &u in &child_uses {
+            for &w in &root_uses {
+                if u != w && !self.are_equal(u, w) && self.congruent(u, w) {
+                    self.pending.push((u, w, Why::Congruence(u, w)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Why `a = b`; they must be in one class.
+    pub fn explain(&self, a: Node, b: Node, tcx: &mut TheoryCtx) -> ReasonId {
+        if a == b {
+            return tcx.r_and(Vec::new());
+        }
+        let mut steps = Vec::new();
+        for why in self.path(a, b) {
+            let step = match why {
+                Why::Given(r) => r,
+                Why::Axiom => tcx.r_and(Vec::new()),
+                Why::Congruence(u, w) => {
+                    let (xs, ys) = (&self.apps[u].as_ref().expect("app").1, &self.apps[w].as_ref().expect("app").1);
+                    let args = xs.iter().zip(ys).filter(|(x, y)| x != y).map(|(&x, &y)| self.explain(x, y, tcx)).collect();
+                    tcx.r_congruence(self.terms[u], self.terms[w], args)
+                }
+            };
+            steps.push(step);
+        }
+        tcx.r_trans(self.terms[a], self.terms[b], steps)
+    }
+
+    /// Edge labels on the proof-forest path from `a` to `b`.
+    fn path(&self, a: Node, b: Node) -> Vec<Why> {
+        let mut prev: HashMap<Node, (Node, Why), FxBuild> = HashMap::default();
+        let mut queue = vec![a];
+        let mut head = 0;
+        while head < queue.len() && !prev.contains_key(&b) {
+            let n = queue[head];
+            head += 1;
+            for &(m, why) in &self.edges[n] {
+                if m != a && !prev.contains_key(&m) {
+                    prev.insert(m, (n, why));
+                    queue.push(m);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        let mut n = b;
+        while n != a {
+            let (p, why) = prev[&n];
+            out.push(why);
+            n = p;
+        }
+        out.reverse();
+        out
+    }
+
+    /// Shared terms' classes: for each class with at least two of `terms`, its members
+    /// among them (first one the representative).
+    fn groups(&self, terms: impl Iterator<Item = TermId>) -> Vec<Vec<Node>> {
+        let mut by_root: HashMap<Node, Vec<Node>, FxBuild> = HashMap::default();
+        for t in terms {
+            if let Some(n) = self.node(t) {
+                by_root.entry(self.find(n)).or_default().push(n);
+            }
+        }
+        let mut out: Vec<Vec<Node>> = by_root.into_values().filter(|g| g.len() > 1).collect();
+        for g in &mut out {
+            g.sort();
+        }
+        out.sort();
+        out
+    }
+
+    /// `groups` as classes for export, each member explained against the representative.
+    /// Explanations are reused across rounds for as long as they hold.
+    pub fn classes(&mut self, terms: impl Iterator<Item = TermId>, tcx: &mut TheoryCtx) -> Vec<EqClass> {
+        let mut out = Vec::new();
+        for group in self.groups(terms) {
+            let rep = group[0];
+            let mut members = Vec::with_capacity(group.len() - 1);
+            for &m in &group[1..] {
+                let explain = match self.explained.get(&(rep, m)) {
+                    Some(&r) => r,
+                    None => {
+                        let r = self.explain(rep, m, tcx);
+                        self.explained.insert((rep, m), r);
+                        self.trail.push(Undo::Explained(rep, m));
+                        r
+                    }
+                };
+                members.push((self.terms[m], explain));
+            }
+            out.push(EqClass { rep: self.terms[rep], members });
+        }
+        out
+    }
+
+    pub fn push_level(&mut self) {
+        self.levels.push(self.trail.len());
+    }
+
+    pub fn pop_levels(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let to = self.levels[self.levels.len() - n];
+        self.levels.truncate(self.levels.len() - n);
+        self.pending.clear();
+        while self.trail.len() > to {
+            match self.trail.pop().expect("non-empty") {
+                Undo::Union { child, root, members, constant } => {
+                    self.parent[child] = child;
+                    self.members[root].truncate(members);
+                    self.constant[root] = constant;
+                }
+                Undo::Edge(a, b) => {
+                    self.edges[a].pop();
+                    self.edges[b].pop();
+                }
+                Undo::Diseq => {
+                    self.diseqs.pop();
+                }
+                Undo::Checked(n) => self.recheck.push(n),
+                Undo::Explained(a, b) => {
+                    self.explained.remove(&(a, b));
+                }
+            }
+        }
+    }
+}
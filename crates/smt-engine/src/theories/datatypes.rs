@@ -0,0 +1,664 @@
+#![forbid(unsafe_code)]
+//! Algebraic datatypes over a congruence closure.
+//!
+//! The theory owns equalities between datatype values and testers. Its closure holds every
+//! constructor and selector term of the context and the terms its atoms mention, with
+//! constructors and selectors as function symbols (nullary constructors are constants).
+//! Each closure is saturated with:
+//!
+//! - unification: two applications of one constructor in a class have equal arguments;
+//! - clashes: applications of two constructors in one class are a conflict;
+//! - selection: `sel(t)` equals the argument of a matching constructor in the class of
+//!   `t` (a selector of another constructor is left unspecified);
+//! - testers: an asserted tester must agree with the constructor of its argument's class.
+//!
+//! A final check rejects cycles (a class reachable from itself through constructor
+//! arguments, as in `x = cons(1, x)`), then splits classes that need a constructor and
+//! have none, with `is_c1(t) ∨ … ∨ is_cn(t)` and `is_c(t) → t = c(sel_1(t), …)` as
+//! `TheoryOutcome::Lemmas`. A class needs one if its sort is finite or a selector or an
+//! asserted tester is applied to it; the model builder gives the others distinct values.
+//!
+//! Fields of other sorts reach the closure through `foreign_endpoints` and equality
+//! sharing, as with arrays and UF: unification exports the equalities it finds between
+//! shared fields, and imported equalities make constructor applications congruent. An
+//! arithmetic field (a constant or a sum, say) has no variable for arithmetic to share,
+//! so the final check states `sel(c(…, x, …)) = x` for it as a lemma instead.
+
+use hashbrown::{HashMap, HashSet};
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{Context, SortId, TermId, TermKind, Value};
+use smt_sat::{Lit, VarId};
+
+use crate::atoms::Atom;
+use crate::reason::ReasonId;
+use crate::shared_terms::SharedTermOracle;
+use crate::theories::congruence::{CongruenceClosure, Node};
+use crate::theory::{EqClass, EqualitySharing, SharedEq, Theory, TheoryLemma, TheoryOutcome};
+use crate::theory_ctx::TheoryCtx;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+/// A split already made, by the term or tester it was made for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Instance {
+    Split(TermId),
+    Unfold(TermId),
+    Field(TermId, u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Assertion {
+    /// `a = b` or `a != b`.
+    Eq(TermId, TermId, bool),
+    /// Tester term, its argument and constructor, and its value.
+    Tester(TermId, TermId, u32, bool),
+}
+
+/// An assertion with its reason and the owned atom it came from.
+type Pending = (Assertion, ReasonId, Option<VarId>);
+
+/// An assertion made at level 0, replayed when the closure is rebuilt.
+struct Fact {
+    what: Assertion,
+    reason: ReasonId,
+    /// The owned atom it came from; `None` for imported facts.
+    atom: Option<VarId>,
+    scope: usize,
+}
+
+#[derive(Default)]
+struct Scope {
+    atoms: Vec<(VarId, TermId)>,
+    foreign: Vec<TermId>,
+    instances: Vec<Instance>,
+}
+
+#[derive(Default)]
+pub struct DatatypeTheory {
+    cc: CongruenceClosure,
+    /// Closure symbol of each constructor `(sort, ctor, 0)` and selector
+    /// `(sort, ctor, field + 1)`, and the other way around.
+    symbols: HashMap<(SortId, u32, u32), u32, FxBuild>,
+    symbol_info: Vec<(SortId, u32, u32)>,
+    /// Owned atoms, as the assertion they make when true.
+    atoms: HashMap<VarId, Assertion, FxBuild>,
+    /// Terms mentioned by each atom, owned or foreign.
+    mentions: HashMap<TermId, Vec<TermId>, FxBuild>,
+    /// Context terms already scanned for constructors and selectors.
+    scanned: usize,
+    /// Asserted testers: term, argument node, constructor, value, reason.
+    testers: Vec<(TermId, Node, u32, bool, ReasonId)>,
+    /// Assertions over terms the closure has no node for yet, applied on the next `sync`.
+    deferred: Vec<Pending>,
+    /// `testers` and `deferred` lengths at each open decision level.
+    levels: Vec<(usize, usize)>,
+    facts: Vec<Fact>,
+    instances: HashSet<Instance, FxBuild>,
+    scopes: Vec<Scope>,
+    /// A scope was popped: rebuild the closure before using it.
+    dirty: bool,
+}
+
+impl DatatypeTheory {
+    pub fn new() -> Self { Self::default() }
+
+    fn keep(&self, r: ReasonId, tcx: &mut TheoryCtx) -> ReasonId {
+        if self.levels.is_empty() { tcx.pin(r) } else { r }
+    }
+
+    fn symbol(&mut self, key: (SortId, u32, u32)) -> u32 {
+        let next = self.symbol_info.len() as u32;
+        let f = *self.symbols.entry(key).or_insert(next);
+        if f == next {
+            self.symbol_info.push(key);
+        }
+        f
+    }
+
+    fn add_term(&mut self, ctx: &Context, t: TermId) -> Node {
+        if let Some(n) = self.cc.node(t) {
+            return n;
+        }
+        match ctx.term_node(t).0 {
+            TermKind::Construct(k, args) if args.is_empty() => {
+                let name = ctx.constructor(ctx.term_sort(t), *k).name.clone();
+                self.cc.add_leaf(t, Some(Value::Ctor(name, Vec::new())))
+            }
+            TermKind::Construct(k, args) => {
+                let f = self.symbol((ctx.term_sort(t), *k, 0));
+                let args = args.clone().into_iter().map(|a| self.add_term(ctx, a)).collect();
+                self.cc.add_app(t, f, args)
+            }
+            TermKind::Selector(k, i, a) => {
+                let f = self.symbol((ctx.term_sort(*a), *k, i + 1));
+                let arg = self.add_term(ctx, *a);
+                self.cc.add_app(t, f, vec![arg])
+            }
+            TermKind::IntConst(k) => self.cc.add_leaf(t, Some(Value::Int(*k))),
+            TermKind::RatConst(k) => self.cc.add_leaf(t, Some(Value::Real(*k))),
+            TermKind::BvConst(v) => {
+                let width = ctx.bv_width(ctx.term_sort(t)).expect("bit-vector sort");
+                self.cc.add_leaf(t, Some(Value::BitVec(width, *v)))
+            }
+            _ => self.cc.add_leaf(t, None),
+        }
+    }
+
+    /// Bring the closure up to date with the context, rebuilding it after a pop, and
+    /// saturate it.
+    fn sync(&mut self, ctx: &Context, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        if self.dirty {
+            self.dirty = false;
+            self.cc = CongruenceClosure::new();
+            self.scanned = 0;
+            self.testers.clear();
+            let mut terms: Vec<TermId> = self.mentions.values().flatten().copied().collect();
+            terms.sort();
+            for t in terms {
+                self.add_term(ctx, t);
+            }
+            self.scan(ctx);
+            // As in the arrays theory: facts are permanent, assertions deferred since are
+            // reapplied at their decision levels.
+            let marks: Vec<usize> = self.levels.iter().map(|&(_, deferred)| deferred).collect();
+            let mut deferred = core::mem::take(&mut self.deferred);
+            self.levels.clear();
+            let mut replayed: Vec<Pending> = self.facts.iter().map(|f| (f.what, f.reason, f.atom)).collect();
+            let mut chunks = Vec::new();
+            for &mark in marks.iter().rev() {
+                chunks.push(deferred.split_off(mark));
+            }
+            replayed.append(&mut deferred);
+            let mut result = self.apply_all(ctx, replayed, tcx);
+            for chunk in chunks.into_iter().rev() {
+                self.push_level();
+                result = result.and_then(|()| self.apply_all(ctx, chunk, tcx));
+            }
+            result?;
+        }
+        self.scan(ctx);
+        let deferred = core::mem::take(&mut self.deferred);
+        self.apply_all(ctx, deferred, tcx)?;
+        self.saturate(ctx, tcx)
+    }
+
+    fn apply_all(&mut self, ctx: &Context, pending: Vec<Pending>, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        for (what, r, atom) in pending {
+            match what {
+                Assertion::Eq(a, b, _) => {
+                    self.add_term(ctx, a);
+                    self.add_term(ctx, b);
+                }
+                Assertion::Tester(_, a, _, _) => {
+                    self.add_term(ctx, a);
+                }
+            }
+            self.apply(what, r, atom, tcx)?;
+        }
+        Ok(())
+    }
+
+    fn scan(&mut self, ctx: &Context) {
+        for k in self.scanned..ctx.num_terms() {
+            let t = TermId(k as u32);
+            if matches!(ctx.term_node(t).0, TermKind::Construct(..) | TermKind::Selector(..)) && ctx.is_ground(t) {
+                self.add_term(ctx, t);
+            }
+        }
+        self.scanned = ctx.num_terms();
+    }
+
+    fn apply(&mut self, what: Assertion, r: ReasonId, atom: Option<VarId>, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        match what {
+            Assertion::Eq(a, b, eq) => {
+                let (Some(na), Some(nb)) = (self.cc.node(a), self.cc.node(b)) else {
+                    self.deferred.push((what, r, atom));
+                    return Ok(());
+                };
+                if eq { self.cc.assert_eq(na, nb, r, tcx) } else { self.cc.assert_diseq(na, nb, r, tcx) }
+            }
+            Assertion::Tester(t, a, k, value) => {
+                let Some(n) = self.cc.node(a) else {
+                    self.deferred.push((what, r, atom));
+                    return Ok(());
+                };
+                self.testers.push((t, n, k, value, r));
+                Ok(())
+            }
+        }
+    }
+
+    /// Apply `what`, recording it as a fact if at level 0.
+    fn assert_fact(&mut self, what: Assertion, r: ReasonId, atom: Option<VarId>, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let reason = self.keep(r, tcx);
+        if self.levels.is_empty() {
+            self.facts.push(Fact { what, reason, atom, scope: self.scopes.len() });
+        }
+        if self.dirty {
+            self.deferred.push((what, reason, atom));
+            return TheoryOutcome::Ok;
+        }
+        match self.apply(what, reason, atom, tcx) {
+            Ok(()) => TheoryOutcome::Ok,
+            Err(conflict) => TheoryOutcome::Conflict(conflict),
+        }
+    }
+
+    /// Constructor of `n`, if it is a constructor application.
+    fn constructor_of(&self, ctx: &Context, n: Node) -> Option<u32> {
+        match ctx.term_node(self.cc.term(n)).0 {
+            TermKind::Construct(k, _) => Some(*k),
+            _ => None,
+        }
+    }
+
+    /// The first constructor application of each class that has one, by root.
+    fn constructors(&self, ctx: &Context) -> HashMap<Node, Node, FxBuild> {
+        let mut out: HashMap<Node, Node, FxBuild> = HashMap::default();
+        for n in 0..self.cc.num_nodes() {
+            if self.constructor_of(ctx, n).is_some() {
+                out.entry(self.cc.find(n)).or_insert(n);
+            }
+        }
+        out
+    }
+
+    /// Close, then unify, select and check testers until nothing changes.
+    fn saturate(&mut self, ctx: &Context, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        'changed: loop {
+            self.cc.close(tcx)?;
+            let ctors = self.constructors(ctx);
+            for n in 0..self.cc.num_nodes() {
+                let Some(k) = self.constructor_of(ctx, n) else { continue };
+                let c = ctors[&self.cc.find(n)];
+                if c == n {
+                    continue;
+                }
+                if self.constructor_of(ctx, c) != Some(k) {
+                    return Err(self.cc.explain(c, n, tcx));
+                }
+                let pairs: Vec<(Node, Node)> = match (self.cc.app(c), self.cc.app(n)) {
+                    (Some((_, xs)), Some((_, ys))) => xs.iter().copied().zip(ys.iter().copied()).collect(),
+                    _ => Vec::new(),
+                };
+                if let Some(&(x, y)) = pairs.iter().find(|&&(x, y)| !self.cc.are_equal(x, y)) {
+                    let why = self.cc.explain(c, n, tcx);
+                    let why = self.keep(why, tcx);
+                    self.cc.assert_eq(x, y, why, tcx)?;
+                    continue 'changed;
+                }
+            }
+            for n in 0..self.cc.num_nodes() {
+                let Some((f, &[a])) = self.cc.app(n) else { continue };
+                let (_, k, field) = self.symbol_info[f as usize];
+                let Some(&c) = ctors.get(&self.cc.find(a)) else { continue };
+                if field == 0 || self.constructor_of(ctx, c) != Some(k) {
+                    continue;
+                }
+                let Some((_, args)) = self.cc.app(c) else { continue };
+                let x = args[field as usize - 1];
+                if !self.cc.are_equal(n, x) {
+                    let why = self.cc.explain(a, c, tcx);
+                    let why = self.keep(why, tcx);
+                    self.cc.assert_eq(n, x, why, tcx)?;
+                    continue 'changed;
+                }
+            }
+            for &(_, a, k, value, r) in &self.testers {
+                let Some(&c) = ctors.get(&self.cc.find(a)) else { continue };
+                if (self.constructor_of(ctx, c) == Some(k)) != value {
+                    let eq = self.cc.explain(a, c, tcx);
+                    return Err(tcx.r_and(vec![r, eq]));
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    /// Why some class is reachable from itself through constructor arguments, if one is.
+    fn find_cycle(&self, ctors: &HashMap<Node, Node, FxBuild>, tcx: &mut TheoryCtx) -> Option<ReasonId> {
+        let mut done: HashMap<Node, bool, FxBuild> = HashMap::default();
+        let mut path = Vec::new();
+        let mut roots: Vec<Node> = ctors.keys().copied().collect();
+        roots.sort();
+        roots.into_iter().find_map(|r| self.visit(r, ctors, &mut done, &mut path, tcx))
+    }
+
+    /// Depth-first search from class `r`. `path` holds, for each class being visited, its
+    /// constructor application and the argument followed; `done[r]` is false while `r`
+    /// is on the path.
+    fn visit(
+        &self,
+        r: Node,
+        ctors: &HashMap<Node, Node, FxBuild>,
+        done: &mut HashMap<Node, bool, FxBuild>,
+        path: &mut Vec<(Node, Node, Node)>,
+        tcx: &mut TheoryCtx,
+    ) -> Option<ReasonId> {
+        match done.get(&r) {
+            Some(true) => return None,
+            Some(false) => {
+                // Each step's constructor is in the class the previous argument led to.
+                let start = path.iter().position(|&(s, _, _)| s == r).expect("class on the path");
+                let steps = &path[start..];
+                let mut why = Vec::new();
+                for (i, &(_, c, _)) in steps.iter().enumerate() {
+                    let (_, _, prev) = steps[(i + steps.len() - 1) % steps.len()];
+                    if prev != c {
+                        why.push(self.cc.explain(prev, c, tcx));
+                    }
+                }
+                return Some(tcx.r_and(why));
+            }
+            None => {}
+        }
+        let &c = ctors.get(&r)?;
+        done.insert(r, false);
+        for a in self.cc.app(c).map(|(_, args)| args.to_vec()).unwrap_or_default() {
+            path.push((r, c, a));
+            if let Some(why) = self.visit(self.cc.find(a), ctors, done, path, tcx) {
+                return Some(why);
+            }
+            path.pop();
+        }
+        done.insert(r, true);
+        None
+    }
+
+    /// Record `key`; false if it was already made.
+    fn instantiate(&mut self, key: Instance) -> bool {
+        if !self.instances.insert(key) {
+            return false;
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.instances.push(key);
+        }
+        true
+    }
+
+    /// `is_c(t) → t = c(sel_1(t), …)` for the tester `is_c(t)`.
+    fn unfold(&mut self, ctx: &mut Context, tester: TermId, tcx: &mut TheoryCtx) -> TheoryLemma {
+        let TermKind::Tester(k, t) = *ctx.term_node(tester).0 else { unreachable!("tester term") };
+        let sort = ctx.term_sort(t);
+        let fields = ctx.constructor(sort, k).fields.len() as u32;
+        let sels: Vec<TermId> = (0..fields).map(|i| ctx.selector(k, i, t)).collect();
+        let c = ctx.construct(sort, k, &sels);
+        let eq = ctx.eq(t, c);
+        let not = ctx.not(tester);
+        TheoryLemma { because: tcx.r_and(Vec::new()), atoms: vec![not, eq] }
+    }
+
+    /// Field lemmas, then splits for the classes that need a constructor and have none.
+    fn lemmas(&mut self, ctx: &mut Context, ctors: &HashMap<Node, Node, FxBuild>, tcx: &mut TheoryCtx) -> Vec<TheoryLemma> {
+        let mut lemmas = Vec::new();
+        for n in 0..self.cc.num_nodes() {
+            let c = self.cc.term(n);
+            let TermKind::Construct(k, args) = ctx.term_node(c).0 else { continue };
+            let (k, args) = (*k, args.clone());
+            for (i, x) in (0u32..).zip(args) {
+                let arithmetic = matches!(
+                    ctx.term_node(x).0,
+                    TermKind::IntConst(_) | TermKind::RatConst(_) | TermKind::Add(_) | TermKind::Sub(..) | TermKind::Mul(..)
+                );
+                if arithmetic && self.instantiate(Instance::Field(c, i)) {
+                    let sel = ctx.selector(k, i, c);
+                    let eq = ctx.eq(sel, x);
+                    lemmas.push(TheoryLemma { because: tcx.r_and(Vec::new()), atoms: vec![eq] });
+                }
+            }
+        }
+
+        let mut needy = Vec::new();
+        for n in 0..self.cc.num_nodes() {
+            match self.cc.app(n) {
+                Some((f, &[a])) if self.symbol_info[f as usize].2 > 0 => needy.push(self.cc.find(a)),
+                _ => {}
+            }
+            let sort = ctx.term_sort(self.cc.term(n));
+            if ctx.datatype(sort).is_some() && ctx.is_finite_sort(sort) {
+                needy.push(self.cc.find(n));
+            }
+        }
+        needy.extend(self.testers.iter().map(|&(_, a, _, _, _)| self.cc.find(a)));
+        needy.sort();
+        needy.dedup();
+        for r in needy {
+            if ctors.contains_key(&r) {
+                continue;
+            }
+            let asserted = self.testers.iter().find(|&&(_, a, _, value, _)| value && self.cc.find(a) == r).map(|x| x.0);
+            if let Some(tester) = asserted {
+                if self.instantiate(Instance::Unfold(tester)) {
+                    lemmas.push(self.unfold(ctx, tester, tcx));
+                }
+                continue;
+            }
+            let t = self.cc.term(r);
+            if !self.instantiate(Instance::Split(t)) {
+                continue;
+            }
+            let count = ctx.datatype(ctx.term_sort(t)).expect("datatype class").constructors.len() as u32;
+            let testers: Vec<TermId> = (0..count).map(|k| ctx.tester(k, t)).collect();
+            lemmas.push(TheoryLemma { because: tcx.r_and(Vec::new()), atoms: testers.clone() });
+            for tester in testers {
+                self.instantiate(Instance::Unfold(tester));
+                lemmas.push(self.unfold(ctx, tester, tcx));
+            }
+        }
+        lemmas
+    }
+}
+
+/// `t` and, through constructors and selectors, their arguments.
+fn mentioned(ctx: &Context, t: TermId, out: &mut Vec<TermId>) {
+    out.push(t);
+    match ctx.term_node(t).0 {
+        TermKind::Construct(_, args) => {
+            for &a in args {
+                mentioned(ctx, a, out);
+            }
+        }
+        TermKind::Selector(_, _, a) => mentioned(ctx, *a, out),
+        _ => {}
+    }
+}
+
+/// Datatype terms (and what they mention) under the structure of another theory's atom.
+fn foreign_mentions(ctx: &Context, t: TermId, out: &mut Vec<TermId>) {
+    match ctx.term_node(t).0 {
+        TermKind::Construct(..) | TermKind::Selector(..) => mentioned(ctx, t, out),
+        TermKind::Eq(a, b) | TermKind::Le(a, b) | TermKind::Sub(a, b) | TermKind::Mul(a, b) | TermKind::Select(a, b) => {
+            foreign_mentions(ctx, *a, out);
+            foreign_mentions(ctx, *b, out);
+        }
+        TermKind::Store(a, i, v) => {
+            for x in [a, i, v] {
+                foreign_mentions(ctx, *x, out);
+            }
+        }
+        TermKind::Add(xs) | TermKind::Bv(_, xs) | TermKind::App { args: xs, .. } => {
+            for &x in xs {
+                foreign_mentions(ctx, x, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl Theory for DatatypeTheory {
+    fn name(&self) -> &'static str { "DT" }
+
+    fn owns_atom(&self, ctx: &Context, atom_term: TermId) -> bool {
+        match *ctx.term_node(atom_term).0 {
+            TermKind::Eq(a, _) => ctx.datatype(ctx.term_sort(a)).is_some(),
+            TermKind::Tester(..) => true,
+            _ => false,
+        }
+    }
+
+    fn atom_endpoints(&self, atom_term: TermId) -> Vec<TermId> {
+        self.mentions.get(&atom_term).cloned().unwrap_or_default()
+    }
+
+    fn foreign_endpoints(&self, atom_term: TermId) -> Vec<TermId> {
+        self.mentions.get(&atom_term).cloned().unwrap_or_default()
+    }
+
+    fn register_atom(&mut self, ctx: &Context, atom: Atom) {
+        let mut ends = Vec::new();
+        let what = match *ctx.term_node(atom.term).0 {
+            TermKind::Eq(a, b) => {
+                mentioned(ctx, a, &mut ends);
+                mentioned(ctx, b, &mut ends);
+                Assertion::Eq(a, b, true)
+            }
+            TermKind::Tester(k, a) => {
+                mentioned(ctx, a, &mut ends);
+                Assertion::Tester(atom.term, a, k, true)
+            }
+            _ => panic!("datatype atom is not an equality or a tester"),
+        };
+        self.atoms.insert(atom.var, what);
+        ends.sort();
+        ends.dedup();
+        if !self.dirty {
+            for &t in &ends {
+                self.add_term(ctx, t);
+            }
+        }
+        self.mentions.insert(atom.term, ends);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.atoms.push((atom.var, atom.term));
+        }
+    }
+
+    fn register_foreign_atom(&mut self, ctx: &Context, atom: Atom) {
+        let mut ends = Vec::new();
+        foreign_mentions(ctx, atom.term, &mut ends);
+        // An equality over terms the closure knows is one it must hear about.
+        if let TermKind::Eq(a, b) = *ctx.term_node(atom.term).0 {
+            if !ends.is_empty() || self.cc.node(a).is_some() || self.cc.node(b).is_some() {
+                ends.extend([a, b]);
+            }
+        }
+        if ends.is_empty() {
+            return;
+        }
+        ends.sort();
+        ends.dedup();
+        if !self.dirty {
+            for &t in &ends {
+                self.add_term(ctx, t);
+            }
+        }
+        self.mentions.insert(atom.term, ends);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.foreign.push(atom.term);
+        }
+    }
+
+    fn assert_atom(&mut self, ctx: &Context, atom: Atom, value: bool, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        if let Err(conflict) = self.sync(ctx, tcx) {
+            return TheoryOutcome::Conflict(conflict);
+        }
+        let Some(&what) = self.atoms.get(&atom.var) else { return TheoryOutcome::Ok };
+        let what = match what {
+            Assertion::Eq(a, b, _) => Assertion::Eq(a, b, value),
+            Assertion::Tester(t, a, k, _) => Assertion::Tester(t, a, k, value),
+        };
+        let lit = if value { Lit::pos(atom.var) } else { Lit::neg(atom.var) };
+        let r = tcx.r_lit(lit);
+        self.assert_fact(what, r, Some(atom.var), tcx)
+    }
+
+    fn propagate(&mut self, ctx: &Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        match self.sync(ctx, tcx) {
+            Ok(()) => TheoryOutcome::Ok,
+            Err(conflict) => TheoryOutcome::Conflict(conflict),
+        }
+    }
+
+    fn final_check(&mut self, ctx: &mut Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        if let Err(conflict) = self.sync(ctx, tcx) {
+            return TheoryOutcome::Conflict(conflict);
+        }
+        let ctors = self.constructors(ctx);
+        if let Some(cycle) = self.find_cycle(&ctors, tcx) {
+            return TheoryOutcome::Conflict(cycle);
+        }
+        let lemmas = self.lemmas(ctx, &ctors, tcx);
+        if lemmas.is_empty() { TheoryOutcome::Ok } else { TheoryOutcome::Lemmas(lemmas) }
+    }
+
+    /// The class of `t`; the model builder turns datatype classes into values.
+    fn model_value(&self, ctx: &Context, t: TermId) -> Option<Value> {
+        let n = self.cc.node(t)?;
+        Some(Value::Elem(ctx.term_sort(t), self.cc.find(n) as u32))
+    }
+
+    fn push_level(&mut self) {
+        self.cc.push_level();
+        self.levels.push((self.testers.len(), self.deferred.len()));
+    }
+
+    fn pop_levels(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.cc.pop_levels(n);
+        let (testers, deferred) = self.levels[self.levels.len() - n];
+        self.levels.truncate(self.levels.len() - n);
+        self.testers.truncate(testers);
+        self.deferred.truncate(deferred);
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn pop_scopes(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        for scope in self.scopes.drain(self.scopes.len() - n..) {
+            for (v, t) in scope.atoms {
+                self.atoms.remove(&v);
+                self.mentions.remove(&t);
+            }
+            for t in scope.foreign {
+                self.mentions.remove(&t);
+            }
+            for key in scope.instances {
+                self.instances.remove(&key);
+            }
+        }
+        let (atoms, depth) = (&self.atoms, self.scopes.len());
+        self.facts.retain(|f| match f.atom {
+            Some(v) => atoms.contains_key(&v),
+            None => f.scope <= depth,
+        });
+        self.dirty = true;
+    }
+
+    fn equality_sharing_mut(&mut self) -> Option<&mut dyn EqualitySharing> { Some(self) }
+}
+
+impl EqualitySharing for DatatypeTheory {
+    /// Classes of the closure among shared terms.
+    fn export_classes(&mut self, oracle: &SharedTermOracle, _export_epoch: u64, tcx: &mut TheoryCtx) -> Vec<EqClass> {
+        if self.dirty {
+            return Vec::new();
+        }
+        self.cc.classes(oracle.shared_set().iter().copied(), tcx)
+    }
+
+    fn import_equality(&mut self, eq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        self.assert_fact(Assertion::Eq(eq.a, eq.b, true), eq.explain, None, tcx)
+    }
+
+    fn import_disequality(&mut self, diseq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        self.assert_fact(Assertion::Eq(diseq.a, diseq.b, false), diseq.explain, None, tcx)
+    }
+}
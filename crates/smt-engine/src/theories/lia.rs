@@ -0,0 +1,208 @@
+#![forbid(unsafe_code)]
+//! Linear integer arithmetic over `<=` and `=` on Int.
+//!
+//! The integer [`Domain`] of the arithmetic solver. Atoms are normalized to coprime
+//! integer coefficients, which rounds `k` (the GCD test: `2x + 4y = 3` is simply false),
+//! and strict bounds are the next integer. Once the relaxation is feasible, a variable
+//! with fractional value `v` is split on `x <= ⌊v⌋` over a fresh atom (branch and bound),
+//! replaced every few rounds by a Gomory cut when the row allows it.
+
+use hashbrown::HashMap;
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{Context, Rational, SortId, TermId, Value};
+
+use crate::theories::arith::{ArithTheory, Domain};
+use crate::theories::linear::LinExpr;
+use crate::theories::simplex::Var;
+use crate::theory::{TheoryLemma, TheoryOutcome};
+use crate::theory_ctx::TheoryCtx;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+/// Cuts with larger coefficients are dropped (in favour of branching): they make the
+/// tableau's numbers grow quickly.
+const MAX_CUT_COEFF: u64 = 1 << 12;
+
+pub type LiaTheory = ArithTheory<Integers>;
+
+pub struct Integers {
+    /// `x <= k` atoms created for branching.
+    branch_atoms: HashMap<(Var, i128), TermId, FxBuild>,
+    /// Branch atoms created in each open user scope.
+    scopes: Vec<Vec<(Var, i128)>>,
+    /// Try a Gomory cut instead of branching on every `cut_period`-th fractional
+    /// assignment (0: never).
+    cut_period: usize,
+    fractional_rounds: usize,
+}
+
+impl Default for Integers {
+    fn default() -> Self {
+        Self { branch_atoms: HashMap::default(), scopes: Vec::new(), cut_period: 4, fractional_rounds: 0 }
+    }
+}
+
+impl Domain for Integers {
+    type V = Rational;
+
+    const NAME: &'static str = "LIA";
+
+    fn sort(ctx: &Context) -> SortId { ctx.int_sort() }
+
+    fn content(comb: &[(Var, Rational)]) -> Rational {
+        Rational::int(comb.iter().fold(0, |g, &(_, c)| gcd(g, c.numer())))
+    }
+
+    fn round(k: Rational, eq: bool, lower: bool) -> Option<Rational> {
+        match (eq, lower) {
+            (true, _) => k.is_integer().then_some(k),
+            (false, false) => Some(Rational::int(k.floor())),
+            (false, true) => Some(Rational::int(k.ceil())),
+        }
+    }
+
+    fn exact(k: Rational) -> Rational { k }
+
+    fn above(k: Rational) -> Rational { k + Rational::ONE }
+
+    fn below(k: Rational) -> Rational { k - Rational::ONE }
+
+    /// Slacks are integral, so implied bounds round inwards.
+    fn tighten(v: Rational, upper: bool) -> Rational {
+        Rational::int(if upper { v.floor() } else { v.ceil() })
+    }
+
+    fn concrete(&self, v: Rational) -> Option<Rational> { Some(v) }
+
+    fn model_value(&self, v: Rational) -> Option<Value> {
+        if !v.is_integer() {
+            return None;
+        }
+        i64::try_from(v.numer()).ok().map(Value::Int)
+    }
+
+    fn final_check(th: &mut LiaTheory, ctx: &mut Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let Some(x) = (0..th.simplex.num_vars())
+            .find(|&x| th.var_term[x].is_some() && !th.simplex.value(x).is_integer())
+        else {
+            return TheoryOutcome::Ok;
+        };
+        let d = &mut th.domain;
+        d.fractional_rounds += 1;
+        // Non-basic variables sit at (integral) bounds, so `x` is basic.
+        if d.cut_period > 0 && d.fractional_rounds.is_multiple_of(d.cut_period) {
+            if let Some(cut) = th.gomory_cut(ctx, x, tcx) {
+                return cut;
+            }
+        }
+        th.branch(ctx, x, tcx).unwrap_or_else(|| {
+            th.stuck = true;
+            TheoryOutcome::Ok
+        })
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn pop_scopes(&mut self, n: usize) {
+        for key in self.scopes.drain(self.scopes.len() - n..).flatten() {
+            self.branch_atoms.remove(&key);
+        }
+    }
+}
+
+impl LiaTheory {
+    /// Set how often final checks cut instead of branching (0 disables cuts).
+    pub fn with_cut_period(mut self, period: usize) -> Self {
+        self.domain.cut_period = period;
+        self
+    }
+
+    /// `x <= ⌊v⌋ ∨ x > ⌊v⌋` over a fresh atom, if `⌊v⌋` fits an integer constant.
+    fn branch(&mut self, ctx: &mut Context, x: Var, tcx: &mut TheoryCtx) -> Option<TheoryOutcome> {
+        let k = self.simplex.value(x).floor();
+        let atom = match self.domain.branch_atoms.get(&(x, k)) {
+            Some(&t) => t,
+            None => {
+                let bound = ctx.int_const(i64::try_from(k).ok()?);
+                let t = ctx.le(self.var_term[x].expect("original variable"), bound);
+                self.domain.branch_atoms.insert((x, k), t);
+                if let Some(added) = self.domain.scopes.last_mut() {
+                    added.push((x, k));
+                }
+                t
+            }
+        };
+        let not = ctx.not(atom);
+        Some(TheoryOutcome::Lemma(TheoryLemma { because: tcx.r_and(Vec::new()), atoms: vec![atom, not] }))
+    }
+
+    /// Gomory cut from the row of basic `x`, whose value is fractional. Needs every
+    /// non-basic variable of the row at one of its bounds, and numbers that fit.
+    fn gomory_cut(&mut self, ctx: &mut Context, x: Var, tcx: &mut TheoryCtx) -> Option<TheoryOutcome> {
+        let row = self.simplex.row(x)?.to_vec();
+        let f0 = self.simplex.value(x).fract();
+        // x = β + Σ a'_j t_j with t_j >= 0 the distance of y_j from its bound.
+        let mut cut = LinExpr::default();
+        let mut rhs = Rational::ONE;
+        let mut premises = Vec::new();
+        for (y, a) in row {
+            let v = self.simplex.value(y);
+            let (at_lower, b) = match (self.simplex.lower(y), self.simplex.upper(y)) {
+                (Some(l), _) if l.value == v => (true, l),
+                (_, Some(u)) if u.value == v => (false, u),
+                _ => return None,
+            };
+            let a_bar = if at_lower { -a } else { a };
+            let fj = a_bar.fract();
+            let c = if fj <= f0 {
+                fj.checked_div(f0)?
+            } else {
+                (Rational::ONE - fj).checked_div(Rational::ONE - f0)?
+            };
+            if c.is_zero() {
+                continue;
+            }
+            premises.push(b.reason);
+            // c t_j = ±c y_j ∓ c bound
+            let signed = if at_lower { c } else { -c };
+            rhs = rhs.checked_add(signed.checked_mul(b.value)?)?;
+            let def = match self.slack_def.get(&y) {
+                Some(def) => def.clone(),
+                None => vec![(y, Rational::ONE)],
+            };
+            for (z, d) in def {
+                // Variables of popped scopes have no term left.
+                let t = self.var_term[z]?;
+                let term = LinExpr { coeffs: vec![(t, Rational::ONE)], constant: Rational::ZERO };
+                cut = cut.checked_add_scaled(&term, signed.checked_mul(d)?)?;
+            }
+        }
+        // Scale to integers: Σ cut >= rhs.
+        let lcm = cut.coeffs.iter().map(|(_, c)| c.denom()).try_fold(rhs.denom(), |l, d| (l / gcd(l, d)).checked_mul(d))?;
+        let scale = Rational::int(lcm);
+        let small = |r: Rational| i64::try_from(r.checked_mul(scale)?.numer()).ok().filter(|v| v.unsigned_abs() <= MAX_CUT_COEFF);
+        let rhs = small(rhs)?;
+        let coeffs: Vec<(TermId, i64)> = cut.coeffs.iter().map(|&(t, c)| small(c).map(|c| (t, c))).collect::<Option<_>>()?;
+        let mut summands = Vec::new();
+        for (t, c) in coeffs {
+            let c = ctx.int_const(c);
+            summands.push(ctx.mul(c, t));
+        }
+        let sum = ctx.add(&summands);
+        let rhs = ctx.int_const(rhs);
+        let atom = ctx.le(rhs, sum);
+        let because = tcx.r_and(premises);
+        Some(TheoryOutcome::Lemma(TheoryLemma { because, atoms: vec![atom] }))
+    }
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
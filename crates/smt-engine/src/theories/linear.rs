@@ -0,0 +1,79 @@
+#![forbid(unsafe_code)]
+//! Linearization of arithmetic terms.
+//!
+//! Sums, differences, constants and products with a constant factor are interpreted;
+//! every other subterm (constants, UF applications, non-linear products) is an opaque
+//! variable.
+
+use hashbrown::HashMap;
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{Context, Rational, TermId, TermKind};
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+/// `Σ coeffs + constant`, with `coeffs` sorted by term and free of zeros.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinExpr {
+    pub coeffs: Vec<(TermId, Rational)>,
+    pub constant: Rational,
+}
+
+impl LinExpr {
+    /// `self + k * other`.
+    pub fn add_scaled(&self, other: &LinExpr, k: Rational) -> LinExpr {
+        self.checked_add_scaled(other, k).expect("rational arithmetic overflow")
+    }
+
+    /// `self + k * other`, unless that overflows.
+    pub fn checked_add_scaled(&self, other: &LinExpr, k: Rational) -> Option<LinExpr> {
+        let mut acc: HashMap<TermId, Rational, FxBuild> = HashMap::default();
+        for &(t, c) in &self.coeffs {
+            *acc.entry(t).or_insert(Rational::ZERO) = c;
+        }
+        for &(t, c) in &other.coeffs {
+            let e = acc.entry(t).or_insert(Rational::ZERO);
+            *e = e.checked_add(k.checked_mul(c)?)?;
+        }
+        let mut coeffs: Vec<(TermId, Rational)> = acc.into_iter().filter(|(_, c)| !c.is_zero()).collect();
+        coeffs.sort_by_key(|&(t, _)| t);
+        Some(LinExpr { coeffs, constant: self.constant.checked_add(k.checked_mul(other.constant)?)? })
+    }
+
+    fn scale(&self, k: Rational) -> LinExpr {
+        LinExpr::default().add_scaled(self, k)
+    }
+
+    fn var(t: TermId) -> LinExpr {
+        LinExpr { coeffs: vec![(t, Rational::ONE)], constant: Rational::ZERO }
+    }
+
+    fn constant(k: Rational) -> LinExpr {
+        LinExpr { coeffs: Vec::new(), constant: k }
+    }
+}
+
+/// Linear form of the arithmetic term `t`.
+pub fn linearize(ctx: &Context, t: TermId) -> LinExpr {
+    match ctx.term_node(t).0 {
+        TermKind::IntConst(k) => LinExpr::constant(Rational::from(*k)),
+        TermKind::RatConst(k) => LinExpr::constant(*k),
+        TermKind::Add(xs) => xs.iter().fold(LinExpr::default(), |acc, &x| acc.add_scaled(&linearize(ctx, x), Rational::ONE)),
+        TermKind::Sub(a, b) => linearize(ctx, *a).add_scaled(&linearize(ctx, *b), -Rational::ONE),
+        TermKind::Mul(a, b) => {
+            let (la, lb) = (linearize(ctx, *a), linearize(ctx, *b));
+            match (la.coeffs.is_empty(), lb.coeffs.is_empty()) {
+                (true, _) => lb.scale(la.constant),
+                (_, true) => la.scale(lb.constant),
+                _ => LinExpr::var(t),
+            }
+        }
+        _ => LinExpr::var(t),
+    }
+}
+
+/// `lhs - rhs`, the form atoms are normalized from.
+pub fn difference(ctx: &Context, lhs: TermId, rhs: TermId) -> LinExpr {
+    linearize(ctx, lhs).add_scaled(&linearize(ctx, rhs), -Rational::ONE)
+}
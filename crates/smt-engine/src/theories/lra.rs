@@ -0,0 +1,120 @@
+#![forbid(unsafe_code)]
+//! Linear real arithmetic over `<=` and `=` on Real.
+//!
+//! The real [`Domain`] of the arithmetic solver. The simplex runs over `r + kδ` with δ
+//! a positive infinitesimal, so a strict bound (from a negated `<=`) is an ordinary one:
+//! `¬(x <= k)` bounds `x >= k + δ`. Once the relaxation is feasible, δ is fixed to a
+//! rational small enough for every bound to hold, which gives the model.
+
+use core::ops::{Add, Sub};
+
+use smt_core::{Context, Rational, SortId, Value};
+
+use crate::theories::arith::{ArithTheory, Domain};
+use crate::theories::simplex::{Num, Overflow, Var};
+
+/// `r + dδ`, ordered lexicographically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QDelta {
+    pub r: Rational,
+    pub d: Rational,
+}
+
+impl QDelta {
+    pub fn new(r: Rational, d: Rational) -> Self {
+        Self { r, d }
+    }
+}
+
+impl Add for QDelta {
+    type Output = QDelta;
+    fn add(self, o: QDelta) -> QDelta {
+        QDelta::new(self.r + o.r, self.d + o.d)
+    }
+}
+
+impl Sub for QDelta {
+    type Output = QDelta;
+    fn sub(self, o: QDelta) -> QDelta {
+        QDelta::new(self.r - o.r, self.d - o.d)
+    }
+}
+
+impl Num for QDelta {
+    fn checked_add(self, o: Self) -> Option<Self> {
+        Some(QDelta::new(self.r.checked_add(o.r)?, self.d.checked_add(o.d)?))
+    }
+
+    fn checked_sub(self, o: Self) -> Option<Self> {
+        Some(QDelta::new(self.r.checked_sub(o.r)?, self.d.checked_sub(o.d)?))
+    }
+
+    fn checked_scale(self, k: Rational) -> Option<Self> {
+        Some(QDelta::new(self.r.checked_mul(k)?, self.d.checked_mul(k)?))
+    }
+}
+
+pub type LraTheory = ArithTheory<Reals>;
+
+pub struct Reals {
+    /// Value of δ in the model, set by the last final check.
+    delta: Rational,
+}
+
+impl Default for Reals {
+    fn default() -> Self {
+        Self { delta: Rational::ONE }
+    }
+}
+
+impl Domain for Reals {
+    type V = QDelta;
+
+    const NAME: &'static str = "LRA";
+
+    fn sort(ctx: &Context) -> SortId { ctx.real_sort() }
+
+    fn content(comb: &[(Var, Rational)]) -> Rational {
+        comb[0].1.abs()
+    }
+
+    fn round(k: Rational, _eq: bool, _lower: bool) -> Option<Rational> {
+        Some(k)
+    }
+
+    fn exact(k: Rational) -> QDelta { QDelta::new(k, Rational::ZERO) }
+
+    fn above(k: Rational) -> QDelta { QDelta::new(k, Rational::ONE) }
+
+    fn below(k: Rational) -> QDelta { QDelta::new(k, -Rational::ONE) }
+
+    fn tighten(v: QDelta, _upper: bool) -> QDelta { v }
+
+    fn concrete(&self, v: QDelta) -> Option<Rational> {
+        v.r.checked_add(v.d.checked_mul(self.delta)?)
+    }
+
+    fn model_value(&self, v: Rational) -> Option<Value> {
+        Some(Value::Real(v))
+    }
+
+    /// Largest δ <= 1 keeping every `bound <= value <= bound` pair ordered.
+    fn settle(th: &mut LraTheory) -> Result<(), Overflow> {
+        let s = &th.simplex;
+        let mut delta = Rational::ONE;
+        for x in 0..s.num_vars() {
+            let v = s.value(x);
+            let pairs = [s.lower(x).map(|l| (l.value, v)), s.upper(x).map(|u| (v, u.value))];
+            for (a, b) in pairs.into_iter().flatten() {
+                // a <= b holds for small δ; it holds up to δ = (b.r - a.r) / (a.d - b.d).
+                if a.r < b.r && a.d > b.d {
+                    let gap = b.r.checked_sub(a.r).ok_or(Overflow)?;
+                    let slope = a.d.checked_sub(b.d).ok_or(Overflow)?;
+                    delta = delta.min(gap.checked_div(slope).ok_or(Overflow)?);
+                }
+            }
+        }
+        th.domain.delta = delta;
+        Ok(())
+    }
+}
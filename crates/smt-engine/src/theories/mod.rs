@@ -0,0 +1,14 @@
+#![forbid(unsafe_code)]
+//! Built-in theory solvers.
+
+pub mod arith;
+pub mod arrays;
+pub mod bv;
+pub mod congruence;
+pub mod datatypes;
+pub mod lia;
+pub mod linear;
+pub mod lra;
+pub mod simplex;
+pub mod uf;
+pub mod utvpi;
@@ -0,0 +1,452 @@
+#![forbid(unsafe_code)]
+//! General simplex over exact rationals (Dutertre & de Moura), shared by the arithmetic
+//! theories.
+//!
+//! Coefficients are rationals; assigned values and bounds are any [`Num`], so the real
+//! theory can run it over rationals extended with an infinitesimal.
+//!
+//! Every row defines a basic variable as a combination of non-basic ones. Bounds carry
+//! the reason that justifies them and are undone with their decision level; the
+//! assignment is not, since relaxing bounds keeps it consistent with the rows. `check`
+//! pivots with Bland's rule, so it terminates, and reports infeasibility as a Farkas
+//! combination of bounds.
+//!
+//! Numbers that outgrow `i128` do not panic: a pivot that would overflow is not made and
+//! `check` reports `CheckFailure::Overflow`, leaving the tableau as it was. A bound whose
+//! update overflows is still recorded, and the next `check` recomputes the assignment.
+
+use hashbrown::HashSet;
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use core::fmt::Debug;
+use core::ops::{Add, Sub};
+
+use smt_core::Rational;
+
+use crate::reason::ReasonId;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+pub type Var = usize;
+
+/// Values the simplex assigns: an ordered group with a rational scalar product. The
+/// `checked_*` forms return `None` where the operators would overflow.
+pub trait Num: Copy + Ord + Default + Debug + Add<Output = Self> + Sub<Output = Self> {
+    fn checked_add(self, o: Self) -> Option<Self>;
+
+    fn checked_sub(self, o: Self) -> Option<Self>;
+
+    fn checked_scale(self, k: Rational) -> Option<Self>;
+
+    fn scale(self, k: Rational) -> Self {
+        self.checked_scale(k).expect("rational arithmetic overflow")
+    }
+}
+
+impl Num for Rational {
+    fn checked_add(self, o: Self) -> Option<Self> {
+        Rational::checked_add(self, o)
+    }
+
+    fn checked_sub(self, o: Self) -> Option<Self> {
+        Rational::checked_sub(self, o)
+    }
+
+    fn checked_scale(self, k: Rational) -> Option<Self> {
+        self.checked_mul(k)
+    }
+}
+
+/// Sparse linear combination, sorted by variable, without zero coefficients.
+pub type Combination = Vec<(Var, Rational)>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Bound<V = Rational> {
+    pub value: V,
+    pub reason: ReasonId,
+}
+
+/// Bounds whose combination with the (positive) coefficients is infeasible.
+pub type Infeasible = Vec<(Rational, ReasonId)>;
+
+/// A step whose numbers do not fit the rationals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow;
+
+/// Why `Simplex::check` found no feasible assignment.
+#[derive(Debug, Clone)]
+pub enum CheckFailure {
+    Infeasible(Infeasible),
+    Overflow,
+}
+
+/// Other rows using the entering variable of a pivot, rewritten: `(row, combination,
+/// variables that appeared, variables that vanished)`.
+type Substituted = Vec<(usize, Combination, Vec<Var>, Vec<Var>)>;
+
+#[derive(Debug, Clone, Copy)]
+enum Undo<V> {
+    Lower(Var, Option<Bound<V>>),
+    Upper(Var, Option<Bound<V>>),
+}
+
+#[derive(Debug)]
+pub struct Simplex<V = Rational> {
+    value: Vec<V>,
+    lower: Vec<Option<Bound<V>>>,
+    upper: Vec<Option<Bound<V>>>,
+    /// Row defining a basic variable.
+    row_of: Vec<Option<usize>>,
+    /// `rows[r] = (basic, combination of non-basic variables)`.
+    rows: Vec<(Var, Combination)>,
+    /// Rows mentioning a non-basic variable.
+    cols: Vec<HashSet<usize, FxBuild>>,
+    trail: Vec<Undo<V>>,
+    levels: Vec<usize>,
+    /// An update overflowed: the assignment may break rows or non-basic bounds until
+    /// `check` recomputes it.
+    stale: bool,
+}
+
+impl<V> Default for Simplex<V> {
+    fn default() -> Self {
+        Self {
+            value: Vec::new(),
+            lower: Vec::new(),
+            upper: Vec::new(),
+            row_of: Vec::new(),
+            rows: Vec::new(),
+            cols: Vec::new(),
+            trail: Vec::new(),
+            levels: Vec::new(),
+            stale: false,
+        }
+    }
+}
+
+/// `dst + k * src`, with the variables that appeared and vanished; `None` on overflow.
+fn add_scaled(dst: &[(Var, Rational)], src: &[(Var, Rational)], k: Rational) -> Option<(Combination, Vec<Var>, Vec<Var>)> {
+    let (mut out, mut added, mut removed) = (Combination::new(), Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < dst.len() || j < src.len() {
+        let take_old = j == src.len() || (i < dst.len() && dst[i].0 < src[j].0);
+        let take_src = i == dst.len() || (j < src.len() && src[j].0 < dst[i].0);
+        if take_old {
+            out.push(dst[i]);
+            i += 1;
+        } else if take_src {
+            out.push((src[j].0, k.checked_mul(src[j].1)?));
+            added.push(src[j].0);
+            j += 1;
+        } else {
+            let c = dst[i].1.checked_add(k.checked_mul(src[j].1)?)?;
+            if c.is_zero() {
+                removed.push(dst[i].0);
+            } else {
+                out.push((dst[i].0, c));
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+    Some((out, added, removed))
+}
+
+/// `Σ a x` over `row` under `value`.
+fn row_value<V: Num>(row: &[(Var, Rational)], value: &[V]) -> Option<V> {
+    row.iter().try_fold(V::default(), |acc, &(x, a)| acc.checked_add(value[x].checked_scale(a)?))
+}
+
+fn coeff(comb: &[(Var, Rational)], x: Var) -> Option<Rational> {
+    comb.binary_search_by_key(&x, |&(v, _)| v).ok().map(|i| comb[i].1)
+}
+
+impl<V: Num> Simplex<V> {
+    pub fn num_vars(&self) -> usize { self.value.len() }
+
+    pub fn new_var(&mut self) -> Var {
+        self.value.push(V::default());
+        self.lower.push(None);
+        self.upper.push(None);
+        self.row_of.push(None);
+        self.cols.push(HashSet::default());
+        self.value.len() - 1
+    }
+
+    /// New basic variable equal to `comb` (over any variables), unless substituting the
+    /// rows of its basic variables overflows.
+    pub fn add_row(&mut self, comb: &[(Var, Rational)]) -> Result<Var, Overflow> {
+        let mut row = Combination::new();
+        for &(x, a) in comb {
+            let (src, k) = match self.row_of[x] {
+                Some(r) => (self.rows[r].1.as_slice(), a),
+                None => (&[(x, a)][..], Rational::ONE),
+            };
+            row = add_scaled(&row, src, k).ok_or(Overflow)?.0;
+        }
+        let s = self.new_var();
+        let r = self.rows.len();
+        for &(x, _) in &row {
+            self.cols[x].insert(r);
+        }
+        match row_value(&row, &self.value) {
+            Some(v) => self.value[s] = v,
+            None => self.stale = true,
+        }
+        self.rows.push((s, row));
+        self.row_of[s] = Some(r);
+        Ok(s)
+    }
+
+    pub fn value(&self, x: Var) -> V { self.value[x] }
+
+    pub fn lower(&self, x: Var) -> Option<Bound<V>> { self.lower[x] }
+
+    pub fn upper(&self, x: Var) -> Option<Bound<V>> { self.upper[x] }
+
+    pub fn is_basic(&self, x: Var) -> bool { self.row_of[x].is_some() }
+
+    /// Row of a basic variable.
+    pub fn row(&self, x: Var) -> Option<&[(Var, Rational)]> {
+        self.row_of[x].map(|r| self.rows[r].1.as_slice())
+    }
+
+    /// Basic variables, in row order.
+    pub fn basics(&self) -> impl Iterator<Item = Var> + '_ {
+        self.rows.iter().map(|(b, _)| *b)
+    }
+
+    pub fn push_level(&mut self) {
+        self.levels.push(self.trail.len());
+    }
+
+    pub fn pop_levels(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let target = self.levels[self.levels.len() - n];
+        self.levels.truncate(self.levels.len() - n);
+        while self.trail.len() > target {
+            match self.trail.pop().expect("non-empty") {
+                Undo::Lower(x, b) => self.lower[x] = b,
+                Undo::Upper(x, b) => self.upper[x] = b,
+            }
+        }
+    }
+
+    pub fn level(&self) -> usize { self.levels.len() }
+
+    /// `x <= value`. Returns `Ok(false)` if the bound is not tighter than the current one.
+    pub fn assert_upper(&mut self, x: Var, b: Bound<V>) -> Result<bool, Infeasible> {
+        if self.upper[x].is_some_and(|u| u.value <= b.value) {
+            return Ok(false);
+        }
+        if let Some(l) = self.lower[x].filter(|l| l.value > b.value) {
+            return Err(vec![(Rational::ONE, l.reason), (Rational::ONE, b.reason)]);
+        }
+        self.trail.push(Undo::Upper(x, self.upper[x]));
+        self.upper[x] = Some(b);
+        if !self.is_basic(x) && self.value[x] > b.value && self.update(x, b.value).is_err() {
+            self.stale = true;
+        }
+        Ok(true)
+    }
+
+    /// `x >= value`. Returns `Ok(false)` if the bound is not tighter than the current one.
+    pub fn assert_lower(&mut self, x: Var, b: Bound<V>) -> Result<bool, Infeasible> {
+        if self.lower[x].is_some_and(|l| l.value >= b.value) {
+            return Ok(false);
+        }
+        if let Some(u) = self.upper[x].filter(|u| u.value < b.value) {
+            return Err(vec![(Rational::ONE, u.reason), (Rational::ONE, b.reason)]);
+        }
+        self.trail.push(Undo::Lower(x, self.lower[x]));
+        self.lower[x] = Some(b);
+        if !self.is_basic(x) && self.value[x] < b.value && self.update(x, b.value).is_err() {
+            self.stale = true;
+        }
+        Ok(true)
+    }
+
+    /// Restore feasibility of every row, or explain why that is impossible.
+    pub fn check(&mut self) -> Result<(), CheckFailure> {
+        if self.stale {
+            self.resync().map_err(|_| CheckFailure::Overflow)?;
+        }
+        loop {
+            // Bland: smallest violated basic variable, then smallest entering variable.
+            let Some((b, below)) = self
+                .basics()
+                .filter_map(|b| {
+                    let v = self.value[b];
+                    if self.lower[b].is_some_and(|l| v < l.value) {
+                        Some((b, true))
+                    } else if self.upper[b].is_some_and(|u| v > u.value) {
+                        Some((b, false))
+                    } else {
+                        None
+                    }
+                })
+                .min_by_key(|&(b, _)| b)
+            else {
+                return Ok(());
+            };
+            let row = self.row(b).expect("basic");
+            // Moving `b` up needs a variable with a positive coefficient that can grow or a
+            // negative one that can shrink; the other way round to move it down.
+            let entering = row
+                .iter()
+                .filter(|&&(x, a)| {
+                    let up = (a.signum() > 0) == below;
+                    if up {
+                        self.upper[x].is_none_or(|u| self.value[x] < u.value)
+                    } else {
+                        self.lower[x].is_none_or(|l| self.value[x] > l.value)
+                    }
+                })
+                .map(|&(x, _)| x)
+                .min();
+            let target = if below { self.lower[b] } else { self.upper[b] }.expect("violated bound").value;
+            match entering {
+                Some(x) => self.pivot_and_update(b, x, target).map_err(|_| CheckFailure::Overflow)?,
+                None => return Err(CheckFailure::Infeasible(self.explain_row(b, below))),
+            }
+        }
+    }
+
+    /// Farkas combination behind "`b` cannot reach its lower (`below`) or upper bound":
+    /// that bound plus the bounds holding each non-basic variable of its row.
+    fn explain_row(&self, b: Var, below: bool) -> Infeasible {
+        let own = if below { self.lower[b] } else { self.upper[b] }.expect("violated bound");
+        let mut out = vec![(Rational::ONE, own.reason)];
+        for &(x, a) in self.row(b).expect("basic") {
+            let blocking = if (a.signum() > 0) == below { self.upper[x] } else { self.lower[x] };
+            out.push((a.abs(), blocking.expect("blocking bound").reason));
+        }
+        out
+    }
+
+    /// Bounds on `Σ row` implied by the bounds of the row's variables: `(value, premises)`
+    /// for the upper (`upper = true`) or lower end, if every needed bound exists and the
+    /// sum fits.
+    pub fn implied_bound(&self, b: Var, upper: bool) -> Option<(V, Infeasible)> {
+        let mut total = V::default();
+        let mut premises = Vec::new();
+        for &(x, a) in self.row(b)? {
+            let bound = if (a.signum() > 0) == upper { self.upper[x] } else { self.lower[x] }?;
+            total = total.checked_add(bound.value.checked_scale(a)?)?;
+            premises.push((a.abs(), bound.reason));
+        }
+        Some((total, premises))
+    }
+
+    /// Move every non-basic variable back within its bounds and recompute the basic ones.
+    fn resync(&mut self) -> Result<(), Overflow> {
+        let mut value = self.value.clone();
+        for (x, v) in value.iter_mut().enumerate() {
+            if self.is_basic(x) {
+                continue;
+            }
+            if let Some(l) = self.lower[x].filter(|l| *v < l.value) {
+                *v = l.value;
+            } else if let Some(u) = self.upper[x].filter(|u| *v > u.value) {
+                *v = u.value;
+            }
+        }
+        for (b, row) in &self.rows {
+            value[*b] = row_value(row, &value).ok_or(Overflow)?;
+        }
+        self.value = value;
+        self.stale = false;
+        Ok(())
+    }
+
+    /// Set non-basic `x` to `v`, keeping the rows satisfied; nothing moves on overflow.
+    fn update(&mut self, x: Var, v: V) -> Result<(), Overflow> {
+        let delta = v.checked_sub(self.value[x]).ok_or(Overflow)?;
+        let mut moved = Vec::with_capacity(self.cols[x].len() + 1);
+        for &r in &self.cols[x] {
+            let (b, row) = &self.rows[r];
+            let a = coeff(row, x).expect("column entry");
+            moved.push((*b, delta.checked_scale(a).and_then(|d| self.value[*b].checked_add(d)).ok_or(Overflow)?));
+        }
+        moved.push((x, v));
+        for (y, w) in moved {
+            self.value[y] = w;
+        }
+        Ok(())
+    }
+
+    /// Make `b` take `v` by moving `x`, then swap their roles; nothing changes on overflow.
+    fn pivot_and_update(&mut self, b: Var, x: Var, v: V) -> Result<(), Overflow> {
+        let r = self.row_of[b].expect("basic");
+        let a = coeff(&self.rows[r].1, x).expect("entering in row");
+        let theta = v
+            .checked_sub(self.value[b])
+            .and_then(|d| d.checked_scale(Rational::ONE.checked_div(a)?))
+            .ok_or(Overflow)?;
+        let mut moved = vec![(b, v), (x, self.value[x].checked_add(theta).ok_or(Overflow)?)];
+        for &r2 in &self.cols[x] {
+            if r2 != r {
+                let (b2, row2) = &self.rows[r2];
+                let a2 = coeff(row2, x).expect("column entry");
+                let w = theta.checked_scale(a2).and_then(|d| self.value[*b2].checked_add(d)).ok_or(Overflow)?;
+                moved.push((*b2, w));
+            }
+        }
+        let (row, users) = self.pivot_rows(r, x).ok_or(Overflow)?;
+        for (y, w) in moved {
+            self.value[y] = w;
+        }
+        self.pivot(r, x, row, users);
+        Ok(())
+    }
+
+    /// Row `r` solved for `x`, and the other rows using `x` with that substituted.
+    fn pivot_rows(&self, r: usize, x: Var) -> Option<(Combination, Substituted)> {
+        let (b, old) = &self.rows[r];
+        let a = coeff(old, x).expect("entering in row");
+        // b = a x + rest  =>  x = (1/a) b - (1/a) rest
+        let inv = Rational::ONE.checked_div(a)?;
+        let mut row = Combination::with_capacity(old.len());
+        for &(y, c) in old {
+            if y != x {
+                row.push((y, c.checked_mul(inv)?.checked_neg()?));
+            }
+        }
+        let pos = row.partition_point(|&(y, _)| y < *b);
+        row.insert(pos, (*b, inv));
+        let mut users = Vec::new();
+        for &r2 in &self.cols[x] {
+            if r2 == r {
+                continue;
+            }
+            let row2 = &self.rows[r2].1;
+            let c = coeff(row2, x).expect("column entry");
+            let (mut sub, added, removed) = add_scaled(row2, &row, c)?;
+            sub.retain(|&(y, _)| y != x);
+            users.push((r2, sub, added, removed));
+        }
+        Some((row, users))
+    }
+
+    /// Make `x` basic in row `r`, given `pivot_rows(r, x)`.
+    fn pivot(&mut self, r: usize, x: Var, row: Combination, users: Substituted) {
+        let b = self.rows[r].0;
+        self.cols[x].clear();
+        self.cols[b].insert(r);
+        for (r2, sub, added, removed) in users {
+            self.rows[r2].1 = sub;
+            for y in added {
+                self.cols[y].insert(r2);
+            }
+            for y in removed {
+                self.cols[y].remove(&r2);
+            }
+        }
+        self.rows[r] = (x, row);
+        self.row_of[x] = Some(r);
+        self.row_of[b] = None;
+    }
+}
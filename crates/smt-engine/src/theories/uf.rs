@@ -0,0 +1,454 @@
+#![forbid(unsafe_code)]
+//! Uninterpreted functions: congruence closure over UF applications.
+//!
+//! The theory owns equalities between elements of uninterpreted sorts or between UF
+//! applications, and UF predicates (Boolean applications). Its closure holds every UF
+//! application of the context and the terms its atoms mention. A predicate's asserted
+//! value belongs to its class: two congruent predicates with different values are a
+//! conflict. Boolean arguments are opaque (compared as terms, not by value).
+//!
+//! Arguments and results owned by other theories reach the closure through
+//! `foreign_endpoints` and equality sharing, as with arrays; classes of shared terms,
+//! including the congruences among applications, are exported back.
+//!
+//! The closure is also the E-graph quantifier triggers are matched against.
+
+use hashbrown::HashMap;
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{Context, OpKind, SortKind, TermId, TermKind, Value};
+use smt_sat::{Lit, VarId};
+
+use crate::atoms::Atom;
+use crate::quantifiers::EGraph;
+use crate::reason::ReasonId;
+use crate::shared_terms::SharedTermOracle;
+use crate::theories::congruence::{CongruenceClosure, Node};
+use crate::theory::{EqClass, EqualitySharing, SharedEq, Theory, TheoryOutcome};
+use crate::theory_ctx::TheoryCtx;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+#[derive(Debug, Clone, Copy)]
+enum Assertion {
+    /// `a = b` or `a != b`.
+    Eq(TermId, TermId, bool),
+    /// A predicate application and its value.
+    Pred(TermId, bool),
+}
+
+/// An assertion with its reason and the owned atom it came from.
+type Pending = (Assertion, ReasonId, Option<VarId>);
+
+/// An assertion made at level 0, replayed when the closure is rebuilt.
+struct Fact {
+    what: Assertion,
+    reason: ReasonId,
+    /// The owned atom it came from; `None` for imported facts.
+    atom: Option<VarId>,
+    scope: usize,
+}
+
+#[derive(Default)]
+struct Scope {
+    atoms: Vec<(VarId, TermId)>,
+    foreign: Vec<TermId>,
+}
+
+#[derive(Default)]
+pub struct UfTheory {
+    cc: CongruenceClosure,
+    /// Closure symbol of each function name.
+    symbols: HashMap<String, u32, FxBuild>,
+    /// Owned atoms, as the assertion they make when true.
+    atoms: HashMap<VarId, Assertion, FxBuild>,
+    /// Terms mentioned by each atom, owned or foreign.
+    mentions: HashMap<TermId, Vec<TermId>, FxBuild>,
+    /// Context terms already scanned for applications.
+    scanned: usize,
+    /// Asserted predicates: application node, value, reason.
+    preds: Vec<(Node, bool, ReasonId)>,
+    /// Assertions over terms the closure has no node for yet, applied on the next `sync`.
+    deferred: Vec<Pending>,
+    /// `preds` and `deferred` lengths at each open decision level.
+    levels: Vec<(usize, usize)>,
+    facts: Vec<Fact>,
+    scopes: Vec<Scope>,
+    /// A scope was popped: rebuild the closure before using it.
+    dirty: bool,
+}
+
+impl UfTheory {
+    pub fn new() -> Self { Self::default() }
+
+    fn keep(&self, r: ReasonId, tcx: &mut TheoryCtx) -> ReasonId {
+        if self.levels.is_empty() { tcx.pin(r) } else { r }
+    }
+
+    fn add_term(&mut self, ctx: &Context, t: TermId) -> Node {
+        if let Some(n) = self.cc.node(t) {
+            return n;
+        }
+        match ctx.term_node(t).0 {
+            TermKind::App { op, args } if !args.is_empty() => {
+                let OpKind::Uf(name) = &op.kind;
+                let next = self.symbols.len() as u32;
+                let f = *self.symbols.entry(name.clone()).or_insert(next);
+                let args = args.clone().into_iter().map(|a| self.add_term(ctx, a)).collect();
+                self.cc.add_app(t, f, args)
+            }
+            TermKind::IntConst(k) => self.cc.add_leaf(t, Some(Value::Int(*k))),
+            TermKind::RatConst(k) => self.cc.add_leaf(t, Some(Value::Real(*k))),
+            TermKind::BvConst(v) => {
+                let width = ctx.bv_width(ctx.term_sort(t)).expect("bit-vector sort");
+                self.cc.add_leaf(t, Some(Value::BitVec(width, *v)))
+            }
+            _ => self.cc.add_leaf(t, None),
+        }
+    }
+
+    /// Bring the closure up to date with the context, rebuilding it after a pop.
+    fn sync(&mut self, ctx: &Context, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        if self.dirty {
+            self.dirty = false;
+            self.cc = CongruenceClosure::new();
+            self.scanned = 0;
+            self.preds.clear();
+            let mut terms: Vec<TermId> = self.mentions.values().flatten().copied().collect();
+            terms.sort();
+            for t in terms {
+                self.add_term(ctx, t);
+            }
+            self.scan(ctx);
+            // As in the arrays theory: facts are permanent, assertions deferred since are
+            // reapplied at their decision levels.
+            let marks: Vec<usize> = self.levels.iter().map(|&(_, deferred)| deferred).collect();
+            let mut deferred = core::mem::take(&mut self.deferred);
+            self.levels.clear();
+            let mut replayed: Vec<Pending> = self.facts.iter().map(|f| (f.what, f.reason, f.atom)).collect();
+            let mut chunks = Vec::new();
+            for &mark in marks.iter().rev() {
+                chunks.push(deferred.split_off(mark));
+            }
+            replayed.append(&mut deferred);
+            let mut result = self.apply_all(ctx, replayed, tcx);
+            for chunk in chunks.into_iter().rev() {
+                self.push_level();
+                result = result.and_then(|()| self.apply_all(ctx, chunk, tcx));
+            }
+            result?;
+        }
+        self.scan(ctx);
+        let deferred = core::mem::take(&mut self.deferred);
+        self.apply_all(ctx, deferred, tcx)?;
+        self.cc.close(tcx)?;
+        self.check_preds(tcx)
+    }
+
+    fn apply_all(&mut self, ctx: &Context, pending: Vec<Pending>, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        for (what, r, atom) in pending {
+            match what {
+                Assertion::Eq(a, b, _) => {
+                    self.add_term(ctx, a);
+                    self.add_term(ctx, b);
+                }
+                Assertion::Pred(t, _) => {
+                    self.add_term(ctx, t);
+                }
+            }
+            self.apply(what, r, atom, tcx)?;
+        }
+        Ok(())
+    }
+
+    fn scan(&mut self, ctx: &Context) {
+        for k in self.scanned..ctx.num_terms() {
+            let t = TermId(k as u32);
+            if matches!(ctx.term_node(t).0, TermKind::App { .. }) && ctx.is_ground(t) {
+                self.add_term(ctx, t);
+            }
+        }
+        self.scanned = ctx.num_terms();
+    }
+
+    fn apply(&mut self, what: Assertion, r: ReasonId, atom: Option<VarId>, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        match what {
+            Assertion::Eq(a, b, eq) => {
+                let (Some(na), Some(nb)) = (self.cc.node(a), self.cc.node(b)) else {
+                    self.deferred.push((what, r, atom));
+                    return Ok(());
+                };
+                if eq { self.cc.assert_eq(na, nb, r, tcx) } else { self.cc.assert_diseq(na, nb, r, tcx) }
+            }
+            Assertion::Pred(t, value) => {
+                let Some(n) = self.cc.node(t) else {
+                    self.deferred.push((what, r, atom));
+                    return Ok(());
+                };
+                self.preds.push((n, value, r));
+                self.check_preds(tcx)
+            }
+        }
+    }
+
+    /// Two predicates of one class with different values are a conflict.
+    fn check_preds(&self, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        let mut first: HashMap<Node, (Node, bool, ReasonId), FxBuild> = HashMap::default();
+        for &(n, value, r) in &self.preds {
+            match first.get(&self.cc.find(n)) {
+                Some(&(m, v, q)) if v != value => {
+                    let eq = self.cc.explain(m, n, tcx);
+                    return Err(tcx.r_and(vec![q, r, eq]));
+                }
+                Some(_) => {}
+                None => {
+                    first.insert(self.cc.find(n), (n, value, r));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply `what`, recording it as a fact if at level 0.
+    fn assert_fact(&mut self, what: Assertion, r: ReasonId, atom: Option<VarId>, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let reason = self.keep(r, tcx);
+        if self.levels.is_empty() {
+            self.facts.push(Fact { what, reason, atom, scope: self.scopes.len() });
+        }
+        if self.dirty {
+            self.deferred.push((what, reason, atom));
+            return TheoryOutcome::Ok;
+        }
+        match self.apply(what, reason, atom, tcx) {
+            Ok(()) => TheoryOutcome::Ok,
+            Err(conflict) => TheoryOutcome::Conflict(conflict),
+        }
+    }
+
+    fn mention(&mut self, ctx: &Context, atom_term: TermId, mut ends: Vec<TermId>) {
+        ends.sort();
+        ends.dedup();
+        if !self.dirty {
+            for &t in &ends {
+                self.add_term(ctx, t);
+            }
+        }
+        self.mentions.insert(atom_term, ends);
+    }
+}
+
+/// `t` and, through UF applications, their arguments.
+fn mentioned(ctx: &Context, t: TermId, out: &mut Vec<TermId>) {
+    out.push(t);
+    if let TermKind::App { args, .. } = ctx.term_node(t).0 {
+        for &a in args {
+            mentioned(ctx, a, out);
+        }
+    }
+}
+
+/// UF applications (and what they mention) under the structure of another theory's atom.
+fn foreign_mentions(ctx: &Context, t: TermId, out: &mut Vec<TermId>) {
+    match ctx.term_node(t).0 {
+        TermKind::App { .. } => mentioned(ctx, t, out),
+        TermKind::Eq(a, b) | TermKind::Le(a, b) | TermKind::Sub(a, b) | TermKind::Mul(a, b) | TermKind::Select(a, b) => {
+            foreign_mentions(ctx, *a, out);
+            foreign_mentions(ctx, *b, out);
+        }
+        TermKind::Selector(_, _, a) | TermKind::Tester(_, a) => foreign_mentions(ctx, *a, out),
+        TermKind::Store(a, i, v) => {
+            for x in [a, i, v] {
+                foreign_mentions(ctx, *x, out);
+            }
+        }
+        TermKind::Add(xs) | TermKind::Bv(_, xs) | TermKind::Construct(_, xs) => {
+            for &x in xs {
+                foreign_mentions(ctx, x, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_app(ctx: &Context, t: TermId) -> bool {
+    matches!(ctx.term_node(t).0, TermKind::App { .. })
+}
+
+impl Theory for UfTheory {
+    fn name(&self) -> &'static str { "UF" }
+
+    fn owns_atom(&self, ctx: &Context, atom_term: TermId) -> bool {
+        match *ctx.term_node(atom_term).0 {
+            TermKind::Eq(a, b) => {
+                let sort = ctx.term_sort(a);
+                sort != ctx.bool_sort() && (matches!(ctx.sort_kind(sort), SortKind::Uninterpreted(_)) || is_app(ctx, a) || is_app(ctx, b))
+            }
+            TermKind::App { .. } => true,
+            _ => false,
+        }
+    }
+
+    fn atom_endpoints(&self, atom_term: TermId) -> Vec<TermId> {
+        self.mentions.get(&atom_term).cloned().unwrap_or_default()
+    }
+
+    fn foreign_endpoints(&self, atom_term: TermId) -> Vec<TermId> {
+        self.mentions.get(&atom_term).cloned().unwrap_or_default()
+    }
+
+    fn register_atom(&mut self, ctx: &Context, atom: Atom) {
+        let mut ends = Vec::new();
+        let what = match *ctx.term_node(atom.term).0 {
+            TermKind::Eq(a, b) => {
+                mentioned(ctx, a, &mut ends);
+                mentioned(ctx, b, &mut ends);
+                Assertion::Eq(a, b, true)
+            }
+            _ => {
+                mentioned(ctx, atom.term, &mut ends);
+                Assertion::Pred(atom.term, true)
+            }
+        };
+        self.atoms.insert(atom.var, what);
+        self.mention(ctx, atom.term, ends);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.atoms.push((atom.var, atom.term));
+        }
+    }
+
+    fn register_foreign_atom(&mut self, ctx: &Context, atom: Atom) {
+        let mut ends = Vec::new();
+        foreign_mentions(ctx, atom.term, &mut ends);
+        // An equality over terms the closure knows is one it must hear about.
+        if let TermKind::Eq(a, b) = *ctx.term_node(atom.term).0 {
+            if !ends.is_empty() || self.cc.node(a).is_some() || self.cc.node(b).is_some() {
+                ends.extend([a, b]);
+            }
+        }
+        if ends.is_empty() {
+            return;
+        }
+        self.mention(ctx, atom.term, ends);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.foreign.push(atom.term);
+        }
+    }
+
+    fn assert_atom(&mut self, ctx: &Context, atom: Atom, value: bool, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        if let Err(conflict) = self.sync(ctx, tcx) {
+            return TheoryOutcome::Conflict(conflict);
+        }
+        let Some(&what) = self.atoms.get(&atom.var) else { return TheoryOutcome::Ok };
+        let what = match what {
+            Assertion::Eq(a, b, _) => Assertion::Eq(a, b, value),
+            Assertion::Pred(t, _) => Assertion::Pred(t, value),
+        };
+        let lit = if value { Lit::pos(atom.var) } else { Lit::neg(atom.var) };
+        let r = tcx.r_lit(lit);
+        self.assert_fact(what, r, Some(atom.var), tcx)
+    }
+
+    fn propagate(&mut self, ctx: &Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        match self.sync(ctx, tcx) {
+            Ok(()) => TheoryOutcome::Ok,
+            Err(conflict) => TheoryOutcome::Conflict(conflict),
+        }
+    }
+
+    fn final_check(&mut self, ctx: &mut Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        match self.sync(ctx, tcx) {
+            Ok(()) => TheoryOutcome::Ok,
+            Err(conflict) => TheoryOutcome::Conflict(conflict),
+        }
+    }
+
+    /// The class of `t`.
+    fn model_value(&self, ctx: &Context, t: TermId) -> Option<Value> {
+        let n = self.cc.node(t)?;
+        Some(Value::Elem(ctx.term_sort(t), self.cc.find(n) as u32))
+    }
+
+    fn push_level(&mut self) {
+        self.cc.push_level();
+        self.levels.push((self.preds.len(), self.deferred.len()));
+    }
+
+    fn pop_levels(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.cc.pop_levels(n);
+        let (preds, deferred) = self.levels[self.levels.len() - n];
+        self.levels.truncate(self.levels.len() - n);
+        self.preds.truncate(preds);
+        self.deferred.truncate(deferred);
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn pop_scopes(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        for scope in self.scopes.drain(self.scopes.len() - n..) {
+            for (v, t) in scope.atoms {
+                self.atoms.remove(&v);
+                self.mentions.remove(&t);
+            }
+            for t in scope.foreign {
+                self.mentions.remove(&t);
+            }
+        }
+        let (atoms, depth) = (&self.atoms, self.scopes.len());
+        self.facts.retain(|f| match f.atom {
+            Some(v) => atoms.contains_key(&v),
+            None => f.scope <= depth,
+        });
+        self.dirty = true;
+    }
+
+    fn egraph(&self) -> Option<&dyn EGraph> {
+        if self.dirty { None } else { Some(self) }
+    }
+
+    fn equality_sharing_mut(&mut self) -> Option<&mut dyn EqualitySharing> { Some(self) }
+}
+
+impl EGraph for UfTheory {
+    fn apps(&self, f: &str) -> Vec<TermId> {
+        let Some(&f) = self.symbols.get(f) else { return Vec::new() };
+        (0..self.cc.num_nodes()).filter(|&n| self.cc.app(n).is_some_and(|(g, _)| g == f)).map(|n| self.cc.term(n)).collect()
+    }
+
+    fn find(&self, t: TermId) -> Option<TermId> {
+        self.cc.node(t).map(|n| self.cc.term(self.cc.find(n)))
+    }
+
+    fn class(&self, t: TermId) -> Vec<TermId> {
+        match self.cc.node(t) {
+            Some(n) => self.cc.class(n).iter().map(|&m| self.cc.term(m)).collect(),
+            None => vec![t],
+        }
+    }
+}
+
+impl EqualitySharing for UfTheory {
+    /// Classes of the closure among shared terms.
+    fn export_classes(&mut self, oracle: &SharedTermOracle, _export_epoch: u64, tcx: &mut TheoryCtx) -> Vec<EqClass> {
+        if self.dirty {
+            return Vec::new();
+        }
+        self.cc.classes(oracle.shared_set().iter().copied(), tcx)
+    }
+
+    fn import_equality(&mut self, eq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        self.assert_fact(Assertion::Eq(eq.a, eq.b, true), eq.explain, None, tcx)
+    }
+
+    fn import_disequality(&mut self, diseq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        self.assert_fact(Assertion::Eq(diseq.a, diseq.b, false), diseq.explain, None, tcx)
+    }
+}
@@ -0,0 +1,870 @@
+#![forbid(unsafe_code)]
+//! Unit two-variable-per-inequality constraints over Int: `±x ± y <= k` and `±x <= k`.
+//!
+//! Every variable `x` has two signed vertices, `+x` and `-x`, and `p - q <= k` over
+//! signed vertices is an edge `q -> p` of weight `k` together with its twin
+//! `-p -> -q`: `x + y <= k` is `+x - (-y) <= k`, and `x <= k` is `+x - (-x) <= 2k`. A
+//! negative cycle is a conflict explained by the edges on it, as in difference logic;
+//! the paths of these reasons list the variables of the vertices they visit.
+//!
+//! The graph is kept transitively closed, one edge at a time, with the last edge of
+//! every shortest path. Integer tightening turns an odd bound `2x <= k` into `2x <= k - 1`
+//! (an edge explained by the path it shortens); with no negative cycle left, the
+//! constraints have an integer solution. Atoms and equalities between shared terms
+//! follow from paths, or from two bounds on single variables (`x - y <= a + b` given
+//! `x <= a` and `-y <= b`). Propagated atoms are explained by the paths recorded when
+//! they were propagated.
+//!
+//! Disequalities are split in final checks, as in LIA, and so are shared terms the model
+//! makes equal without the closure entailing it.
+//!
+//! The theory owns every `<=` and `=` atom over Int, since two arithmetic theories sharing
+//! variables would only exchange equalities. Atoms outside the fragment (or with a bound
+//! beyond `MAX_BOUND`) go to an embedded LIA instance instead: it sees every atom, and on a
+//! branch that asserts one of them it is handed everything asserted so far and decides the
+//! rest of the branch, model included.
+
+use hashbrown::{HashMap, HashSet};
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{Context, Rational, TermId, TermKind, Value};
+use smt_sat::{Lit, VarId};
+
+use crate::atoms::Atom;
+use crate::reason::ReasonId;
+use crate::shared_terms::SharedTermOracle;
+use crate::theories::lia::LiaTheory;
+use crate::theories::linear::{difference, linearize};
+use crate::theory::{EqClass, EqualitySharing, SharedEq, Theory, TheoryLemma, TheoryOutcome, TheoryPropagation};
+use crate::theory_ctx::TheoryCtx;
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+/// Largest bound (in absolute value) an owned atom may have, so that path weights
+/// cannot overflow.
+const MAX_BOUND: i64 = 1 << 40;
+
+/// Signed variable: vertex `2x` is `+x`, vertex `2x + 1` is `-x`.
+type Vertex = usize;
+
+fn neg(p: Vertex) -> Vertex {
+    p ^ 1
+}
+
+/// `p - q <= k` over signed vertices. A bound on one variable has `q == neg(p)` and an
+/// even `k`.
+#[derive(Debug, Clone, Copy)]
+struct Diff {
+    p: Vertex,
+    q: Vertex,
+    k: i64,
+}
+
+impl Diff {
+    fn new(p: Vertex, q: Vertex, k: i64) -> Self {
+        let k = if q == neg(p) { k.div_euclid(2) * 2 } else { k };
+        Self { p, q, k }
+    }
+
+    /// The integer negation, `q - p <= -k - 1`.
+    fn negated(self) -> Self {
+        Diff::new(self.q, self.p, -self.k - 1)
+    }
+
+    /// `q - p <= -k`, the other half of `p - q = k`.
+    fn reversed(self) -> Self {
+        Diff::new(self.q, self.p, -self.k)
+    }
+}
+
+/// Normalized atom.
+#[derive(Debug, Clone, Copy)]
+enum Constraint {
+    Le(Diff),
+    Eq(Diff),
+    Const(bool),
+    /// Outside the fragment: left to LIA.
+    Other,
+}
+
+/// `Σ signs * terms ⋈ k`: an atom in the fragment, before it gets vertices.
+struct Shape {
+    terms: Vec<(TermId, bool)>,
+    k: Option<i64>,
+    eq: bool,
+}
+
+/// The atom `t` (over Int) as a UTVPI constraint, if it is one. `k` is `None` for an equality
+/// whose bound is not integral.
+fn shape(ctx: &Context, t: TermId) -> Option<Shape> {
+    let (a, b, eq) = sides(ctx, t)?;
+    let e = difference(ctx, a, b);
+    if e.coeffs.len() > 2 {
+        return None;
+    }
+    let g = e.coeffs.first().map_or(Rational::ONE, |&(_, c)| c.abs());
+    if e.coeffs.iter().any(|&(_, c)| c.abs() != g) {
+        return None;
+    }
+    let k = -e.constant / g;
+    let k = match (eq, k.is_integer()) {
+        (true, false) => None,
+        (true, true) => Some(k.floor()),
+        (false, _) => Some(k.floor()),
+    };
+    let k = match k {
+        Some(k) => Some(i64::try_from(k).ok().filter(|k| k.abs() <= MAX_BOUND)?),
+        None => None,
+    };
+    let terms = e.coeffs.iter().map(|&(t, c)| (t, c.signum() > 0)).collect();
+    Some(Shape { terms, k, eq })
+}
+
+fn sides(ctx: &Context, t: TermId) -> Option<(TermId, TermId, bool)> {
+    match ctx.term_node(t).0 {
+        TermKind::Le(a, b) => Some((*a, *b, false)),
+        TermKind::Eq(a, b) => Some((*a, *b, true)),
+        _ => None,
+    }
+}
+
+/// An edge, as kept for explanations: its target is known from the cell it ends.
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    from: Vertex,
+    reason: ReasonId,
+}
+
+/// Shortest path distance and the edge ending the path (`NO_EDGE` on the diagonal).
+type Cell = Option<(i64, u32)>;
+
+const NO_EDGE: u32 = u32::MAX;
+
+/// Variables a path visits and the reasons of its edges.
+type Walk = (Vec<TermId>, Vec<ReasonId>);
+
+/// Terms equal to the first one, each with the paths showing it.
+type Class = Vec<(TermId, Vec<(Vertex, Vertex)>)>;
+
+/// What a propagated literal follows from, recorded when it was propagated.
+#[derive(Debug, Clone)]
+enum Implied {
+    Paths(Vec<Walk>),
+    /// LIA propagated it, and explains it.
+    Lia,
+}
+
+/// Something asserted to the theory, kept for LIA to take over with.
+#[derive(Debug, Clone)]
+enum Fact {
+    Atom(Atom, bool),
+    Eq(SharedEq),
+    Diseq(SharedEq),
+}
+
+#[derive(Debug, Clone)]
+struct Diseq {
+    a: TermId,
+    b: TermId,
+    explain: ReasonId,
+    /// Atom it comes from (`None` if imported).
+    atom: Option<VarId>,
+    /// User scopes open when it was asserted.
+    scope: usize,
+}
+
+/// Per-level undo entries (edges are truncated).
+#[derive(Debug, Clone, Copy)]
+enum Undo {
+    Cell(Vertex, Vertex, Cell),
+    Assigned(VarId),
+    Diseq,
+    Fact,
+    Implied(VarId),
+}
+
+/// Per-scope undo entries: everything keyed by terms that a pop may delete.
+#[derive(Debug, Clone, Copy)]
+enum ScopeUndo {
+    TermVar(TermId),
+    Atom(VarId, TermId),
+    Split(TermId, TermId, Option<VarId>),
+    Shared,
+    Arranged(TermId, TermId),
+}
+
+#[derive(Default)]
+pub struct UtvpiTheory {
+    term_var: HashMap<TermId, usize, FxBuild>,
+    /// Term of every variable; popped variables keep theirs, unreachable.
+    var_term: Vec<TermId>,
+    atoms: HashMap<VarId, Constraint, FxBuild>,
+    endpoints: HashMap<TermId, Vec<TermId>, FxBuild>,
+    /// Atoms asserted on the current branch.
+    assigned: HashSet<VarId, FxBuild>,
+    edges: Vec<Edge>,
+    /// `dist[u][v]` bounds `v - u`, over signed vertices.
+    dist: Vec<Vec<Cell>>,
+    diseqs: Vec<Diseq>,
+    /// Everything asserted, with the user scopes open at the time.
+    facts: Vec<(Fact, usize)>,
+    implied: HashMap<VarId, Implied, FxBuild>,
+    /// Decides the atoms outside the fragment, once the branch asserts one of them.
+    lia: LiaTheory,
+    /// Decision level at which LIA took over the branch.
+    engaged: Option<usize>,
+    trail: Vec<Undo>,
+    /// Trail and edge counts at each open decision level.
+    levels: Vec<(usize, usize)>,
+    /// The closure changed since the last `propagate`.
+    touched: bool,
+    /// Literals of constant atoms, not yet propagated.
+    pending_const: Vec<Lit>,
+    /// Disequalities already split, with the atom each came from.
+    split: HashSet<(TermId, TermId, Option<VarId>), FxBuild>,
+    /// Terms shared with other theories.
+    shared: Vec<TermId>,
+    /// Pairs of shared terms already split on.
+    arranged: HashSet<(TermId, TermId), FxBuild>,
+    scope_undo: Vec<ScopeUndo>,
+    scope_marks: Vec<usize>,
+    /// Value of every variable, set by the last final check.
+    model: Vec<i64>,
+}
+
+impl UtvpiTheory {
+    pub fn new() -> Self { Self::default() }
+
+    fn var_of(&mut self, t: TermId) -> usize {
+        if let Some(&x) = self.term_var.get(&t) {
+            return x;
+        }
+        let x = self.var_term.len();
+        self.var_term.push(t);
+        self.term_var.insert(t, x);
+        let n = self.dist.len();
+        for row in &mut self.dist {
+            row.extend([None, None]);
+        }
+        for v in [n, n + 1] {
+            let mut row = vec![None; n + 2];
+            row[v] = Some((0, NO_EDGE));
+            self.dist.push(row);
+        }
+        self.log(ScopeUndo::TermVar(t));
+        x
+    }
+
+    fn vertex(&mut self, t: TermId, positive: bool) -> Vertex {
+        2 * self.var_of(t) + usize::from(!positive)
+    }
+
+    fn log(&mut self, u: ScopeUndo) {
+        if !self.scope_marks.is_empty() {
+            self.scope_undo.push(u);
+        }
+    }
+
+    fn keep(&self, r: ReasonId, tcx: &mut TheoryCtx) -> ReasonId {
+        if self.levels.is_empty() { tcx.pin(r) } else { r }
+    }
+
+    fn normalize(&mut self, s: Shape) -> Constraint {
+        let Some(k) = s.k else { return Constraint::Const(false) };
+        let d = match s.terms[..] {
+            [] => return Constraint::Const(if s.eq { k == 0 } else { k >= 0 }),
+            [(x, sx)] => {
+                let p = self.vertex(x, sx);
+                Diff::new(p, neg(p), 2 * k)
+            }
+            [(x, sx), (y, sy)] => {
+                let p = self.vertex(x, sx);
+                let q = self.vertex(y, !sy);
+                Diff::new(p, q, k)
+            }
+            _ => unreachable!("at most two variables"),
+        };
+        if s.eq { Constraint::Eq(d) } else { Constraint::Le(d) }
+    }
+
+    fn dist(&self, u: Vertex, v: Vertex) -> Option<i64> {
+        self.dist[u][v].map(|(d, _)| d)
+    }
+
+    fn set(&mut self, u: Vertex, v: Vertex, cell: Cell) {
+        if !self.levels.is_empty() {
+            self.trail.push(Undo::Cell(u, v, self.dist[u][v]));
+        }
+        self.dist[u][v] = cell;
+    }
+
+    /// Variables and edge reasons of the shortest path `from -> to`.
+    fn walk(&self, from: Vertex, to: Vertex) -> Walk {
+        let mut path = vec![self.var_term[to / 2]];
+        let mut edges = Vec::new();
+        let mut v = to;
+        while v != from {
+            let (_, e) = self.dist[from][v].expect("reachable");
+            let e = self.edges[e as usize];
+            edges.push(e.reason);
+            path.push(self.var_term[e.from / 2]);
+            v = e.from;
+        }
+        path.reverse();
+        edges.reverse();
+        (path, edges)
+    }
+
+    fn explain_path(&self, from: Vertex, to: Vertex, tcx: &mut TheoryCtx) -> ReasonId {
+        let (path, edges) = self.walk(from, to);
+        tcx.r_dl_path(path, edges)
+    }
+
+    /// Add `from -> to` with weight `w` and close the graph over it; a negative cycle
+    /// is returned as a conflict.
+    fn add_edge(&mut self, from: Vertex, to: Vertex, w: i64, reason: ReasonId, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        if let Some(back) = self.dist(to, from) {
+            if back + w < 0 {
+                let (mut path, mut edges) = self.walk(to, from);
+                path.push(self.var_term[to / 2]);
+                edges.push(reason);
+                return Err(tcx.r_dl_path(path, edges));
+            }
+        }
+        if self.dist(from, to).is_some_and(|d| d <= w) {
+            return Ok(());
+        }
+        let e = self.edges.len() as u32;
+        self.edges.push(Edge { from, reason });
+        // Neither the column of `from` nor the row of `to` can shrink: that would take a
+        // negative cycle.
+        let n = self.dist.len();
+        let into: Vec<(Vertex, i64)> = (0..n).filter_map(|i| Some((i, self.dist(i, from)?))).collect();
+        let out: Vec<(Vertex, i64, u32)> = (0..n)
+            .filter_map(|j| {
+                let (d, last) = self.dist[to][j]?;
+                Some((j, d, if j == to { e } else { last }))
+            })
+            .collect();
+        for &(i, a) in &into {
+            for &(j, b, last) in &out {
+                let d = a + w + b;
+                if self.dist(i, j).is_none_or(|old| d < old) {
+                    self.set(i, j, Some((d, last)));
+                }
+            }
+        }
+        self.touched = true;
+        Ok(())
+    }
+
+    /// `d` and its twin.
+    fn assert_diff(&mut self, d: Diff, reason: ReasonId, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        self.add_edge(d.q, d.p, d.k, reason, tcx)?;
+        if d.q != neg(d.p) {
+            self.add_edge(neg(d.p), neg(d.q), d.k, reason, tcx)?;
+        }
+        Ok(())
+    }
+
+    /// Round odd bounds `2x <= k` down, until none is left.
+    fn tighten(&mut self, tcx: &mut TheoryCtx) -> Result<(), ReasonId> {
+        loop {
+            let odd = (0..self.dist.len()).find(|&p| self.dist(neg(p), p).is_some_and(|d| d % 2 != 0));
+            let Some(p) = odd else { return Ok(()) };
+            let d = self.dist(neg(p), p).expect("bounded");
+            let r = self.explain_path(neg(p), p, tcx);
+            let r = self.keep(r, tcx);
+            self.add_edge(neg(p), p, d - 1, r, tcx)?;
+        }
+    }
+
+    /// Least upper bound on `p - q` the closure shows, with the paths proving it.
+    fn upper(&self, p: Vertex, q: Vertex) -> Option<(i64, Vec<(Vertex, Vertex)>)> {
+        let path = self.dist(q, p).map(|d| (d, vec![(q, p)]));
+        if q == neg(p) {
+            return path;
+        }
+        let bounds = match (self.dist(neg(p), p), self.dist(q, neg(q))) {
+            (Some(a), Some(b)) => Some((a.div_euclid(2) + b.div_euclid(2), vec![(neg(p), p), (q, neg(q))])),
+            _ => None,
+        };
+        match (path, bounds) {
+            (Some(a), Some(b)) => Some(if b.0 < a.0 { b } else { a }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Paths showing `p - q <= k`, if the closure does.
+    fn entails(&self, d: Diff) -> Option<Vec<(Vertex, Vertex)>> {
+        self.upper(d.p, d.q).filter(|&(u, _)| u <= d.k).map(|(_, paths)| paths)
+    }
+
+    /// Truth value of atom `c` forced by the closure, with the paths explaining it.
+    fn forced(&self, c: Constraint) -> Option<(bool, Vec<(Vertex, Vertex)>)> {
+        match c {
+            Constraint::Le(d) => self.entails(d).map(|ps| (true, ps)).or_else(|| self.entails(d.negated()).map(|ps| (false, ps))),
+            Constraint::Eq(d) => {
+                if let (Some(mut ps), Some(qs)) = (self.entails(d), self.entails(d.reversed())) {
+                    ps.extend(qs);
+                    return Some((true, ps));
+                }
+                let below = Diff::new(d.p, d.q, d.k - 1);
+                self.entails(below).or_else(|| self.entails(d.negated())).map(|ps| (false, ps))
+            }
+            Constraint::Const(_) | Constraint::Other => None,
+        }
+    }
+
+    fn explain_paths(&self, paths: &[(Vertex, Vertex)], tcx: &mut TheoryCtx) -> ReasonId {
+        let kids = paths.iter().map(|&(u, v)| self.explain_path(u, v, tcx)).collect();
+        tcx.r_and(kids)
+    }
+
+    /// Record what `lit` was propagated from, for `explain`. The first record stands: it
+    /// is the one the SAT solver's assignment goes with.
+    fn record(&mut self, lit: Lit, implied: Implied) {
+        if self.implied.contains_key(&lit.var()) {
+            return;
+        }
+        self.implied.insert(lit.var(), implied);
+        // Level-0 records live as long as their atom; `pop_scopes` drops them.
+        if !self.levels.is_empty() {
+            self.trail.push(Undo::Implied(lit.var()));
+        }
+    }
+
+    /// Classes of `terms` under the equalities the closure entails, each listing its
+    /// members (the first one the representative) with the paths linking them to it.
+    fn classes(&self, terms: &[TermId]) -> Vec<Class> {
+        let mut classes: Vec<Class> = Vec::new();
+        'terms: for &b in terms {
+            let Some(&y) = self.term_var.get(&b) else { continue };
+            for class in &mut classes {
+                let (p, q) = (2 * self.term_var[&class[0].0], 2 * y);
+                if let (Some(mut ps), Some(qs)) = (self.entails(Diff::new(p, q, 0)), self.entails(Diff::new(q, p, 0))) {
+                    ps.extend(qs);
+                    class.push((b, ps));
+                    continue 'terms;
+                }
+            }
+            classes.push(vec![(b, Vec::new())]);
+        }
+        classes
+    }
+
+    /// Split on `a = b` for the first two shared terms the model makes equal without the
+    /// closure entailing it; `Ok` if there are none.
+    fn arrange_shared(&mut self, ctx: &mut Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let mut terms = self.shared.clone();
+        terms.sort_unstable();
+        let mut reps: Vec<(i64, TermId)> =
+            self.classes(&terms).into_iter().map(|class| (self.model[self.term_var[&class[0].0]], class[0].0)).collect();
+        reps.sort_unstable();
+        for w in reps.windows(2) {
+            let ((va, a), (vb, b)) = (w[0], w[1]);
+            if va != vb || !self.arranged.insert((a, b)) {
+                continue;
+            }
+            self.log(ScopeUndo::Arranged(a, b));
+            let eq = ctx.eq(a, b);
+            let ne = ctx.not(eq);
+            return TheoryOutcome::Lemma(TheoryLemma { because: tcx.r_and(Vec::new()), atoms: vec![eq, ne] });
+        }
+        TheoryOutcome::Ok
+    }
+
+    /// Keep `fact` for LIA to take over with.
+    fn note(&mut self, fact: Fact) {
+        self.facts.push((fact, self.scope_marks.len()));
+        // Level-0 facts outlive the search; `pop_scopes` filters them.
+        if !self.levels.is_empty() {
+            self.trail.push(Undo::Fact);
+        }
+    }
+
+    fn forward(&mut self, ctx: &Context, fact: Fact, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        match fact {
+            Fact::Atom(atom, value) => self.lia.assert_atom(ctx, atom, value, tcx),
+            Fact::Eq(eq) => self.lia.import_equality(eq, tcx),
+            Fact::Diseq(diseq) => self.lia.import_disequality(diseq, tcx),
+        }
+    }
+
+    /// Let LIA take over the branch: it gets every fact asserted so far, at the current
+    /// level, and lets go when that level is undone.
+    fn engage(&mut self, ctx: &Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        self.engaged = Some(self.levels.len());
+        let mut outcome = TheoryOutcome::Ok;
+        // All of them, even past a conflict: the branch may resume above it.
+        for (fact, _) in self.facts.clone() {
+            let out = self.forward(ctx, fact, tcx);
+            if outcome.is_ok() {
+                outcome = out;
+            }
+        }
+        outcome
+    }
+
+    /// An integer solution of the closure: tighten it into its integer hull, then fix
+    /// each variable in turn to the value in its bounds closest to 0.
+    fn settle(&mut self) {
+        let mut m: Vec<Vec<Option<i64>>> = self.dist.iter().map(|row| row.iter().map(|c| c.map(|(d, _)| d)).collect()).collect();
+        tight_close(&mut m);
+        self.model.clear();
+        for x in 0..self.var_term.len() {
+            let (p, q) = (2 * x, 2 * x + 1);
+            let hi = m[q][p].map(|d| d / 2);
+            let lo = m[p][q].map(|d| -d / 2);
+            debug_assert!(lo.zip(hi).is_none_or(|(l, h)| l <= h), "tightly closed constraints are satisfiable");
+            let v = match (lo, hi) {
+                (Some(l), _) if l > 0 => l,
+                (_, Some(h)) if h < 0 => h,
+                _ => 0,
+            };
+            self.model.push(v);
+            close_over(&mut m, q, p, 2 * v);
+            close_over(&mut m, p, q, -2 * v);
+            tight_close(&mut m);
+        }
+    }
+
+    /// Value of the arithmetic term `t` in the current model, if all its variables are
+    /// known.
+    fn eval(&self, ctx: &Context, t: TermId) -> Option<Rational> {
+        let e = linearize(ctx, t);
+        e.coeffs.iter().try_fold(e.constant, |acc, &(x, c)| Some(acc + c * Rational::from(*self.model.get(*self.term_var.get(&x)?)?)))
+    }
+
+    fn push_diseq(&mut self, a: TermId, b: TermId, explain: ReasonId, atom: Option<VarId>) {
+        self.diseqs.push(Diseq { a, b, explain, atom, scope: self.scope_marks.len() });
+        if !self.levels.is_empty() {
+            self.trail.push(Undo::Diseq);
+        }
+    }
+}
+
+/// Close `m` over a new edge `u -> v` of weight `w`.
+fn close_over(m: &mut [Vec<Option<i64>>], u: Vertex, v: Vertex, w: i64) {
+    let n = m.len();
+    let into: Vec<(Vertex, i64)> = (0..n).filter_map(|i| Some((i, m[i][u]?))).collect();
+    let out: Vec<(Vertex, i64)> = (0..n).filter_map(|j| Some((j, m[v][j]?))).collect();
+    for &(i, a) in &into {
+        for &(j, b) in &out {
+            if m[i][j].is_none_or(|old| a + w + b < old) {
+                m[i][j] = Some(a + w + b);
+            }
+        }
+    }
+}
+
+/// Tight closure of a closed `m`: make bounds on single variables even, then combine
+/// them (`q - p <= (2q + (-2p)) / 2`).
+fn tight_close(m: &mut [Vec<Option<i64>>]) {
+    let n = m.len();
+    for p in 0..n {
+        if let Some(d) = m[neg(p)][p] {
+            m[neg(p)][p] = Some(d.div_euclid(2) * 2);
+        }
+    }
+    for i in 0..n {
+        let Some(a) = m[i][neg(i)] else { continue };
+        for j in 0..n {
+            let Some(b) = m[neg(j)][j] else { continue };
+            if m[i][j].is_none_or(|old| (a + b) / 2 < old) {
+                m[i][j] = Some((a + b) / 2);
+            }
+        }
+    }
+}
+
+impl Theory for UtvpiTheory {
+    fn name(&self) -> &'static str { "UTVPI" }
+
+    fn owns_atom(&self, ctx: &Context, atom_term: TermId) -> bool {
+        sides(ctx, atom_term).is_some_and(|(a, _, _)| ctx.term_sort(a) == ctx.int_sort())
+    }
+
+    fn atom_endpoints(&self, atom_term: TermId) -> Vec<TermId> {
+        self.endpoints.get(&atom_term).cloned().unwrap_or_default()
+    }
+
+    fn notify_shared(&mut self, t: TermId) {
+        self.lia.notify_shared(t);
+        if !self.shared.contains(&t) {
+            self.shared.push(t);
+            self.log(ScopeUndo::Shared);
+        }
+    }
+
+    fn register_atom(&mut self, ctx: &Context, atom: Atom) {
+        self.lia.register_atom(ctx, atom);
+        let (a, b, _) = sides(ctx, atom.term).expect("arithmetic atom");
+        let mut ends: Vec<TermId> = [a, b].iter().flat_map(|&side| linearize(ctx, side).coeffs).map(|(t, _)| t).collect();
+        ends.sort();
+        ends.dedup();
+        let c = match shape(ctx, atom.term) {
+            Some(s) => self.normalize(s),
+            None => {
+                for &t in &ends {
+                    self.var_of(t);
+                }
+                Constraint::Other
+            }
+        };
+        self.endpoints.insert(atom.term, ends);
+        if let Constraint::Const(v) = c {
+            self.pending_const.push(if v { Lit::pos(atom.var) } else { Lit::neg(atom.var) });
+        }
+        self.atoms.insert(atom.var, c);
+        self.log(ScopeUndo::Atom(atom.var, atom.term));
+    }
+
+    fn assert_atom(&mut self, ctx: &Context, atom: Atom, value: bool, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let Some(&c) = self.atoms.get(&atom.var) else { return TheoryOutcome::Ok };
+        if !self.assigned.insert(atom.var) {
+            return TheoryOutcome::Ok;
+        }
+        self.trail.push(Undo::Assigned(atom.var));
+        self.note(Fact::Atom(atom, value));
+        let handed = if self.engaged.is_some() { self.lia.assert_atom(ctx, atom, value, tcx) } else { TheoryOutcome::Ok };
+        let lit = if value { Lit::pos(atom.var) } else { Lit::neg(atom.var) };
+        let r = tcx.r_lit(lit);
+        let reason = self.keep(r, tcx);
+        let result = match (c, value) {
+            (Constraint::Const(v), _) if v != value => Err(reason),
+            (Constraint::Const(_), _) => Ok(()),
+            (Constraint::Other, _) if self.engaged.is_none() => return self.engage(ctx, tcx),
+            (Constraint::Other, _) => Ok(()),
+            (Constraint::Le(d), true) => self.assert_diff(d, reason, tcx),
+            (Constraint::Le(d), false) => self.assert_diff(d.negated(), reason, tcx),
+            (Constraint::Eq(d), true) => self.assert_diff(d, reason, tcx).and_then(|()| self.assert_diff(d.reversed(), reason, tcx)),
+            (Constraint::Eq(_), false) => {
+                let (a, b, _) = sides(ctx, atom.term).expect("arithmetic atom");
+                self.push_diseq(a, b, reason, Some(atom.var));
+                Ok(())
+            }
+        };
+        match result {
+            Ok(()) => handed,
+            Err(conflict) => TheoryOutcome::Conflict(conflict),
+        }
+    }
+
+    fn propagate(&mut self, ctx: &Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let mut props = Vec::new();
+        for lit in core::mem::take(&mut self.pending_const) {
+            if self.atoms.contains_key(&lit.var()) {
+                props.push(TheoryPropagation::new(lit, tcx.r_and(Vec::new())));
+            }
+        }
+        if self.touched {
+            if let Err(conflict) = self.tighten(tcx) {
+                return TheoryOutcome::Conflict(conflict);
+            }
+            self.touched = false;
+            let mut open: Vec<VarId> = self.atoms.keys().copied().filter(|v| !self.assigned.contains(v)).collect();
+            open.sort_unstable_by_key(|v| v.0);
+            for v in open {
+                let Some((val, paths)) = self.forced(self.atoms[&v]) else { continue };
+                let lit = if val { Lit::pos(v) } else { Lit::neg(v) };
+                let walks = paths.iter().map(|&(u, w)| self.walk(u, w)).collect();
+                self.record(lit, Implied::Paths(walks));
+                props.push(TheoryPropagation::lazy(lit));
+            }
+        }
+        if self.engaged.is_some() {
+            match self.lia.propagate(ctx, tcx) {
+                TheoryOutcome::Ok => {}
+                TheoryOutcome::Propagate(more) => {
+                    for p in more {
+                        if p.explain.is_none() {
+                            self.record(p.lit, Implied::Lia);
+                        }
+                        props.push(p);
+                    }
+                }
+                other => return other,
+            }
+        }
+        if props.is_empty() { TheoryOutcome::Ok } else { TheoryOutcome::Propagate(props) }
+    }
+
+    fn final_check(&mut self, ctx: &mut Context, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        if let Err(conflict) = self.tighten(tcx) {
+            return TheoryOutcome::Conflict(conflict);
+        }
+        if self.engaged.is_some() {
+            return self.lia.final_check(ctx, tcx);
+        }
+        self.settle();
+        for d in self.diseqs.clone() {
+            let key = (d.a.min(d.b), d.a.max(d.b), d.atom);
+            if self.split.contains(&key) || self.eval(ctx, d.a).is_none() || self.eval(ctx, d.a) != self.eval(ctx, d.b) {
+                continue;
+            }
+            self.split.insert(key);
+            self.log(ScopeUndo::Split(key.0, key.1, key.2));
+            // `a < b ∨ b < a`, given the disequality's reason.
+            let (ge, le) = (ctx.le(d.b, d.a), ctx.le(d.a, d.b));
+            let (lt, gt) = (ctx.not(ge), ctx.not(le));
+            return TheoryOutcome::Lemma(TheoryLemma { because: d.explain, atoms: vec![lt, gt] });
+        }
+        self.arrange_shared(ctx, tcx)
+    }
+
+    fn incomplete(&self) -> bool { self.engaged.is_some() && self.lia.incomplete() }
+
+    fn model_value(&self, ctx: &Context, t: TermId) -> Option<Value> {
+        if self.engaged.is_some() {
+            return self.lia.model_value(ctx, t);
+        }
+        self.model.get(*self.term_var.get(&t)?).map(|&v| Value::Int(v))
+    }
+
+    fn explain(&mut self, ctx: &Context, lit: Lit, tcx: &mut TheoryCtx) -> ReasonId {
+        match &self.implied[&lit.var()] {
+            Implied::Paths(walks) => {
+                let kids = walks.iter().map(|(path, edges)| tcx.r_dl_path(path.clone(), edges.clone())).collect();
+                tcx.r_and(kids)
+            }
+            Implied::Lia => self.lia.explain(ctx, lit, tcx),
+        }
+    }
+
+    fn push_level(&mut self) {
+        self.lia.push_level();
+        self.levels.push((self.trail.len(), self.edges.len()));
+    }
+
+    fn pop_levels(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.lia.pop_levels(n);
+        let (trail, edges) = self.levels[self.levels.len() - n];
+        self.levels.truncate(self.levels.len() - n);
+        while self.trail.len() > trail {
+            match self.trail.pop().expect("non-empty") {
+                Undo::Cell(u, v, cell) => self.dist[u][v] = cell,
+                Undo::Assigned(v) => {
+                    self.assigned.remove(&v);
+                }
+                Undo::Diseq => {
+                    self.diseqs.pop();
+                }
+                Undo::Fact => {
+                    self.facts.pop();
+                }
+                Undo::Implied(v) => {
+                    self.implied.remove(&v);
+                }
+            }
+        }
+        self.edges.truncate(edges);
+        self.touched = false;
+        if self.engaged.is_some_and(|level| self.levels.len() < level) {
+            self.engaged = None;
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.lia.push_scope();
+        self.scope_marks.push(self.scope_undo.len());
+    }
+
+    fn pop_scopes(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.lia.pop_scopes(n);
+        let target = self.scope_marks[self.scope_marks.len() - n];
+        self.scope_marks.truncate(self.scope_marks.len() - n);
+        // Vertices stay in the graph, unreachable from the remaining terms: the edges at
+        // level 0 still hold of them.
+        while self.scope_undo.len() > target {
+            match self.scope_undo.pop().expect("non-empty") {
+                ScopeUndo::TermVar(t) => {
+                    self.term_var.remove(&t);
+                }
+                ScopeUndo::Atom(v, t) => {
+                    self.atoms.remove(&v);
+                    self.assigned.remove(&v);
+                    self.implied.remove(&v);
+                    self.endpoints.remove(&t);
+                }
+                ScopeUndo::Split(a, b, atom) => {
+                    self.split.remove(&(a, b, atom));
+                }
+                ScopeUndo::Shared => {
+                    self.shared.pop();
+                }
+                ScopeUndo::Arranged(a, b) => {
+                    self.arranged.remove(&(a, b));
+                }
+            }
+        }
+        let depth = self.scope_marks.len();
+        let atoms = &self.atoms;
+        self.diseqs.retain(|d| match d.atom {
+            Some(v) => atoms.contains_key(&v),
+            None => d.scope <= depth,
+        });
+        self.facts.retain(|(fact, scope)| match fact {
+            Fact::Atom(atom, _) => atoms.contains_key(&atom.var),
+            Fact::Eq(_) | Fact::Diseq(_) => *scope <= depth,
+        });
+        self.pending_const.retain(|l| self.atoms.contains_key(&l.var()));
+    }
+
+    fn equality_sharing_mut(&mut self) -> Option<&mut dyn EqualitySharing> { Some(self) }
+}
+
+impl EqualitySharing for UtvpiTheory {
+    /// Classes of shared terms the closure bounds both ways (`x - y <= 0` and
+    /// `y - x <= 0`), and LIA's once it has the branch.
+    fn export_classes(&mut self, oracle: &SharedTermOracle, export_epoch: u64, tcx: &mut TheoryCtx) -> Vec<EqClass> {
+        let mut terms: Vec<TermId> = oracle.shared_set().iter().copied().collect();
+        terms.sort_unstable();
+        let mut out: Vec<EqClass> = self
+            .classes(&terms)
+            .into_iter()
+            .filter(|class| class.len() > 1)
+            .map(|class| {
+                let members = class[1..].iter().map(|(t, paths)| (*t, self.explain_paths(paths, tcx))).collect();
+                EqClass { rep: class[0].0, members }
+            })
+            .collect();
+        if self.engaged.is_some() {
+            out.extend(self.lia.export_classes(oracle, export_epoch, tcx));
+        }
+        out
+    }
+
+    fn import_equality(&mut self, eq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let (Some(&x), Some(&y)) = (self.term_var.get(&eq.a), self.term_var.get(&eq.b)) else {
+            return TheoryOutcome::Ok;
+        };
+        if x == y {
+            return TheoryOutcome::Ok;
+        }
+        let reason = self.keep(eq.explain, tcx);
+        let eq = SharedEq { explain: reason, ..eq };
+        self.note(Fact::Eq(eq.clone()));
+        let handed = if self.engaged.is_some() { self.lia.import_equality(eq, tcx) } else { TheoryOutcome::Ok };
+        let d = Diff::new(2 * x, 2 * y, 0);
+        match self.assert_diff(d, reason, tcx).and_then(|()| self.assert_diff(d.reversed(), reason, tcx)) {
+            Ok(()) => handed,
+            Err(conflict) => TheoryOutcome::Conflict(conflict),
+        }
+    }
+
+    fn import_disequality(&mut self, diseq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome {
+        let explain = self.keep(diseq.explain, tcx);
+        self.push_diseq(diseq.a, diseq.b, explain, None);
+        let diseq = SharedEq { explain, ..diseq };
+        self.note(Fact::Diseq(diseq.clone()));
+        if self.engaged.is_some() { self.lia.import_disequality(diseq, tcx) } else { TheoryOutcome::Ok }
+    }
+}
@@ -1,8 +1,11 @@
 #![forbid(unsafe_code)]
 //! Theory traits + equality sharing messages.
 
-use smt_core::TermId;
+use smt_core::{Context, TermId, Value};
+use smt_sat::Lit;
 
+use crate::atoms::Atom;
+use crate::quantifiers::EGraph;
 use crate::reason::ReasonId;
 use crate::shared_terms::SharedTermOracle;
 use crate::theory_ctx::TheoryCtx;
@@ -14,17 +17,164 @@ pub struct SharedEq {
     pub explain: ReasonId,
 }
 
+/// A theory's equivalence class over shared terms, for shaped export.
+#[derive(Debug, Clone)]
+pub struct EqClass {
+    pub rep: TermId,
+    /// Other members, each with the reason it equals `rep`.
+    pub members: Vec<(TermId, ReasonId)>,
+}
+
+/// `lit` is implied by the (true) literals of `explain`. `None` defers the explanation
+/// to `Theory::explain`, which is only called if conflict analysis needs it.
+#[derive(Debug, Clone)]
+pub struct TheoryPropagation {
+    pub lit: Lit,
+    pub explain: Option<ReasonId>,
+}
+
+impl TheoryPropagation {
+    pub fn new(lit: Lit, explain: ReasonId) -> Self {
+        Self { lit, explain: Some(explain) }
+    }
+
+    pub fn lazy(lit: Lit) -> Self {
+        Self { lit, explain: None }
+    }
+}
+
+/// The (true) literals of `because` imply the disjunction of `atoms`.
+///
+/// `atoms` are Boolean terms, possibly created for the lemma; each must be a theory atom
+/// or the negation of one, so encoding it adds no clauses mid-search. New atoms are
+/// registered with their owners like any other.
+#[derive(Debug, Clone)]
+pub struct TheoryLemma {
+    pub because: ReasonId,
+    pub atoms: Vec<TermId>,
+}
+
+/// What a theory learned from an assignment, an import or a check.
+///
+/// The engine turns propagations into implication clauses, conflicts into conflict
+/// clauses and lemmas into lemma clauses, and hands them to the SAT kernel right away.
+#[derive(Debug, Clone, Default)]
+pub enum TheoryOutcome {
+    #[default]
+    Ok,
+    Propagate(Vec<TheoryPropagation>),
+    /// The literals of the reason are jointly inconsistent.
+    Conflict(ReasonId),
+    /// A valid clause, typically over new atoms (splits, cuts). Lemmas stay after
+    /// backtracking, so they must not depend on the current branch beyond `because`.
+    Lemma(TheoryLemma),
+    /// Several lemmas at once (e.g. the clauses defining new atoms).
+    Lemmas(Vec<TheoryLemma>),
+}
+
+impl TheoryOutcome {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, TheoryOutcome::Ok)
+    }
+}
+
 pub trait EqualitySharing {
-    fn export_equalities(&mut self, oracle: &SharedTermOracle, export_epoch: u64, tcx: &mut TheoryCtx) -> Vec<SharedEq>;
-    fn import_equality(&mut self, eq: SharedEq, tcx: &mut TheoryCtx);
+    /// Equalities to export as they are. Theories that know their classes over shared
+    /// terms implement `export_classes` instead.
+    fn export_equalities(&mut self, _oracle: &SharedTermOracle, _export_epoch: u64, _tcx: &mut TheoryCtx) -> Vec<SharedEq> {
+        Vec::new()
+    }
+
+    /// Classes to export, shaped by the engine according to `SharingConfig::export_shape`.
+    /// Alternative (or complement) to `export_equalities`.
+    fn export_classes(&mut self, _oracle: &SharedTermOracle, _export_epoch: u64, _tcx: &mut TheoryCtx) -> Vec<EqClass> {
+        Vec::new()
+    }
+
+    /// Merge `eq` into the theory state and report what follows from it.
+    fn import_equality(&mut self, eq: SharedEq, tcx: &mut TheoryCtx) -> TheoryOutcome;
+
+    /// `eq.a != eq.b` (an interface equality assigned false). Ignoring it is sound but
+    /// may leave the combination incomplete.
+    fn import_disequality(&mut self, _diseq: SharedEq, _tcx: &mut TheoryCtx) -> TheoryOutcome {
+        TheoryOutcome::Ok
+    }
 }
 
 pub trait Theory {
     fn name(&self) -> &'static str;
 
+    /// Whether this theory owns the Boolean `atom_term` (used to classify propositionalizer leaves).
+    ///
+    /// Leaves no theory claims are encoded as plain propositional variables.
+    fn owns_atom(&self, _ctx: &Context, _atom_term: TermId) -> bool { false }
+
     /// Return the endpoint terms used by the engine to compute shared terms.
     fn atom_endpoints(&self, atom_term: TermId) -> Vec<TermId>;
 
+    /// Terms this theory mentions in an atom another theory owns (as arrays do with the
+    /// reads and indices of arithmetic atoms); they count toward sharing like endpoints.
+    fn foreign_endpoints(&self, _atom_term: TermId) -> Vec<TermId> { Vec::new() }
+
+    /// Called when `t`, which this theory mentions, becomes shared with it.
+    fn notify_shared(&mut self, _t: TermId) {}
+
+    /// Called once per atom of this theory, after it received its SAT variable.
+    fn register_atom(&mut self, _ctx: &Context, _atom: Atom) {}
+
+    /// Called once per atom of another theory, right after its owner's `register_atom`.
+    fn register_foreign_atom(&mut self, _ctx: &Context, _atom: Atom) {}
+
+    /// The SAT kernel assigned `atom` to `value`.
+    fn assert_atom(&mut self, _ctx: &Context, _atom: Atom, _value: bool, _tcx: &mut TheoryCtx) -> TheoryOutcome {
+        TheoryOutcome::Ok
+    }
+
+    /// Cheap consistency check / propagation after a batch of assignments.
+    fn propagate(&mut self, _ctx: &Context, _tcx: &mut TheoryCtx) -> TheoryOutcome { TheoryOutcome::Ok }
+
+    /// Complete check on a full Boolean assignment. The context is mutable so the theory
+    /// can build the atoms of a `TheoryOutcome::Lemma`.
+    fn final_check(&mut self, _ctx: &mut Context, _tcx: &mut TheoryCtx) -> TheoryOutcome { TheoryOutcome::Ok }
+
+    /// Whether the last final check accepted an assignment the theory cannot vouch for
+    /// (numbers too large for it to represent, constraints outside what it decides that
+    /// its model violates). A `Sat` answer then becomes `Unknown`.
+    fn incomplete(&self) -> bool { false }
+
+    /// Value of `t` in the theory's current candidate model, if it has an opinion.
+    ///
+    /// Values are only compared within one theory (model-based combination, model
+    /// merging). Return `Value::Elem` when the value only identifies a class (as UF
+    /// does); any other variant is taken as the term's actual value.
+    fn model_value(&self, _ctx: &Context, _t: TermId) -> Option<Value> { None }
+
+    /// Reason for a literal this theory propagated with `TheoryPropagation::lazy`. Called
+    /// while `lit` is still assigned, possibly after later levels were undone in the
+    /// kernel but not yet in the theory: use only atoms asserted before `lit` was
+    /// propagated (e.g. antecedents recorded at that point).
+    fn explain(&mut self, _ctx: &Context, lit: Lit, _tcx: &mut TheoryCtx) -> ReasonId {
+        panic!("theory {} propagated {lit:?} lazily but does not implement explain", self.name())
+    }
+
+    /// A decision level was opened.
+    fn push_level(&mut self) {}
+
+    /// The `n` most recent decision levels were undone. Reasons created since the
+    /// matching `push_level` are freed by the engine and must be forgotten.
+    fn pop_levels(&mut self, _n: usize) {}
+
+    /// A user scope was opened (always at decision level 0).
+    fn push_scope(&mut self) {}
+
+    /// The `n` most recent user scopes were closed: atoms registered since the matching
+    /// `push_scope` (and their terms) no longer exist and must be forgotten.
+    fn pop_scopes(&mut self, _n: usize) {}
+
+    /// Ground terms and classes to E-match quantifier triggers against (as the UF theory
+    /// provides); only asked after a final check of this theory passed.
+    fn egraph(&self) -> Option<&dyn EGraph> { None }
+
     /// Optional equality sharing hook.
     fn equality_sharing_mut(&mut self) -> Option<&mut dyn EqualitySharing> { None }
 }
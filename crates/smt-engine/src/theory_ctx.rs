@@ -1,6 +1,10 @@
 #![forbid(unsafe_code)]
 //! TheoryCtx: convenience builder for reason composition.
 
+use smt_core::{Rational, TermId};
+use smt_sat::Lit;
+
+use crate::atoms::TheoryId;
 use crate::reason::{Reason, ReasonArena, ReasonId};
 
 pub struct TheoryCtx<'a> {
@@ -10,9 +14,53 @@ pub struct TheoryCtx<'a> {
 impl<'a> TheoryCtx<'a> {
     pub fn new(arena: &'a mut ReasonArena) -> Self { Self { arena } }
 
+    /// Leaf reason: the SAT literal `lit` is true. An `Assumption` if a labeled input
+    /// assertion asserts `lit`.
+    pub fn r_lit(&mut self, lit: Lit) -> ReasonId {
+        let leaf = match self.arena.label_of(lit) {
+            Some(label) => Reason::Assumption { label: label.to_string(), lit },
+            None => Reason::Atom(lit),
+        };
+        self.arena.push(leaf)
+    }
+
     /// Create an AND reason from component reasons.
     pub fn r_and(&mut self, kids: Vec<ReasonId>) -> ReasonId {
         if kids.len() == 1 { return kids[0]; }
         self.arena.push(Reason::And(kids))
     }
+
+    /// `a = b` by congruence, given reasons for the argument equalities.
+    pub fn r_congruence(&mut self, a: TermId, b: TermId, args: Vec<ReasonId>) -> ReasonId {
+        self.arena.push(Reason::Congruence { a, b, args })
+    }
+
+    /// `a = b` by a transitivity chain.
+    pub fn r_trans(&mut self, a: TermId, b: TermId, steps: Vec<ReasonId>) -> ReasonId {
+        if steps.len() == 1 { return steps[0]; }
+        self.arena.push(Reason::Trans { a, b, steps })
+    }
+
+    /// A difference-logic path; `edges.len() + 1 == path.len()`.
+    pub fn r_dl_path(&mut self, path: Vec<TermId>, edges: Vec<ReasonId>) -> ReasonId {
+        debug_assert_eq!(path.len(), edges.len() + 1);
+        self.arena.push(Reason::DlPath { path, edges })
+    }
+
+    /// `a = b` received from theory `src`.
+    pub fn r_imported(&mut self, src: TheoryId, a: TermId, b: TermId, explain: ReasonId) -> ReasonId {
+        self.arena.push(Reason::Imported { src, a, b, explain })
+    }
+
+    /// A Farkas combination of arithmetic bounds, as `(coefficient, bound reason)` pairs.
+    pub fn r_farkas(&mut self, terms: Vec<(Rational, ReasonId)>) -> ReasonId {
+        let (coeffs, premises) = terms.into_iter().unzip();
+        self.arena.push(Reason::Farkas { coeffs, premises })
+    }
+
+    /// Copy `r` into the permanent region, for state that outlives the current decision
+    /// level and user scope (e.g. facts a theory keeps at level 0).
+    pub fn pin(&mut self, r: ReasonId) -> ReasonId {
+        self.arena.pin(r)
+    }
 }
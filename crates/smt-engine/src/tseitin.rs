@@ -0,0 +1,275 @@
+#![forbid(unsafe_code)]
+//! Tseitin propositionalizer for the Boolean skeleton of asserted terms.
+//!
+//! Connectives (`not/and/or/=>` and `=` over Bool) are encoded structurally; every other
+//! Boolean term is a leaf. Leaves claimed by a theory become atoms in the `AtomTable`,
+//! the rest are plain propositional variables. Quantified formulas are leaves too; the
+//! encoder lists them for instantiation.
+//!
+//! The encoding is polarity-aware (Plaisted–Greenbaum): a definition `v <-> phi` is only
+//! emitted in the direction(s) the occurrence needs. Results are cached per `TermId`, so a
+//! shared subformula is encoded once and only gets its missing direction on later reuse.
+
+use hashbrown::HashMap;
+use rustc_hash::FxHasher;
+use core::hash::BuildHasherDefault;
+
+use smt_core::{Context, TermId, TermKind};
+use smt_sat::{Lit, SatKernel};
+
+use crate::atoms::{Atom, AtomTable, TheoryId};
+
+type FxBuild = BuildHasherDefault<FxHasher>;
+
+const POS: u8 = 0b01;
+const NEG: u8 = 0b10;
+const BOTH: u8 = POS | NEG;
+
+fn flip(pol: u8) -> u8 {
+    ((pol & POS) << 1) | ((pol & NEG) >> 1)
+}
+
+/// Everything the encoder needs besides its own cache.
+pub struct EncodeCx<'a> {
+    pub ctx: &'a Context,
+    pub atoms: &'a mut AtomTable,
+    /// Owning theory of a Boolean leaf, `None` for plain propositional leaves.
+    pub classify: &'a dyn Fn(&Context, TermId) -> Option<TheoryId>,
+    pub sat: &'a mut dyn SatKernel,
+}
+
+/// Cache change to revert when a user scope is popped.
+enum Undo {
+    Lit(TermId),
+    Emitted(TermId, u8),
+    Quantified,
+}
+
+#[derive(Default)]
+pub struct TseitinEncoder {
+    lits: HashMap<TermId, Lit, FxBuild>,
+    /// Polarities whose defining clauses were already emitted.
+    emitted: HashMap<TermId, u8, FxBuild>,
+    /// Quantified formulas encoded, in order.
+    quantified: Vec<TermId>,
+    /// Changes made inside user scopes, and the undo length at each push.
+    undo: Vec<Undo>,
+    scopes: Vec<usize>,
+}
+
+impl TseitinEncoder {
+    pub fn push_scope(&mut self) {
+        self.scopes.push(self.undo.len());
+    }
+
+    /// Forget everything encoded since the `n`-th most recent push: later uses of those
+    /// terms get fresh variables and definitions (the old clauses stay, over variables
+    /// nothing refers to any more).
+    pub fn pop_scopes(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let keep = self.scopes.len() - n;
+        let target = self.scopes[keep];
+        self.scopes.truncate(keep);
+        while self.undo.len() > target {
+            match self.undo.pop().expect("non-empty") {
+                Undo::Lit(t) => {
+                    self.lits.remove(&t);
+                }
+                Undo::Emitted(t, 0) => {
+                    self.emitted.remove(&t);
+                }
+                Undo::Emitted(t, old) => {
+                    self.emitted.insert(t, old);
+                }
+                Undo::Quantified => {
+                    self.quantified.pop();
+                }
+            }
+        }
+    }
+
+    fn mark_emitted(&mut self, t: TermId, pol: u8) {
+        let done = self.emitted.entry(t).or_insert(0);
+        if !self.scopes.is_empty() {
+            self.undo.push(Undo::Emitted(t, *done));
+        }
+        *done |= pol;
+    }
+
+    /// Literal already assigned to `t`, if it was encoded.
+    pub fn lit_of(&self, t: TermId) -> Option<Lit> {
+        self.lits.get(&t).copied()
+    }
+
+    /// Quantified formulas that have a literal.
+    pub fn quantified(&self) -> &[TermId] {
+        &self.quantified
+    }
+
+    /// Encode `root` and assert it as a unit. Top-level conjunctions are split.
+    pub fn assert_root(&mut self, cx: &mut EncodeCx<'_>, root: TermId) -> smt_core::Result<()> {
+        check_root(cx.ctx, root)?;
+        if let TermKind::And(kids) = cx.ctx.term_node(root).0 {
+            for &k in kids {
+                self.assert_root(cx, k)?;
+            }
+            return Ok(());
+        }
+        let l = self.encode(cx, root, POS);
+        cx.sat.add_clause(&[l]);
+        Ok(())
+    }
+
+    /// Encode `root` under `guard`: every top-level conjunct `c` gets the clause
+    /// `¬guard ∨ c`, so the assertion only holds while `guard` is assumed.
+    pub fn assert_guarded(&mut self, cx: &mut EncodeCx<'_>, root: TermId, guard: Lit) -> smt_core::Result<()> {
+        check_root(cx.ctx, root)?;
+        if let TermKind::And(kids) = cx.ctx.term_node(root).0 {
+            for &k in kids {
+                self.assert_guarded(cx, k, guard)?;
+            }
+            return Ok(());
+        }
+        let l = self.encode(cx, root, POS);
+        cx.sat.add_clause(&[!guard, l]);
+        Ok(())
+    }
+
+    /// Encode `t` so that it can be used with both polarities (e.g. as an assumption).
+    pub fn encode_both(&mut self, cx: &mut EncodeCx<'_>, t: TermId) -> Lit {
+        self.encode(cx, t, BOTH)
+    }
+
+    fn encode(&mut self, cx: &mut EncodeCx<'_>, t: TermId, pol: u8) -> Lit {
+        let ctx = cx.ctx;
+        let (kind, _) = ctx.term_node(t);
+
+        if let TermKind::Not(a) = kind {
+            return !self.encode(cx, *a, flip(pol));
+        }
+
+        let lit = self.lit_for(cx, t);
+        let todo = pol & !self.emitted.get(&t).copied().unwrap_or(0);
+        if todo == 0 {
+            return lit;
+        }
+        self.mark_emitted(t, todo);
+
+        match kind {
+            TermKind::BoolConst(v) => {
+                self.mark_emitted(t, BOTH);
+                cx.sat.add_clause(&[if *v { lit } else { !lit }]);
+            }
+            TermKind::And(xs) => {
+                let kids: Vec<Lit> = xs.iter().map(|&x| self.encode(cx, x, todo)).collect();
+                if todo & POS != 0 {
+                    for &k in &kids {
+                        cx.sat.add_clause(&[!lit, k]);
+                    }
+                }
+                if todo & NEG != 0 {
+                    let mut c: Vec<Lit> = kids.iter().map(|&k| !k).collect();
+                    c.push(lit);
+                    cx.sat.add_clause(&c);
+                }
+            }
+            TermKind::Or(xs) => {
+                let kids: Vec<Lit> = xs.iter().map(|&x| self.encode(cx, x, todo)).collect();
+                if todo & POS != 0 {
+                    let mut c = kids.clone();
+                    c.push(!lit);
+                    cx.sat.add_clause(&c);
+                }
+                if todo & NEG != 0 {
+                    for &k in &kids {
+                        cx.sat.add_clause(&[lit, !k]);
+                    }
+                }
+            }
+            TermKind::Implies(a, b) => {
+                let ka = self.encode(cx, *a, flip(todo));
+                let kb = self.encode(cx, *b, todo);
+                if todo & POS != 0 {
+                    cx.sat.add_clause(&[!lit, !ka, kb]);
+                }
+                if todo & NEG != 0 {
+                    cx.sat.add_clause(&[lit, ka]);
+                    cx.sat.add_clause(&[lit, !kb]);
+                }
+            }
+            TermKind::Eq(a, b) if is_bool(ctx, *a) => {
+                let ka = self.encode(cx, *a, BOTH);
+                let kb = self.encode(cx, *b, BOTH);
+                if todo & POS != 0 {
+                    cx.sat.add_clause(&[!lit, !ka, kb]);
+                    cx.sat.add_clause(&[!lit, ka, !kb]);
+                }
+                if todo & NEG != 0 {
+                    cx.sat.add_clause(&[lit, ka, kb]);
+                    cx.sat.add_clause(&[lit, !ka, !kb]);
+                }
+            }
+            // Leaves carry no defining clauses.
+            _ => {}
+        }
+
+        lit
+    }
+
+    /// Literal for `t`, allocating a variable (and registering a theory atom) on first use.
+    fn lit_for(&mut self, cx: &mut EncodeCx<'_>, t: TermId) -> Lit {
+        if let Some(&l) = self.lits.get(&t) {
+            return l;
+        }
+        let l = if let Some(atom) = cx.atoms.lookup(t) {
+            Lit::pos(atom.var)
+        } else {
+            let var = cx.sat.new_var();
+            if is_leaf(cx.ctx, t) {
+                if let Some(theory) = (cx.classify)(cx.ctx, t) {
+                    cx.atoms.push(Atom { term: t, theory, var });
+                }
+            }
+            if matches!(cx.ctx.term_node(t).0, TermKind::Forall(_) | TermKind::Exists(_)) {
+                self.quantified.push(t);
+                if !self.scopes.is_empty() {
+                    self.undo.push(Undo::Quantified);
+                }
+            }
+            Lit::pos(var)
+        };
+        self.lits.insert(t, l);
+        if !self.scopes.is_empty() {
+            self.undo.push(Undo::Lit(t));
+        }
+        l
+    }
+}
+
+fn check_root(ctx: &Context, root: TermId) -> smt_core::Result<()> {
+    if ctx.term_sort(root) != ctx.bool_sort() {
+        return Err(format!("asserted term {root:?} is not Boolean").into());
+    }
+    if let Some(v) = ctx.free_vars(root).first() {
+        return Err(format!("asserted term {root:?} has the free variable {v:?}").into());
+    }
+    Ok(())
+}
+
+fn is_bool(ctx: &Context, t: TermId) -> bool {
+    ctx.term_sort(t) == ctx.bool_sort()
+}
+
+fn is_leaf(ctx: &Context, t: TermId) -> bool {
+    match ctx.term_node(t).0 {
+        TermKind::BoolConst(_)
+        | TermKind::Not(_)
+        | TermKind::And(_)
+        | TermKind::Or(_)
+        | TermKind::Implies(..) => false,
+        TermKind::Eq(a, _) => !is_bool(ctx, *a),
+        _ => true,
+    }
+}
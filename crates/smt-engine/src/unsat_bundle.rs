@@ -1,41 +1,97 @@
 #![forbid(unsafe_code)]
-//! Writes an UNSAT debug bundle (eqshare.dot + conflict.dot + README.txt).
+//! Writes debug bundles: on UNSAT (eqshare.dot + conflict.dot + README.txt), and on a
+//! failed model self-check (the same files plus model.txt).
 
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use smt_core::Model;
 
 use crate::reason::ReasonId;
 use crate::engine::SmtEngine;
 
-pub fn write_unsat_debug_bundle<K: smt_sat::SatKernel>(
-    engine: &SmtEngine<K>,
-    conflict_reason: ReasonId,
-) {
+fn bundle_dir<K: smt_sat::SatKernel>(engine: &SmtEngine<K>) -> Option<PathBuf> {
     let pid = std::process::id();
     let dir = Path::new("target").join(format!("smt-debug-{}-{}", pid, engine.export_epoch));
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
 
-    if std::fs::create_dir_all(&dir).is_err() { return; }
-
+/// eqshare.dot, conflict.dot (if there is a conflict reason) and the README header.
+fn write_common<K: smt_sat::SatKernel>(
+    engine: &SmtEngine<K>,
+    dir: &Path,
+    conflict_reason: Option<ReasonId>,
+    title: &str,
+) -> Option<std::fs::File> {
     let eqshare_dot = engine.dump_eqshare_dot();
     let _ = std::fs::write(dir.join("eqshare.dot"), eqshare_dot.as_bytes());
 
-    let conflict_dot = engine.dump_conflict_reason_dot(conflict_reason);
-    let _ = std::fs::write(dir.join("conflict.dot"), conflict_dot.as_bytes());
+    if let Some(r) = conflict_reason {
+        let conflict_dot = engine.dump_conflict_reason_dot(r);
+        let _ = std::fs::write(dir.join("conflict.dot"), conflict_dot.as_bytes());
+    }
 
-    let mut f = match std::fs::File::create(dir.join("README.txt")) {
-        Ok(f) => f,
-        Err(_) => return,
-    };
+    let mut f = std::fs::File::create(dir.join("README.txt")).ok()?;
 
-    let _ = writeln!(f, "SMT UNSAT DEBUG BUNDLE");
-    let _ = writeln!(f, "");
+    let _ = writeln!(f, "{title}");
+    let _ = writeln!(f);
     let _ = writeln!(f, "Files:");
     let _ = writeln!(f, "  - eqshare.dot   : equality-sharing exchanges (terms + edges with direction/epoch)");
-    let _ = writeln!(f, "  - conflict.dot  : reason DAG for the reported conflict reason");
-    let _ = writeln!(f, "");
+    if conflict_reason.is_some() {
+        let _ = writeln!(f, "  - conflict.dot  : reason DAG for the reported conflict reason");
+    }
+    Some(f)
+}
+
+pub fn write_unsat_debug_bundle<K: smt_sat::SatKernel>(
+    engine: &SmtEngine<K>,
+    conflict_reason: ReasonId,
+) {
+    let Some(dir) = bundle_dir(engine) else { return };
+    let Some(mut f) = write_common(engine, &dir, Some(conflict_reason), "SMT UNSAT DEBUG BUNDLE") else { return };
+
+    let _ = writeln!(f);
     let _ = writeln!(f, "Render to SVG:");
     let _ = writeln!(f, "  dot -Tsvg eqshare.dot  > eqshare.svg");
     let _ = writeln!(f, "  dot -Tsvg conflict.dot > conflict.svg");
-    let _ = writeln!(f, "");
+    let _ = writeln!(f);
     let _ = writeln!(f, "Conflict root ReasonId: {}", conflict_reason.0);
+    let labels = engine.reasons.assumption_labels(conflict_reason);
+    if !labels.is_empty() {
+        let _ = writeln!(f, "Labeled assertions it uses: {}", labels.join(", "));
+    }
+}
+
+/// Bundle for a model that falsifies an assertion; returns the bundle directory.
+pub fn write_model_check_bundle<K: smt_sat::SatKernel>(
+    engine: &SmtEngine<K>,
+    model: &Model,
+    failure: &str,
+) -> Option<PathBuf> {
+    let dir = bundle_dir(engine)?;
+    let mut f = write_common(engine, &dir, engine.last_conflict, "SMT MODEL CHECK FAILURE BUNDLE")?;
+
+    let _ = writeln!(f, "  - model.txt     : the model that falsifies the assertion");
+    let _ = writeln!(f);
+    let _ = writeln!(f, "Failure: {failure}");
+
+    let mut values: Vec<_> = model.values().collect();
+    values.sort_by_key(|(t, _)| *t);
+    let mut out = String::new();
+    for (t, v) in values {
+        out.push_str(&format!("t{} : {:?} = {}\n", t.0, engine.ctx.term_node(t).0, v));
+    }
+    let mut funcs: Vec<_> = model.funcs().collect();
+    funcs.sort_by_key(|(n, _)| *n);
+    for (name, interp) in funcs {
+        out.push_str(&format!("{name}:\n"));
+        for (args, v) in &interp.entries {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            out.push_str(&format!("  ({}) -> {}\n", args.join(", "), v));
+        }
+        out.push_str(&format!("  else -> {}\n", interp.default));
+    }
+    let _ = std::fs::write(dir.join("model.txt"), out.as_bytes());
+    Some(dir)
 }
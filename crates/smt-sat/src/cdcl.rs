@@ -0,0 +1,525 @@
+#![forbid(unsafe_code)]
+//! Small engine-driven CDCL kernel: two-watched-literal propagation, 1-UIP learning,
+//! VSIDS-style activities, phase saving, assumptions and lazily explained assignments.
+//!
+//! The SMT engine owns the search loop: it calls `propagate`, reads the trail, feeds
+//! theory lemmas back through `add_clause` (which may backjump, propagate the clause or
+//! analyse it as a conflict on the spot) and asks for a `decide` when everything is quiet.
+
+use crate::{Decide, LazyReasons, Lit, NoLazyReasons, SatKernel, VarId};
+
+struct Clause {
+    lits: Vec<Lit>,
+}
+
+/// Why an implied literal holds.
+#[derive(Clone, Copy)]
+enum Why {
+    Clause(usize),
+    /// Assigned by `assign_lazy`; replaced by an (unwatched) clause once explained.
+    Lazy(u32),
+}
+
+#[derive(Default)]
+pub struct Cdcl {
+    clauses: Vec<Clause>,
+    /// Per literal code: clauses watching that literal (visited when it becomes false).
+    watches: Vec<Vec<usize>>,
+
+    assigns: Vec<Option<bool>>,
+    level: Vec<usize>,
+    /// What implied the variable; `None` for decisions and level-0 units.
+    reason: Vec<Option<Why>>,
+    activity: Vec<f64>,
+    /// Binary max-heap of decision candidates by activity (ties to the lower index);
+    /// assigned variables are dropped lazily when they reach the top.
+    order: Vec<usize>,
+    /// Position of each variable in `order`, `NOT_QUEUED` if absent.
+    order_pos: Vec<usize>,
+    phase: Vec<bool>,
+    seen: Vec<bool>,
+
+    trail: Vec<Lit>,
+    trail_lim: Vec<usize>,
+    qhead: usize,
+    /// Engine notification cursor (see `SatKernel::trail_head`).
+    notify_head: usize,
+
+    /// Decided first, level `i + 1` holding `assumptions[i]` (empty if already true).
+    assumptions: Vec<Lit>,
+    failed: Vec<Lit>,
+
+    var_inc: f64,
+    unsat: bool,
+    conflicts: u64,
+}
+
+const VAR_DECAY: f64 = 0.95;
+const NOT_QUEUED: usize = usize::MAX;
+
+fn code(l: Lit) -> usize {
+    l.var().0 as usize * 2 + usize::from(!l.is_pos())
+}
+
+impl Cdcl {
+    pub fn new() -> Self {
+        Self { var_inc: 1.0, ..Self::default() }
+    }
+
+    pub fn num_vars(&self) -> usize { self.assigns.len() }
+
+    /// Number of conflicts analysed so far.
+    pub fn conflicts(&self) -> u64 { self.conflicts }
+
+    fn var_level(&self, v: VarId) -> usize { self.level[v.0 as usize] }
+
+    fn enqueue(&mut self, l: Lit, reason: Option<Why>) {
+        let v = l.var().0 as usize;
+        debug_assert!(self.assigns[v].is_none());
+        self.assigns[v] = Some(l.is_pos());
+        self.level[v] = self.trail_lim.len();
+        self.reason[v] = reason;
+        self.trail.push(l);
+    }
+
+    fn attach(&mut self, lits: Vec<Lit>) -> usize {
+        let cref = self.clauses.len();
+        self.watches[code(lits[0])].push(cref);
+        self.watches[code(lits[1])].push(cref);
+        self.clauses.push(Clause { lits });
+        cref
+    }
+
+    /// Unit propagation over the queue; returns a conflicting clause, if any.
+    fn bcp(&mut self) -> Option<usize> {
+        while self.qhead < self.trail.len() {
+            let p = self.trail[self.qhead];
+            self.qhead += 1;
+            let false_lit = !p;
+            let ws = core::mem::take(&mut self.watches[code(false_lit)]);
+            let mut kept = Vec::with_capacity(ws.len());
+            let mut conflict = None;
+
+            for (i, &cref) in ws.iter().enumerate() {
+                if conflict.is_some() {
+                    kept.extend_from_slice(&ws[i..]);
+                    break;
+                }
+                let lits = &mut self.clauses[cref].lits;
+                if lits[0] == false_lit {
+                    lits.swap(0, 1);
+                }
+                let first = lits[0];
+                if value_of(&self.assigns, first) == Some(true) {
+                    kept.push(cref);
+                    continue;
+                }
+                if let Some(k) = (2..lits.len()).find(|&k| value_of(&self.assigns, lits[k]) != Some(false)) {
+                    lits.swap(1, k);
+                    let w = lits[1];
+                    self.watches[code(w)].push(cref);
+                    continue;
+                }
+                kept.push(cref);
+                if value_of(&self.assigns, first) == Some(false) {
+                    conflict = Some(cref);
+                } else {
+                    self.enqueue(first, Some(Why::Clause(cref)));
+                }
+            }
+
+            self.watches[code(false_lit)] = kept;
+            if conflict.is_some() {
+                self.qhead = self.trail.len();
+                return conflict;
+            }
+        }
+        None
+    }
+
+    /// 1-UIP analysis. Returns the learnt clause (asserting literal first, the literal of
+    /// the backjump level second) and the backjump level.
+    fn analyze(&mut self, confl: usize, ex: &mut dyn LazyReasons) -> (Vec<Lit>, usize) {
+        let cur = self.trail_lim.len();
+        let mut learnt = vec![Lit::pos(VarId(0))];
+        let mut pending = 0usize;
+        let mut p: Option<Lit> = None;
+        let mut idx = self.trail.len();
+        let mut cref = confl;
+
+        loop {
+            let skip = usize::from(p.is_some());
+            for k in skip..self.clauses[cref].lits.len() {
+                let q = self.clauses[cref].lits[k];
+                let v = q.var().0 as usize;
+                if self.seen[v] || self.level[v] == 0 {
+                    continue;
+                }
+                self.seen[v] = true;
+                self.bump(v);
+                if self.level[v] == cur {
+                    pending += 1;
+                } else {
+                    learnt.push(q);
+                }
+            }
+            loop {
+                idx -= 1;
+                if self.seen[self.trail[idx].var().0 as usize] {
+                    break;
+                }
+            }
+            let lit = self.trail[idx];
+            self.seen[lit.var().0 as usize] = false;
+            p = Some(lit);
+            pending -= 1;
+            if pending == 0 {
+                break;
+            }
+            cref = self.reason_clause(lit, ex).expect("implied literal has a reason");
+        }
+
+        learnt[0] = !p.expect("conflict at the current level");
+        for l in &learnt[1..] {
+            self.seen[l.var().0 as usize] = false;
+        }
+
+        let mut bt = 0;
+        if learnt.len() > 1 {
+            let best = (1..learnt.len()).max_by_key(|&k| self.var_level(learnt[k].var())).expect("non-empty");
+            learnt.swap(1, best);
+            bt = self.var_level(learnt[1].var());
+        }
+        (learnt, bt)
+    }
+
+    fn bump(&mut self, v: usize) {
+        self.activity[v] += self.var_inc;
+        if self.activity[v] > 1e100 {
+            for a in &mut self.activity {
+                *a *= 1e-100;
+            }
+            self.var_inc *= 1e-100;
+        }
+        let pos = self.order_pos[v];
+        if pos != NOT_QUEUED {
+            self.sift_up(pos);
+        }
+    }
+
+    /// Whether `a` is decided before `b`.
+    fn precedes(&self, a: usize, b: usize) -> bool {
+        self.activity[a].total_cmp(&self.activity[b]).then(b.cmp(&a)).is_gt()
+    }
+
+    fn enqueue_order(&mut self, v: usize) {
+        if self.order_pos[v] == NOT_QUEUED {
+            self.order_pos[v] = self.order.len();
+            self.order.push(v);
+            self.sift_up(self.order.len() - 1);
+        }
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if !self.precedes(self.order[i], self.order[parent]) {
+                break;
+            }
+            self.swap_order(i, parent);
+            i = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let (l, r) = (2 * i + 1, 2 * i + 2);
+            let mut top = i;
+            if l < self.order.len() && self.precedes(self.order[l], self.order[top]) {
+                top = l;
+            }
+            if r < self.order.len() && self.precedes(self.order[r], self.order[top]) {
+                top = r;
+            }
+            if top == i {
+                break;
+            }
+            self.swap_order(i, top);
+            i = top;
+        }
+    }
+
+    fn swap_order(&mut self, i: usize, j: usize) {
+        self.order.swap(i, j);
+        self.order_pos[self.order[i]] = i;
+        self.order_pos[self.order[j]] = j;
+    }
+
+    /// Most active unassigned variable, if any.
+    fn pick_branch_var(&mut self) -> Option<usize> {
+        while let Some(&v) = self.order.first() {
+            let last = self.order.pop().expect("non-empty");
+            self.order_pos[v] = NOT_QUEUED;
+            if !self.order.is_empty() {
+                self.order[0] = last;
+                self.order_pos[last] = 0;
+                self.sift_down(0);
+            }
+            if self.assigns[v].is_none() {
+                return Some(v);
+            }
+        }
+        None
+    }
+
+    /// Clause that implied the assigned `lit` (explaining a lazy assignment on demand).
+    fn reason_clause(&mut self, lit: Lit, ex: &mut dyn LazyReasons) -> Option<usize> {
+        let v = lit.var().0 as usize;
+        match self.reason[v]? {
+            Why::Clause(cref) => Some(cref),
+            Why::Lazy(handle) => {
+                let mut lits = ex.explain(lit, handle);
+                let at = lits.iter().position(|&l| l == lit).expect("explanation contains the literal");
+                lits.swap(0, at);
+                assert!(
+                    lits[1..].iter().all(|&l| self.value(l) == Some(false)),
+                    "explanation of {lit:?} has a literal that is not false"
+                );
+                // Only needed as a reason, so it is not watched.
+                let cref = self.clauses.len();
+                self.clauses.push(Clause { lits });
+                self.reason[v] = Some(Why::Clause(cref));
+                Some(cref)
+            }
+        }
+    }
+
+    /// Analyse a conflict at the current level, learn, backjump and assert.
+    fn resolve_conflict(&mut self, confl: usize, ex: &mut dyn LazyReasons) {
+        self.conflicts += 1;
+        if self.trail_lim.is_empty() {
+            self.unsat = true;
+            return;
+        }
+        let (learnt, bt) = self.analyze(confl, ex);
+        self.backtrack(bt);
+        let asserting = learnt[0];
+        if learnt.len() == 1 {
+            self.enqueue(asserting, None);
+        } else {
+            let cref = self.attach(learnt);
+            self.enqueue(asserting, Some(Why::Clause(cref)));
+        }
+        self.var_inc /= VAR_DECAY;
+    }
+
+    /// Assumption `a` is false: collect the assumptions its falsity depends on.
+    fn analyze_final(&mut self, a: Lit, ex: &mut dyn LazyReasons) {
+        self.failed.clear();
+        self.failed.push(a);
+        let v = a.var().0 as usize;
+        if self.level[v] == 0 {
+            return;
+        }
+        self.seen[v] = true;
+        for i in (self.trail_lim[0]..self.trail.len()).rev() {
+            let x = self.trail[i].var().0 as usize;
+            if !self.seen[x] {
+                continue;
+            }
+            self.seen[x] = false;
+            match self.reason_clause(self.trail[i], ex) {
+                // Only assumptions are decided below the failing one.
+                None => self.failed.push(self.trail[i]),
+                Some(cref) => {
+                    for k in 1..self.clauses[cref].lits.len() {
+                        let y = self.clauses[cref].lits[k].var().0 as usize;
+                        if self.level[y] > 0 {
+                            self.seen[y] = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ordering used before watching a new clause: true literals (lowest level first),
+    /// then unassigned, then false literals by decreasing level.
+    fn watch_rank(&self, l: Lit) -> (u8, isize) {
+        match self.value(l) {
+            Some(true) => (0, self.var_level(l.var()) as isize),
+            None => (1, 0),
+            Some(false) => (2, -(self.var_level(l.var()) as isize)),
+        }
+    }
+}
+
+fn value_of(assigns: &[Option<bool>], l: Lit) -> Option<bool> {
+    assigns[l.var().0 as usize].map(|v| v == l.is_pos())
+}
+
+impl SatKernel for Cdcl {
+    fn new_var(&mut self) -> VarId {
+        let v = VarId(self.assigns.len() as u32);
+        self.assigns.push(None);
+        self.level.push(0);
+        self.reason.push(None);
+        self.activity.push(0.0);
+        self.order_pos.push(NOT_QUEUED);
+        self.enqueue_order(v.0 as usize);
+        self.phase.push(false);
+        self.seen.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        v
+    }
+
+    fn add_clause(&mut self, lits: &[Lit]) {
+        self.add_clause_with(lits, &mut NoLazyReasons)
+    }
+
+    fn add_clause_with(&mut self, lits: &[Lit], ex: &mut dyn LazyReasons) {
+        if self.unsat {
+            return;
+        }
+        let mut c: Vec<Lit> = Vec::with_capacity(lits.len());
+        for &l in lits {
+            let root = self.value(l).filter(|_| self.var_level(l.var()) == 0);
+            match root {
+                Some(true) => return,
+                Some(false) => continue,
+                None => {}
+            }
+            if c.contains(&!l) {
+                return;
+            }
+            if !c.contains(&l) {
+                c.push(l);
+            }
+        }
+
+        match c.len() {
+            0 => self.unsat = true,
+            1 => {
+                self.backtrack(0);
+                self.enqueue(c[0], None);
+            }
+            _ => {
+                c.sort_by_key(|&l| self.watch_rank(l));
+                let (l0, l1) = (c[0], c[1]);
+                let (v0, v1) = (self.value(l0), self.value(l1));
+                let (lv0, lv1) = (self.var_level(l0.var()), self.var_level(l1.var()));
+                let cref = self.attach(c);
+                match (v0, v1) {
+                    // Conflicting under the current assignment.
+                    (Some(false), _) => {
+                        self.backtrack(lv0);
+                        self.resolve_conflict(cref, ex);
+                    }
+                    // Unit (possibly "late": implied below the current level).
+                    (None, Some(false)) => {
+                        self.backtrack(lv1);
+                        self.enqueue(l0, Some(Why::Clause(cref)));
+                    }
+                    (Some(true), Some(false)) if lv0 > lv1 => {
+                        self.backtrack(lv1);
+                        self.enqueue(l0, Some(Why::Clause(cref)));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn propagate(&mut self) -> Result<(), ()> {
+        self.propagate_with(&mut NoLazyReasons)
+    }
+
+    fn propagate_with(&mut self, ex: &mut dyn LazyReasons) -> Result<(), ()> {
+        while !self.unsat {
+            match self.bcp() {
+                None => return Ok(()),
+                Some(confl) => self.resolve_conflict(confl, ex),
+            }
+        }
+        Err(())
+    }
+
+    fn value(&self, l: Lit) -> Option<bool> {
+        value_of(&self.assigns, l)
+    }
+
+    fn decision_level(&self) -> usize { self.trail_lim.len() }
+
+    fn trail(&self) -> &[Lit] { &self.trail }
+
+    fn trail_head(&self) -> usize { self.notify_head }
+
+    fn set_trail_head(&mut self, head: usize) {
+        self.notify_head = head.min(self.trail.len());
+    }
+
+    fn decide(&mut self) -> Decide {
+        self.decide_with(&mut NoLazyReasons)
+    }
+
+    fn decide_with(&mut self, ex: &mut dyn LazyReasons) -> Decide {
+        while self.trail_lim.len() < self.assumptions.len() {
+            let a = self.assumptions[self.trail_lim.len()];
+            match self.value(a) {
+                Some(true) => self.trail_lim.push(self.trail.len()),
+                Some(false) => {
+                    self.analyze_final(a, ex);
+                    return Decide::Failed;
+                }
+                None => {
+                    self.trail_lim.push(self.trail.len());
+                    self.enqueue(a, None);
+                    return Decide::Decided;
+                }
+            }
+        }
+        let Some(v) = self.pick_branch_var() else { return Decide::Complete };
+        let var = VarId(v as u32);
+        let lit = if self.phase[v] { Lit::pos(var) } else { Lit::neg(var) };
+        self.trail_lim.push(self.trail.len());
+        self.enqueue(lit, None);
+        Decide::Decided
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if level >= self.trail_lim.len() {
+            return;
+        }
+        let keep = self.trail_lim[level];
+        for i in keep..self.trail.len() {
+            let l = self.trail[i];
+            let v = l.var().0 as usize;
+            self.phase[v] = l.is_pos();
+            self.assigns[v] = None;
+            self.reason[v] = None;
+            self.enqueue_order(v);
+        }
+        self.trail.truncate(keep);
+        self.trail_lim.truncate(level);
+        self.qhead = self.qhead.min(keep);
+        self.notify_head = self.notify_head.min(keep);
+    }
+
+    fn set_phase(&mut self, lit: Lit) {
+        self.phase[lit.var().0 as usize] = lit.is_pos();
+    }
+
+    fn set_assumptions(&mut self, lits: &[Lit]) {
+        self.assumptions = lits.to_vec();
+        self.failed.clear();
+    }
+
+    fn failed_assumptions(&self) -> &[Lit] { &self.failed }
+
+    fn assign_lazy(&mut self, lit: Lit, handle: u32) -> bool {
+        debug_assert!(self.value(lit).is_none());
+        self.enqueue(lit, Some(Why::Lazy(handle)));
+        true
+    }
+}
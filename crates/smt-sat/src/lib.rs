@@ -1,8 +1,11 @@
 #![forbid(unsafe_code)]
 //! SAT kernel interface + basic literal type.
 //!
-//! This is a stub to keep the workspace compiling. Replace with a real CDCL
-//! kernel (or wrap an existing solver).
+//! `Cdcl` is the kernel used by the engine; `DummySat` only records clauses.
+
+pub mod cdcl;
+
+pub use cdcl::Cdcl;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct VarId(pub u32);
@@ -21,20 +24,142 @@ impl Lit {
     pub fn is_pos(self) -> bool { self.sign }
 }
 
+impl core::ops::Not for Lit {
+    type Output = Lit;
+
+    fn not(self) -> Lit { Self { var: self.var, sign: !self.sign } }
+}
+
+/// Supplies clauses for literals assigned through `SatKernel::assign_lazy`, when conflict
+/// analysis reaches them.
+pub trait LazyReasons {
+    /// Clause explaining `lit`: `lit` itself plus literals that were all false before
+    /// `lit` was assigned. `handle` is the one given to `assign_lazy`.
+    fn explain(&mut self, lit: Lit, handle: u32) -> Vec<Lit>;
+}
+
+/// Explainer for callers that never assign lazily.
+pub struct NoLazyReasons;
+
+impl LazyReasons for NoLazyReasons {
+    fn explain(&mut self, lit: Lit, _handle: u32) -> Vec<Lit> {
+        panic!("lazily assigned {lit:?} needs an explainer (use the `*_with` kernel methods)")
+    }
+}
+
+/// Outcome of `SatKernel::decide`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decide {
+    /// A decision literal was pushed on a new level.
+    Decided,
+    /// Every variable is assigned.
+    Complete,
+    /// An assumption is false under the clauses (see `SatKernel::failed_assumptions`).
+    Failed,
+    /// The kernel cannot search (stub kernels).
+    Unknown,
+}
+
 /// SAT kernel interface expected by the SMT engine.
 ///
-/// A production solver would expose:
-/// - enqueue / propagate
-/// - conflict analysis
-/// - decision heuristic
+/// The engine drives the search: `propagate` to fixpoint, read the trail past
+/// `trail_head`, feed theory clauses through `add_clause`, then `decide`.
+#[allow(clippy::result_unit_err)]
 pub trait SatKernel {
+    /// Allocate a fresh variable.
+    fn new_var(&mut self) -> VarId;
+
+    /// Add a permanent clause (disjunction of `lits`).
+    ///
+    /// Valid at any point of the search: a clause that is unit or conflicting under the
+    /// current assignment backjumps as needed and is propagated / analysed immediately.
+    fn add_clause(&mut self, lits: &[Lit]);
+
+    /// Unit propagation to fixpoint, learning from conflicts on the way.
+    /// `Err` means the clause set is unsatisfiable.
     fn propagate(&mut self) -> Result<(), ()>;
+
+    /// Current value of `lit`, `None` if unassigned.
+    fn value(&self, lit: Lit) -> Option<bool>;
+
+    fn decision_level(&self) -> usize;
+
+    /// Assigned literals in assignment order.
+    fn trail(&self) -> &[Lit];
+
+    /// Trail prefix already seen by the engine; clamped on backtrack.
+    fn trail_head(&self) -> usize;
+
+    fn set_trail_head(&mut self, head: usize);
+
+    /// Open a new decision level with a branching literal.
+    fn decide(&mut self) -> Decide;
+
+    /// Undo every level above `level`.
+    fn backtrack(&mut self, level: usize);
+
+    /// Hint: branch on `lit` (rather than its negation) when its variable is decided.
+    fn set_phase(&mut self, _lit: Lit) {}
+
+    /// Literals `decide` branches on first, one level each, before any free decision.
+    /// Replaces earlier assumptions; set them at level 0.
+    fn set_assumptions(&mut self, _lits: &[Lit]) {}
+
+    /// After `Decide::Failed`: assumptions that together contradict the clauses.
+    fn failed_assumptions(&self) -> &[Lit] { &[] }
+
+    /// Assign the unassigned `lit` at the current level as implied, deferring its clause
+    /// to `LazyReasons::explain(lit, handle)`. Returns `false` (and assigns nothing) if
+    /// the kernel cannot do this; the caller then adds the clause itself.
+    fn assign_lazy(&mut self, _lit: Lit, _handle: u32) -> bool { false }
+
+    /// `propagate`, asking `ex` for the clauses of lazy assignments analysis needs.
+    fn propagate_with(&mut self, _ex: &mut dyn LazyReasons) -> Result<(), ()> { self.propagate() }
+
+    /// `add_clause`, asking `ex` for the clauses of lazy assignments analysis needs.
+    fn add_clause_with(&mut self, lits: &[Lit], _ex: &mut dyn LazyReasons) { self.add_clause(lits) }
+
+    /// `decide`, asking `ex` for the clauses of lazy assignments behind a failed assumption.
+    fn decide_with(&mut self, _ex: &mut dyn LazyReasons) -> Decide { self.decide() }
 }
 
-/// Trivial kernel: never propagates, never conflicts.
+/// Trivial kernel: never propagates, never conflicts. Clauses are only recorded.
 #[derive(Default)]
-pub struct DummySat;
+pub struct DummySat {
+    num_vars: u32,
+    clauses: Vec<Vec<Lit>>,
+}
+
+impl DummySat {
+    pub fn num_vars(&self) -> u32 { self.num_vars }
+
+    pub fn clauses(&self) -> &[Vec<Lit>] { &self.clauses }
+}
 
 impl SatKernel for DummySat {
+    fn new_var(&mut self) -> VarId {
+        let v = VarId(self.num_vars);
+        self.num_vars += 1;
+        v
+    }
+
+    fn add_clause(&mut self, lits: &[Lit]) {
+        self.clauses.push(lits.to_vec());
+    }
+
     fn propagate(&mut self) -> Result<(), ()> { Ok(()) }
+
+    fn value(&self, _lit: Lit) -> Option<bool> { None }
+
+    fn decision_level(&self) -> usize { 0 }
+
+    fn trail(&self) -> &[Lit] { &[] }
+
+    fn trail_head(&self) -> usize { 0 }
+
+    fn set_trail_head(&mut self, _head: usize) {}
+
+    fn decide(&mut self) -> Decide { Decide::Unknown }
+
+    fn backtrack(&mut self, _level: usize) {}
 }
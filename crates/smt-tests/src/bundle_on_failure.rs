@@ -29,11 +29,10 @@ where
     setup(&mut sess)?;
     let got = sess.check_sat();
 
-    let ok = match (expect, got) {
-        (Expect::Sat, CheckSat::Sat) => true,
-        (Expect::Unsat, CheckSat::Unsat) => true,
-        _ => false,
-    };
+    let ok = matches!(
+        (expect, got),
+        (Expect::Sat, CheckSat::Sat) | (Expect::Unsat, CheckSat::Unsat)
+    );
 
     if ok {
         return Ok(());
@@ -5,12 +5,14 @@ use smt_engine::config::SharingConfig;
 
 /// Build a session with two placeholder theories named UF and DL.
 /// Replace these with real implementations.
-pub fn make_session(_sharing: SharingConfig) -> Session {
+pub fn make_session(sharing: SharingConfig) -> Session {
     let theories: Vec<Box<dyn smt_engine::theory::Theory>> = vec![
         Box::new(PlaceholderTheory { nm: "UF" }),
         Box::new(PlaceholderTheory { nm: "DL" }),
     ];
-    Session::new(theories)
+    let mut sess = Session::new(theories);
+    sess.config_mut().sharing = sharing;
+    sess
 }
 
 struct PlaceholderTheory {
@@ -25,3 +27,273 @@ impl smt_engine::theory::Theory for PlaceholderTheory {
         Vec::new()
     }
 }
+
+/// Scripted theory for engine-level tests: owns a fixed set of atoms with fixed endpoints.
+///
+/// While an `exports` atom is true its equality is exported every round; importing an
+/// equality whose `diseqs` atom is true (or a disequality whose `exports` atom is true) is
+/// a conflict. `implies` pairs are propagated lazily; `explained` counts the explanations
+/// the engine asked for.
+pub struct ScriptedTheory {
+    pub nm: &'static str,
+    /// `(atom term, endpoints)`
+    pub atoms: Vec<(smt_core::TermId, Vec<smt_core::TermId>)>,
+    /// `(atom, a, b)`: `atom` true implies `a = b`.
+    pub exports: Vec<(smt_core::TermId, smt_core::TermId, smt_core::TermId)>,
+    /// `(atom, a, b)`: `atom` true implies `a != b`.
+    pub diseqs: Vec<(smt_core::TermId, smt_core::TermId, smt_core::TermId)>,
+    /// `(a, b)`: atom `a` true implies atom `b` true.
+    pub implies: Vec<(smt_core::TermId, smt_core::TermId)>,
+    pub explained: std::rc::Rc<std::cell::Cell<usize>>,
+    /// Registered atoms with their SAT variable.
+    vars: Vec<(smt_core::TermId, smt_sat::VarId)>,
+    /// Atoms assigned true, with their literal.
+    trail: Vec<(smt_core::TermId, smt_sat::Lit)>,
+    lims: Vec<usize>,
+}
+
+impl ScriptedTheory {
+    pub fn new(nm: &'static str) -> Self {
+        Self {
+            nm,
+            atoms: Vec::new(),
+            exports: Vec::new(),
+            diseqs: Vec::new(),
+            implies: Vec::new(),
+            explained: Default::default(),
+            vars: Vec::new(),
+            trail: Vec::new(),
+            lims: Vec::new(),
+        }
+    }
+
+    fn true_lit(&self, atom: smt_core::TermId) -> Option<smt_sat::Lit> {
+        self.trail.iter().find(|(t, _)| *t == atom).map(|&(_, l)| l)
+    }
+
+    /// Conflict if some `(atom, a, b)` in `facts` matches `eq` and `atom` is true.
+    fn clash(
+        &self,
+        facts: &[(smt_core::TermId, smt_core::TermId, smt_core::TermId)],
+        eq: &smt_engine::theory::SharedEq,
+        tcx: &mut smt_engine::theory_ctx::TheoryCtx,
+    ) -> smt_engine::theory::TheoryOutcome {
+        for &(atom, a, b) in facts {
+            if (a, b) != (eq.a, eq.b) && (b, a) != (eq.a, eq.b) { continue; }
+            if let Some(l) = self.true_lit(atom) {
+                let why = tcx.r_lit(l);
+                return smt_engine::theory::TheoryOutcome::Conflict(tcx.r_and(vec![eq.explain, why]));
+            }
+        }
+        smt_engine::theory::TheoryOutcome::Ok
+    }
+}
+
+impl smt_engine::theory::Theory for ScriptedTheory {
+    fn name(&self) -> &'static str { self.nm }
+
+    fn owns_atom(&self, _ctx: &smt_core::Context, atom_term: smt_core::TermId) -> bool {
+        self.atoms.iter().any(|(t, _)| *t == atom_term)
+    }
+
+    fn atom_endpoints(&self, atom_term: smt_core::TermId) -> Vec<smt_core::TermId> {
+        self.atoms.iter().find(|(t, _)| *t == atom_term).map(|(_, e)| e.clone()).unwrap_or_default()
+    }
+
+    fn register_atom(&mut self, _ctx: &smt_core::Context, atom: smt_engine::atoms::Atom) {
+        self.vars.push((atom.term, atom.var));
+    }
+
+    fn propagate(
+        &mut self,
+        _ctx: &smt_core::Context,
+        _tcx: &mut smt_engine::theory_ctx::TheoryCtx,
+    ) -> smt_engine::theory::TheoryOutcome {
+        let props: Vec<_> = self
+            .implies
+            .iter()
+            .filter(|(a, _)| self.true_lit(*a).is_some())
+            .filter_map(|(_, b)| self.vars.iter().find(|(t, _)| t == b))
+            .map(|&(_, v)| smt_engine::theory::TheoryPropagation::lazy(smt_sat::Lit::pos(v)))
+            .collect();
+        if props.is_empty() {
+            smt_engine::theory::TheoryOutcome::Ok
+        } else {
+            smt_engine::theory::TheoryOutcome::Propagate(props)
+        }
+    }
+
+    fn explain(
+        &mut self,
+        _ctx: &smt_core::Context,
+        lit: smt_sat::Lit,
+        tcx: &mut smt_engine::theory_ctx::TheoryCtx,
+    ) -> smt_engine::reason::ReasonId {
+        self.explained.set(self.explained.get() + 1);
+        let b = self.vars.iter().find(|(_, v)| *v == lit.var()).expect("own atom").0;
+        let why = self
+            .implies
+            .iter()
+            .filter(|(_, t)| *t == b)
+            .find_map(|(a, _)| self.true_lit(*a))
+            .expect("propagated from a true atom");
+        tcx.r_lit(why)
+    }
+
+    fn assert_atom(
+        &mut self,
+        _ctx: &smt_core::Context,
+        atom: smt_engine::atoms::Atom,
+        value: bool,
+        _tcx: &mut smt_engine::theory_ctx::TheoryCtx,
+    ) -> smt_engine::theory::TheoryOutcome {
+        if value {
+            self.trail.push((atom.term, smt_sat::Lit::pos(atom.var)));
+        }
+        smt_engine::theory::TheoryOutcome::Ok
+    }
+
+    /// Abstract values over the terms it mentions: terms linked by true `exports` atoms
+    /// share a value, all others are distinct.
+    fn model_value(&self, ctx: &smt_core::Context, t: smt_core::TermId) -> Option<smt_core::Value> {
+        if !self.atoms.iter().any(|(_, ends)| ends.contains(&t)) {
+            return None;
+        }
+        let mut class = vec![t];
+        let mut i = 0;
+        while i < class.len() {
+            for &(atom, a, b) in &self.exports {
+                if self.true_lit(atom).is_none() { continue; }
+                for (u, v) in [(a, b), (b, a)] {
+                    if u == class[i] && !class.contains(&v) {
+                        class.push(v);
+                    }
+                }
+            }
+            i += 1;
+        }
+        class.iter().min().map(|m| smt_core::Value::Elem(ctx.term_sort(t), m.0))
+    }
+
+    fn push_level(&mut self) {
+        self.lims.push(self.trail.len());
+    }
+
+    fn pop_levels(&mut self, n: usize) {
+        let keep = self.lims.len() - n;
+        self.trail.truncate(self.lims[keep]);
+        self.lims.truncate(keep);
+    }
+
+    fn equality_sharing_mut(&mut self) -> Option<&mut dyn smt_engine::theory::EqualitySharing> {
+        Some(self)
+    }
+}
+
+impl smt_engine::theory::EqualitySharing for ScriptedTheory {
+    fn export_equalities(
+        &mut self,
+        _oracle: &smt_engine::shared_terms::SharedTermOracle,
+        _export_epoch: u64,
+        tcx: &mut smt_engine::theory_ctx::TheoryCtx,
+    ) -> Vec<smt_engine::theory::SharedEq> {
+        let mut out = Vec::new();
+        for &(atom, a, b) in &self.exports {
+            let Some(l) = self.true_lit(atom) else { continue };
+            out.push(smt_engine::theory::SharedEq { a, b, explain: tcx.r_lit(l) });
+        }
+        out
+    }
+
+    fn import_equality(
+        &mut self,
+        eq: smt_engine::theory::SharedEq,
+        tcx: &mut smt_engine::theory_ctx::TheoryCtx,
+    ) -> smt_engine::theory::TheoryOutcome {
+        self.clash(&self.diseqs, &eq, tcx)
+    }
+
+    fn import_disequality(
+        &mut self,
+        diseq: smt_engine::theory::SharedEq,
+        tcx: &mut smt_engine::theory_ctx::TheoryCtx,
+    ) -> smt_engine::theory::TheoryOutcome {
+        self.clash(&self.exports, &diseq, tcx)
+    }
+}
+
+/// What `RecordingSat` was asked to do, in call order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KernelEvent {
+    Clause(Vec<smt_sat::Lit>),
+    Decided(smt_sat::Lit),
+}
+
+/// `Cdcl` logging the clauses and decisions the engine hands it into `log`.
+#[derive(Default)]
+pub struct RecordingSat {
+    inner: smt_sat::Cdcl,
+    pub log: std::rc::Rc<std::cell::RefCell<Vec<KernelEvent>>>,
+}
+
+impl RecordingSat {
+    pub fn new() -> Self {
+        Self { inner: smt_sat::Cdcl::new(), log: Default::default() }
+    }
+
+    fn record_decision(&mut self, d: smt_sat::Decide) -> smt_sat::Decide {
+        if d == smt_sat::Decide::Decided {
+            let lit = *smt_sat::SatKernel::trail(&self.inner).last().expect("decision on the trail");
+            self.log.borrow_mut().push(KernelEvent::Decided(lit));
+        }
+        d
+    }
+}
+
+impl smt_sat::SatKernel for RecordingSat {
+    fn new_var(&mut self) -> smt_sat::VarId { self.inner.new_var() }
+
+    fn add_clause(&mut self, lits: &[smt_sat::Lit]) {
+        self.log.borrow_mut().push(KernelEvent::Clause(lits.to_vec()));
+        self.inner.add_clause(lits)
+    }
+
+    fn propagate(&mut self) -> Result<(), ()> { self.inner.propagate() }
+
+    fn value(&self, lit: smt_sat::Lit) -> Option<bool> { self.inner.value(lit) }
+
+    fn decision_level(&self) -> usize { self.inner.decision_level() }
+
+    fn trail(&self) -> &[smt_sat::Lit] { self.inner.trail() }
+
+    fn trail_head(&self) -> usize { self.inner.trail_head() }
+
+    fn set_trail_head(&mut self, head: usize) { self.inner.set_trail_head(head) }
+
+    fn decide(&mut self) -> smt_sat::Decide {
+        let d = self.inner.decide();
+        self.record_decision(d)
+    }
+
+    fn backtrack(&mut self, level: usize) { self.inner.backtrack(level) }
+
+    fn set_phase(&mut self, lit: smt_sat::Lit) { self.inner.set_phase(lit) }
+
+    fn set_assumptions(&mut self, lits: &[smt_sat::Lit]) { self.inner.set_assumptions(lits) }
+
+    fn failed_assumptions(&self) -> &[smt_sat::Lit] { self.inner.failed_assumptions() }
+
+    fn assign_lazy(&mut self, lit: smt_sat::Lit, handle: u32) -> bool { self.inner.assign_lazy(lit, handle) }
+
+    fn propagate_with(&mut self, ex: &mut dyn smt_sat::LazyReasons) -> Result<(), ()> { self.inner.propagate_with(ex) }
+
+    fn add_clause_with(&mut self, lits: &[smt_sat::Lit], ex: &mut dyn smt_sat::LazyReasons) {
+        self.log.borrow_mut().push(KernelEvent::Clause(lits.to_vec()));
+        self.inner.add_clause_with(lits, ex)
+    }
+
+    fn decide_with(&mut self, ex: &mut dyn smt_sat::LazyReasons) -> smt_sat::Decide {
+        let d = self.inner.decide_with(ex);
+        self.record_decision(d)
+    }
+}
@@ -1,9 +1,11 @@
 #![forbid(unsafe_code)]
-//! UNSAT core assertion helpers (template).
+//! UNSAT core assertion helpers.
 //!
-//! This scaffold does not compute real cores yet, but keeps the API shape.
+//! On a mismatch the instance is rerun with eq-sharing debug enabled (so the engine
+//! writes its UNSAT bundle) before failing.
 
 use smt_api::Session;
+use smt_engine::engine::CheckSat;
 use smt_engine::test_debug;
 
 #[derive(Debug, Clone)]
@@ -15,17 +17,32 @@ pub struct CoreReq {
 pub fn assert_unsat_core_contains_any<FMake, FSetup>(
     mut make_sess: FMake,
     setup: FSetup,
-    _reqs: &[CoreReq],
+    reqs: &[CoreReq],
 ) -> smt_core::Result<()>
 where
     FMake: FnMut() -> Session,
     FSetup: Fn(&mut Session) -> smt_core::Result<()>,
 {
-    // Placeholder: rerun with debug on mismatch would go here once the core exists.
     test_debug::set_eqshare_debug_enabled(false);
     let mut sess = make_sess();
     setup(&mut sess)?;
-    let _ = sess.check_sat();
-    let _core = sess.get_unsat_core();
-    Ok(())
+    let got = sess.check_sat();
+    let core: Vec<String> = sess.get_unsat_core().into_iter().map(|l| l.0).collect();
+
+    let missing: Vec<&str> = reqs
+        .iter()
+        .filter(|r| !r.any_of.iter().any(|l| core.iter().any(|c| c == l)))
+        .map(|r| r.name)
+        .collect();
+    if got == CheckSat::Unsat && missing.is_empty() {
+        return Ok(());
+    }
+
+    test_debug::set_eqshare_debug_enabled(true);
+    let mut sess2 = make_sess();
+    setup(&mut sess2)?;
+    let _ = sess2.check_sat();
+    test_debug::set_eqshare_debug_enabled(false);
+
+    Err(format!("expected unsat with core requirements {missing:?} met; got {got:?} with core {core:?}").into())
 }
@@ -4,15 +4,18 @@ use smt_core::TermId;
 use smt_engine::atoms::TheoryId;
 use smt_engine::eqshare_trace::EqShareEvent;
 
-fn has_pair(events: &[EqShareEvent], src: TheoryId, dst: TheoryId, a: TermId, b: TermId) -> bool {
+#[doc(hidden)]
+pub fn has_pair(events: &[EqShareEvent], src: TheoryId, dst: TheoryId, a: TermId, b: TermId) -> bool {
     events.iter().any(|e| e.src == src && e.dst == dst && ((e.a == a && e.b == b) || (e.a == b && e.b == a)))
 }
 
-fn has_dir(events: &[EqShareEvent], src: TheoryId, dst: TheoryId) -> bool {
+#[doc(hidden)]
+pub fn has_dir(events: &[EqShareEvent], src: TheoryId, dst: TheoryId) -> bool {
     events.iter().any(|e| e.src == src && e.dst == dst)
 }
 
-fn dump(events: &[EqShareEvent]) -> String {
+#[doc(hidden)]
+pub fn dump(events: &[EqShareEvent]) -> String {
     events.iter().take(40)
         .map(|e| format!("epoch={} {:?}->{:?} a={:?} b={:?} reason={:?}", e.epoch, e.src, e.dst, e.a, e.b, e.explain))
         .collect::<Vec<_>>()
@@ -17,9 +17,1313 @@ mod tests {
 
     #[test]
     fn smoke_builds_session_and_runs_check_sat() {
-        // With no real theories, the engine returns Unknown, but we exercise the plumbing.
-        let mut sess = make_session(SharingConfig { uf_to_dl: true, dl_to_uf: true });
+        // Nothing asserted: trivially SAT, but we exercise the plumbing.
+        let mut sess = make_session(SharingConfig::uf_dl(true, true));
         let _ = sess.check_sat();
         let _events = sess.take_eqshare_events();
     }
+
+    #[test]
+    fn tseitin_encodes_shared_subformula_once_per_polarity() {
+        use smt_core::Context;
+        use smt_engine::engine::SmtEngine;
+        use smt_sat::DummySat;
+
+        let mut eng = SmtEngine::new(Context::new(), DummySat::default(), Vec::new());
+        let b = eng.ctx.bool_sort();
+        let p = eng.ctx.const_term("p", b);
+        let q = eng.ctx.const_term("q", b);
+        let r = eng.ctx.const_term("r", b);
+        let p_or_q = eng.ctx.or(&[p, q]);
+        let r_imp = eng.ctx.implies(r, p_or_q);
+        let not_or = eng.ctx.not(p_or_q);
+
+        // Positive occurrence only: one definition clause + the unit.
+        eng.assert_formula(p_or_q).unwrap();
+        assert_eq!(eng.sat.clauses().len(), 2);
+        let l = eng.tseitin.lit_of(p_or_q).unwrap();
+
+        // Reused positively: the cached definition is not emitted again.
+        eng.assert_formula(r_imp).unwrap();
+        assert_eq!(eng.sat.clauses().len(), 4);
+
+        // Negative occurrence adds only the missing direction.
+        eng.assert_formula(not_or).unwrap();
+        assert_eq!(eng.sat.clauses().len(), 7);
+        assert_eq!(eng.tseitin.lit_of(p_or_q), Some(l));
+    }
+
+    #[test]
+    fn sharing_routes_exports_to_owners_through_the_matrix() {
+        use smt_api::Session;
+        use smt_core::Context;
+        use smt_engine::atoms::TheoryId;
+        use crate::common::ScriptedTheory;
+
+        let mut ctx = Context::new();
+        let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+        let x = ctx.const_term("x", i);
+        let y = ctx.const_term("y", i);
+        let p = ctx.const_term("p", b);
+        let q = ctx.const_term("q", b);
+        let r = ctx.const_term("r", b);
+
+        let mut a = ScriptedTheory::new("A");
+        a.atoms.push((p, vec![x, y]));
+        a.exports.push((p, x, y));
+        let mut tb = ScriptedTheory::new("B");
+        tb.atoms.push((q, vec![x]));
+        let mut tc = ScriptedTheory::new("C");
+        tc.atoms.push((r, vec![y]));
+        let d = ScriptedTheory::new("D");
+
+        let mut sess = Session::with_context(ctx, vec![Box::new(a), Box::new(tb), Box::new(tc), Box::new(d)]);
+        sess.config_mut().sharing = SharingConfig::default().deny("A", "C");
+        for t in [p, q, r] {
+            sess.assert(t, None).unwrap();
+        }
+        let _ = sess.check_sat();
+
+        // B owns x and is allowed; C owns y but A->C is denied; D owns nothing.
+        let events = sess.take_eqshare_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].src, events[0].dst), (TheoryId(0), TheoryId(1)));
+    }
+
+    #[test]
+    fn exports_are_imported_once_per_branch_and_again_after_backjumps() {
+        use smt_api::Session;
+        use smt_core::Context;
+        use crate::common::ScriptedTheory;
+
+        let mut ctx = Context::new();
+        let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+        let x = ctx.const_term("x", i);
+        let y = ctx.const_term("y", i);
+        let z = ctx.const_term("z", i);
+        let p = ctx.const_term("p", b);
+        let q = ctx.const_term("q", b);
+        let r = ctx.const_term("r", b);
+        let d = ctx.const_term("d", b);
+        let d_or_r = ctx.or(&[d, r]);
+
+        // A re-exports in every round while the atoms hold.
+        let mut ta = ScriptedTheory::new("A");
+        ta.atoms.push((p, vec![x, y]));
+        ta.atoms.push((r, vec![x, z]));
+        ta.exports.push((p, x, y));
+        ta.exports.push((r, x, z));
+        let mut tb = ScriptedTheory::new("B");
+        tb.atoms.push((q, vec![x, y, z]));
+
+        let mut sess = Session::with_context(ctx, vec![Box::new(ta), Box::new(tb)]);
+        sess.assert(q, None).unwrap();
+        sess.assert(p, None).unwrap();
+        let _ = sess.check_sat();
+        assert_eq!(sess.take_eqshare_events().len(), 1);
+        // Imported at level 0: B keeps it for good.
+        let _ = sess.check_sat();
+        assert!(sess.take_eqshare_events().is_empty());
+
+        // r follows from deciding !d, so it holds from level 1 on: each check backjumps
+        // to level 0 and starts a new branch that imports x = z once more.
+        sess.assert(d_or_r, None).unwrap();
+        for _ in 0..2 {
+            let _ = sess.check_sat();
+            let events = sess.take_eqshare_events();
+            assert_eq!(events.len(), 1);
+            assert_eq!((events[0].a, events[0].b), (x, z));
+        }
+    }
+
+    #[test]
+    fn import_conflict_is_learned_in_the_same_round() {
+        use smt_core::Context;
+        use smt_engine::engine::{CheckSat, SmtEngine};
+        use smt_sat::SatKernel;
+        use crate::common::{KernelEvent, RecordingSat, ScriptedTheory};
+
+        let mut ctx = Context::new();
+        let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+        let x = ctx.const_term("x", i);
+        let y = ctx.const_term("y", i);
+        // p: "x = y" in A, q: "x != y" in B.
+        let p = ctx.const_term("p", b);
+        let q = ctx.const_term("q", b);
+        let r = ctx.const_term("r", b);
+        let r_or_p = ctx.or(&[r, p]);
+        let not_r = ctx.not(r);
+
+        let mut ta = ScriptedTheory::new("A");
+        ta.atoms.push((p, vec![x, y]));
+        ta.exports.push((p, x, y));
+        let mut tb = ScriptedTheory::new("B");
+        tb.atoms.push((q, vec![x, y]));
+        tb.diseqs.push((q, x, y));
+
+        let sat = RecordingSat::new();
+        let log = sat.log.clone();
+        let mut eng = SmtEngine::new(ctx, sat, vec![Box::new(ta), Box::new(tb)]);
+        eng.assert_formula(q).unwrap();
+        eng.assert_formula(r_or_p).unwrap();
+        let (lit_p, lit_r) = (eng.tseitin.lit_of(p).expect("encoded"), eng.tseitin.lit_of(r).expect("encoded"));
+        // The import conflict teaches the kernel `q -> !p`, so `r` has to hold.
+        assert_eq!(eng.check_sat(), CheckSat::Sat);
+        assert_eq!(eng.sat.value(lit_p), Some(false));
+
+        // Deciding !r propagates p, so A exports x = y; B's conflict on importing it
+        // reaches the kernel before its next decision.
+        {
+            let log = log.borrow();
+            let decided = log.iter().position(|e| *e == KernelEvent::Decided(!lit_r)).expect("!r decided");
+            let learned = log
+                .iter()
+                .position(|e| matches!(e, KernelEvent::Clause(c) if c.contains(&!lit_p)))
+                .expect("import conflict fed");
+            assert!(decided < learned);
+            assert!(!log[decided + 1..learned].iter().any(|e| matches!(e, KernelEvent::Decided(_))));
+        }
+
+        eng.assert_formula(not_r).unwrap();
+        assert_eq!(eng.check_sat(), CheckSat::Unsat);
+    }
+
+    #[test]
+    fn unsat_core_names_the_labeled_assertions_of_a_theory_conflict() {
+        use smt_api::Session;
+        use smt_core::{Context, TermId};
+        use crate::common::ScriptedTheory;
+
+        // Deterministic, so every session below gets the same term ids.
+        fn build() -> (Context, [TermId; 4], Vec<ScriptedTheory>) {
+            let mut ctx = Context::new();
+            let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+            let x = ctx.const_term("x", i);
+            let y = ctx.const_term("y", i);
+            let p = ctx.const_term("p", b);
+            let q = ctx.const_term("q", b);
+            let r = ctx.const_term("r", b);
+            let p_or_r = ctx.or(&[p, r]);
+            let not_r = ctx.not(r);
+            let mut ta = ScriptedTheory::new("A");
+            ta.atoms.push((p, vec![x, y]));
+            ta.exports.push((p, x, y));
+            let mut tb = ScriptedTheory::new("B");
+            tb.atoms.push((q, vec![x, y]));
+            tb.diseqs.push((q, x, y));
+            (ctx, [q, p_or_r, not_r, r], vec![ta, tb])
+        }
+        let make = || {
+            let (ctx, _, ths) = build();
+            let ths = ths.into_iter().map(|t| Box::new(t) as Box<dyn smt_engine::theory::Theory>).collect();
+            Session::with_context(ctx, ths)
+        };
+        let [q, p_or_r, not_r, r] = build().1;
+
+        assert_unsat_core_contains_any(
+            make,
+            |sess| {
+                sess.assert(q, Some("ne"))?;
+                sess.assert(p_or_r, Some("either"))?;
+                sess.assert(not_r, Some("not-r"))?;
+                sess.assert(q, Some("ne-again"))
+            },
+            &[
+                CoreReq { any_of: &["ne", "ne-again"], name: "disequality" },
+                CoreReq { any_of: &["either"], name: "disjunction" },
+                CoreReq { any_of: &["not-r"], name: "blocked branch" },
+            ],
+        )
+        .unwrap();
+
+        // Dropping the blocker makes it satisfiable; the core is then empty.
+        let mut sess = make();
+        sess.assert(q, Some("ne")).unwrap();
+        sess.assert(p_or_r, Some("either")).unwrap();
+        sess.assert(r, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        assert!(sess.get_unsat_core().is_empty());
+    }
+
+    #[test]
+    fn core_minimization_drops_labels_the_first_core_picked_up() {
+        use smt_api::core_min::CoreStrategy;
+        use smt_api::Session;
+
+        let mut sess = Session::new(Vec::new());
+        let b = sess.ctx().bool_sort();
+        let (p, r, s) = (sess.declare_const("p", b), sess.declare_const("r", b), sess.declare_const("s", b));
+        let p_imp_r = sess.implies(p, r);
+        let r_and_s = sess.and(&[r, s]);
+        let (not_r, not_s) = (sess.not(r), sess.not(s));
+        let not_both = sess.or(&[not_r, not_s]);
+        // `r` is already implied through `a`/`b` when `x` is assumed, so the final
+        // conflict on `e` depends on all four; `x` and `e` alone are unsat.
+        sess.assert(p, Some("a")).unwrap();
+        sess.assert(p_imp_r, Some("b")).unwrap();
+        sess.assert(r_and_s, Some("x")).unwrap();
+        sess.assert(not_both, Some("e")).unwrap();
+
+        let names = |core: Vec<smt_api::Label>| core.into_iter().map(|l| l.0).collect::<Vec<_>>();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        assert_eq!(names(sess.get_unsat_core()), ["a", "b", "x", "e"]);
+
+        sess.core_min_mut().max_checks = 0;
+        assert_eq!(names(sess.get_minimal_unsat_core()).len(), 4);
+
+        for strategy in [CoreStrategy::Deletion, CoreStrategy::QuickXplain] {
+            assert_eq!(sess.check_sat(), CheckSat::Unsat);
+            *sess.core_min_mut() = smt_api::core_min::CoreMinConfig { strategy, max_checks: 100 };
+            assert_eq!(names(sess.get_minimal_unsat_core()), ["x", "e"], "{strategy:?}");
+            assert_eq!(names(sess.get_unsat_core()), ["x", "e"]);
+        }
+    }
+
+    #[test]
+    fn pop_retracts_scoped_assertions_and_terms_but_keeps_lemmas() {
+        use smt_api::Session;
+        use smt_core::{Context, Value};
+        use crate::common::ScriptedTheory;
+
+        let mut ctx = Context::new();
+        let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+        let x = ctx.const_term("x", i);
+        let y = ctx.const_term("y", i);
+        let p = ctx.const_term("p", b);
+        let q = ctx.const_term("q", b);
+        let r = ctx.const_term("r", b);
+        let p_or_r = ctx.or(&[p, r]);
+        let mut ta = ScriptedTheory::new("A");
+        ta.atoms.push((p, vec![x, y]));
+        ta.exports.push((p, x, y));
+        let mut tb = ScriptedTheory::new("B");
+        tb.atoms.push((q, vec![x, y]));
+        tb.diseqs.push((q, x, y));
+
+        let mut sess = Session::with_context(ctx, vec![Box::new(ta), Box::new(tb)]);
+        sess.assert(q, None).unwrap();
+        let base_terms = sess.ctx().num_terms();
+
+        sess.push();
+        let s = sess.declare_const("s", b);
+        let p_and_s = sess.and(&[p, s]);
+        sess.assert(p_and_s, Some("p")).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        assert_eq!(sess.get_unsat_core().len(), 1);
+        sess.pop(1).unwrap();
+        assert_eq!(sess.ctx().num_terms(), base_terms);
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+
+        // `p_or_r` is first encoded inside the scope and again after it.
+        sess.push();
+        sess.assert(p_or_r, None).unwrap();
+        let not_r = sess.not(r);
+        sess.assert(not_r, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        sess.pop(1).unwrap();
+        sess.assert(p_or_r, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        assert_eq!(sess.get_value(&[p, r]).unwrap(), [Value::Bool(false), Value::Bool(true)]);
+
+        assert!(sess.pop(1).is_err());
+    }
+
+    #[test]
+    fn check_sat_assuming_reports_the_failing_assumptions_only() {
+        use smt_api::Session;
+
+        let mut sess = Session::new(Vec::new());
+        let (b, i) = (sess.ctx().bool_sort(), sess.ctx().int_sort());
+        let [p, q, r, s] = ["p", "q", "r", "s"].map(|n| sess.declare_const(n, b));
+        let x = sess.declare_const("x", i);
+        let p_imp_q = sess.implies(p, q);
+        let q_imp_r = sess.implies(q, r);
+        sess.assert(p_imp_q, None).unwrap();
+        sess.assert(q_imp_r, None).unwrap();
+        let not_r = sess.not(r);
+
+        for _ in 0..3 {
+            assert_eq!(sess.check_sat_assuming(&[s, p, not_r]).unwrap(), CheckSat::Unsat);
+            assert_eq!(sess.get_unsat_assumptions(), [p, not_r]);
+            assert_eq!(sess.check_sat_assuming(&[p, s]).unwrap(), CheckSat::Sat);
+            assert!(sess.get_unsat_assumptions().is_empty());
+            assert_eq!(sess.get_value(&[r]).unwrap(), [smt_core::Value::Bool(true)]);
+        }
+        // Nothing was asserted by the queries.
+        assert_eq!(sess.check_sat_assuming(&[not_r]).unwrap(), CheckSat::Sat);
+        assert!(sess.check_sat_assuming(&[x]).is_err());
+    }
+
+    #[test]
+    fn lazy_propagations_are_explained_only_for_conflicts() {
+        use smt_api::Session;
+        use smt_core::{Context, Value};
+        use crate::common::ScriptedTheory;
+
+        let mut ctx = Context::new();
+        let b = ctx.bool_sort();
+        let chain: Vec<_> = (0..6).map(|k| ctx.const_term(format!("p{k}"), b)).collect();
+        let q = ctx.const_term("q", b);
+        let not_q = ctx.not(q);
+        let p0_or_q = ctx.or(&[chain[0], q]);
+        let p0_or_not_q = ctx.or(&[chain[0], not_q]);
+        let not_last = ctx.not(chain[5]);
+        // Only mentioned atoms get registered (and can be propagated).
+        let any = ctx.or(&chain);
+        let mut th = ScriptedTheory::new("L");
+        th.atoms = chain.iter().map(|&p| (p, Vec::new())).collect();
+        th.implies = chain.windows(2).map(|w| (w[0], w[1])).collect();
+        let explained = th.explained.clone();
+
+        let mut sess = Session::with_context(ctx, vec![Box::new(th)]);
+        sess.assert(p0_or_q, None).unwrap();
+        sess.assert(any, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        if sess.get_value(&[chain[0]]).unwrap() == [Value::Bool(true)] {
+            assert_eq!(sess.get_value(&[chain[5]]).unwrap(), [Value::Bool(true)]);
+        }
+        assert_eq!(explained.get(), 0);
+
+        // `p0` is forced, so the chain runs into `!p5`; learning walks it back.
+        sess.assert(p0_or_not_q, None).unwrap();
+        sess.assert(not_last, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        assert!(explained.get() > 0);
+    }
+
+    #[test]
+    fn lia_branches_on_fractional_relaxations_and_shares_fixed_equalities() {
+        use smt_api::Session;
+        use smt_core::{Context, Value};
+        use smt_engine::theories::lia::LiaTheory;
+        use crate::common::ScriptedTheory;
+
+        // x + y = 1, x = y: the relaxation has x = y = 1/2, the integers nothing.
+        let mut sess = Session::new(vec![Box::new(LiaTheory::new())]);
+        sess.config_mut().check_models = true;
+        let int = sess.ctx().int_sort();
+        let (x, y) = (sess.declare_const("x", int), sess.declare_const("y", int));
+        let (zero, one) = (sess.int_const(0), sess.int_const(1));
+        let sum = sess.add(&[x, y]);
+        let diff = sess.sub(x, y);
+        let sum_is_one = sess.eq(sum, one);
+        let diff_is_zero = sess.eq(diff, zero);
+        sess.assert(sum_is_one, None).unwrap();
+        sess.assert(diff_is_zero, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+
+        // 2x + 3y = 7 with x, y >= 0 only has (2, 1).
+        let mut sess = Session::new(vec![Box::new(LiaTheory::new())]);
+        sess.config_mut().check_models = true;
+        let (x, y) = (sess.declare_const("x", int), sess.declare_const("y", int));
+        let (zero, two, three, seven) = (sess.int_const(0), sess.int_const(2), sess.int_const(3), sess.int_const(7));
+        let two_x = sess.mul(two, x);
+        let three_y = sess.mul(three, y);
+        let lhs = sess.add(&[two_x, three_y]);
+        let eq = sess.eq(lhs, seven);
+        let x_nonneg = sess.le(zero, x);
+        let y_nonneg = sess.le(zero, y);
+        let all = sess.and(&[eq, x_nonneg, y_nonneg]);
+        sess.assert(all, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        assert_eq!(sess.get_value(&[x, y]).unwrap(), [Value::Int(2), Value::Int(1)]);
+
+        // Sharing both ways with a scripted theory over the same x, y.
+        let build = || {
+            let mut ctx = Context::new();
+            let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+            let x = ctx.const_term("x", i);
+            let y = ctx.const_term("y", i);
+            let p = ctx.const_term("p", b);
+            let q = ctx.const_term("q", b);
+            let mut th = ScriptedTheory::new("S");
+            th.atoms = vec![(p, vec![x, y]), (q, vec![x, y])];
+            th.exports.push((p, x, y));
+            th.diseqs.push((q, x, y));
+            let sess = Session::with_context(ctx, vec![Box::new(LiaTheory::new()), Box::new(th)]);
+            (sess, x, y, p, q)
+        };
+
+        // S exports x = y into x + 1 <= y.
+        let (mut sess, x, y, p, _) = build();
+        let one = sess.int_const(1);
+        let x1 = sess.add(&[x, one]);
+        let lt = sess.le(x1, y);
+        sess.assert(lt, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        sess.assert(p, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+
+        // LIA exports x = y (both fixed to 3) into S's x != y.
+        let (mut sess, x, y, _, q) = build();
+        let three = sess.int_const(3);
+        let fixed: Vec<_> = [x, y].iter().flat_map(|&t| [sess.le(t, three), sess.le(three, t)]).collect();
+        let fixed = sess.and(&fixed);
+        sess.assert(fixed, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        sess.assert(q, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+    }
+
+    #[test]
+    fn lra_solves_over_rationals_with_strict_bounds() {
+        use smt_api::Session;
+        use smt_core::{Rational, Value};
+        use smt_engine::theories::lra::LraTheory;
+
+        let mut sess = Session::new(vec![Box::new(LraTheory::new())]);
+        sess.config_mut().check_models = true;
+        let real = sess.ctx().real_sort();
+        let (x, y) = (sess.declare_const("x", real), sess.declare_const("y", real));
+        let (zero, half, one) = (sess.rat_const(Rational::ZERO), sess.rat_const(Rational::new(1, 2)), sess.rat_const(Rational::ONE));
+
+        // x + y = 1, x = y: unsatisfiable over Int, (1/2, 1/2) over Real.
+        let sum = sess.add(&[x, y]);
+        let diff = sess.sub(x, y);
+        let sum_is_one = sess.eq(sum, one);
+        let diff_is_zero = sess.eq(diff, zero);
+        sess.assert(sum_is_one, None).unwrap();
+        sess.assert(diff_is_zero, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        let half_value = Value::Real(Rational::new(1, 2));
+        assert_eq!(sess.get_value(&[x, y]).unwrap(), [half_value.clone(), half_value]);
+        assert_eq!(sess.get_value(&[x]).unwrap()[0].to_string(), "(/ 1.0 2.0)");
+
+        // x > 1/2 on top: x + y = 1 then forces y < 1/2 = x.
+        sess.push();
+        let x_le_half = sess.le(x, half);
+        let x_gt_half = sess.not(x_le_half);
+        sess.assert(x_gt_half, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        sess.pop(1).unwrap();
+
+        // 0 < z <= 1 with w = 2z: the strict bound is met by a concrete choice of δ.
+        let mut sess = Session::new(vec![Box::new(LraTheory::new())]);
+        sess.config_mut().check_models = true;
+        let (z, w) = (sess.declare_const("z", real), sess.declare_const("w", real));
+        let (zero, one, two) = (sess.rat_const(Rational::ZERO), sess.rat_const(Rational::ONE), sess.rat_const(Rational::int(2)));
+        let z_le_zero = sess.le(z, zero);
+        let z_pos = sess.not(z_le_zero);
+        let z_le_one = sess.le(z, one);
+        let two_z = sess.mul(two, z);
+        let w_is_two_z = sess.eq(w, two_z);
+        let all = sess.and(&[z_pos, z_le_one, w_is_two_z]);
+        sess.assert(all, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        let vals = sess.get_value(&[z, w]).unwrap();
+        let (Value::Real(zv), Value::Real(wv)) = (&vals[0], &vals[1]) else { panic!("non-real values {vals:?}") };
+        assert!(Rational::ZERO < *zv && *zv <= Rational::ONE);
+        assert_eq!(*wv, Rational::int(2) * *zv);
+    }
+
+    #[test]
+    fn arith_shares_entailed_equalities_with_uf_under_eager_combination() {
+        use smt_api::Session;
+        use smt_engine::theories::{lia::LiaTheory, lra::LraTheory, uf::UfTheory};
+        use smt_engine::theory::Theory;
+
+        let run = |arith: Box<dyn Theory>, real: bool, build: &dyn Fn(&mut Session, [smt_core::TermId; 3]) -> Vec<smt_core::TermId>| {
+            let mut sess = Session::new(vec![arith, Box::new(UfTheory::new())]);
+            sess.config_mut().check_models = true;
+            let sort = if real { sess.ctx().real_sort() } else { sess.ctx().int_sort() };
+            let xyz = ["x", "y", "z"].map(|n| sess.declare_const(n, sort));
+            for t in build(&mut sess, xyz) {
+                sess.assert(t, None).unwrap();
+            }
+            sess.check_sat()
+        };
+        let f_ne = |s: &mut Session, a, b| {
+            let sort = s.ctx().term_sort(a);
+            let (fa, fb) = (s.app_uf("f", &[a], sort), s.app_uf("f", &[b], sort));
+            let eq = s.eq(fa, fb);
+            s.not(eq)
+        };
+
+        // x <= y <= x bounds x - y to 0: exported as soon as both bounds hold.
+        let antisym = |s: &mut Session, [x, y, _]: [smt_core::TermId; 3]| vec![s.le(x, y), s.le(y, x), f_ne(s, x, y)];
+        // x <= y <= z <= x: x = z only follows from the rows, the model has to split on it.
+        let cycle = |s: &mut Session, [x, y, z]: [smt_core::TermId; 3]| vec![s.le(x, y), s.le(y, z), s.le(z, x), f_ne(s, x, z)];
+        // x <= y alone leaves room for x < y.
+        let open = |s: &mut Session, [x, y, _]: [smt_core::TermId; 3]| vec![s.le(x, y), f_ne(s, x, y)];
+        for real in [false, true] {
+            let arith = || -> Box<dyn Theory> { if real { Box::new(LraTheory::new()) } else { Box::new(LiaTheory::new()) } };
+            assert_eq!(run(arith(), real, &antisym), CheckSat::Unsat);
+            assert_eq!(run(arith(), real, &cycle), CheckSat::Unsat);
+            assert_eq!(run(arith(), real, &open), CheckSat::Sat);
+        }
+
+        // f(x) = x + 1 and f(y) = y + 2 with x = y: UF has to hand f(x) = f(y) back.
+        let offsets = |s: &mut Session, [x, y, _]: [smt_core::TermId; 3]| {
+            let int = s.ctx().int_sort();
+            let (fx, fy) = (s.app_uf("f", &[x], int), s.app_uf("f", &[y], int));
+            let (one, two) = (s.int_const(1), s.int_const(2));
+            let (x1, y2) = (s.add(&[x, one]), s.add(&[y, two]));
+            vec![s.eq(fx, x1), s.eq(fy, y2), s.le(x, y), s.le(y, x)]
+        };
+        assert_eq!(run(Box::new(LiaTheory::new()), false, &offsets), CheckSat::Unsat);
+    }
+
+    #[test]
+    fn arithmetic_gives_up_on_overflow_instead_of_panicking() {
+        use smt_api::Session;
+        use smt_core::Rational;
+        use smt_engine::theories::{lia::LiaTheory, lra::LraTheory};
+        use smt_engine::theory::Theory;
+
+        for real in [false, true] {
+            let th: Box<dyn Theory> = if real { Box::new(LraTheory::new()) } else { Box::new(LiaTheory::new()) };
+            let mut sess = Session::new(vec![th]);
+            sess.config_mut().check_models = true;
+            let sort = if real { sess.ctx().real_sort() } else { sess.ctx().int_sort() };
+            let num = |s: &mut Session, v: i64| if real { s.rat_const(Rational::from(v)) } else { s.int_const(v) };
+            let xs: Vec<_> = (0..4).map(|i| sess.declare_const(&format!("x{i}"), sort)).collect();
+
+            // Eight dense rows with coefficients near 1e9 and constants near 1e12: pivoting
+            // them soon needs more than i128.
+            sess.push();
+            for row in 0..8i64 {
+                let mut sum = Vec::new();
+                for (j, &x) in (0i64..).zip(&xs) {
+                    let i = row * 4 + j;
+                    let c = 1_000_000_000 - 7919 * i * (i + 3);
+                    let c = num(&mut sess, if (row + j) % 3 == 0 { -c } else { c });
+                    sum.push(sess.mul(c, x));
+                }
+                let lhs = sess.add(&sum);
+                let k = num(&mut sess, if row % 2 == 0 { 1_000_000_000_000 + row } else { -999_999_999_989 - row });
+                let le = sess.le(lhs, k);
+                sess.assert(le, None).unwrap();
+            }
+            assert_eq!(sess.check_sat(), CheckSat::Unknown);
+            sess.pop(1).unwrap();
+
+            // The scope's rows stay in the tableau, but small problems are decided again.
+            let zero = num(&mut sess, 0);
+            let sum = sess.add(&xs);
+            let le = sess.le(sum, zero);
+            let ge = sess.le(zero, xs[0]);
+            sess.assert(le, None).unwrap();
+            sess.assert(ge, None).unwrap();
+            assert_eq!(sess.check_sat(), CheckSat::Sat);
+            let gt = sess.not(le);
+            assert_eq!(sess.check_sat_assuming(&[gt]).unwrap(), CheckSat::Unsat);
+
+            // Values past i64 fit the solver's rationals but not an Int model value.
+            sess.push();
+            let (a, b, x) = (sess.declare_const("a", sort), sess.declare_const("b", sort), sess.declare_const("x", sort));
+            let big = num(&mut sess, i64::MAX - 5);
+            let (a_big, b_big) = (sess.le(big, a), sess.le(big, b));
+            let sum = sess.add(&[a, b]);
+            let is_sum = sess.eq(x, sum);
+            let all = sess.and(&[a_big, b_big, is_sum]);
+            sess.assert(all, None).unwrap();
+            assert_eq!(sess.check_sat(), if real { CheckSat::Sat } else { CheckSat::Unknown });
+            sess.pop(1).unwrap();
+        }
+
+        // The model checker's evaluator reports an overflowing product instead of panicking.
+        let mut sess = Session::new(Vec::new());
+        let x = sess.declare_const("x", sess.ctx().real_sort());
+        let square = sess.mul(x, x);
+        let mut model = smt_core::Model::default();
+        model.set_value(x, smt_core::Value::Real(Rational::int(i128::MAX / 3)));
+        assert!(smt_core::eval(sess.ctx(), &model, square).is_err());
+    }
+
+    #[test]
+    fn arrays_instantiate_read_over_write_and_extensionality_on_demand() {
+        use smt_api::Session;
+        use smt_core::Value;
+        use smt_engine::config::CombinationStrategy;
+        use smt_engine::theories::arrays::ArraysTheory;
+        use smt_engine::theories::lia::LiaTheory;
+
+        for strategy in [CombinationStrategy::Eager, CombinationStrategy::ModelBased] {
+            let mut sess = Session::new(vec![Box::new(LiaTheory::new()), Box::new(ArraysTheory::new())]);
+            sess.config_mut().check_models = true;
+            sess.config_mut().combination = strategy;
+            let int = sess.ctx().int_sort();
+            let arr = sess.array_sort(int, int);
+            let (a, b) = (sess.declare_const("a", arr), sess.declare_const("b", arr));
+            let (i, j) = (sess.declare_const("i", int), sess.declare_const("j", int));
+            let (one, two) = (sess.int_const(1), sess.int_const(2));
+
+            // select(store(a, i, 1), j) = 2 needs i != j, and then reads a at j.
+            let written = sess.store(a, i, one);
+            let read = sess.select(written, j);
+            let read_is_two = sess.eq(read, two);
+            sess.assert(read_is_two, None).unwrap();
+            assert_eq!(sess.check_sat(), CheckSat::Sat);
+            let vals = sess.get_value(&[i, j, read]).unwrap();
+            assert_ne!(vals[0], vals[1]);
+            assert_eq!(vals[2], Value::Int(2));
+
+            sess.push();
+            let same = sess.eq(i, j);
+            sess.assert(same, None).unwrap();
+            assert_eq!(sess.check_sat(), CheckSat::Unsat);
+            sess.pop(1).unwrap();
+
+            // Writing back what was read changes nothing: b = store(a, i, a[i]) and a != b.
+            sess.push();
+            let old = sess.select(a, i);
+            let rewritten = sess.store(a, i, old);
+            let b_is_rewritten = sess.eq(b, rewritten);
+            let a_is_b = sess.eq(a, b);
+            let a_is_not_b = sess.not(a_is_b);
+            sess.assert(b_is_rewritten, None).unwrap();
+            sess.assert(a_is_not_b, None).unwrap();
+            assert_eq!(sess.check_sat(), CheckSat::Unsat);
+            sess.pop(1).unwrap();
+
+            assert_eq!(sess.check_sat(), CheckSat::Sat);
+        }
+    }
+
+    #[test]
+    fn bit_vectors_blast_wraparound_signedness_and_division() {
+        use smt_api::Session;
+        use smt_core::{BvOp, Value};
+        use smt_engine::theories::bv::BitVecTheory;
+
+        let mut sess = Session::new(vec![Box::new(BitVecTheory::new())]);
+        sess.config_mut().check_models = true;
+        let byte = sess.bv_sort(8);
+        let (x, y) = (sess.declare_const("x", byte), sess.declare_const("y", byte));
+        let c = |sess: &mut Session, v: u128| sess.bv_const(v, 8);
+
+        // x + 1 <u x only when the addition wraps around.
+        let one = c(&mut sess, 1);
+        let next = sess.bv(BvOp::Add, &[x, one]);
+        let wraps = sess.bv(BvOp::Ult, &[next, x]);
+        sess.assert(wraps, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        assert_eq!(sess.get_value(&[x]).unwrap()[0], Value::BitVec(8, 0xff));
+
+        // Every byte above 0x7f unsigned is negative signed.
+        sess.push();
+        let (max, zero) = (c(&mut sess, 0x7f), c(&mut sess, 0));
+        let above = sess.bv(BvOp::Ult, &[max, y]);
+        let negative = sess.bv(BvOp::Slt, &[y, zero]);
+        let not_negative = sess.not(negative);
+        sess.assert(above, None).unwrap();
+        sess.assert(not_negative, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        sess.pop(1).unwrap();
+
+        // -7 by 2: truncating quotient and remainder, floored modulus; division by zero.
+        sess.push();
+        let (minus_seven, two, zero) = (c(&mut sess, 0xf9), c(&mut sess, 2), c(&mut sess, 0));
+        let y_is = sess.eq(y, minus_seven);
+        sess.assert(y_is, None).unwrap();
+        let expected = [
+            (BvOp::Sdiv, two, 0xfd),
+            (BvOp::Srem, two, 0xff),
+            (BvOp::Smod, two, 0x01),
+            (BvOp::Udiv, zero, 0xff),
+            (BvOp::Urem, zero, 0xf9),
+        ];
+        let mut all = Vec::new();
+        for (op, d, v) in expected {
+            let (t, v) = (sess.bv(op, &[y, d]), c(&mut sess, v));
+            all.push(sess.eq(t, v));
+        }
+        let conj = sess.and(&all);
+        let wrong = sess.not(conj);
+        sess.assert(wrong, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        sess.pop(1).unwrap();
+
+        // Swapping the nibbles of y gives 0x21.
+        let (lo, hi) = (sess.bv(BvOp::Extract(3, 0), &[y]), sess.bv(BvOp::Extract(7, 4), &[y]));
+        let swapped = sess.bv(BvOp::Concat, &[lo, hi]);
+        let target = c(&mut sess, 0x21);
+        let swap_is = sess.eq(swapped, target);
+        sess.assert(swap_is, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        assert_eq!(sess.get_value(&[x, y]).unwrap(), vec![Value::BitVec(8, 0xff), Value::BitVec(8, 0x12)]);
+    }
+
+    #[test]
+    fn datatypes_unify_clash_reject_cycles_and_share_fields() {
+        use smt_api::Session;
+        use smt_core::{ConstructorDecl, DatatypeDecl, FieldSort, Value};
+        use smt_engine::theories::{datatypes::DatatypeTheory, lia::LiaTheory, uf::UfTheory};
+
+        let mut sess =
+            Session::new(vec![Box::new(LiaTheory::new()), Box::new(UfTheory::new()), Box::new(DatatypeTheory::new())]);
+        sess.config_mut().check_models = true;
+        let int = sess.ctx().int_sort();
+        let list = sess
+            .declare_datatype(DatatypeDecl::new(
+                "List",
+                vec![
+                    ConstructorDecl::new("nil", vec![]),
+                    ConstructorDecl::new("cons", vec![("head", FieldSort::Sort(int)), ("tail", FieldSort::Group(0))]),
+                ],
+            ))
+            .unwrap();
+        let (x, y) = (sess.declare_const("x", list), sess.declare_const("y", list));
+        let (a, b) = (sess.declare_const("a", int), sess.declare_const("b", int));
+        let nil = sess.construct(list, 0, &[]);
+        let three = sess.int_const(3);
+
+        // x = cons(a, x) has no finite solution.
+        sess.push();
+        let looped = sess.construct(list, 1, &[a, x]);
+        let x_loops = sess.eq(x, looped);
+        sess.assert(x_loops, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        sess.pop(1).unwrap();
+
+        // cons(a, x) = cons(b, y) forces a = b; nil = cons(a, x) clashes.
+        sess.push();
+        let (ax, by) = (sess.construct(list, 1, &[a, x]), sess.construct(list, 1, &[b, y]));
+        let same = sess.eq(ax, by);
+        let a_is_b = sess.eq(a, b);
+        let a_is_not_b = sess.not(a_is_b);
+        sess.assert(same, None).unwrap();
+        sess.assert(a_is_not_b, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        sess.pop(1).unwrap();
+        sess.push();
+        let ax = sess.construct(list, 1, &[a, x]);
+        let clash = sess.eq(nil, ax);
+        sess.assert(clash, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        sess.pop(1).unwrap();
+
+        // Three distinct values of a three-constructor enum fit, four do not.
+        let color = sess
+            .declare_datatype(DatatypeDecl::new(
+                "Color",
+                ["red", "green", "blue"].into_iter().map(|c| ConstructorDecl::new(c, vec![])).collect(),
+            ))
+            .unwrap();
+        let colors: Vec<_> = (0..4).map(|k| sess.declare_const(&format!("c{k}"), color)).collect();
+        sess.push();
+        for i in 0..4 {
+            for j in i + 1..4 {
+                let same = sess.eq(colors[i], colors[j]);
+                let differ = sess.not(same);
+                sess.assert(differ, None).unwrap();
+            }
+        }
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        sess.pop(1).unwrap();
+
+        // A field derived by the datatype theory reaches UF: head(cons(3, nil)) and 3 agree.
+        sess.push();
+        let single = sess.construct(list, 1, &[three, nil]);
+        let head = sess.selector(1, 0, single);
+        let (f_head, f_three) = (sess.app_uf("f", &[head], int), sess.app_uf("f", &[three], int));
+        let same = sess.eq(f_head, f_three);
+        let differ = sess.not(same);
+        sess.assert(differ, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        sess.pop(1).unwrap();
+
+        // is-cons(x), head(x) = 3 and tail(x) = nil pin x down.
+        let is_cons = sess.tester(1, x);
+        let head = sess.selector(1, 0, x);
+        let head_is_three = sess.eq(head, three);
+        let tail = sess.selector(1, 1, x);
+        let tail_is_nil = sess.eq(tail, nil);
+        for t in [is_cons, head_is_three, tail_is_nil] {
+            sess.assert(t, None).unwrap();
+        }
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        let expected = Value::Ctor("cons".into(), vec![Value::Int(3), Value::Ctor("nil".into(), vec![])]);
+        assert_eq!(sess.get_value(&[x]).unwrap()[0], expected);
+    }
+
+    #[test]
+    fn quantifiers_instantiate_by_e_matching_within_limits() {
+        use smt_api::Session;
+        use smt_engine::theories::{lia::LiaTheory, uf::UfTheory};
+
+        let mut sess = Session::new(vec![Box::new(LiaTheory::new()), Box::new(UfTheory::new())]);
+        sess.config_mut().check_models = true;
+        let int = sess.ctx().int_sort();
+        let (a, b) = (sess.declare_const("a", int), sess.declare_const("b", int));
+        let (one, three) = (sess.int_const(1), sess.int_const(3));
+
+        // forall x. f(x) = x + 1, triggered by f(x): f(b) with b = a = 3 matches through
+        // the congruence closure, so f(b) = 5 is refuted by the instance for b.
+        sess.push();
+        let x = sess.bound_var("x", int);
+        let fx = sess.app_uf("f", &[x], int);
+        let next = sess.add(&[x, one]);
+        let body = sess.eq(fx, next);
+        let axiom = sess.forall(&[x], body, &[vec![fx]]);
+        sess.assert(axiom, None).unwrap();
+        let (a_is_b, a_is_three) = (sess.eq(a, b), sess.eq(a, three));
+        let fb = sess.app_uf("f", &[b], int);
+        let five = sess.int_const(5);
+        let fb_is_five = sess.eq(fb, five);
+        for t in [a_is_b, a_is_three, fb_is_five] {
+            sess.assert(t, None).unwrap();
+        }
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        let instances = sess.instances();
+        assert_eq!(instances.len(), 1);
+        assert_eq!((instances[0].quantifier, instances[0].binding.clone(), instances[0].trigger), (axiom, vec![b], Some(0)));
+        sess.pop(1).unwrap();
+        assert!(sess.instances().is_empty());
+
+        // forall x. f(x) < f(f(x)) feeds itself: instantiation stops at the generation
+        // limit and the answer is unknown.
+        sess.push();
+        sess.config_mut().quantifiers.max_generation = 3;
+        let x = sess.bound_var("x", int);
+        let fx = sess.app_uf("f", &[x], int);
+        let ffx = sess.app_uf("f", &[fx], int);
+        let not_less = sess.le(ffx, fx);
+        let body = sess.not(not_less);
+        let axiom = sess.forall(&[x], body, &[vec![fx]]);
+        sess.assert(axiom, None).unwrap();
+        let fa = sess.app_uf("f", &[a], int);
+        let fa_is_three = sess.eq(fa, three);
+        sess.assert(fa_is_three, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unknown);
+        assert_eq!(sess.instances().iter().map(|i| i.generation).collect::<Vec<_>>(), vec![0, 1, 2]);
+        sess.pop(1).unwrap();
+
+        // An existential is skolemized: exists y. 3 < y <= 4 is sat, its constant being 4.
+        let y = sess.bound_var("y", int);
+        let four = sess.int_const(4);
+        let (at_most_three, at_most_four) = (sess.le(y, three), sess.le(y, four));
+        let above_three = sess.not(at_most_three);
+        let body = sess.and(&[above_three, at_most_four]);
+        let witness = sess.exists(&[y], body, &[]);
+        sess.assert(witness, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        let skolem = sess.instances()[0].binding[0];
+        assert_eq!(sess.get_value(&[skolem]).unwrap()[0], smt_core::Value::Int(4));
+    }
+
+    #[test]
+    fn utvpi_tightens_parity_cycles_and_exports_equalities() {
+        use smt_api::Session;
+        use smt_core::Value;
+        use smt_engine::theories::{uf::UfTheory, utvpi::UtvpiTheory};
+
+        let session = || {
+            let mut sess = Session::new(vec![Box::new(UtvpiTheory::new()), Box::new(UfTheory::new())]);
+            sess.config_mut().check_models = true;
+            sess
+        };
+
+        // x + y = 1, x = y: no negative cycle, but 2x <= 1 tightens to x <= 0 and
+        // -2x <= -1 to x >= 1.
+        let mut sess = session();
+        let int = sess.ctx().int_sort();
+        let (x, y) = (sess.declare_const("x", int), sess.declare_const("y", int));
+        let (one, three) = (sess.int_const(1), sess.int_const(3));
+        let sum = sess.add(&[x, y]);
+        let sum_is_one = sess.eq(sum, one);
+        let same = sess.eq(x, y);
+        sess.assert(sum_is_one, None).unwrap();
+        sess.push();
+        sess.assert(same, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        sess.pop(1).unwrap();
+
+        // x + y = 1 with 3 <= x - y: x = 2, y = -1 is the solution closest to 0.
+        let diff = sess.sub(x, y);
+        let apart = sess.le(three, diff);
+        sess.assert(apart, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        assert_eq!(sess.get_value(&[x, y]).unwrap(), [Value::Int(2), Value::Int(-1)]);
+
+        // x + y = 2 = z + y, as bounds: paths through -y bound x - z both ways, and UF
+        // gets x = z.
+        let mut sess = session();
+        let (x, y, z) = (sess.declare_const("x", int), sess.declare_const("y", int), sess.declare_const("z", int));
+        let two = sess.int_const(2);
+        let (sum, other) = (sess.add(&[x, y]), sess.add(&[z, y]));
+        let (lo, hi) = (sess.le(two, sum), sess.le(sum, two));
+        let (z_lo, z_hi) = (sess.le(two, other), sess.le(other, two));
+        let all = sess.and(&[lo, hi, z_lo, z_hi]);
+        sess.assert(all, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        let (fx, fz) = (sess.app_uf("f", &[x], int), sess.app_uf("f", &[z], int));
+        let f_eq = sess.eq(fx, fz);
+        let f_differ = sess.not(f_eq);
+        sess.assert(f_differ, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+
+        // Atoms outside the fragment go to LIA, together with the ones inside: x = y = z
+        // leaves 3x = 4, 1 <= 3x <= 5 has a solution.
+        let mut sess = session();
+        let (x, y, z) = (sess.declare_const("x", int), sess.declare_const("y", int), sess.declare_const("z", int));
+        let (one, four, five) = (sess.int_const(1), sess.int_const(4), sess.int_const(5));
+        let sum = sess.add(&[x, y, z]);
+        let (at_least_one, at_most_five) = (sess.le(one, sum), sess.le(sum, five));
+        let range = sess.and(&[at_least_one, at_most_five]);
+        sess.assert(range, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        let (xy, yz, is_four) = (sess.eq(x, y), sess.eq(y, z), sess.eq(sum, four));
+        let all = sess.and(&[xy, yz]);
+        sess.assert(all, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        assert_eq!(sess.get_value(&[x]).unwrap(), [Value::Int(1)]);
+        sess.assert(is_four, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+
+        // Shared terms the model makes equal are split on: f(x) != f(y) needs x != y.
+        let mut sess = session();
+        let (x, y) = (sess.declare_const("x", int), sess.declare_const("y", int));
+        let (zero, one) = (sess.int_const(0), sess.int_const(1));
+        let (x_lo, x_hi, y_lo, y_hi) = (sess.le(zero, x), sess.le(x, one), sess.le(zero, y), sess.le(y, one));
+        let (fx, fy) = (sess.app_uf("f", &[x], int), sess.app_uf("f", &[y], int));
+        let f_eq = sess.eq(fx, fy);
+        let f_differ = sess.not(f_eq);
+        let all = sess.and(&[x_lo, x_hi, y_lo, y_hi, f_differ]);
+        sess.assert(all, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+        let values = sess.get_value(&[x, y]).unwrap();
+        assert_ne!(values[0], values[1]);
+    }
+
+    #[test]
+    fn interface_equality_splits_complete_the_combination() {
+        use smt_api::Session;
+        use smt_core::Context;
+        use smt_engine::engine::CheckSat;
+        use crate::common::ScriptedTheory;
+
+        let build = |split: bool| {
+            let mut ctx = Context::new();
+            let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+            let x = ctx.const_term("x", i);
+            let y = ctx.const_term("y", i);
+            let p = ctx.const_term("p", b);
+            let q = ctx.const_term("q", b);
+
+            let mut ta = ScriptedTheory::new("A");
+            ta.atoms.push((p, vec![x, y]));
+            ta.exports.push((p, x, y));
+            let mut tb = ScriptedTheory::new("B");
+            tb.atoms.push((q, vec![x, y]));
+            tb.diseqs.push((q, x, y));
+
+            let mut sess = Session::with_context(ctx, vec![Box::new(ta), Box::new(tb)]);
+            // No eager exchange: only the arrangement can expose the clash.
+            sess.config_mut().sharing = SharingConfig::none().split_interface_eqs(split);
+            sess.assert(p, None).unwrap();
+            sess.assert(q, None).unwrap();
+            (sess, x, y)
+        };
+
+        let (mut sess, _, _) = build(false);
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+
+        let (mut sess, x, y) = build(true);
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+        let splits = sess.take_split_events();
+        assert_eq!(splits.len(), 1);
+        assert_eq!((splits[0].a, splits[0].b), (x, y));
+    }
+
+    #[test]
+    fn model_based_combination_splits_only_disagreeing_pairs() {
+        use smt_api::Session;
+        use smt_core::Context;
+        use smt_engine::config::CombinationStrategy;
+        use smt_engine::engine::CheckSat;
+        use crate::common::ScriptedTheory;
+
+        let mut ctx = Context::new();
+        let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+        let x = ctx.const_term("x", i);
+        let y = ctx.const_term("y", i);
+        let z = ctx.const_term("z", i);
+        let p = ctx.const_term("p", b);
+        let q = ctx.const_term("q", b);
+
+        let mut ta = ScriptedTheory::new("A");
+        ta.atoms.push((p, vec![x, y, z]));
+        ta.exports.push((p, x, y));
+        let mut tb = ScriptedTheory::new("B");
+        tb.atoms.push((q, vec![x, y, z]));
+        tb.diseqs.push((q, x, y));
+
+        let mut sess = Session::with_context(ctx, vec![Box::new(ta), Box::new(tb)]);
+        sess.config_mut().combination = CombinationStrategy::ModelBased;
+        sess.assert(p, None).unwrap();
+        sess.assert(q, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Unsat);
+
+        // Both models keep z apart from x and y; only x/y is disputed.
+        assert!(sess.take_eqshare_events().is_empty());
+        let splits = sess.take_split_events();
+        assert_eq!(splits.len(), 1);
+        assert_eq!((splits[0].a, splits[0].b), (x, y));
+    }
+
+    #[test]
+    fn model_merges_theory_classes_and_interprets_uf() {
+        use smt_api::Session;
+        use smt_core::{Context, Value};
+        use smt_engine::engine::CheckSat;
+        use crate::common::ScriptedTheory;
+
+        let mut ctx = Context::new();
+        let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+        let u = ctx.declare_uninterpreted_sort("U");
+        let x = ctx.const_term("x", i);
+        let y = ctx.const_term("y", i);
+        let z = ctx.const_term("z", i);
+        let fx = ctx.uf_app("f", &[x], u);
+        let fz = ctx.uf_app("f", &[z], u);
+        let p = ctx.const_term("p", b);
+        let q = ctx.const_term("q", b);
+
+        let mut ta = ScriptedTheory::new("A");
+        ta.atoms.push((p, vec![x, y, z]));
+        ta.exports.push((p, x, y));
+        let mut tb = ScriptedTheory::new("B");
+        tb.atoms.push((q, vec![x, y, fx, fz]));
+
+        let mut sess = Session::with_context(ctx, vec![Box::new(ta), Box::new(tb)]);
+        assert!(sess.get_model().is_err());
+        sess.assert(p, None).unwrap();
+        sess.assert(q, None).unwrap();
+        assert_eq!(sess.check_sat(), CheckSat::Sat);
+
+        let vals = sess.get_value(&[x, y, z, p]).unwrap();
+        assert_eq!(vals[0], vals[1]);
+        assert_ne!(vals[0], vals[2]);
+        assert!(matches!(vals[0], Value::Int(_)));
+        assert_eq!(vals[3], Value::Bool(true));
+
+        let model = sess.get_model().unwrap();
+        let f = model.func("f").unwrap();
+        assert_eq!(f.entries.len(), 2);
+        assert_eq!(f.apply(&[vals[2].clone()]), model.value(fz).unwrap());
+        assert!(matches!(model.value(fx), Some(Value::Elem(s, _)) if *s == u));
+    }
+
+    #[test]
+    #[should_panic(expected = "model check failed")]
+    fn model_check_catches_a_combination_that_drops_equalities() {
+        use smt_api::Session;
+        use smt_core::Context;
+        use crate::common::ScriptedTheory;
+
+        let mut ctx = Context::new();
+        let i = ctx.int_sort();
+        let x = ctx.const_term("x", i);
+        let y = ctx.const_term("y", i);
+        let e_a = ctx.eq(x, y);
+        let e_b = ctx.eq(x, y);
+        let not_e_b = ctx.not(e_b);
+
+        let mut ta = ScriptedTheory::new("A");
+        ta.atoms.push((e_a, vec![x, y]));
+        ta.exports.push((e_a, x, y));
+        let mut tb = ScriptedTheory::new("B");
+        tb.atoms.push((e_b, vec![x, y]));
+
+        let mut sess = Session::with_context(ctx, vec![Box::new(ta), Box::new(tb)]);
+        // With sharing off B never hears `x = y`, so `Sat` is wrong.
+        sess.config_mut().sharing = SharingConfig::none();
+        sess.config_mut().check_models = true;
+        sess.assert(e_a, None).unwrap();
+        sess.assert(not_e_b, Some("b")).unwrap();
+        let _ = sess.check_sat();
+    }
+
+    #[test]
+    fn export_shapes_compose_member_reasons() {
+        use smt_core::TermId;
+        use smt_engine::export_shape::{shape_class, ExportShape};
+        use smt_engine::reason::ReasonArena;
+        use smt_engine::theory::EqClass;
+        use smt_engine::theory_ctx::TheoryCtx;
+        use smt_sat::{Lit, VarId};
+
+        let mut arena = ReasonArena::default();
+        let mut tcx = TheoryCtx::new(&mut arena);
+        let members: Vec<_> = (1..4).map(|k| (TermId(k), tcx.r_lit(Lit::pos(VarId(k))))).collect();
+        let class = EqClass { rep: TermId(0), members };
+
+        let star = shape_class(ExportShape::Star, &class, &mut tcx);
+        let chain = shape_class(ExportShape::Chain, &class, &mut tcx);
+        let all = shape_class(ExportShape::AllPairs, &class, &mut tcx);
+        assert_eq!((star.len(), chain.len(), all.len()), (3, 3, 6));
+
+        let lits = |eq: &smt_engine::theory::SharedEq, arena: &ReasonArena| {
+            let mut v: Vec<u32> = arena.expand_lits(eq.explain).iter().map(|l| l.var().0).collect();
+            v.sort();
+            v
+        };
+        // Star: `t0 = t3` because v3; chain: `t2 = t3` because v2 & v3.
+        assert_eq!((star[2].a, star[2].b), (TermId(0), TermId(3)));
+        assert_eq!((chain[2].a, chain[2].b), (TermId(2), TermId(3)));
+        assert_eq!(lits(&star[2], &arena), vec![3]);
+        assert_eq!(lits(&chain[2], &arena), vec![2, 3]);
+    }
+
+    #[test]
+    fn typed_reasons_flatten_like_and_and_render_their_tags() {
+        use smt_core::{Context, TermId};
+        use smt_engine::atoms::TheoryId;
+        use smt_engine::engine::{CheckSat, SmtEngine};
+        use smt_engine::reason::ReasonArena;
+        use smt_engine::reason_dot::{reason_to_dot, DotLimits};
+        use smt_engine::theory_ctx::TheoryCtx;
+        use smt_sat::{Cdcl, Lit, VarId};
+        use crate::common::ScriptedTheory;
+
+        let mut arena = ReasonArena::default();
+        arena.label(Lit::neg(VarId(4)), "in");
+        let mut tcx = TheoryCtx::new(&mut arena);
+        let l: Vec<_> = (0..5).map(|k| tcx.r_lit(if k == 4 { Lit::neg(VarId(k)) } else { Lit::pos(VarId(k)) })).collect();
+        let (t0, t1, t2) = (TermId(0), TermId(1), TermId(2));
+        let cong = tcx.r_congruence(t0, t1, vec![l[0]]);
+        let trans = tcx.r_trans(t0, t2, vec![cong, l[1]]);
+        let path = tcx.r_dl_path(vec![t0, t1, t2], vec![l[2], l[3]]);
+        let imported = tcx.r_imported(TheoryId(1), t0, t2, trans);
+        let typed = tcx.r_and(vec![imported, path, l[4]]);
+        let flat = tcx.r_and(l.clone());
+
+        let sorted = |mut lits: Vec<Lit>| {
+            lits.sort_by_key(|l| (l.var().0, l.is_pos()));
+            lits
+        };
+        assert_eq!(sorted(arena.expand_lits(typed)), sorted(arena.expand_lits(flat)));
+        let dot = reason_to_dot(&arena, typed, DotLimits::default());
+        for label in ["CONG t0 = t1", "TRANS t0 = t2 (2)", "DL t0 -> t1 -> t2", "IMPORT t0 = t2 from th1", "ASSUME in (¬v4)"] {
+            assert!(dot.contains(label), "{label} missing from\n{dot}");
+        }
+
+        // Leaves on labeled assertions name them in the conflict.
+        let mut ctx = Context::new();
+        let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+        let (x, y) = (ctx.const_term("x", i), ctx.const_term("y", i));
+        let (p, q) = (ctx.const_term("p", b), ctx.const_term("q", b));
+        let mut ta = ScriptedTheory::new("A");
+        ta.atoms.push((p, vec![x, y]));
+        ta.exports.push((p, x, y));
+        let mut tb = ScriptedTheory::new("B");
+        tb.atoms.push((q, vec![x, y]));
+        tb.diseqs.push((q, x, y));
+        let mut eng = SmtEngine::new(ctx, Cdcl::new(), vec![Box::new(ta), Box::new(tb)]);
+        eng.config.check_models = true;
+        // q first: the scripted B only checks imports against atoms already true.
+        for (t, label) in [(q, "ne"), (p, "eq")] {
+            eng.assert_formula(t).unwrap();
+            eng.label_assertion(t, label);
+        }
+        assert_eq!(eng.check_sat(), CheckSat::Unsat);
+        let conflict = eng.last_conflict.expect("theory conflict");
+        assert_eq!(eng.reasons.assumption_labels(conflict), ["eq", "ne"]);
+        assert!(eng.dump_conflict_reason_dot(conflict).contains("ASSUME ne"));
+    }
+
+    #[test]
+    fn reason_checkpoints_reclaim_all_but_pinned_reasons() {
+        use smt_core::TermId;
+        use smt_engine::reason::ReasonArena;
+        use smt_engine::theory_ctx::TheoryCtx;
+        use smt_sat::{Lit, VarId};
+
+        let mut arena = ReasonArena::default();
+        let base = TheoryCtx::new(&mut arena).r_lit(Lit::pos(VarId(0)));
+        arena.push_checkpoint();
+        let mut tcx = TheoryCtx::new(&mut arena);
+        let l1 = tcx.r_lit(Lit::pos(VarId(1)));
+        let l2 = tcx.r_lit(Lit::neg(VarId(2)));
+        let step = tcx.r_and(vec![base, l1]);
+        // `l1` is shared below both steps; pinning copies it once.
+        let root = tcx.r_trans(TermId(0), TermId(2), vec![step, l1, l2]);
+        let pinned = arena.pin(root);
+        arena.pop_checkpoints(1);
+
+        let stats = arena.stats();
+        assert!(pinned.is_pinned());
+        assert_eq!((stats.live, stats.pinned, stats.reclaimed, stats.checkpoints), (1, 5, 4, 0));
+        let mut vars: Vec<u32> = arena.expand_lits(pinned).iter().map(|l| l.var().0).collect();
+        vars.sort();
+        vars.dedup();
+        assert_eq!(vars, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn reason_expansion_walks_shared_nodes_once_and_caches_roots() {
+        use smt_engine::reason::ReasonArena;
+        use smt_engine::theory_ctx::TheoryCtx;
+        use smt_sat::{Lit, VarId};
+
+        let mut arena = ReasonArena::default();
+        let mut tcx = TheoryCtx::new(&mut arena);
+        let leaves: Vec<_> = (0..3).map(|k| tcx.r_lit(Lit::pos(VarId(k)))).collect();
+        let shared = tcx.r_and(leaves.clone());
+        let dup = tcx.r_lit(Lit::pos(VarId(1)));
+        let mut layer = tcx.r_and(vec![shared, dup]);
+        // A diamond ladder: naive expansion would list 2^20 copies of the leaves.
+        for _ in 0..20 {
+            layer = tcx.r_and(vec![layer, layer]);
+        }
+
+        assert_eq!(arena.expand_lits(layer), vec![Lit::pos(VarId(0)), Lit::pos(VarId(1)), Lit::pos(VarId(2))]);
+        assert_eq!(arena.expand_lits_bounded(layer, 2), (vec![Lit::pos(VarId(0)), Lit::pos(VarId(1))], true));
+        assert!(!arena.expand_lits_bounded(layer, 3).1);
+
+        arena.set_caching(true);
+        arena.push_checkpoint();
+        let top = TheoryCtx::new(&mut arena).r_and(vec![layer, leaves[0]]);
+        assert_eq!(arena.expand_lits_cached(top).len(), 3);
+        assert_eq!(arena.expand_lits_cached(top).len(), 3);
+        assert_eq!((arena.stats().cached, arena.stats().cache_hits), (1, 1));
+        arena.pop_checkpoints(1);
+        assert_eq!(arena.stats().cached, 0);
+    }
+
+    #[test]
+    fn shared_term_oracle_tracks_atoms_incrementally() {
+        use smt_core::Context;
+        use smt_engine::atoms::{Atom, AtomTable, TheoryId};
+        use smt_engine::shared_terms::SharedTermOracle;
+        use smt_engine::theory::Theory;
+        use smt_sat::VarId;
+        use crate::common::ScriptedTheory;
+
+        let mut ctx = Context::new();
+        let (b, i) = (ctx.bool_sort(), ctx.int_sort());
+        let x = ctx.const_term("x", i);
+        let p = ctx.const_term("p", b);
+        let q = ctx.const_term("q", b);
+        let r = ctx.const_term("r", b);
+
+        let mut ta = ScriptedTheory::new("A");
+        ta.atoms.push((p, vec![x]));
+        let mut tb = ScriptedTheory::new("B");
+        tb.atoms.push((q, vec![x]));
+        let mut tc = ScriptedTheory::new("C");
+        tc.atoms.push((r, vec![x]));
+        let theories: Vec<Box<dyn Theory>> = vec![Box::new(ta), Box::new(tb), Box::new(tc)];
+
+        let mut atoms = AtomTable::default();
+        let mut oracle = SharedTermOracle::default();
+        atoms.push(Atom { term: p, theory: TheoryId(0), var: VarId(0) });
+        oracle.sync(&atoms, &theories);
+        assert!(!oracle.is_shared(x));
+
+        atoms.push(Atom { term: q, theory: TheoryId(1), var: VarId(1) });
+        atoms.push(Atom { term: r, theory: TheoryId(2), var: VarId(2) });
+        oracle.sync(&atoms, &theories);
+        assert!(oracle.is_shared(x));
+        assert_eq!(oracle.owners(x).unwrap().len(), 3);
+        assert_eq!(oracle.shared_with(TheoryId(2)).collect::<Vec<_>>(), vec![x]);
+        let mut notes = oracle.take_newly_shared();
+        notes.sort_by_key(|(th, _)| th.0);
+        assert_eq!(notes, vec![(TheoryId(0), x), (TheoryId(1), x), (TheoryId(2), x)]);
+
+        oracle.truncate(1);
+        assert!(!oracle.is_shared(x));
+        assert_eq!(oracle.shared_with(TheoryId(0)).count(), 0);
+    }
 }